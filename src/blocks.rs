@@ -1,13 +1,18 @@
+pub mod amd_gpu;
 pub mod apt;
 pub mod backlight;
 pub mod base_block;
 pub mod battery;
 pub mod bluetooth;
+pub mod caffeine;
+pub mod countdown;
 pub mod cpu;
 pub mod custom;
 pub mod custom_dbus;
+pub mod disk_io;
 pub mod disk_space;
 pub mod docker;
+pub mod external_ip;
 pub mod fan;
 pub mod focused_window;
 pub mod github;
@@ -16,8 +21,10 @@ pub mod ibus;
 pub mod kdeconnect;
 pub mod keyboard_layout;
 pub mod load;
+pub mod mail;
 pub mod maildir;
 pub mod memory;
+pub mod microphone;
 pub mod music;
 pub mod net;
 pub mod networkmanager;
@@ -26,29 +33,43 @@ pub mod notify;
 pub mod notmuch;
 pub mod nvidia_gpu;
 pub mod pacman;
+pub mod ping;
 pub mod pomodoro;
+pub mod recording;
+pub mod smart;
 pub mod sound;
+pub mod sound_device;
 pub mod speedtest;
+pub mod systemd;
 pub mod taskwarrior;
 pub mod temperature;
 pub mod template;
 pub mod time;
 pub mod toggle;
+pub mod top;
+pub mod updates;
 pub mod uptime;
+pub mod vpn;
 pub mod watson;
 pub mod weather;
+pub mod worker;
 pub mod xrandr;
 
+use self::amd_gpu::*;
 use self::apt::*;
 use self::backlight::*;
 use self::base_block::*;
 use self::battery::*;
 use self::bluetooth::*;
+use self::caffeine::*;
+use self::countdown::*;
 use self::cpu::*;
 use self::custom::*;
 use self::custom_dbus::*;
+use self::disk_io::*;
 use self::disk_space::*;
 use self::docker::*;
+use self::external_ip::*;
 use self::fan::*;
 use self::focused_window::*;
 use self::github::*;
@@ -57,8 +78,10 @@ use self::ibus::*;
 use self::kdeconnect::*;
 use self::keyboard_layout::*;
 use self::load::*;
+use self::mail::*;
 use self::maildir::*;
 use self::memory::*;
+use self::microphone::*;
 use self::music::*;
 use self::net::*;
 use self::networkmanager::*;
@@ -67,34 +90,49 @@ use self::notify::*;
 use self::notmuch::*;
 use self::nvidia_gpu::*;
 use self::pacman::*;
+use self::ping::*;
 use self::pomodoro::*;
+use self::recording::*;
+use self::smart::*;
 use self::sound::*;
 use self::speedtest::*;
+use self::systemd::*;
 use self::taskwarrior::*;
 use self::temperature::*;
 use self::template::*;
 use self::time::*;
 use self::toggle::*;
+use self::top::*;
+use self::updates::*;
 use self::uptime::*;
+use self::vpn::*;
 use self::watson::*;
 use self::weather::*;
 use self::xrandr::*;
 
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use crossbeam_channel::Sender;
 use serde::de::Deserialize;
 use toml::value::Value;
 
 use crate::config::Config;
+use crate::de::struct_fields;
 use crate::errors::*;
 use crate::input::I3BarEvent;
 use crate::scheduler::Task;
+use crate::signals::convert_to_valid_signal;
+use crate::themes::Theme;
 use crate::widget::I3BarWidget;
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum Update {
     Every(Duration),
+    /// Update at every multiple of the interval on the wall clock, e.g. when
+    /// the minute flips
+    Aligned(Duration),
+    /// Update once more at the given time
+    At(Instant),
     Once,
 }
 
@@ -133,6 +171,36 @@ pub trait Block {
     fn click(&mut self, _event: &I3BarEvent) -> Result<()> {
         Ok(())
     }
+    /// The theme of the block, if it differs from the bar's, e.g. through its
+    /// `theme_overrides`.
+    fn theme(&self) -> Option<&Theme> {
+        None
+    }
+
+    /// How long an update may take before the block is greyed out, from its
+    /// `timeout` option.
+    fn timeout(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Stops the threads and commands of the block, which is dropped after
+    /// this when the config is reloaded. Threads which only run until sending
+    /// an update request fails stop on their own once the old update channel
+    /// is dropped.
+    fn shutdown(&mut self) {}
+
+    /// Called when i3bar hides the bar and pauses it, and when it shows the
+    /// bar again. No updates are scheduled while the bar is paused, and every
+    /// block is updated once it resumes. Blocks with their own threads or
+    /// processes may pause them as well.
+    fn set_paused(&mut self, _paused: bool) {}
+
+    /// Shows what the block did in the background since it was last polled,
+    /// for blocks which update on a thread of their own. Returns when to update
+    /// the block next, like `update`, if an update finished in the meantime.
+    fn poll(&mut self) -> Result<Option<Update>> {
+        Ok(None)
+    }
 }
 
 pub trait ConfigBlock: Block {
@@ -154,7 +222,8 @@ pub trait ConfigBlock: Block {
 
 macro_rules! block {
     ($block_type:ident, $block_config:expr, $config:expr, $update_request:expr) => {{
-        let common_config = BaseBlockConfig::extract(&mut $block_config);
+        let block_fields = struct_fields::<<$block_type as ConfigBlock>::Config>();
+        let common_config = BaseBlockConfig::extract(&mut $block_config, block_fields);
         let mut common_config = BaseBlockConfig::deserialize(common_config)
             .configuration_error("Failed to deserialize common block config.")?;
 
@@ -163,6 +232,8 @@ macro_rules! block {
                 .configuration_error("Failed to deserialize block config.")?;
 
         let mut main_config = $config;
+        main_config.markup = common_config.markup;
+        main_config.gradient = common_config.gradient;
         if let Some(ref overrides) = block_config.color_overrides {
             for entry in overrides {
                 match entry.0.as_str() {
@@ -186,36 +257,62 @@ macro_rules! block {
             }
         }
 
-        let mut block = $block_type::new(block_config, main_config, $update_request)?;
+        common_config.apply_overrides(&mut main_config)?;
+        let signal = common_config
+            .signal
+            .map(convert_to_valid_signal)
+            .transpose()?;
+
+        let update_request = match $update_request {
+            Some(update_request) => update_request,
+            None => return Ok(None),
+        };
+        let mut block =
+            $block_type::new(block_config, main_config.clone(), update_request.clone())?;
         if let Some(overrided) = block.override_on_click() {
             *overrided = common_config.on_click.take();
         }
 
-        Ok(Box::new(BaseBlock {
+        Ok(Some(Box::new(BaseBlock {
             name: stringify!($block_type).to_string(),
             inner: block,
             on_click: common_config.on_click,
-        }) as Box<dyn Block>)
+            on_click_middle: common_config.on_click_middle,
+            on_click_right: common_config.on_click_right,
+            signal,
+            timeout: common_config.timeout,
+            tx_update_request: update_request,
+            config: main_config,
+            error: None,
+        }) as Box<dyn Block>))
     }};
 }
 
-pub fn create_block(
+/// Deserializes the config of a block and creates the block with it. Without
+/// an `update_request` channel, only the config is checked and the block isn't
+/// created, so that no threads or commands are started.
+fn build_block(
     name: &str,
     mut block_config: Value,
     config: Config,
-    update_request: Sender<Task>,
-) -> Result<Box<dyn Block>> {
+    update_request: Option<Sender<Task>>,
+) -> Result<Option<Box<dyn Block>>> {
     match name {
         // Please keep these in alphabetical order.
+        "amd_gpu" => block!(AmdGpu, block_config, config, update_request),
         "apt" => block!(Apt, block_config, config, update_request),
         "backlight" => block!(Backlight, block_config, config, update_request),
         "battery" => block!(Battery, block_config, config, update_request),
         "bluetooth" => block!(Bluetooth, block_config, config, update_request),
+        "caffeine" => block!(Caffeine, block_config, config, update_request),
+        "countdown" => block!(Countdown, block_config, config, update_request),
         "cpu" => block!(Cpu, block_config, config, update_request),
         "custom" => block!(Custom, block_config, config, update_request),
         "custom_dbus" => block!(CustomDBus, block_config, config, update_request),
+        "disk_io" => block!(DiskIo, block_config, config, update_request),
         "disk_space" => block!(DiskSpace, block_config, config, update_request),
         "docker" => block!(Docker, block_config, config, update_request),
+        "external_ip" => block!(ExternalIp, block_config, config, update_request),
         "fan" => block!(Fan, block_config, config, update_request),
         "focused_window" => block!(FocusedWindow, block_config, config, update_request),
         "github" => block!(Github, block_config, config, update_request),
@@ -224,8 +321,10 @@ pub fn create_block(
         "kdeconnect" => block!(KDEConnect, block_config, config, update_request),
         "keyboard_layout" => block!(KeyboardLayout, block_config, config, update_request),
         "load" => block!(Load, block_config, config, update_request),
+        "mail" => block!(Mail, block_config, config, update_request),
         "maildir" => block!(Maildir, block_config, config, update_request),
         "memory" => block!(Memory, block_config, config, update_request),
+        "microphone" => block!(Microphone, block_config, config, update_request),
         "music" => block!(Music, block_config, config, update_request),
         "net" => block!(Net, block_config, config, update_request),
         "networkmanager" => block!(NetworkManager, block_config, config, update_request),
@@ -234,18 +333,40 @@ pub fn create_block(
         "notmuch" => block!(Notmuch, block_config, config, update_request),
         "nvidia_gpu" => block!(NvidiaGpu, block_config, config, update_request),
         "pacman" => block!(Pacman, block_config, config, update_request),
+        "ping" => block!(Ping, block_config, config, update_request),
         "pomodoro" => block!(Pomodoro, block_config, config, update_request),
+        "recording" => block!(Recording, block_config, config, update_request),
+        "smart" => block!(Smart, block_config, config, update_request),
         "sound" => block!(Sound, block_config, config, update_request),
         "speedtest" => block!(SpeedTest, block_config, config, update_request),
+        "systemd" => block!(Systemd, block_config, config, update_request),
         "taskwarrior" => block!(Taskwarrior, block_config, config, update_request),
         "temperature" => block!(Temperature, block_config, config, update_request),
         "template" => block!(Template, block_config, config, update_request),
         "time" => block!(Time, block_config, config, update_request),
         "toggle" => block!(Toggle, block_config, config, update_request),
+        "top" => block!(Top, block_config, config, update_request),
+        "updates" => block!(Updates, block_config, config, update_request),
         "uptime" => block!(Uptime, block_config, config, update_request),
+        "vpn" => block!(Vpn, block_config, config, update_request),
         "watson" => block!(Watson, block_config, config, update_request),
         "weather" => block!(Weather, block_config, config, update_request),
         "xrandr" => block!(Xrandr, block_config, config, update_request),
         other => Err(BlockError(other.to_string(), "Unknown block!".to_string())),
     }
 }
+
+pub fn create_block(
+    name: &str,
+    block_config: Value,
+    config: Config,
+    update_request: Sender<Task>,
+) -> Result<Box<dyn Block>> {
+    build_block(name, block_config, config, Some(update_request))?
+        .internal_error("blocks", "the block wasn't created")
+}
+
+/// Checks that the config of a block can be deserialized, without creating the block.
+pub fn check_block_config(name: &str, block_config: Value, config: Config) -> Result<()> {
+    build_block(name, block_config, config, None).map(|_| ())
+}
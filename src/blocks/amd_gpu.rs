@@ -0,0 +1,282 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crossbeam_channel::Sender;
+use serde_derive::Deserialize;
+
+use crate::blocks::{Block, ConfigBlock, Update};
+use crate::config::Config;
+use crate::de::deserialize_duration;
+use crate::errors::*;
+use crate::scheduler::Task;
+use crate::util::{pseudo_uuid, read_file, FormatTemplate};
+use crate::widget::{I3BarWidget, State};
+use crate::widgets::text::TextWidget;
+
+/// The PCI vendor id of AMD
+const AMD_VENDOR: &str = "0x1002";
+
+pub struct AmdGpu {
+    id: String,
+    text: TextWidget,
+    update_interval: Duration,
+    format: FormatTemplate,
+    /// The `device` directory of the card, e.g. `/sys/class/drm/card0/device`
+    device: PathBuf,
+    maximum_idle: u64,
+    maximum_good: u64,
+    maximum_info: u64,
+    maximum_warning: u64,
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct AmdGpuConfig {
+    /// Update interval in seconds
+    #[serde(
+        default = "AmdGpuConfig::default_interval",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub interval: Duration,
+
+    /// The index of the card, e.g. 1 for `/sys/class/drm/card1`
+    #[serde(default = "AmdGpuConfig::default_card")]
+    pub card: Option<u32>,
+
+    /// The PCI id of the card, e.g. `0000:03:00.0`
+    #[serde(default = "AmdGpuConfig::default_pci_id")]
+    pub pci_id: Option<String>,
+
+    /// Format override
+    #[serde(default = "AmdGpuConfig::default_format")]
+    pub format: String,
+
+    /// Maximum temperature, below which state is set to idle
+    #[serde(default = "AmdGpuConfig::default_idle")]
+    pub idle: u64,
+
+    /// Maximum temperature, below which state is set to good
+    #[serde(default = "AmdGpuConfig::default_good")]
+    pub good: u64,
+
+    /// Maximum temperature, below which state is set to info
+    #[serde(default = "AmdGpuConfig::default_info")]
+    pub info: u64,
+
+    /// Maximum temperature, below which state is set to warning
+    #[serde(default = "AmdGpuConfig::default_warning")]
+    pub warning: u64,
+
+    #[serde(default = "AmdGpuConfig::default_color_overrides")]
+    pub color_overrides: Option<BTreeMap<String, String>>,
+}
+
+impl AmdGpuConfig {
+    fn default_interval() -> Duration {
+        Duration::from_secs(3)
+    }
+
+    fn default_card() -> Option<u32> {
+        None
+    }
+
+    fn default_pci_id() -> Option<String> {
+        None
+    }
+
+    fn default_format() -> String {
+        "{utilization} {vram_percent} {temperature}".to_owned()
+    }
+
+    fn default_idle() -> u64 {
+        50
+    }
+
+    fn default_good() -> u64 {
+        70
+    }
+
+    fn default_info() -> u64 {
+        75
+    }
+
+    fn default_warning() -> u64 {
+        80
+    }
+
+    fn default_color_overrides() -> Option<BTreeMap<String, String>> {
+        None
+    }
+}
+
+fn is_amd(device: &Path) -> bool {
+    read_file("amd_gpu", &device.join("vendor"))
+        .map(|vendor| vendor.trim() == AMD_VENDOR)
+        .unwrap_or(false)
+}
+
+/// Finds the `device` directory of the card. Without `card` or `pci_id`, the
+/// first AMD card is used.
+fn find_device(card: Option<u32>, pci_id: &Option<String>) -> Result<PathBuf> {
+    if let Some(card) = card {
+        let device = PathBuf::from(format!("/sys/class/drm/card{}/device", card));
+        return if is_amd(&device) {
+            Ok(device)
+        } else {
+            Err(BlockError(
+                "amd_gpu".to_owned(),
+                format!("card{} is not an AMD GPU", card),
+            ))
+        };
+    }
+
+    let mut cards: Vec<PathBuf> = fs::read_dir("/sys/class/drm")
+        .block_error("amd_gpu", "Failed to read /sys/class/drm")?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            // Skip the connectors, e.g. card0-DP-1
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .map_or(false, |name| {
+                    name.starts_with("card") && name[4..].chars().all(|c| c.is_ascii_digit())
+                })
+        })
+        .collect();
+    cards.sort();
+
+    cards
+        .into_iter()
+        .map(|card| card.join("device"))
+        .filter(|device| is_amd(device))
+        .find(|device| match pci_id {
+            // The device is a link to the PCI device, e.g. ../../../0000:03:00.0
+            Some(pci_id) => fs::read_link(device)
+                .ok()
+                .and_then(|target| target.file_name().map(|name| name == pci_id.as_str()))
+                .unwrap_or(false),
+            None => true,
+        })
+        .block_error(
+            "amd_gpu",
+            &match pci_id {
+                Some(pci_id) => format!("No AMD GPU with PCI id {} found", pci_id),
+                None => "No AMD GPU found".to_owned(),
+            },
+        )
+}
+
+/// Reads a number from a sysfs file, which is missing on older kernels or
+/// other GPUs.
+fn read_number(path: &Path) -> Option<u64> {
+    read_file("amd_gpu", path).ok()?.trim().parse().ok()
+}
+
+/// The hwmon directory of the device, e.g. `device/hwmon/hwmon3`.
+fn find_hwmon(device: &Path) -> Option<PathBuf> {
+    fs::read_dir(device.join("hwmon"))
+        .ok()?
+        .flatten()
+        .map(|entry| entry.path())
+        .next()
+}
+
+fn percent(used: u64, total: u64) -> Option<u64> {
+    if total == 0 {
+        None
+    } else {
+        Some(used * 100 / total)
+    }
+}
+
+impl ConfigBlock for AmdGpu {
+    type Config = AmdGpuConfig;
+
+    fn new(
+        block_config: Self::Config,
+        config: Config,
+        _tx_update_request: Sender<Task>,
+    ) -> Result<Self> {
+        let id = pseudo_uuid();
+
+        Ok(AmdGpu {
+            text: TextWidget::new(config, &id).with_icon("gpu"),
+            id,
+            update_interval: block_config.interval,
+            format: FormatTemplate::from_string(&block_config.format)
+                .block_error("amd_gpu", "Invalid format specified for amd_gpu")?,
+            device: find_device(block_config.card, &block_config.pci_id)?,
+            maximum_idle: block_config.idle,
+            maximum_good: block_config.good,
+            maximum_info: block_config.info,
+            maximum_warning: block_config.warning,
+        })
+    }
+}
+
+impl Block for AmdGpu {
+    fn update(&mut self) -> Result<Option<Update>> {
+        let utilization = read_number(&self.device.join("gpu_busy_percent"));
+        let vram_used = read_number(&self.device.join("mem_info_vram_used"));
+        let vram_total = read_number(&self.device.join("mem_info_vram_total"));
+        let hwmon = find_hwmon(&self.device);
+        let temperature = hwmon
+            .as_ref()
+            .and_then(|hwmon| read_number(&hwmon.join("temp1_input")))
+            .map(|millidegrees| millidegrees / 1000);
+        let fan_speed = hwmon
+            .as_ref()
+            .and_then(|hwmon| read_number(&hwmon.join("fan1_input")));
+
+        // Placeholders the card doesn't report are left empty
+        let show = |value: Option<u64>, unit: &str| {
+            value.map_or_else(String::new, |value| format!("{:02}{}", value, unit))
+        };
+        let vram_percent = match (vram_used, vram_total) {
+            (Some(used), Some(total)) => percent(used, total),
+            _ => None,
+        };
+        let values = map!(
+            "{utilization}" => show(utilization, "%"),
+            "{vram}" => show(vram_used.map(|bytes| bytes / 1024 / 1024), "MB"),
+            "{vram_total}" => show(vram_total.map(|bytes| bytes / 1024 / 1024), "MB"),
+            "{vram_percent}" => show(vram_percent, "%"),
+            "{temperature}" => show(temperature, "°C"),
+            "{fan_speed}" => show(fan_speed, "RPM")
+        );
+        self.text
+            .set_text(self.format.render_static_str(&values)?.trim().to_string());
+
+        self.text.set_state(match temperature {
+            Some(t) if t <= self.maximum_idle => State::Idle,
+            Some(t) if t <= self.maximum_good => State::Good,
+            Some(t) if t <= self.maximum_info => State::Info,
+            Some(t) if t <= self.maximum_warning => State::Warning,
+            Some(_) => State::Critical,
+            None => State::Idle,
+        });
+
+        Ok(Some(self.update_interval.into()))
+    }
+
+    fn view(&self) -> Vec<&dyn I3BarWidget> {
+        vec![&self.text]
+    }
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percent() {
+        assert_eq!(percent(2_147_483_648, 8_589_934_592), Some(25));
+        assert_eq!(percent(0, 0), None);
+    }
+}
@@ -98,25 +98,7 @@ impl ConfigBlock for Apt {
         config: Config,
         _tx_update_request: Sender<Task>,
     ) -> Result<Self> {
-        let mut cache_dir = env::temp_dir();
-        cache_dir.push("i3rs-apt");
-        if !cache_dir.exists() {
-            fs::create_dir(cache_dir.clone()).block_error("apt", "Failed to create temp dir")?;
-        }
-
-        let apt_conf = format!(
-            "Dir::State \"{}\";\n
-             Dir::State::lists \"lists\";\n
-             Dir::Cache \"{}\";\n
-             Dir::Cache::srcpkgcache \"srcpkgcache.bin\";\n
-             Dir::Cache::pkgcache \"pkgcache.bin\";",
-            cache_dir.clone().into_os_string().into_string().unwrap(),
-            cache_dir.clone().into_os_string().into_string().unwrap()
-        );
-        cache_dir.push("apt.conf");
-        let mut config_file = fs::File::create(cache_dir.clone())
-            .block_error("apt", "Failed to create config file")?;
-        write!(config_file, "{}", apt_conf).block_error("apt", "Failed to write to config file")?;
+        let config_path = create_apt_config()?;
 
         let id = pseudo_uuid();
         let output = ButtonWidget::new(config, &id).with_icon("update");
@@ -161,11 +143,37 @@ impl ConfigBlock for Apt {
                     Some(regex)
                 }
             },
-            config_path: cache_dir.into_os_string().into_string().unwrap(),
+            config_path,
         })
     }
 }
 
+/// Writes an apt configuration using a package cache of its own in the temp
+/// directory, so that updating it needs no root privileges. Returns its path.
+pub(super) fn create_apt_config() -> Result<String> {
+    let mut cache_dir = env::temp_dir();
+    cache_dir.push("i3rs-apt");
+    if !cache_dir.exists() {
+        fs::create_dir(cache_dir.clone()).block_error("apt", "Failed to create temp dir")?;
+    }
+
+    let apt_conf = format!(
+        "Dir::State \"{}\";\n
+         Dir::State::lists \"lists\";\n
+         Dir::Cache \"{}\";\n
+         Dir::Cache::srcpkgcache \"srcpkgcache.bin\";\n
+         Dir::Cache::pkgcache \"pkgcache.bin\";",
+        cache_dir.clone().into_os_string().into_string().unwrap(),
+        cache_dir.clone().into_os_string().into_string().unwrap()
+    );
+    cache_dir.push("apt.conf");
+    let mut config_file =
+        fs::File::create(cache_dir.clone()).block_error("apt", "Failed to create config file")?;
+    write!(config_file, "{}", apt_conf).block_error("apt", "Failed to write to config file")?;
+
+    Ok(cache_dir.into_os_string().into_string().unwrap())
+}
+
 fn has_warning_update(updates: &str, regex: &Regex) -> bool {
     updates.lines().filter(|line| regex.is_match(line)).count() > 0
 }
@@ -174,7 +182,7 @@ fn has_critical_update(updates: &str, regex: &Regex) -> bool {
     updates.lines().filter(|line| regex.is_match(line)).count() > 0
 }
 
-fn get_updates_list(config_path: &str) -> Result<String> {
+pub(super) fn get_updates_list(config_path: &str) -> Result<String> {
     // Update database
     Command::new("sh")
         .env("APT_CONFIG", config_path)
@@ -193,7 +201,7 @@ fn get_updates_list(config_path: &str) -> Result<String> {
     .block_error("apt", "Problem capturing apt command output")
 }
 
-fn get_update_count(updates: &str) -> usize {
+pub(super) fn get_update_count(updates: &str) -> usize {
     updates
         .lines()
         .filter(|line| line.contains("[upgradable"))
@@ -6,25 +6,32 @@
 //! does not depend on `xrandr` (and thus it works on Wayland). To set
 //! brightness levels using `xrandr`, see the
 //! [`Xrandr`](../xrandr/struct.Xrandr.html) block.
+//!
+//! External monitors are supported through DDC/CI, either with the
+//! `ddcci-backlight` kernel driver, which exposes them in `sysfs` as well, or
+//! by running `ddcutil`.
 
 use std::collections::BTreeMap;
 use std::fs::OpenOptions;
 use std::io::prelude::*;
 use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::{Duration, Instant};
 
-use crossbeam_channel::Sender;
+use crossbeam_channel::{unbounded, Receiver, RecvTimeoutError, Sender};
 use inotify::{EventMask, Inotify, WatchMask};
 use serde_derive::Deserialize;
 
 use crate::blocks::{Block, ConfigBlock, Update};
 use crate::config::{Config, LogicalDirection, Scrolling};
+use crate::de::deserialize_opt_duration;
 use crate::errors::*;
 use crate::input::I3BarEvent;
 use crate::scheduler::Task;
 use crate::util::pseudo_uuid;
-use crate::widget::I3BarWidget;
+use crate::widget::{I3BarWidget, State};
 use crate::widgets::button::ButtonWidget;
 
 /// Read a brightness value from the given path.
@@ -48,6 +55,7 @@ pub struct BacklitDevice {
     max_brightness: u64,
     device_path: PathBuf,
     root_scaling: f64,
+    brightness_cmd: Option<String>,
 }
 
 /// Clamp scale root to a safe range. Useful values are 1.0 to 3.0.
@@ -86,6 +94,25 @@ impl BacklitDevice {
             max_brightness,
             device_path: first_device.path(),
             root_scaling: clamp_root_scaling(root_scaling),
+            brightness_cmd: None,
+        })
+    }
+
+    /// Use the first device of the `ddcci-backlight` kernel driver, if it is
+    /// loaded.
+    pub fn ddcci(root_scaling: f64) -> Option<Self> {
+        let device = Path::new("/sys/class/backlight")
+            .read_dir()
+            .ok()?
+            .filter_map(|entry| entry.ok())
+            .find(|entry| entry.file_name().to_string_lossy().starts_with("ddcci"))?;
+        let max_brightness = read_brightness(&device.path().join("max_brightness")).ok()?;
+
+        Some(BacklitDevice {
+            max_brightness,
+            device_path: device.path(),
+            root_scaling: clamp_root_scaling(root_scaling),
+            brightness_cmd: None,
         })
     }
 
@@ -109,9 +136,18 @@ impl BacklitDevice {
             max_brightness,
             device_path,
             root_scaling: clamp_root_scaling(root_scaling),
+            brightness_cmd: None,
         })
     }
 
+    /// Use a shell command to set the brightness if the brightness file is not
+    /// writable. `{raw}` is replaced with the raw value and `{percent}` with
+    /// the raw value in percent of the maximum.
+    pub fn with_brightness_cmd(mut self, brightness_cmd: Option<String>) -> Self {
+        self.brightness_cmd = brightness_cmd;
+        self
+    }
+
     /// Query the brightness value for this backlit device, as a percent.
     pub fn brightness(&self) -> Result<u64> {
         let raw = read_brightness(&self.brightness_file())?;
@@ -137,11 +173,11 @@ impl BacklitDevice {
             .write(true)
             .open(self.device_path.join("brightness"));
         if file.is_err() {
-            // TODO: Find a way to issue a non-fatal error, since this is likely
-            // due to a permissions issue and not the fault of the user. It
-            // should not crash the bar.
-            // Error: "Failed to open brightness file for writing"
-            return self.set_brightness_via_dbus(raw);
+            // This is likely due to a permissions issue
+            return match self.brightness_cmd {
+                Some(ref cmd) => self.set_brightness_via_cmd(cmd, raw),
+                None => self.set_brightness_via_dbus(raw),
+            };
         }
 
         // It's safe to unwrap() here because we checked for errors above.
@@ -150,6 +186,25 @@ impl BacklitDevice {
             .block_error("backlight", "Failed to write into brightness file")
     }
 
+    fn set_brightness_via_cmd(&self, cmd: &str, raw_value: u64) -> Result<()> {
+        let percent = raw_value * 100 / self.max_brightness.max(1);
+        let cmd = cmd
+            .replace("{raw}", &raw_value.to_string())
+            .replace("{percent}", &percent.to_string());
+        let status = Command::new("sh")
+            .args(["-c", &cmd])
+            .status()
+            .block_error("backlight", "Failed to run brightness_cmd")?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(BlockError(
+                "backlight".to_string(),
+                format!("brightness_cmd failed with {}", status),
+            ))
+        }
+    }
+
     fn set_brightness_via_dbus(&self, raw_value: u64) -> Result<()> {
         let device_name = self
             .device_path
@@ -169,9 +224,15 @@ impl BacklitDevice {
         .append2("backlight", device_name)
         .append1(raw_value as u32);
 
+        // logind's reply tells why, e.g. if the session isn't active
         con.send_with_reply_and_block(msg, 1000)
-            .block_error("backlight", "Failed to send D-Bus message")
             .map(|_| ())
+            .map_err(|e| {
+                BlockError(
+                    "backlight".to_string(),
+                    format!("Failed to set brightness via logind: {}", e),
+                )
+            })
     }
 
     /// The brightness file itself.
@@ -186,13 +247,291 @@ impl BacklitDevice {
     }
 }
 
+/// Spins up a thread to watch for changes to the brightness files of the
+/// device, and schedules an update of the block after each one. Whenever the
+/// watch is lost, e.g. because the device disappeared during suspend or when
+/// undocking, it is set up again.
+fn watch_brightness(device_path: PathBuf, id: String, tx_update_request: Sender<Task>) {
+    let request_update = move || {
+        tx_update_request
+            .send(Task {
+                id: id.clone(),
+                update_time: Instant::now(),
+            })
+            .is_ok()
+    };
+
+    thread::Builder::new()
+        .name("backlight".into())
+        .spawn(move || {
+            let mut buffer = [0; 1024];
+            loop {
+                let mut notify = match Inotify::init() {
+                    Ok(notify) => notify,
+                    // There is nothing to watch with
+                    Err(_) => return,
+                };
+                // Tools like brightnessctl write to `brightness`, while
+                // `actual_brightness` is the one that is read.
+                let watched = ["brightness", "actual_brightness"]
+                    .iter()
+                    .filter(|file| {
+                        notify
+                            .add_watch(device_path.join(file), WatchMask::MODIFY)
+                            .is_ok()
+                    })
+                    .count();
+
+                if watched > 0 {
+                    // The brightness may have changed while the device was gone
+                    if !request_update() {
+                        return;
+                    }
+                    loop {
+                        let events = match notify.read_events_blocking(&mut buffer) {
+                            Ok(events) => events,
+                            Err(_) => break,
+                        };
+                        let (mut changed, mut lost) = (false, false);
+                        for event in events {
+                            changed |= event.mask.contains(EventMask::MODIFY);
+                            lost |= event.mask.contains(EventMask::IGNORED);
+                        }
+                        if changed && !request_update() {
+                            return;
+                        }
+                        if lost {
+                            break;
+                        }
+
+                        // Avoid update spam.
+                        thread::sleep(Duration::from_millis(250))
+                    }
+                }
+
+                // Wait for the device to come back
+                thread::sleep(Duration::from_secs(1));
+            }
+        })
+        .unwrap();
+}
+
+/// Parses the current and maximum value out of the output of
+/// `ddcutil --brief getvcp`, e.g. `VCP 10 C 50 100`.
+fn parse_getvcp(output: &str) -> Option<(u64, u64)> {
+    let mut fields = output.split_whitespace();
+    if fields.next()? != "VCP" {
+        return None;
+    }
+    // The feature code
+    fields.next()?;
+    // Continuous value
+    if fields.next()? != "C" {
+        return None;
+    }
+    let current = fields.next()?.parse().ok()?;
+    let max = fields.next()?.parse().ok()?;
+    Some((current, max))
+}
+
+/// The VCP feature code of the brightness
+const VCP_BRIGHTNESS: &str = "10";
+
+/// How often the brightness is read with `ddcutil` unless `interval` is set
+const DDCUTIL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// A monitor controlled over DDC/CI with `ddcutil`. Each call takes hundreds of
+/// milliseconds, so they are made on a worker thread, which requests an update
+/// of the block whenever it has read the brightness.
+pub struct DdcutilDevice {
+    /// The brightness in percent, once it has been read
+    brightness: Arc<Mutex<Option<u64>>>,
+    /// Brightness values in percent for the worker thread to set
+    tx_set: Sender<u64>,
+}
+
+impl DdcutilDevice {
+    pub fn new(
+        display: u32,
+        interval: Duration,
+        id: String,
+        tx_update_request: Sender<Task>,
+    ) -> Self {
+        let brightness = Arc::new(Mutex::new(None));
+        let (tx_set, rx_set) = unbounded();
+
+        let shared = brightness.clone();
+        thread::Builder::new()
+            .name("backlight".into())
+            .spawn(move || ddcutil_worker(display, interval, shared, rx_set, id, tx_update_request))
+            .unwrap();
+
+        DdcutilDevice { brightness, tx_set }
+    }
+
+    /// The last brightness value read, as a percent.
+    pub fn brightness(&self) -> Result<u64> {
+        self.brightness
+            .lock()
+            .block_error("backlight", "mutex poisoned")?
+            .block_error("backlight", "Brightness not read yet")
+    }
+
+    /// Queue setting the brightness value, as a percent.
+    pub fn set_brightness(&self, value: u64) -> Result<()> {
+        let value = value.min(100);
+        // Show the new value right away, the next read corrects it if setting
+        // it failed.
+        *self
+            .brightness
+            .lock()
+            .block_error("backlight", "mutex poisoned")? = Some(value);
+        self.tx_set
+            .send(value)
+            .block_error("backlight", "ddcutil worker thread exited")
+    }
+}
+
+fn run_ddcutil(display: u32, args: &[&str]) -> Option<String> {
+    let output = Command::new("ddcutil")
+        .args(["--display", &display.to_string()])
+        .args(args)
+        .output()
+        .ok()?;
+    if output.status.success() {
+        Some(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        None
+    }
+}
+
+fn ddcutil_worker(
+    display: u32,
+    interval: Duration,
+    brightness: Arc<Mutex<Option<u64>>>,
+    rx_set: Receiver<u64>,
+    id: String,
+    tx_update_request: Sender<Task>,
+) {
+    // Most monitors use a range of 0 to 100, but the actual one is only known
+    // after the first read.
+    let mut max = 100;
+    loop {
+        let read = run_ddcutil(display, &["--brief", "getvcp", VCP_BRIGHTNESS])
+            .and_then(|output| parse_getvcp(&output));
+        if let Some((current, new_max)) = read {
+            max = new_max.max(1);
+            if let Ok(mut brightness) = brightness.lock() {
+                *brightness = Some(current * 100 / max);
+            }
+        }
+        if tx_update_request
+            .send(Task {
+                id: id.clone(),
+                update_time: Instant::now(),
+            })
+            .is_err()
+        {
+            return;
+        }
+
+        match rx_set.recv_timeout(interval) {
+            Ok(value) => {
+                // Only the last of several scroll steps needs to be set
+                let value = rx_set.try_iter().last().unwrap_or(value);
+                let raw = (value * max / 100).to_string();
+                run_ddcutil(display, &["setvcp", VCP_BRIGHTNESS, &raw]);
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+    }
+}
+
+/// How the brightness is read and set.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum BacklightDriver {
+    /// A device in `/sys/class/backlight`
+    Sysfs,
+    /// An external monitor over DDC/CI
+    Ddcci,
+}
+
+impl Default for BacklightDriver {
+    fn default() -> Self {
+        BacklightDriver::Sysfs
+    }
+}
+
+enum Device {
+    Sysfs(BacklitDevice),
+    Ddcutil(DdcutilDevice),
+}
+
+impl Device {
+    fn brightness(&self) -> Result<u64> {
+        match self {
+            Device::Sysfs(device) => device.brightness(),
+            Device::Ddcutil(device) => device.brightness(),
+        }
+    }
+
+    fn set_brightness(&self, value: u64) -> Result<()> {
+        match self {
+            Device::Sysfs(device) => device.set_brightness(value),
+            Device::Ddcutil(device) => device.set_brightness(value),
+        }
+    }
+}
+
+/// How scrolling changes the brightness.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum StepMode {
+    /// Add or subtract `step_width` percent
+    Percent,
+    /// Multiply or divide by `1 + step_width / 100`, which changes the
+    /// perceived brightness more evenly
+    Exponential,
+}
+
+impl Default for StepMode {
+    fn default() -> Self {
+        StepMode::Percent
+    }
+}
+
+/// The brightness in percent after scrolling once. It never goes below 1%,
+/// so that scrolling down doesn't turn the screen off.
+fn step_brightness(brightness: u64, step_width: u64, step_mode: StepMode, up: bool) -> u64 {
+    let factor = 1.0 + step_width as f64 / 100.0;
+    let new = match (step_mode, up) {
+        (StepMode::Percent, true) => brightness + step_width,
+        (StepMode::Percent, false) => brightness.saturating_sub(step_width),
+        // Change by at least 1% so that low brightness levels don't get stuck
+        (StepMode::Exponential, true) => {
+            std::cmp::max((brightness as f64 * factor).round() as u64, brightness + 1)
+        }
+        (StepMode::Exponential, false) => std::cmp::min(
+            (brightness as f64 / factor).round() as u64,
+            brightness.saturating_sub(1),
+        ),
+    };
+    new.max(1).min(100)
+}
+
 /// A block for displaying the brightness of a backlit device.
 pub struct Backlight {
     id: String,
     output: ButtonWidget,
-    device: BacklitDevice,
+    device: Device,
     step_width: u64,
+    step_mode: StepMode,
     scrolling: Scrolling,
+    update_interval: Option<Duration>,
+    /// The error of the last attempt to set the brightness, if it failed
+    error: Option<String>,
 }
 
 /// Configuration for the [`Backlight`](./struct.Backlight.html) block.
@@ -203,10 +542,35 @@ pub struct BacklightConfig {
     #[serde(default = "BacklightConfig::default_device")]
     pub device: Option<String>,
 
+    /// Whether to control a backlit device or an external monitor over DDC/CI
+    #[serde(default)]
+    pub driver: BacklightDriver,
+
+    /// The display number to pass to `ddcutil`
+    #[serde(default = "BacklightConfig::default_display")]
+    pub display: u32,
+
     /// The steps brightness is in/decreased for the selected screen (When greater than 50 it gets limited to 50)
-    #[serde(default = "BacklightConfig::default_step_width")]
+    #[serde(default = "BacklightConfig::default_step_width", alias = "step")]
     pub step_width: u64,
 
+    /// Update interval in seconds, in addition to the updates on changes of
+    /// the brightness files. Changes made by the firmware, e.g. through
+    /// hardware keys, don't always trigger those.
+    #[serde(
+        default = "BacklightConfig::default_interval",
+        deserialize_with = "deserialize_opt_duration"
+    )]
+    pub interval: Option<Duration>,
+
+    /// Whether `step_width` is added or used as a factor when scrolling
+    #[serde(default)]
+    pub step_mode: StepMode,
+
+    /// Shell command to set the brightness with if the brightness file is not writable
+    #[serde(default = "BacklightConfig::default_brightness_cmd")]
+    pub brightness_cmd: Option<String>,
+
     /// Scaling exponent reciprocal (ie. root). Some devices expose raw values
     /// that are best handled with nonlinear scaling. The human perception of
     /// lightness is close to the cube root of relative luminance. Settings
@@ -226,10 +590,22 @@ impl BacklightConfig {
         None
     }
 
+    fn default_display() -> u32 {
+        1
+    }
+
     fn default_step_width() -> u64 {
         5
     }
 
+    fn default_interval() -> Option<Duration> {
+        None
+    }
+
+    fn default_brightness_cmd() -> Option<String> {
+        None
+    }
+
     fn default_root_scaling() -> f64 {
         1f64
     }
@@ -247,61 +623,63 @@ impl ConfigBlock for Backlight {
         config: Config,
         tx_update_request: Sender<Task>,
     ) -> Result<Self> {
-        let device = match block_config.device {
-            Some(path) => BacklitDevice::from_device(path, block_config.root_scaling),
-            None => BacklitDevice::default(block_config.root_scaling),
-        }?;
-
         let id = pseudo_uuid();
-        let brightness_file = device.brightness_file();
+        let root_scaling = block_config.root_scaling;
+
+        let sysfs_device = match (block_config.driver, block_config.device) {
+            (_, Some(path)) => Some(BacklitDevice::from_device(path, root_scaling)?),
+            (BacklightDriver::Sysfs, None) => Some(BacklitDevice::default(root_scaling)?),
+            // Fall back to ddcutil without the kernel driver
+            (BacklightDriver::Ddcci, None) => BacklitDevice::ddcci(root_scaling),
+        };
+        let device = match sysfs_device {
+            Some(device) => {
+                watch_brightness(device.device_path.clone(), id.clone(), tx_update_request);
+                Device::Sysfs(device.with_brightness_cmd(block_config.brightness_cmd))
+            }
+            None => Device::Ddcutil(DdcutilDevice::new(
+                block_config.display,
+                block_config.interval.unwrap_or(DDCUTIL_INTERVAL),
+                id.clone(),
+                tx_update_request,
+            )),
+        };
+
+        // The ddcutil worker thread takes care of its updates
+        let update_interval = if matches!(device, Device::Sysfs(_)) {
+            block_config.interval
+        } else {
+            None
+        };
 
         let scrolling = config.scrolling;
         let backlight = Backlight {
             output: ButtonWidget::new(config, &id),
-            id: id.clone(),
+            id,
             device,
             step_width: block_config.step_width,
+            step_mode: block_config.step_mode,
             scrolling,
+            update_interval,
+            error: None,
         };
 
-        // Spin up a thread to watch for changes to the brightness file for the
-        // device, and schedule an update if needed.
-        thread::Builder::new()
-            .name("backlight".into())
-            .spawn(move || {
-                let mut notify = Inotify::init().expect("Failed to start inotify");
-                notify
-                    .add_watch(brightness_file, WatchMask::MODIFY)
-                    .expect("Failed to watch brightness file");
-
-                let mut buffer = [0; 1024];
-                loop {
-                    let mut events = notify
-                        .read_events_blocking(&mut buffer)
-                        .expect("Error while reading inotify events");
-
-                    if events.any(|event| event.mask.contains(EventMask::MODIFY)) {
-                        tx_update_request
-                            .send(Task {
-                                id: id.clone(),
-                                update_time: Instant::now(),
-                            })
-                            .unwrap();
-                    }
-
-                    // Avoid update spam.
-                    thread::sleep(Duration::from_millis(250))
-                }
-            })
-            .unwrap();
-
         Ok(backlight)
     }
 }
 
 impl Block for Backlight {
     fn update(&mut self) -> Result<Option<Update>> {
-        let brightness = self.device.brightness()?;
+        let brightness = match self.device.brightness() {
+            Ok(brightness) => brightness,
+            // The device is gone for now, e.g. during a dock change
+            Err(_) => {
+                self.output.set_text("N/A".to_string());
+                self.output.set_icon("backlight_empty");
+                self.output.set_state(State::Idle);
+                return Ok(self.update_interval.map(|d| d.into()));
+            }
+        };
         self.output.set_text(format!("{}%", brightness));
         match brightness {
             0..=19 => self.output.set_icon("backlight_empty"),
@@ -310,7 +688,14 @@ impl Block for Backlight {
             60..=79 => self.output.set_icon("backlight_partial3"),
             _ => self.output.set_icon("backlight_full"),
         }
-        Ok(None)
+        match self.error {
+            Some(ref error) => {
+                self.output.set_text(error.clone());
+                self.output.set_state(State::Critical);
+            }
+            None => self.output.set_state(State::Idle),
+        }
+        Ok(self.update_interval.map(|d| d.into()))
     }
 
     fn view(&self) -> Vec<&dyn I3BarWidget> {
@@ -320,21 +705,25 @@ impl Block for Backlight {
     fn click(&mut self, event: &I3BarEvent) -> Result<()> {
         if let Some(ref name) = event.name {
             if name.as_str() == self.id {
-                let brightness = self.device.brightness()?;
+                let brightness = match self.device.brightness() {
+                    Ok(brightness) => brightness,
+                    Err(_) => return Ok(()),
+                };
                 use LogicalDirection::*;
-                match self.scrolling.to_logical_direction(event.button) {
-                    Some(Up) => {
-                        if brightness < 100 {
-                            self.device.set_brightness(brightness + self.step_width)?;
-                        }
-                    }
-                    Some(Down) => {
-                        if brightness > self.step_width {
-                            self.device.set_brightness(brightness - self.step_width)?;
-                        }
-                    }
-                    None => {}
-                }
+                let up = match self.scrolling.to_logical_direction(event.button) {
+                    Some(Up) => true,
+                    Some(Down) => false,
+                    None => return Ok(()),
+                };
+                let new = step_brightness(brightness, self.step_width, self.step_mode, up);
+                // Failing to set the brightness, e.g. due to a missing
+                // permission, shouldn't take down the bar.
+                self.error = match self.device.set_brightness(new) {
+                    Ok(()) => None,
+                    Err(BlockError(_, message)) => Some(message),
+                    Err(e) => Some(e.to_string()),
+                };
+                self.update()?;
             }
         }
 
@@ -345,3 +734,27 @@ impl Block for Backlight {
         &self.id
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_step_brightness() {
+        assert_eq!(step_brightness(50, 5, StepMode::Percent, true), 55);
+        assert_eq!(step_brightness(98, 5, StepMode::Percent, true), 100);
+        assert_eq!(step_brightness(4, 5, StepMode::Percent, false), 1);
+        assert_eq!(step_brightness(50, 10, StepMode::Exponential, true), 55);
+        assert_eq!(step_brightness(50, 10, StepMode::Exponential, false), 45);
+        assert_eq!(step_brightness(2, 10, StepMode::Exponential, true), 3);
+        assert_eq!(step_brightness(2, 10, StepMode::Exponential, false), 1);
+        assert_eq!(step_brightness(1, 10, StepMode::Exponential, false), 1);
+    }
+
+    #[test]
+    fn test_parse_getvcp() {
+        assert_eq!(parse_getvcp("VCP 10 C 50 100\n"), Some((50, 100)));
+        assert_eq!(parse_getvcp("VCP 10 ERR\n"), None);
+        assert_eq!(parse_getvcp("VCP 60 SNC x0f\n"), None);
+    }
+}
@@ -1,11 +1,21 @@
 //! A Base block for common behavior for all blocks
 
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::Sender;
+
+use crate::config::Config;
+use crate::de::deserialize_opt_duration;
 use crate::errors::*;
+use crate::scheduler::Task;
+use crate::themes::{Theme, ThemeOverrides};
 use crate::{
     blocks::Update,
     input::{I3BarEvent, MouseButton},
     subprocess::spawn_child_async,
-    widget::I3BarWidget,
+    widget::{I3BarWidget, Markup, State},
+    widgets::button::ButtonWidget,
     Block,
 };
 use serde_derive::Deserialize;
@@ -15,6 +25,16 @@ pub(super) struct BaseBlock<T: Block> {
     pub name: String,
     pub inner: T,
     pub on_click: Option<String>,
+    pub on_click_middle: Option<String>,
+    pub on_click_right: Option<String>,
+    /// The real-time signal which updates the block
+    pub signal: Option<i32>,
+    pub timeout: Option<Duration>,
+    pub tx_update_request: Sender<Task>,
+    pub config: Config,
+    /// Shown instead of the block if a click command couldn't be run, until
+    /// the next update
+    pub error: Option<ButtonWidget>,
 }
 
 impl<T: Block> Block for BaseBlock<T> {
@@ -23,31 +43,67 @@ impl<T: Block> Block for BaseBlock<T> {
     }
 
     fn view(&self) -> Vec<&dyn I3BarWidget> {
-        self.inner.view()
+        match self.error {
+            Some(ref error) => vec![error],
+            None => self.inner.view(),
+        }
     }
 
     fn update(&mut self) -> Result<Option<Update>> {
+        self.error = None;
         self.inner.update()
     }
 
     fn signal(&mut self, signal: i32) -> Result<()> {
+        if self.signal == Some(signal) {
+            self.tx_update_request.send(Task {
+                id: self.id().to_owned(),
+                update_time: Instant::now(),
+            })?;
+        }
         self.inner.signal(signal)
     }
 
+    fn theme(&self) -> Option<&Theme> {
+        Some(&self.config.theme)
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    fn shutdown(&mut self) {
+        self.inner.shutdown()
+    }
+
+    fn set_paused(&mut self, paused: bool) {
+        self.inner.set_paused(paused)
+    }
+
     fn click(&mut self, e: &I3BarEvent) -> Result<()> {
-        match &self.on_click {
-            Some(cmd) => {
-                if let Some(ref name) = e.name {
-                    if name.as_str() == self.id() {
-                        if let MouseButton::Left = e.button {
-                            spawn_child_async("sh", &["-c", &cmd])
-                                .block_error(&self.name, "could not spawn child")?;
-                        }
-                    }
+        self.error = None;
+        let cmd = match e.button {
+            MouseButton::Left => &self.on_click,
+            MouseButton::Middle => &self.on_click_middle,
+            MouseButton::Right => &self.on_click_right,
+            _ => &None,
+        };
+        // A command replaces the block's own handling of the button, but only
+        // for its main widget, so that e.g. the buttons of the music block
+        // keep working
+        match cmd {
+            Some(cmd) if e.matches_name(self.inner.id()) => {
+                if let Err(err) = spawn_child_async("sh", &["-c", &cmd]) {
+                    let message = format!("{}: could not spawn '{}': {}", self.name, cmd, err);
+                    self.error = Some(
+                        ButtonWidget::new(self.config.clone(), self.inner.id())
+                            .with_text(&message)
+                            .with_state(State::Critical),
+                    );
                 }
                 Ok(())
             }
-            None => self.inner.click(e),
+            _ => self.inner.click(e),
         }
     }
 }
@@ -56,16 +112,60 @@ impl<T: Block> Block for BaseBlock<T> {
 pub(super) struct BaseBlockConfig {
     /// Command to execute when the button is clicked
     pub on_click: Option<String>,
+
+    /// Command to execute when the button is middle clicked
+    pub on_click_middle: Option<String>,
+
+    /// Command to execute when the button is right clicked
+    pub on_click_right: Option<String>,
+
+    /// Whether the text of the block is Pango markup
+    #[serde(default)]
+    pub markup: Markup,
+
+    /// Whether the colors of the block blend between states
+    #[serde(default)]
+    pub gradient: bool,
+
+    /// Theme keys to use for this block instead of the bar's
+    pub theme_overrides: Option<ThemeOverrides>,
+
+    /// Icons to use for this block instead of the bar's
+    pub icons_overrides: Option<HashMap<String, String>>,
+
+    /// Update the block on `SIGRTMIN` plus this number
+    pub signal: Option<i32>,
+
+    /// Grey out the block while an update takes longer than this
+    #[serde(
+        default,
+        alias = "update_timeout",
+        deserialize_with = "deserialize_opt_duration"
+    )]
+    pub timeout: Option<Duration>,
 }
 
 impl BaseBlockConfig {
-    const FIELDS: &'static [&'static str] = &["on_click"];
+    const FIELDS: &'static [&'static str] = &[
+        "on_click",
+        "on_click_middle",
+        "on_click_right",
+        "markup",
+        "gradient",
+        "theme_overrides",
+        "icons_overrides",
+        "signal",
+        "update_timeout",
+    ];
 
     // FIXME: this function is to paper over https://github.com/serde-rs/serde/issues/1957
-    pub(super) fn extract(config: &mut Value) -> Value {
+    /// Takes the common options out of the config of a block. `timeout` is only
+    /// taken if the block has no option of that name itself.
+    pub(super) fn extract(config: &mut Value, block_fields: &[&str]) -> Value {
         let mut common_table = Table::new();
         if let Some(table) = config.as_table_mut() {
-            for &field in Self::FIELDS {
+            let timeout = Some("timeout").filter(|field| !block_fields.contains(field));
+            for &field in Self::FIELDS.iter().chain(&timeout) {
                 if let Some(it) = table.remove(field) {
                     common_table.insert(field.to_string(), it);
                 }
@@ -73,4 +173,82 @@ impl BaseBlockConfig {
         }
         common_table.into()
     }
+
+    /// Applies the block's theme and icon overrides to its copy of the config.
+    pub(super) fn apply_overrides(&mut self, config: &mut Config) -> Result<()> {
+        if let Some(overrides) = self.theme_overrides.take() {
+            config.theme = config.theme.clone().with_overrides(overrides);
+        }
+        if let Some(overrides) = self.icons_overrides.take() {
+            for (name, icon) in overrides {
+                if !config.icons.contains_key(&name) {
+                    return Err(ConfigurationError(
+                        format!("Unknown icon '{}' in icons_overrides", name),
+                        ("no such icon".to_string(), String::new()),
+                    ));
+                }
+                config.icons.insert(name, icon);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::de::Deserialize;
+
+    #[test]
+    fn test_apply_overrides() {
+        let mut block_config: Value = toml::from_str(
+            r##"
+            theme_overrides = { idle_bg = "#0000ff", separator = "|" }
+            icons_overrides = { cpu = "C" }
+            "##,
+        )
+        .unwrap();
+        let mut common_config =
+            BaseBlockConfig::deserialize(BaseBlockConfig::extract(&mut block_config, &[])).unwrap();
+        let mut config = Config::default();
+        common_config.apply_overrides(&mut config).unwrap();
+        assert_eq!(config.theme.idle_bg, Some("#0000ff".to_string()));
+        assert_eq!(config.theme.separator, "|");
+        assert_eq!(config.icons["cpu"], "C");
+
+        let mut block_config: Value =
+            toml::from_str(r##"icons_overrides = { cpuu = "C" }"##).unwrap();
+        let mut common_config =
+            BaseBlockConfig::deserialize(BaseBlockConfig::extract(&mut block_config, &[])).unwrap();
+        assert!(common_config.apply_overrides(&mut config).is_err());
+
+        let mut block_config: Value =
+            toml::from_str(r##"theme_overrides = { idle = "#0000ff" }"##).unwrap();
+        assert!(
+            BaseBlockConfig::deserialize(BaseBlockConfig::extract(&mut block_config, &[])).is_err()
+        );
+    }
+
+    #[test]
+    fn test_extract_timeout() {
+        let mut block_config: Value = toml::from_str("timeout = 5\ninterval = 1").unwrap();
+        let common_config =
+            BaseBlockConfig::deserialize(BaseBlockConfig::extract(&mut block_config, &[])).unwrap();
+        assert_eq!(common_config.timeout, Some(Duration::from_secs(5)));
+        assert!(block_config.get("timeout").is_none());
+
+        // Blocks with a `timeout` option of their own keep it
+        let mut block_config: Value = toml::from_str("timeout = 5").unwrap();
+        let common_config =
+            BaseBlockConfig::deserialize(BaseBlockConfig::extract(&mut block_config, &["timeout"]))
+                .unwrap();
+        assert_eq!(common_config.timeout, None);
+        assert!(block_config.get("timeout").is_some());
+
+        let mut block_config: Value = toml::from_str("update_timeout = 5").unwrap();
+        let common_config =
+            BaseBlockConfig::deserialize(BaseBlockConfig::extract(&mut block_config, &["timeout"]))
+                .unwrap();
+        assert_eq!(common_config.timeout, Some(Duration::from_secs(5)));
+    }
 }
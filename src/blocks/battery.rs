@@ -19,6 +19,7 @@ use crate::config::Config;
 use crate::de::deserialize_duration;
 use crate::errors::*;
 use crate::scheduler::Task;
+use crate::subprocess::spawn_child_async;
 use crate::util::{
     battery_level_to_icon, format_percent_bar, pseudo_uuid, read_file, FormatTemplate,
 };
@@ -41,7 +42,7 @@ pub trait BatteryDevice {
 
     /// Query the device status. One of `"Full"`, `"Charging"`, `"Discharging"`,
     /// or `"Unknown"`. Thinkpad batteries also report "`Not charging`", which
-    /// for our purposes should be treated as equivalent to full.
+    /// for our purposes is equivalent to full at or above `full_threshold`.
     fn status(&self) -> Result<String>;
 
     /// Query the device's current capacity, as a percent.
@@ -285,17 +286,22 @@ impl BatteryDevice for PowerSupplyDevice {
         // voltage in µV
         let voltage_path = self.device_path.join("voltage_now");
 
+        // Some drivers report negative values while discharging, so the sign
+        // is dropped. The rate is the same while charging and discharging.
         if power_path.exists() {
             Ok(read_file("battery", &power_path)?
-                .parse::<u64>()
-                .block_error("battery", "failed to parse power_now")?)
+                .parse::<i64>()
+                .block_error("battery", "failed to parse power_now")?
+                .unsigned_abs())
         } else if current_path.exists() && voltage_path.exists() {
             let current = read_file("battery", &current_path)?
-                .parse::<u64>()
-                .block_error("battery", "failed to parse current_now")?;
+                .parse::<i64>()
+                .block_error("battery", "failed to parse current_now")?
+                .unsigned_abs();
             let voltage = read_file("battery", &voltage_path)?
-                .parse::<u64>()
-                .block_error("battery", "failed to parse voltage_now")?;
+                .parse::<i64>()
+                .block_error("battery", "failed to parse voltage_now")?
+                .unsigned_abs();
             Ok((current * voltage) / 1_000_000)
         } else {
             Err(BlockError(
@@ -306,6 +312,153 @@ impl BatteryDevice for PowerSupplyDevice {
     }
 }
 
+impl PowerSupplyDevice {
+    /// Query the current and full energy (or charge, if that's what the driver
+    /// reports), in µWh (or µAh).
+    fn energy(&self) -> Option<(f64, f64)> {
+        let (now_path, full) = match (self.energy_full, self.charge_full) {
+            (Some(full), _) => (self.device_path.join("energy_now"), full),
+            (None, Some(full)) => (self.device_path.join("charge_now"), full),
+            (None, None) => return None,
+        };
+        let now = read_file("battery", &now_path).ok()?.parse::<f64>().ok()?;
+        Some((now, full as f64))
+    }
+}
+
+/// Combines several power supply devices (e.g. an internal and a removable
+/// battery) into one logical battery. Devices that go missing simply drop out
+/// of the aggregate.
+pub struct AggregateDevice {
+    devices: Vec<PowerSupplyDevice>,
+    allow_missing: bool,
+}
+
+impl AggregateDevice {
+    /// Combine the given devices from `/sys/class/power_supply`. Raises an
+    /// error on refresh if none of them can be found and `allow_missing` is
+    /// `false`.
+    pub fn from_devices(devices: &[String], allow_missing: bool) -> Result<Self> {
+        let devices = devices
+            .iter()
+            .map(|device| PowerSupplyDevice::from_device(device, true))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(AggregateDevice {
+            devices,
+            allow_missing,
+        })
+    }
+
+    fn available(&self) -> impl Iterator<Item = &PowerSupplyDevice> {
+        self.devices.iter().filter(|device| device.is_available())
+    }
+
+    /// The summed current and full energy of all available devices, if every
+    /// one of them reports it.
+    fn energy(&self) -> Option<(f64, f64)> {
+        self.available()
+            .map(|device| device.energy())
+            .try_fold((0.0, 0.0), |(now, full), energy| {
+                energy.map(|(n, f)| (now + n, full + f))
+            })
+    }
+}
+
+impl BatteryDevice for AggregateDevice {
+    fn is_available(&self) -> bool {
+        self.available().next().is_some()
+    }
+
+    fn refresh_device_info(&mut self) -> Result<()> {
+        if !self.is_available() && !self.allow_missing {
+            return Err(BlockError(
+                "battery".into(),
+                "None of the power supply devices exist".into(),
+            ));
+        }
+        for device in &mut self.devices {
+            device.refresh_device_info()?;
+        }
+        Ok(())
+    }
+
+    fn status(&self) -> Result<String> {
+        let statuses = self
+            .available()
+            .map(|device| device.status())
+            .collect::<Result<Vec<_>>>()?;
+        let any = |status: &str| statuses.iter().any(|s| s == status);
+
+        Ok(if any("Charging") {
+            "Charging"
+        } else if any("Discharging") {
+            "Discharging"
+        } else if statuses.iter().all(|s| s == "Full") {
+            "Full"
+        } else if statuses.iter().all(|s| s == "Full" || s == "Not charging") {
+            "Not charging"
+        } else {
+            "Unknown"
+        }
+        .to_string())
+    }
+
+    fn capacity(&self) -> Result<u64> {
+        match self.energy() {
+            Some((now, full)) if full > 0.0 => Ok(((now / full) * 100.0).min(100.0) as u64),
+            _ => {
+                // Fall back to the plain average if the energy isn't known
+                let capacities = self
+                    .available()
+                    .map(|device| device.capacity())
+                    .collect::<Result<Vec<_>>>()?;
+                if capacities.is_empty() {
+                    return Err(BlockError(
+                        "battery".to_string(),
+                        "No battery available".to_string(),
+                    ));
+                }
+                Ok(capacities.iter().sum::<u64>() / capacities.len() as u64)
+            }
+        }
+    }
+
+    fn time_remaining(&self) -> Result<u64> {
+        // Units cancel out the same way as for a single device
+        let status = self.status()?;
+        match (self.energy(), self.power_consumption()) {
+            (Some((now, full)), Ok(power)) if power > 0 => match status.as_str() {
+                "Discharging" => Ok(((now / power as f64) * 60.0) as u64),
+                "Charging" => Ok((((full - now) / power as f64) * 60.0) as u64),
+                _ => Ok(0),
+            },
+            _ => self
+                .available()
+                .filter_map(|device| device.time_remaining().ok())
+                .max()
+                .block_error(
+                    "battery",
+                    "No battery supports calculating the time remaining",
+                ),
+        }
+    }
+
+    fn power_consumption(&self) -> Result<u64> {
+        let powers: Vec<u64> = self
+            .available()
+            .filter_map(|device| device.power_consumption().ok())
+            .collect();
+        if powers.is_empty() {
+            Err(BlockError(
+                "battery".to_string(),
+                "Device does not support power consumption".to_string(),
+            ))
+        } else {
+            Ok(powers.iter().sum())
+        }
+    }
+}
+
 /// Represents a battery known to UPower.
 pub struct UpowerDevice {
     device_path: String,
@@ -333,11 +486,15 @@ impl UpowerDevice {
                 "org.freedesktop.UPower",
                 "EnumerateDevices",
             )
-            .unwrap();
-            let dbus_reply = con.send_with_reply_and_block(msg, 2000).unwrap();
+            .block_error("battery", "Failed to create D-Bus message")?;
+            let dbus_reply = con
+                .send_with_reply_and_block(msg, 2000)
+                .block_error("battery", "Failed to enumerate UPower devices")?;
 
             // EnumerateDevices returns one argument, which is an array of ObjectPaths (not dbus::tree:ObjectPath).
-            let mut paths: Array<dbus::Path, _> = dbus_reply.get1().unwrap();
+            let mut paths: Array<dbus::Path, _> = dbus_reply
+                .get1()
+                .block_error("battery", "Failed to read UPower devices")?;
             let path = paths.find(|entry| entry.ends_with(device));
             if path.is_none() {
                 return Err(BlockError(
@@ -364,27 +521,41 @@ impl UpowerDevice {
     }
 
     /// Monitor UPower property changes in a separate thread and send updates
-    /// via the `update_request` channel.
+    /// via the `update_request` channel. UPower (re)appearing on the bus also
+    /// triggers an update, so the block recovers when the daemon is restarted.
     pub fn monitor(&self, id: String, update_request: Sender<Task>) {
         let path = self.device_path.clone();
         thread::Builder::new()
             .name("battery".into())
-            .spawn(move || {
-                let con = dbus::ffidisp::Connection::get_private(dbus::ffidisp::BusType::System)
-                    .expect("Failed to establish D-Bus connection.");
-                let rule = format!(
+            .spawn(move || loop {
+                let con =
+                    match dbus::ffidisp::Connection::get_private(dbus::ffidisp::BusType::System) {
+                        Ok(con) => con,
+                        Err(_) => {
+                            // The bus itself may be restarting, try again later
+                            thread::sleep(Duration::from_secs(5));
+                            continue;
+                        }
+                    };
+                let properties_rule = format!(
                     "type='signal',\
                  path='{}',\
                  interface='org.freedesktop.DBus.Properties',\
                  member='PropertiesChanged'",
                     path
                 );
+                let owner_rule = "type='signal',\
+                 interface='org.freedesktop.DBus',\
+                 member='NameOwnerChanged',\
+                 arg0='org.freedesktop.UPower'";
 
                 // First we're going to get an (irrelevant) NameAcquired event.
                 con.incoming(10_000).next();
 
-                con.add_match(&rule)
-                    .expect("Failed to add D-Bus match rule.");
+                if con.add_match(&properties_rule).is_err() || con.add_match(owner_rule).is_err() {
+                    thread::sleep(Duration::from_secs(5));
+                    continue;
+                }
 
                 loop {
                     if con.incoming(10_000).next().is_some() {
@@ -406,7 +577,18 @@ impl UpowerDevice {
 
 impl BatteryDevice for UpowerDevice {
     fn is_available(&self) -> bool {
-        true // TODO: has to be implemented for UPower
+        // UPower may be restarting, in which case its name has no owner
+        dbus::Message::new_method_call(
+            "org.freedesktop.DBus",
+            "/org/freedesktop/DBus",
+            "org.freedesktop.DBus",
+            "NameHasOwner",
+        )
+        .ok()
+        .map(|msg| msg.append1("org.freedesktop.UPower"))
+        .and_then(|msg| self.con.send_with_reply_and_block(msg, 1000).ok())
+        .and_then(|reply| reply.get1::<bool>())
+        .unwrap_or(false)
     }
 
     fn refresh_device_info(&mut self) -> Result<()> {
@@ -483,8 +665,16 @@ pub struct Battery {
     format: FormatTemplate,
     full_format: FormatTemplate,
     missing_format: FormatTemplate,
+    short_format: Option<FormatTemplate>,
     allow_missing: bool,
     hide_missing: bool,
+    available: bool,
+    hide_full: bool,
+    full_threshold: u64,
+    full: bool,
+    on_low_command: Option<String>,
+    low_threshold: u64,
+    low_command_fired: bool,
     driver: BatteryDriver,
     good: u64,
     info: u64,
@@ -521,25 +711,35 @@ pub struct BatteryConfig {
     #[serde(default = "BatteryConfig::default_device")]
     pub device: String,
 
+    /// Several devices in `/sys/class/power_supply/` to combine into one
+    /// battery (sysfs only). Overrides `device`.
+    #[serde(default = "BatteryConfig::default_devices")]
+    pub devices: Option<Vec<String>>,
+
     /// (DEPRECATED) Options for displaying battery information.
     #[serde()]
     pub show: Option<String>,
 
     /// Format string for displaying battery information.
-    /// placeholders: {percentage}, {bar}, {time} and {power}
+    /// placeholders: {percentage}, {bar}, {time}, {time_hours}, {time_minutes} and {power}
     #[serde(default = "BatteryConfig::default_format")]
     pub format: String,
 
     /// Format string for displaying battery information when battery is full.
-    /// placeholders: {percentage}, {bar}, {time} and {power}
+    /// placeholders: {percentage}, {bar}, {time}, {time_hours}, {time_minutes} and {power}
     #[serde(default = "BatteryConfig::default_full_format")]
     pub full_format: String,
 
     /// Format string that's displayed if a battery is missing.
-    /// placeholders: {percentage}, {bar}, {time} and {power}
+    /// placeholders: {percentage}, {bar}, {time}, {time_hours}, {time_minutes} and {power}
     #[serde(default = "BatteryConfig::default_missing_format")]
     pub missing_format: String,
 
+    /// Format string shown instead of `format` when the bar is too narrow.
+    /// placeholders: {percentage}, {bar}, {time}, {time_hours}, {time_minutes} and {power}
+    #[serde(default = "BatteryConfig::default_short_format")]
+    pub short_format: Option<String>,
+
     /// (DEPRECATED) Use UPower to monitor battery status and events.
     #[serde(default = "BatteryConfig::default_upower")]
     pub upower: bool,
@@ -571,6 +771,23 @@ pub struct BatteryConfig {
     #[serde(default = "BatteryConfig::default_hide_missing")]
     pub hide_missing: bool,
 
+    /// Completely hide this block if the battery is full.
+    #[serde(default = "BatteryConfig::default_hide_full")]
+    pub hide_full: bool,
+
+    /// The level at which a battery that is not discharging is considered full,
+    /// e.g. for batteries with a charge threshold.
+    #[serde(default = "BatteryConfig::default_full_threshold")]
+    pub full_threshold: u64,
+
+    /// A shell command to run once when the battery drops to or below `low_threshold` while discharging.
+    #[serde(default = "BatteryConfig::default_on_low_command")]
+    pub on_low_command: Option<String>,
+
+    /// The level at or below which `on_low_command` is run.
+    #[serde(default = "BatteryConfig::default_low_threshold")]
+    pub low_threshold: u64,
+
     #[serde(default = "BatteryConfig::default_color_overrides")]
     pub color_overrides: Option<BTreeMap<String, String>>,
 }
@@ -584,6 +801,10 @@ impl BatteryConfig {
         "BAT0".to_string()
     }
 
+    fn default_devices() -> Option<Vec<String>> {
+        None
+    }
+
     fn default_format() -> String {
         "{percentage}%".into()
     }
@@ -596,6 +817,10 @@ impl BatteryConfig {
         "{percentage}%".into()
     }
 
+    fn default_short_format() -> Option<String> {
+        None
+    }
+
     fn default_upower() -> bool {
         false
     }
@@ -624,6 +849,22 @@ impl BatteryConfig {
         false
     }
 
+    fn default_hide_full() -> bool {
+        false
+    }
+
+    fn default_full_threshold() -> u64 {
+        100
+    }
+
+    fn default_on_low_command() -> Option<String> {
+        None
+    }
+
+    fn default_low_threshold() -> u64 {
+        10
+    }
+
     fn default_color_overrides() -> Option<BTreeMap<String, String>> {
         None
     }
@@ -659,28 +900,59 @@ impl ConfigBlock for Battery {
 
         let id = pseudo_uuid();
         let device: Box<dyn BatteryDevice> = match driver {
+            BatteryDriver::Upower if block_config.devices.is_some() => {
+                return Err(BlockError(
+                    "battery".into(),
+                    "`devices` is only supported by the sysfs driver, use `device = \"DisplayDevice\"` with UPower".into(),
+                ));
+            }
             BatteryDriver::Upower => {
                 let out = UpowerDevice::from_device(&block_config.device)?;
                 out.monitor(id.clone(), update_request);
                 Box::new(out)
             }
+            BatteryDriver::Sysfs if block_config.devices.is_some() => {
+                Box::new(AggregateDevice::from_devices(
+                    &block_config.devices.unwrap_or_default(),
+                    block_config.allow_missing,
+                )?)
+            }
             BatteryDriver::Sysfs => Box::new(PowerSupplyDevice::from_device(
                 &block_config.device,
                 block_config.allow_missing,
             )?),
         };
 
+        let format = FormatTemplate::from_string(&format)?.with_glyphs(&config.icons);
+        let full_format =
+            FormatTemplate::from_string(&block_config.full_format)?.with_glyphs(&config.icons);
+        let missing_format =
+            FormatTemplate::from_string(&block_config.missing_format)?.with_glyphs(&config.icons);
+        let short_format = match block_config.short_format {
+            Some(ref short_format) => {
+                Some(FormatTemplate::from_string(short_format)?.with_glyphs(&config.icons))
+            }
+            None => None,
+        };
         let output = TextWidget::new(config, &id);
         Ok(Battery {
             id,
             update_interval: block_config.interval,
             output,
             device,
-            format: FormatTemplate::from_string(&format)?,
-            full_format: FormatTemplate::from_string(&block_config.full_format)?,
-            missing_format: FormatTemplate::from_string(&block_config.missing_format)?,
+            format,
+            full_format,
+            missing_format,
+            short_format,
             allow_missing: block_config.allow_missing,
             hide_missing: block_config.hide_missing,
+            available: true,
+            hide_full: block_config.hide_full,
+            full_threshold: block_config.full_threshold,
+            full: false,
+            on_low_command: block_config.on_low_command,
+            low_threshold: block_config.low_threshold,
+            low_command_fired: false,
             driver,
             good: block_config.good,
             info: block_config.info,
@@ -695,8 +967,15 @@ impl Block for Battery {
         // TODO: Maybe use dbus to immediately signal when the battery state changes.
 
         // Exit early, if the battery device went missing, but the user
-        // allows this device to go missing.
-        if !self.device.is_available() && self.allow_missing {
+        // allows this device to go missing. UPower going away is always
+        // tolerated, since it is most likely just restarting.
+        self.available = self.device.is_available();
+        let missing_allowed = match self.driver {
+            BatteryDriver::Sysfs => self.allow_missing,
+            BatteryDriver::Upower => true,
+        };
+        if !self.available && missing_allowed {
+            self.full = false;
             // Respect the original format string, even if the battery
             // cannot be found right now.
             let empty_percent_bar = format_percent_bar(0.0);
@@ -704,13 +983,17 @@ impl Block for Battery {
                 "{percentage}" => "X",
                 "{bar}" => &empty_percent_bar,
                 "{time}" => "xx:xx",
+                "{time_hours}" => "xx",
+                "{time_minutes}" => "xx",
                 "{power}" => "N/A"
             );
 
             self.output.set_icon("bat_not_available");
             self.output
                 .set_text(self.missing_format.render_static_str(&values)?);
+            self.output.set_short_text(None);
             self.output.set_state(State::Warning);
+            self.output.set_urgent(false);
 
             return match self.driver {
                 BatteryDriver::Sysfs => Ok(Some(Update::Every(self.update_interval))),
@@ -732,32 +1015,72 @@ impl Block for Battery {
             Ok(capacity) => format_percent_bar(capacity as f32),
             Err(_) => "×".into(),
         };
-        let time = match self.device.time_remaining() {
-            Ok(time) => match time {
-                0 => "".into(),
-                _ => format!("{}:{:02}", std::cmp::min(time / 60, 99), time % 60),
-            },
-            Err(_) => "×".into(),
+        // Without any power draw (or with very little) the estimates are meaningless
+        let (time, time_hours, time_minutes) = match self.device.time_remaining() {
+            Ok(time) if time == 0 || time > 24 * 60 => ("—".into(), "—".into(), "—".into()),
+            Ok(time) => (
+                format!("{}:{:02}", time / 60, time % 60),
+                format!("{}", time / 60),
+                format!("{:02}", time % 60),
+            ),
+            Err(_) => ("×".into(), "×".into(), "×".into()),
         };
         // convert µW to W for display
         let power = match self.device.power_consumption() {
-            Ok(power) => format!("{:.2}", power as f64 / 1000.0 / 1000.0),
-            Err(_) => "×".into(),
+            Ok(power) => format!("{:.1}", power as f64 / 1000.0 / 1000.0),
+            Err(_) => "N/A".into(),
         };
         let values = map!("{percentage}" => percentage,
                             "{bar}" => bar,
                             "{time}" => time,
+                            "{time_hours}" => time_hours,
+                            "{time_minutes}" => time_minutes,
                             "{power}" => power);
 
-        if status == "Full" || status == "Not charging" {
+        // Run the low battery command once per discharge below the threshold.
+        // It is re-armed once the battery is charging or back above the threshold.
+        let mut low_command_error = None;
+        if let Some(ref cmd) = self.on_low_command {
+            match capacity {
+                Ok(capacity) if status == "Discharging" && capacity <= self.low_threshold => {
+                    if !self.low_command_fired {
+                        self.low_command_fired = true;
+                        // Shown instead of the battery until the next update
+                        if let Err(e) = spawn_child_async("sh", &["-c", cmd]) {
+                            low_command_error = Some(format!("on_low_command failed: {}", e));
+                        }
+                    }
+                }
+                Ok(_) => self.low_command_fired = false,
+                Err(_) => {}
+            }
+        }
+
+        // This is checked on every update, so the block reappears as soon as
+        // the battery starts discharging.
+        self.full = match status.as_str() {
+            "Full" => true,
+            "Discharging" => false,
+            // e.g. "Not charging", when held below 100% by a charge threshold
+            _ => matches!(capacity, Ok(capacity) if capacity >= self.full_threshold),
+        };
+
+        if self.full {
             self.output.set_icon("bat_full");
             self.output
                 .set_text(self.full_format.render_static_str(&values)?);
+            self.output.set_short_text(None);
             self.output.set_state(State::Good);
+            self.output.set_urgent(false);
             self.output.set_spacing(Spacing::Hidden);
         } else {
             self.output
                 .set_text(self.format.render_static_str(&values)?);
+            let short_text = match self.short_format {
+                Some(ref short_format) => Some(short_format.render_static_str(&values)?),
+                None => None,
+            };
+            self.output.set_short_text(short_text);
 
             // Check if the battery is in charging mode and change the state to Good.
             // Otherwise, adjust the state depeding the power percentance.
@@ -782,9 +1105,25 @@ impl Block for Battery {
                         }
                         Err(_) => State::Warning,
                     });
+                    if let Ok(capacity) = capacity {
+                        self.output.set_gradient(
+                            capacity as f64,
+                            &[
+                                (self.critical as f64, State::Critical),
+                                (self.warning as f64, State::Warning),
+                                (self.good as f64, State::Good),
+                            ],
+                        );
+                    }
                 }
             }
 
+            // Make the bar highlight the block while it's critically low
+            self.output.set_urgent(
+                status == "Discharging"
+                    && matches!(capacity, Ok(capacity) if capacity <= self.critical),
+            );
+
             self.output.set_icon(match status.as_str() {
                 "Discharging" => battery_level_to_icon(capacity),
                 "Charging" => "bat_charging",
@@ -793,6 +1132,12 @@ impl Block for Battery {
             self.output.set_spacing(Spacing::Normal);
         }
 
+        if let Some(error) = low_command_error {
+            self.output.set_text(error);
+            self.output.set_short_text(None);
+            self.output.set_state(State::Critical);
+        }
+
         match self.driver {
             BatteryDriver::Sysfs => Ok(Some(self.update_interval.into())),
             BatteryDriver::Upower => Ok(None),
@@ -801,7 +1146,11 @@ impl Block for Battery {
 
     fn view(&self) -> Vec<&dyn I3BarWidget> {
         // Don't display the block at all, if it's configured to be hidden on missing batteries
-        if !self.device.is_available() && self.hide_missing {
+        if !self.available && self.hide_missing {
+            return Vec::new();
+        }
+
+        if self.full && self.hide_full {
             return Vec::new();
         }
 
@@ -16,7 +16,9 @@ use crate::widget::{I3BarWidget, State};
 use crate::widgets::button::ButtonWidget;
 
 pub struct BluetoothDevice {
-    pub path: String,
+    pub mac: String,
+    /// The object path of the device, while BlueZ knows about it
+    pub path: Option<String>,
     pub icon: Option<String>,
     pub label: String,
     con: dbus::ffidisp::Connection,
@@ -27,73 +29,70 @@ impl BluetoothDevice {
         let con = dbus::ffidisp::Connection::get_private(dbus::ffidisp::BusType::System)
             .block_error("bluetooth", "Failed to establish D-Bus connection.")?;
 
+        let mut device = BluetoothDevice {
+            mac,
+            path: None,
+            icon: None,
+            label: label.unwrap_or_default(),
+            con,
+        };
+        // The device may show up later, e.g. once the adapter is plugged in.
+        device.find();
+
+        Ok(device)
+    }
+
+    /// Look up the object of the device, which is missing while there is no
+    /// adapter, or BlueZ isn't running.
+    pub fn find(&mut self) {
         // Bluez does not provide a convenient way to, say, list devices, so we
         // have to employ a rather verbose workaround.
-
-        let objects = con
+        let objects = match self
+            .con
             .with_path("org.bluez", "/", 1000)
             .get_managed_objects()
-            .block_error("bluetooth", "Failed to get managed objects from org.bluez.")?;
+        {
+            Ok(objects) => objects,
+            Err(_) => return,
+        };
 
-        let devices: Vec<(dbus::Path, String)> = objects
+        let mac = &self.mac;
+        self.path = objects
             .into_iter()
-            .filter(|(_, interfaces)| interfaces.contains_key("org.bluez.Device1"))
-            .map(|(path, interfaces)| {
-                let props = interfaces.get("org.bluez.Device1").unwrap();
-                // This could be made safer; however, this is the documented
-                // D-Bus API format, so it's not a terrible idea to panic if it
-                // is violated.
-                let address: String = props
-                    .get("Address")
-                    .unwrap()
-                    .0
-                    .as_str()
-                    .unwrap()
-                    .to_string();
-                (path, address)
+            .find(|(_, interfaces)| {
+                interfaces
+                    .get("org.bluez.Device1")
+                    .and_then(|props| props.get("Address"))
+                    .and_then(|address| address.0.as_str())
+                    == Some(mac.as_str())
             })
-            .collect();
-
-        // If we need to suppress errors from missing devices, this is the place
-        // to do it. We could also pick the "default" device here, although that
-        // does not make much sense to me in the context of Bluetooth.
-
-        let path = devices
-            .into_iter()
-            .filter(|(_, address)| address == &mac)
-            .map(|(path, _)| path)
-            .next()
-            .block_error(
-                "bluetooth",
-                "Failed find a device with matching MAC address.",
-            )?
-            .to_string();
-
-        // Swallow errors, since this is optional.
-        let icon: Option<String> = con
-            .with_path("org.bluez", &path, 1000)
-            .get("org.bluez.Device1", "Icon")
-            .ok();
-
-        Ok(BluetoothDevice {
-            path,
-            icon,
-            label: label.unwrap_or_else(|| "".to_string()),
-            con,
-        })
+            .map(|(path, _)| path.to_string());
+
+        if let Some(ref path) = self.path {
+            // Swallow errors, since this is optional.
+            self.icon = self
+                .con
+                .with_path("org.bluez", path, 1000)
+                .get("org.bluez.Device1", "Icon")
+                .ok();
+        }
     }
 
     pub fn battery(&self) -> Option<u8> {
         // Swallow errors here; not all devices implement this API.
         self.con
-            .with_path("org.bluez", &self.path, 1000)
+            .with_path("org.bluez", self.path.as_ref()?, 1000)
             .get("org.bluez.Battery1", "Percentage")
             .ok()
     }
 
     pub fn connected(&self) -> bool {
+        let path = match self.path {
+            Some(ref path) => path,
+            None => return false,
+        };
         self.con
-            .with_path("org.bluez", &self.path, 1000)
+            .with_path("org.bluez", path, 1000)
             .get("org.bluez.Device1", "Connected")
             // In the case that the D-Bus interface missing or responds
             // incorrectly, it seems reasonable to treat the device as "down"
@@ -102,14 +101,18 @@ impl BluetoothDevice {
     }
 
     pub fn toggle(&self) -> Result<()> {
+        let path = match self.path {
+            Some(ref path) => path,
+            // There is nothing to connect to
+            None => return Ok(()),
+        };
         let method = if self.connected() {
             "Disconnect"
         } else {
             "Connect"
         };
-        let msg =
-            dbus::Message::new_method_call("org.bluez", &self.path, "org.bluez.Device1", method)
-                .block_error("bluetooth", "Failed to build D-Bus method.")?;
+        let msg = dbus::Message::new_method_call("org.bluez", path, "org.bluez.Device1", method)
+            .block_error("bluetooth", "Failed to build D-Bus method.")?;
 
         // Swallow errors rather than nuke the bar.
         let _ = self.con.send(msg);
@@ -117,36 +120,57 @@ impl BluetoothDevice {
     }
 
     /// Monitor Bluetooth property changes in a separate thread and send updates
-    /// via the `update_request` channel.
+    /// via the `update_request` channel. The device objects appearing and
+    /// disappearing are monitored as well.
     pub fn monitor(&self, id: String, update_request: Sender<Task>) {
-        let path = self.path.clone();
+        // The last component of the object path, e.g. /org/bluez/hci0/dev_A0_8A_F5_B8_01_FD
+        let object_name = format!("dev_{}", self.mac.to_uppercase().replace(':', "_"));
         thread::Builder::new()
             .name("bluetooth".into())
             .spawn(move || {
                 let con = dbus::ffidisp::Connection::get_private(dbus::ffidisp::BusType::System)
                     .expect("Failed to establish D-Bus connection.");
-                let rule = format!(
-                    "type='signal',\
-                 path='{}',\
-                 interface='org.freedesktop.DBus.Properties',\
-                 member='PropertiesChanged'",
-                    path
-                );
+                let properties_rule = "type='signal',\
+                     sender='org.bluez',\
+                     path_namespace='/org/bluez',\
+                     interface='org.freedesktop.DBus.Properties',\
+                     member='PropertiesChanged'";
+                let objects_rule = "type='signal',\
+                     sender='org.bluez',\
+                     interface='org.freedesktop.DBus.ObjectManager'";
 
                 // Skip the NameAcquired event.
                 con.incoming(10_000).next();
 
-                con.add_match(&rule)
+                con.add_match(properties_rule)
+                    .expect("Failed to add D-Bus match rule.");
+                con.add_match(objects_rule)
                     .expect("Failed to add D-Bus match rule.");
 
                 loop {
-                    if con.incoming(10_000).next().is_some() {
-                        update_request
+                    let msg = match con.incoming(10_000).next() {
+                        Some(msg) => msg,
+                        None => continue,
+                    };
+                    // Other devices change their properties while scanning
+                    let relevant = match (msg.member(), msg.path()) {
+                        (Some(member), _)
+                            if &*member == "InterfacesAdded" || &*member == "InterfacesRemoved" =>
+                        {
+                            true
+                        }
+                        (_, Some(path)) => path.ends_with(&object_name),
+                        _ => false,
+                    };
+                    if relevant
+                        && update_request
                             .send(Task {
                                 id: id.clone(),
                                 update_time: Instant::now(),
                             })
-                            .unwrap();
+                            .is_err()
+                    {
+                        break;
                     }
                 }
             })
@@ -154,10 +178,22 @@ impl BluetoothDevice {
     }
 }
 
+/// The icon for the type of the device as reported by BlueZ.
+fn device_icon(icon: Option<&str>) -> &'static str {
+    match icon {
+        Some("audio-card") | Some("audio-headset") | Some("audio-headphones") => "headphones",
+        Some("input-gaming") => "joystick",
+        Some("input-keyboard") => "keyboard",
+        Some("input-mouse") => "mouse",
+        _ => "bluetooth",
+    }
+}
+
 pub struct Bluetooth {
     id: String,
     output: ButtonWidget,
     device: BluetoothDevice,
+    connected: bool,
     hide_disconnected: bool,
 }
 
@@ -192,14 +228,9 @@ impl ConfigBlock for Bluetooth {
 
         Ok(Bluetooth {
             id: id.clone(),
-            output: ButtonWidget::new(config, &id).with_icon(match device.icon {
-                Some(ref icon) if icon == "audio-card" => "headphones",
-                Some(ref icon) if icon == "input-gaming" => "joystick",
-                Some(ref icon) if icon == "input-keyboard" => "keyboard",
-                Some(ref icon) if icon == "input-mouse" => "mouse",
-                _ => "bluetooth",
-            }),
+            output: ButtonWidget::new(config, &id).with_icon(device_icon(device.icon.as_deref())),
             device,
+            connected: false,
             hide_disconnected: block_config.hide_disconnected,
         })
     }
@@ -211,13 +242,20 @@ impl Block for Bluetooth {
     }
 
     fn update(&mut self) -> Result<Option<Update>> {
+        // The object is gone once the device is removed along with its adapter,
+        // and a new one is created when it comes back.
+        self.device.find();
+        self.output
+            .set_icon(device_icon(self.device.icon.as_deref()));
+
         let connected = self.device.connected();
+        self.connected = connected;
         self.output.set_text(self.device.label.to_string());
         self.output
             .set_state(if connected { State::Good } else { State::Idle });
 
         // Use battery info, when available.
-        if let Some(value) = self.device.battery() {
+        if let Some(value) = self.device.battery().filter(|_| connected) {
             self.output.set_state(match value {
                 0..=15 => State::Critical,
                 16..=30 => State::Warning,
@@ -235,7 +273,7 @@ impl Block for Bluetooth {
     fn click(&mut self, event: &I3BarEvent) -> Result<()> {
         if let Some(ref name) = event.name {
             if name.as_str() == self.id {
-                if let MouseButton::Right = event.button {
+                if let MouseButton::Left | MouseButton::Right = event.button {
                     self.device.toggle()?;
                 }
             }
@@ -244,7 +282,7 @@ impl Block for Bluetooth {
     }
 
     fn view(&self) -> Vec<&dyn I3BarWidget> {
-        if !self.device.connected() && self.hide_disconnected {
+        if !self.connected && self.hide_disconnected {
             vec![]
         } else {
             vec![&self.output]
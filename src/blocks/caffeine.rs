@@ -0,0 +1,234 @@
+use std::collections::BTreeMap;
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::Sender;
+use dbus::arg::OwnedFd;
+use dbus::ffidisp::{BusType, Connection};
+use nix::sys::signal::{killpg, Signal};
+use nix::unistd::Pid;
+use serde_derive::Deserialize;
+
+use crate::blocks::{Block, ConfigBlock, Update};
+use crate::config::Config;
+use crate::de::deserialize_opt_duration;
+use crate::errors::*;
+use crate::input::{I3BarEvent, MouseButton};
+use crate::scheduler::Task;
+use crate::subprocess::die_with_parent;
+use crate::util::{pseudo_uuid, FormatTemplate};
+use crate::widget::{I3BarWidget, State};
+use crate::widgets::button::ButtonWidget;
+
+/// How often an `inhibit_cmd` is checked for having exited on its own
+const COMMAND_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// An acquired idle inhibitor, which is released when dropped.
+enum Inhibitor {
+    /// The file descriptor returned by logind. The inhibitor is released once
+    /// it is closed, which the kernel also does when the bar exits. It is only
+    /// held, never read.
+    Logind(#[allow(dead_code)] OwnedFd),
+    /// A running `inhibit_cmd`, which leads its own process group
+    Command(Child),
+}
+
+impl Inhibitor {
+    fn logind() -> Result<Self> {
+        let c = Connection::get_private(BusType::System)
+            .block_error("caffeine", "Failed to establish D-Bus connection")?;
+        let (fd,): (OwnedFd,) = c
+            .with_path("org.freedesktop.login1", "/org/freedesktop/login1", 5000)
+            .method_call(
+                "org.freedesktop.login1.Manager",
+                "Inhibit",
+                ("idle", "i3status-rs", "Caffeine block enabled", "block"),
+            )
+            .block_error(
+                "caffeine",
+                "Failed to acquire an idle inhibitor from logind",
+            )?;
+        Ok(Inhibitor::Logind(fd))
+    }
+
+    fn command(cmd: &str) -> Result<Self> {
+        let mut command = Command::new("sh");
+        command
+            .args(&["-c", cmd])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null());
+        // Have the command terminated when the bar exits, so that the system
+        // doesn't stay awake without the bar.
+        die_with_parent(&mut command);
+        // The processes the shell starts are killed along with it
+        unsafe {
+            command.pre_exec(|| {
+                nix::libc::setpgid(0, 0);
+                Ok(())
+            });
+        }
+        let child = command
+            .spawn()
+            .block_error("caffeine", &format!("Failed to run inhibit_cmd '{}'", cmd))?;
+        Ok(Inhibitor::Command(child))
+    }
+
+    /// Whether the inhibitor is still held. An `inhibit_cmd` may exit on its
+    /// own, e.g. if it fails.
+    fn is_held(&mut self) -> bool {
+        match self {
+            Inhibitor::Logind(_) => true,
+            Inhibitor::Command(child) => matches!(child.try_wait(), Ok(None)),
+        }
+    }
+}
+
+impl Drop for Inhibitor {
+    fn drop(&mut self) {
+        if let Inhibitor::Command(child) = self {
+            let _ = killpg(Pid::from_raw(child.id() as i32), Signal::SIGKILL);
+            let _ = child.wait();
+        }
+    }
+}
+
+pub struct Caffeine {
+    id: String,
+    output: ButtonWidget,
+    format: FormatTemplate,
+    inhibit_cmd: Option<String>,
+    timeout: Option<Duration>,
+    /// The inhibitor while the block is enabled, and when it was acquired
+    inhibitor: Option<(Inhibitor, Instant)>,
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct CaffeineConfig {
+    /// Format override
+    #[serde(default = "CaffeineConfig::default_format")]
+    pub format: String,
+
+    /// A command which inhibits idling while it runs, used instead of logind
+    #[serde(default = "CaffeineConfig::default_inhibit_cmd")]
+    pub inhibit_cmd: Option<String>,
+
+    /// Release the inhibitor after this many seconds
+    #[serde(default, deserialize_with = "deserialize_opt_duration")]
+    pub timeout: Option<Duration>,
+
+    #[serde(default = "CaffeineConfig::default_color_overrides")]
+    pub color_overrides: Option<BTreeMap<String, String>>,
+}
+
+impl CaffeineConfig {
+    fn default_format() -> String {
+        // display just the icon
+        "".to_owned()
+    }
+
+    fn default_inhibit_cmd() -> Option<String> {
+        None
+    }
+
+    fn default_color_overrides() -> Option<BTreeMap<String, String>> {
+        None
+    }
+}
+
+impl ConfigBlock for Caffeine {
+    type Config = CaffeineConfig;
+
+    fn new(block_config: Self::Config, config: Config, _send: Sender<Task>) -> Result<Self> {
+        let id = pseudo_uuid();
+
+        Ok(Caffeine {
+            output: ButtonWidget::new(config, &id).with_icon("caffeine_off"),
+            id,
+            format: FormatTemplate::from_string(&block_config.format)
+                .block_error("caffeine", "Invalid format specified for caffeine")?,
+            inhibit_cmd: block_config.inhibit_cmd,
+            timeout: block_config.timeout,
+            inhibitor: None,
+        })
+    }
+}
+
+impl Block for Caffeine {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn update(&mut self) -> Result<Option<Update>> {
+        let timeout = self.timeout;
+        let expired = match self.inhibitor {
+            Some((ref mut inhibitor, acquired)) => {
+                !inhibitor.is_held() || timeout.map_or(false, |t| acquired.elapsed() >= t)
+            }
+            None => false,
+        };
+        if expired {
+            self.inhibitor = None;
+        }
+
+        let enabled = self.inhibitor.is_some();
+        let values = map!("{state}" => if enabled { "on" } else { "off" });
+        self.output
+            .set_text(self.format.render_static_str(&values)?);
+        self.output.set_icon(if enabled {
+            "caffeine_on"
+        } else {
+            "caffeine_off"
+        });
+        self.output
+            .set_state(if enabled { State::Info } else { State::Idle });
+
+        // Check again once the timeout expires, or the command may have exited
+        Ok(match self.inhibitor {
+            Some((ref inhibitor, acquired)) => {
+                let remaining =
+                    timeout.map(|t| t.checked_sub(acquired.elapsed()).unwrap_or_default());
+                let check = match inhibitor {
+                    Inhibitor::Command(_) => Some(COMMAND_CHECK_INTERVAL),
+                    Inhibitor::Logind(_) => None,
+                };
+                match (remaining, check) {
+                    (Some(remaining), Some(check)) => Some(remaining.min(check).into()),
+                    (Some(next), None) | (None, Some(next)) => Some(next.into()),
+                    (None, None) => None,
+                }
+            }
+            None => None,
+        })
+    }
+
+    fn view(&self) -> Vec<&dyn I3BarWidget> {
+        vec![&self.output]
+    }
+
+    fn click(&mut self, event: &I3BarEvent) -> Result<()> {
+        if event.matches_name(&self.id) && event.button == MouseButton::Left {
+            // Dropping the inhibitor releases it
+            let acquired = match self.inhibitor.take() {
+                Some(_) => Ok(()),
+                None => match self.inhibit_cmd {
+                    Some(ref cmd) => Inhibitor::command(cmd),
+                    None => Inhibitor::logind(),
+                }
+                .map(|inhibitor| self.inhibitor = Some((inhibitor, Instant::now()))),
+            };
+            self.update()?;
+            if let Err(BlockError(_, message)) = acquired {
+                self.output.set_text(message);
+                self.output.set_state(State::Critical);
+            }
+        }
+        Ok(())
+    }
+
+    fn shutdown(&mut self) {
+        // Dropping the inhibitor releases it
+        self.inhibitor = None;
+    }
+}
@@ -0,0 +1,250 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use chrono::{DateTime, Datelike, Local, NaiveDate, NaiveDateTime, TimeZone};
+use crossbeam_channel::Sender;
+use serde::de::{self, Deserialize, Deserializer};
+use serde_derive::Deserialize;
+
+use crate::blocks::{Block, ConfigBlock, Update};
+use crate::config::{Config, LogicalDirection};
+use crate::errors::*;
+use crate::input::{I3BarEvent, MouseButton};
+use crate::scheduler::Task;
+use crate::util::{pseudo_uuid, FormatTemplate};
+use crate::widget::{I3BarWidget, State};
+use crate::widgets::text::TextWidget;
+
+/// Parses a date as `YYYY-MM-DD` (midnight in the local timezone), a local
+/// date and time as `YYYY-MM-DDTHH:MM:SS`, or an RFC3339 timestamp.
+fn parse_date(date: &str) -> Option<DateTime<Local>> {
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(date) {
+        return Some(datetime.with_timezone(&Local));
+    }
+    let datetime = match NaiveDateTime::parse_from_str(date, "%Y-%m-%dT%H:%M:%S") {
+        Ok(datetime) => datetime,
+        Err(_) => NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .ok()?
+            .and_hms(0, 0, 0),
+    };
+    Local.from_local_datetime(&datetime).earliest()
+}
+
+/// Accepts dates both as strings and as TOML dates.
+fn deserialize_date<'de, D>(deserializer: D) -> std::result::Result<DateTime<Local>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let date = match toml::Value::deserialize(deserializer)? {
+        toml::Value::String(date) => date,
+        toml::Value::Datetime(date) => date.to_string(),
+        _ => return Err(de::Error::custom("expected a date")),
+    };
+    parse_date(&date).ok_or_else(|| {
+        de::Error::custom(format!(
+            "invalid date '{}', expected YYYY-MM-DD or RFC3339",
+            date
+        ))
+    })
+}
+
+/// The number of days from Monday to Friday from `from` up to, but not
+/// including, `to`.
+fn count_weekdays(from: NaiveDate, to: NaiveDate) -> i64 {
+    let days = (to - from).num_days();
+    if days <= 0 {
+        return 0;
+    }
+    let (weeks, rest) = (days / 7, days % 7);
+    let offset = from.weekday().num_days_from_monday() as i64;
+    let rest_weekdays = (0..rest).filter(|day| (offset + day) % 7 < 5).count() as i64;
+    weeks * 5 + rest_weekdays
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct CountdownTarget {
+    #[serde(deserialize_with = "deserialize_date")]
+    pub date: DateTime<Local>,
+
+    #[serde(default)]
+    pub label: String,
+}
+
+pub struct Countdown {
+    text: TextWidget,
+    id: String,
+    targets: Vec<CountdownTarget>,
+    current: usize,
+    format: FormatTemplate,
+    done_format: FormatTemplate,
+    update_interval: Duration,
+    config: Config,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct CountdownConfig {
+    /// The dates to count down to, with their labels
+    pub targets: Vec<CountdownTarget>,
+
+    /// Format override
+    #[serde(default = "CountdownConfig::default_format")]
+    pub format: String,
+
+    /// Format override once the date has passed
+    #[serde(default = "CountdownConfig::default_done_format")]
+    pub done_format: String,
+
+    #[serde(default = "CountdownConfig::default_color_overrides")]
+    pub color_overrides: Option<BTreeMap<String, String>>,
+}
+
+impl CountdownConfig {
+    fn default_format() -> String {
+        "{label} {days} days".to_owned()
+    }
+
+    fn default_done_format() -> String {
+        "{label}".to_owned()
+    }
+
+    fn default_color_overrides() -> Option<BTreeMap<String, String>> {
+        None
+    }
+}
+
+impl ConfigBlock for Countdown {
+    type Config = CountdownConfig;
+
+    fn new(
+        block_config: Self::Config,
+        config: Config,
+        _tx_update_request: Sender<Task>,
+    ) -> Result<Self> {
+        if block_config.targets.is_empty() {
+            return Err(BlockError(
+                "countdown".to_owned(),
+                "at least one target is required".to_owned(),
+            ));
+        }
+
+        let id = pseudo_uuid();
+        // Without the hours, updating hourly is precise enough
+        let update_interval = if block_config.format.contains("{hours}") {
+            Duration::from_secs(60)
+        } else {
+            Duration::from_secs(3600)
+        };
+
+        Ok(Countdown {
+            text: TextWidget::new(config.clone(), &id),
+            id,
+            targets: block_config.targets,
+            current: 0,
+            format: FormatTemplate::from_string(&block_config.format)
+                .block_error("countdown", "Invalid format specified for countdown")?,
+            done_format: FormatTemplate::from_string(&block_config.done_format)
+                .block_error("countdown", "Invalid done_format specified for countdown")?,
+            update_interval,
+            config,
+        })
+    }
+}
+
+impl Block for Countdown {
+    fn update(&mut self) -> Result<Option<Update>> {
+        let target = &self.targets[self.current];
+        let now = Local::now();
+
+        if target.date <= now {
+            let values = map!("{label}" => target.label.clone());
+            self.text
+                .set_text(self.done_format.render_static_str(&values)?);
+            self.text.set_state(State::Good);
+        } else {
+            let remaining = target.date - now;
+            let weekdays =
+                count_weekdays(now.date().naive_local(), target.date.date().naive_local());
+            let values = map!("{label}" => target.label.clone(),
+                              "{days}" => remaining.num_days().to_string(),
+                              "{hours}" => (remaining.num_hours() % 24).to_string(),
+                              "{weekdays}" => weekdays.to_string());
+            self.text.set_text(self.format.render_static_str(&values)?);
+            self.text.set_state(State::Idle);
+        }
+
+        Ok(Some(self.update_interval.into()))
+    }
+
+    fn view(&self) -> Vec<&dyn I3BarWidget> {
+        vec![&self.text]
+    }
+
+    fn click(&mut self, e: &I3BarEvent) -> Result<()> {
+        if !e.matches_name(&self.id) || self.targets.len() < 2 {
+            return Ok(());
+        }
+
+        use LogicalDirection::*;
+        let forward = match e.button {
+            MouseButton::Left => true,
+            MouseButton::Right => false,
+            button => match self.config.scrolling.to_logical_direction(button) {
+                Some(Up) => true,
+                Some(Down) => false,
+                None => return Ok(()),
+            },
+        };
+        let count = self.targets.len();
+        self.current = if forward {
+            (self.current + 1) % count
+        } else {
+            (self.current + count - 1) % count
+        };
+        self.update()?;
+
+        Ok(())
+    }
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Weekday;
+
+    #[test]
+    fn test_parse_date() {
+        assert_eq!(
+            parse_date("2021-03-04").unwrap().naive_local(),
+            NaiveDate::from_ymd(2021, 3, 4).and_hms(0, 0, 0)
+        );
+        assert_eq!(
+            parse_date("2021-03-04T12:00:00Z").unwrap(),
+            Local.timestamp(1614859200, 0)
+        );
+        assert!(parse_date("04.03.2021").is_none());
+    }
+
+    #[test]
+    fn test_count_weekdays() {
+        // A Monday
+        let monday = NaiveDate::from_ymd(2021, 3, 1);
+        assert_eq!(count_weekdays(monday, NaiveDate::from_ymd(2021, 3, 6)), 5);
+        assert_eq!(count_weekdays(monday, NaiveDate::from_ymd(2021, 3, 15)), 10);
+        // Friday until Tuesday
+        assert_eq!(
+            count_weekdays(
+                NaiveDate::from_ymd(2021, 3, 5),
+                NaiveDate::from_ymd(2021, 3, 9)
+            ),
+            2
+        );
+        assert_eq!(count_weekdays(monday, monday), 0);
+        assert_eq!(monday.weekday(), Weekday::Mon);
+    }
+}
@@ -1,10 +1,12 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs::File;
 use std::io::prelude::*;
 use std::io::BufReader;
+use std::path::Path;
 use std::time::Duration;
 
 use crossbeam_channel::Sender;
+use regex::Regex;
 use serde_derive::Deserialize;
 
 use crate::blocks::{Block, ConfigBlock, Update};
@@ -12,17 +14,69 @@ use crate::config::Config;
 use crate::de::deserialize_duration;
 use crate::errors::*;
 use crate::scheduler::Task;
-use crate::util::{format_percent_bar, pseudo_uuid, FormatTemplate};
+use crate::util::{format_percent_bar, pseudo_uuid, read_file, FormatTemplate};
 use crate::widget::{I3BarWidget, State};
 use crate::widgets::button::ButtonWidget;
 
-/// Maximum number of CPUs we support.
-const MAX_CPUS: usize = 32;
+/// The idle and non-idle time of a cpu, as read from /proc/stat.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct CpuSample {
+    idle: u64,
+    non_idle: u64,
+}
+
+impl CpuSample {
+    /// The fraction of time spent non-idle since `prev` was taken.
+    fn utilization_since(&self, prev: &CpuSample) -> f64 {
+        let prev_total = prev.idle + prev.non_idle;
+        let total = self.idle + self.non_idle;
+
+        // This check is needed because the new values may be reset, for
+        // example after hibernation.
+        if prev_total < total && prev.idle <= self.idle {
+            let total_delta = total - prev_total;
+            let idle_delta = self.idle - prev.idle;
+            (total_delta - idle_delta) as f64 / total_delta as f64
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Parses a `cpu` line of /proc/stat into the core number (`None` for the
+/// aggregate line) and its sample.
+fn parse_cpu_line(line: &str) -> Option<(Option<usize>, CpuSample)> {
+    let mut words = line.split_whitespace();
+    let core = match words.next()?.strip_prefix("cpu")? {
+        "" => None,
+        n => Some(n.parse::<usize>().ok()?),
+    };
+    let data: Vec<u64> = words.filter_map(|x| x.parse::<u64>().ok()).collect();
+    if data.len() < 8 {
+        return None;
+    }
+
+    Some((
+        core,
+        CpuSample {
+            // idle = idle + iowait
+            idle: data[3] + data[4],
+            non_idle: data[0] + // user
+                      data[1] + // nice
+                      data[2] + // system
+                      data[5] + // irq
+                      data[6] + // softirq
+                      data[7], // steal
+        },
+    ))
+}
 
 pub struct Cpu {
     output: ButtonWidget,
-    prev_idles: [u64; MAX_CPUS],
-    prev_non_idles: [u64; MAX_CPUS],
+    /// The previous sample of the aggregate at index 0, followed by each core.
+    prev_samples: Vec<Option<CpuSample>>,
+    /// The `{utilizationN}` placeholders used in the format, with their core number.
+    core_placeholders: Vec<(String, usize)>,
     id: String,
     update_interval: Duration,
     minimum_info: u64,
@@ -111,7 +165,7 @@ impl ConfigBlock for Cpu {
         _tx_update_request: Sender<Task>,
     ) -> Result<Self> {
         let format = if block_config.frequency {
-            "{utilization}% {frequency}GHz".into()
+            "{utilization}% {frequency}".into()
         } else if block_config.per_core {
             "{utilization}".to_owned()
         } else {
@@ -120,18 +174,30 @@ impl ConfigBlock for Cpu {
 
         let id = pseudo_uuid();
 
+        let format_template = FormatTemplate::from_string(&format)
+            .block_error("cpu", "Invalid format specified for cpu")?;
+        let core_regex =
+            Regex::new(r"^\{utilization(\d+)\}$").block_error("cpu", "failed to create regex")?;
+        let core_placeholders = format_template
+            .placeholders()
+            .into_iter()
+            .filter_map(|placeholder| {
+                let core = core_regex.captures(placeholder)?[1].parse().ok()?;
+                Some((placeholder.to_string(), core))
+            })
+            .collect();
+
         Ok(Cpu {
             id: id.clone(),
             update_interval: block_config.interval,
             output: ButtonWidget::new(config, &id).with_icon("cpu"),
-            prev_idles: [0; MAX_CPUS],
-            prev_non_idles: [0; MAX_CPUS],
+            prev_samples: Vec::new(),
+            core_placeholders,
             minimum_info: block_config.info,
             minimum_warning: block_config.warning,
             minimum_critical: block_config.critical,
-            format: FormatTemplate::from_string(&format)
-                .block_error("cpu", "Invalid format specified for cpu")?,
-            has_frequency: format.contains("{frequency}"),
+            format: format_template,
+            has_frequency: format.contains("{frequency") || format.contains("{max_frequency}"),
             has_barchart: format.contains("{barchart}"),
             per_core: block_config.per_core,
         })
@@ -144,76 +210,47 @@ impl Block for Cpu {
             .block_error("cpu", "Your system doesn't support /proc/stat")?;
         let f = BufReader::new(f);
 
-        let mut cpu_freqs: [f32; MAX_CPUS] = [0.0; MAX_CPUS];
-        let mut n_cpu = 0;
-        if self.has_frequency {
-            let freq_file =
-                File::open("/proc/cpuinfo").block_error("cpu", "failed to read /proc/cpuinfo")?;
-            let freq_file_content = BufReader::new(freq_file);
-            // read frequency of each cpu and calculate the average which we will display
-            for line in freq_file_content.lines().scan((), |_, x| x.ok()) {
-                if line.starts_with("cpu MHz") {
-                    let words = line.split(' ');
-                    let last = words
-                        .last()
-                        .expect("failed to get last word of line while getting cpu frequency");
-                    let numb = last
-                        .parse::<f32>()
-                        .expect("failed to parse String to f32 while getting cpu frequency");
-                    cpu_freqs[n_cpu] = numb;
-                    n_cpu += 1;
-                    if n_cpu >= MAX_CPUS {
-                        break;
-                    };
-                }
-            }
-        }
+        let cpu_freqs = if self.has_frequency {
+            read_frequencies()
+        } else {
+            Vec::new()
+        };
 
-        let mut cpu_utilizations: [f64; MAX_CPUS] = [0.0; MAX_CPUS];
-        let mut cpu_i = 0;
+        // The first entry is the aggregate over all cores, followed by the
+        // cores that are currently online, in the order listed by the kernel.
+        let mut cpu_utilizations: Vec<f64> = Vec::new();
+        let mut core_utilizations: HashMap<usize, f64> = HashMap::new();
         for line in f.lines().scan((), |_, x| x.ok()) {
-            if line.starts_with("cpu") {
-                let data: Vec<u64> = (&line)
-                    .split(' ')
-                    .collect::<Vec<&str>>()
-                    .iter()
-                    .skip(if cpu_i == 0 { 2 } else { 1 })
-                    .filter_map(|x| x.parse::<u64>().ok())
-                    .collect::<Vec<_>>();
-
-                // idle = idle + iowait
-                let idle = data[3] + data[4];
-                let non_idle = data[0] + // user
-                                data[1] + // nice
-                                data[2] + // system
-                                data[5] + // irq
-                                data[6] + // softirq
-                                data[7]; // steal
-
-                let prev_total = self.prev_idles[cpu_i] + self.prev_non_idles[cpu_i];
-                let total = idle + non_idle;
-
-                // This check is needed because the new values may be reset, for
-                // example after hibernation.
-
-                let (total_delta, idle_delta) =
-                    if prev_total < total && self.prev_idles[cpu_i] <= idle {
-                        (total - prev_total, idle - self.prev_idles[cpu_i])
-                    } else {
-                        (1, 1)
-                    };
-
-                cpu_utilizations[cpu_i] = (total_delta - idle_delta) as f64 / total_delta as f64;
-
-                self.prev_idles[cpu_i] = idle;
-                self.prev_non_idles[cpu_i] = non_idle;
-                cpu_i += 1;
-                if cpu_i >= MAX_CPUS {
-                    break;
+            if let Some((core, sample)) = parse_cpu_line(&line) {
+                // Cores are hotpluggable, so the samples are keyed by the core
+                // number rather than by their position in /proc/stat.
+                let slot = core.map_or(0, |n| n + 1);
+                if self.prev_samples.len() <= slot {
+                    self.prev_samples.resize(slot + 1, None);
+                }
+
+                // There is nothing to compare against on the first sample of a
+                // core, so it is shown as idle.
+                let utilization = match self.prev_samples[slot] {
+                    Some(prev) => sample.utilization_since(&prev),
+                    None => 0.0,
                 };
+                self.prev_samples[slot] = Some(sample);
+
+                cpu_utilizations.push(utilization);
+                if let Some(n) = core {
+                    core_utilizations.insert(n, utilization);
+                }
             }
         }
 
+        if cpu_utilizations.is_empty() {
+            return Err(BlockError(
+                "cpu".to_string(),
+                "Could not find any cpu in /proc/stat".to_string(),
+            ));
+        }
+
         let avg_utilization = (100.0 * cpu_utilizations[0]) as u64;
 
         self.output.set_state(match avg_utilization {
@@ -222,25 +259,44 @@ impl Block for Cpu {
             x if x > self.minimum_info => State::Info,
             _ => State::Idle,
         });
+        self.output.set_gradient(
+            avg_utilization as f64,
+            &[
+                (self.minimum_info as f64, State::Good),
+                (self.minimum_warning as f64, State::Warning),
+                (self.minimum_critical as f64, State::Critical),
+            ],
+        );
 
         let mut barchart = String::new();
 
         if self.has_barchart {
             const BOXCHARS: &[char] = &['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
 
-            for i in 1..cpu_i {
+            for utilization in cpu_utilizations.iter().skip(1) {
                 barchart.push(
-                    BOXCHARS[((7.5 * cpu_utilizations[i]) as usize)
+                    BOXCHARS[((7.5 * utilization) as usize)
                         // TODO: Replace with .clamp once the feature is stable
                         // upper bound just in case the value is negative, e.g. USIZE MAX after conversion
                         .min(BOXCHARS.len() - 1)],
                 );
             }
         }
-        let values = map!("{frequency}" => format_frequency(&cpu_freqs, n_cpu, self.per_core),
+        let mut values = map!("{frequency}" => format_frequencies(&cpu_freqs, self.per_core, format_ghz),
+                          "{frequency_mhz}" => format_frequencies(&cpu_freqs, self.per_core, format_mhz),
+                          "{max_frequency}" => format_max_frequency(&cpu_freqs),
                           "{barchart}" => barchart,
-                          "{utilization}" => format_utilization(&cpu_utilizations, cpu_i, self.per_core),
+                          "{utilization}" => format_utilization(&cpu_utilizations, cpu_utilizations.len(), self.per_core),
                           "{utilizationbar}" => format_percent_bar(avg_utilization as f32));
+        for (placeholder, core) in &self.core_placeholders {
+            values.insert(
+                placeholder,
+                match core_utilizations.get(core) {
+                    Some(utilization) => format!("{:02.0}", 100.0 * utilization),
+                    None => "N/A".to_string(),
+                },
+            );
+        }
 
         self.output
             .set_text(self.format.render_static_str(&values)?);
@@ -272,17 +328,141 @@ fn format_utilization(values: &[f64], count: usize, per_core: bool) -> String {
     }
 }
 
+/// Reads the current frequency of each core in MHz, preferring cpufreq and
+/// falling back to /proc/cpuinfo. Returns nothing if neither is available,
+/// e.g. in containers.
+fn read_frequencies() -> Vec<f32> {
+    let freqs = read_sysfs_frequencies();
+    if !freqs.is_empty() {
+        return freqs;
+    }
+
+    let freq_file = match File::open("/proc/cpuinfo") {
+        Ok(file) => file,
+        Err(_) => return Vec::new(),
+    };
+    BufReader::new(freq_file)
+        .lines()
+        .scan((), |_, x| x.ok())
+        .filter(|line| line.starts_with("cpu MHz"))
+        .filter_map(|line| line.split(':').nth(1)?.trim().parse::<f32>().ok())
+        .collect()
+}
+
+fn read_sysfs_frequencies() -> Vec<f32> {
+    let entries = match Path::new("/sys/devices/system/cpu").read_dir() {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let mut cores: Vec<(usize, f32)> = entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let core = path
+                .file_name()?
+                .to_str()?
+                .strip_prefix("cpu")?
+                .parse::<usize>()
+                .ok()?;
+            // scaling_cur_freq is in kHz
+            let khz = read_file("cpu", &path.join("cpufreq/scaling_cur_freq"))
+                .ok()?
+                .trim()
+                .parse::<f32>()
+                .ok()?;
+            Some((core, khz / 1000.0))
+        })
+        .collect();
+    cores.sort_by_key(|&(core, _)| core);
+    cores.into_iter().map(|(_, mhz)| mhz).collect()
+}
+
+/// Formats a frequency in MHz as GHz, with its unit.
 #[inline]
-fn format_frequency(cpu_freqs: &[f32], count: usize, per_core: bool) -> String {
-    if per_core {
+fn format_ghz(mhz: f32) -> String {
+    format!("{:.1}GHz", mhz / 1000.0)
+}
+
+/// Formats a frequency in MHz, with its unit.
+#[inline]
+fn format_mhz(mhz: f32) -> String {
+    format!("{:.0}MHz", mhz)
+}
+
+/// Formats the frequency of each core, or their average, with `format`.
+#[inline]
+fn format_frequencies(cpu_freqs: &[f32], per_core: bool, format: fn(f32) -> String) -> String {
+    if cpu_freqs.is_empty() {
+        "N/A".to_string()
+    } else if per_core {
         cpu_freqs
             .iter()
-            .take(count)
-            .map(|v| format!("{0:.1}GHz", v / 1000.0))
+            .map(|&v| format(v))
             .collect::<Vec<String>>()
             .join(" ")
     } else {
-        let avg = cpu_freqs.iter().take(count).sum::<f32>() / (count as f32) / 1000.0;
-        format!("{:.1}", avg)
+        format(cpu_freqs.iter().sum::<f32>() / (cpu_freqs.len() as f32))
+    }
+}
+
+#[inline]
+fn format_max_frequency(cpu_freqs: &[f32]) -> String {
+    if cpu_freqs.is_empty() {
+        "N/A".to_string()
+    } else {
+        format_ghz(cpu_freqs.iter().cloned().fold(0.0, f32::max))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cpu_line() {
+        let (core, sample) = parse_cpu_line("cpu  4705 356 584 3699176 23060 0 277 0 0 0").unwrap();
+        assert_eq!(core, None);
+        assert_eq!(
+            sample,
+            CpuSample {
+                idle: 3699176 + 23060,
+                non_idle: 4705 + 356 + 584 + 277,
+            }
+        );
+
+        let (core, _) = parse_cpu_line("cpu12 1393 280 290 1068520 2676 0 95 0 0 0").unwrap();
+        assert_eq!(core, Some(12));
+
+        assert!(parse_cpu_line("intr 1465033 19 0 0").is_none());
+        assert!(parse_cpu_line("cpufreq 1 2").is_none());
+    }
+
+    #[test]
+    fn test_format_frequency() {
+        let freqs = [1200.0, 3900.0];
+        assert_eq!(format_frequencies(&freqs, false, format_ghz), "2.5GHz");
+        assert_eq!(
+            format_frequencies(&freqs, true, format_ghz),
+            "1.2GHz 3.9GHz"
+        );
+        assert_eq!(format_frequencies(&freqs, false, format_mhz), "2550MHz");
+        assert_eq!(format_max_frequency(&freqs), "3.9GHz");
+        assert_eq!(format_frequencies(&[], false, format_ghz), "N/A");
+        assert_eq!(format_frequencies(&[], true, format_mhz), "N/A");
+        assert_eq!(format_max_frequency(&[]), "N/A");
+    }
+
+    #[test]
+    fn test_utilization_since() {
+        let prev = CpuSample {
+            idle: 100,
+            non_idle: 100,
+        };
+        let next = CpuSample {
+            idle: 150,
+            non_idle: 250,
+        };
+        assert!((next.utilization_since(&prev) - 0.75).abs() < f64::EPSILON);
+        // Counters going backwards, e.g. after hibernation
+        assert!(prev.utilization_since(&next).abs() < f64::EPSILON);
     }
 }
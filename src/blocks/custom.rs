@@ -1,21 +1,24 @@
 use std::collections::BTreeMap;
 use std::env;
-use std::iter::{Cycle, Peekable};
-use std::process::Command;
+use std::io::{self, BufRead, BufReader};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
 use std::time::{Duration, Instant};
-use std::vec;
 
 use crossbeam_channel::Sender;
+use nix::sys::signal::{kill, Signal};
+use nix::unistd::Pid;
 use serde_derive::Deserialize;
 
 use crate::blocks::{Block, ConfigBlock, Update};
 use crate::config::Config;
 use crate::de::deserialize_update;
 use crate::errors::*;
-use crate::input::I3BarEvent;
+use crate::input::{I3BarEvent, MouseButton};
 use crate::scheduler::Task;
-use crate::signals::convert_to_valid_signal;
-use crate::subprocess::spawn_child_async;
+use crate::subprocess::die_with_parent;
 use crate::util::pseudo_uuid;
 use crate::widget::{I3BarWidget, State};
 use crate::widgets::button::ButtonWidget;
@@ -26,15 +29,43 @@ pub struct Custom {
     output: ButtonWidget,
     command: Option<String>,
     on_click: Option<String>,
-    cycle: Option<Peekable<Cycle<vec::IntoIter<String>>>>,
-    signal: Option<i32>,
+    cycle: Option<Vec<String>>,
+    /// The command of `cycle` to run
+    cycle_index: usize,
     tx_update_request: Sender<Task>,
     pub json: bool,
     hide_when_empty: bool,
     is_empty: bool,
     shell: String,
+    /// The last line printed by a persistent command
+    last_line: Option<Arc<Mutex<String>>>,
+    persistent: Option<Arc<Persistent>>,
 }
 
+/// The state of a persistent command, shared with the thread running it
+#[derive(Default)]
+struct Persistent {
+    /// The running command, which is killed on shutdown
+    child: Mutex<Option<Child>>,
+    /// Set once the block is shut down, or the bar is gone
+    stopped: AtomicBool,
+    /// Set while the bar is paused, during which the command is stopped
+    paused: AtomicBool,
+}
+
+/// Stops a persistent command while the bar is paused, or continues it.
+fn pause_child(child: &Child, paused: bool) {
+    let signal = if paused {
+        Signal::SIGSTOP
+    } else {
+        Signal::SIGCONT
+    };
+    let _ = kill(Pid::from_raw(child.id() as i32), signal);
+}
+
+/// The longest time to wait before restarting a persistent command
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(60);
+
 #[derive(Deserialize, Debug, Default, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct CustomConfig {
@@ -51,9 +82,6 @@ pub struct CustomConfig {
     /// Commands to execute and change when the button is clicked
     pub cycle: Option<Vec<String>>,
 
-    /// Signal to update upon reception
-    pub signal: Option<i32>,
-
     /// Parse command output if it contains valid bar JSON
     #[serde(default = "CustomConfig::default_json")]
     pub json: bool,
@@ -63,6 +91,10 @@ pub struct CustomConfig {
 
     pub shell: Option<String>,
 
+    /// Run the command once, and update the block with every line it prints
+    #[serde(default = "CustomConfig::default_persistent")]
+    pub persistent: bool,
+
     #[serde(default = "CustomConfig::default_color_overrides")]
     pub color_overrides: Option<BTreeMap<String, String>>,
 }
@@ -80,6 +112,10 @@ impl CustomConfig {
         false
     }
 
+    fn default_persistent() -> bool {
+        false
+    }
+
     fn default_color_overrides() -> Option<BTreeMap<String, String>> {
         None
     }
@@ -96,7 +132,7 @@ impl ConfigBlock for Custom {
             command: None,
             on_click: None,
             cycle: None,
-            signal: None,
+            cycle_index: 0,
             tx_update_request: tx,
             json: block_config.json,
             hide_when_empty: block_config.hide_when_empty,
@@ -106,14 +142,11 @@ impl ConfigBlock for Custom {
             } else {
                 env::var("SHELL").unwrap_or_else(|_| "sh".to_owned())
             },
+            last_line: None,
+            persistent: None,
         };
         custom.output = ButtonWidget::new(config, &custom.id);
 
-        if let Some(signal) = block_config.signal {
-            // If the signal is not in the valid range we return an error
-            custom.signal = Some(convert_to_valid_signal(signal)?);
-        };
-
         if block_config.cycle.is_some() && block_config.command.is_some() {
             return Err(BlockError(
                 "custom".to_string(),
@@ -121,8 +154,29 @@ impl ConfigBlock for Custom {
             ));
         }
 
+        if block_config.persistent {
+            let command = block_config.command.block_error(
+                "custom",
+                "`persistent` requires a `command`, and can't be used with `cycle`",
+            )?;
+            let last_line = Arc::new(Mutex::new(String::new()));
+            let persistent = Arc::new(Persistent::default());
+            run_persistent(
+                custom.shell.clone(),
+                command.clone(),
+                custom.id.clone(),
+                custom.tx_update_request.clone(),
+                last_line.clone(),
+                persistent.clone(),
+            );
+            custom.command = Some(command);
+            custom.last_line = Some(last_line);
+            custom.persistent = Some(persistent);
+            return Ok(custom);
+        }
+
         if let Some(cycle) = block_config.cycle {
-            custom.cycle = Some(cycle.into_iter().cycle().peekable());
+            custom.cycle = Some(cycle);
             return Ok(custom);
         };
 
@@ -142,8 +196,8 @@ fn default_icon() -> String {
     String::from("")
 }
 
-fn default_state() -> State {
-    State::Idle
+fn default_state() -> String {
+    String::from("Idle")
 }
 
 #[derive(Deserialize)]
@@ -151,42 +205,202 @@ struct Output {
     #[serde(default = "default_icon")]
     icon: String,
     #[serde(default = "default_state")]
-    state: State,
+    state: String,
     text: String,
+    short_text: Option<String>,
+}
+
+/// Runs the command in the background, and requests an update for every line it prints. The
+/// command is restarted when it exits, waiting longer each time it exits quickly, until the
+/// block is shut down.
+fn run_persistent(
+    shell: String,
+    command: String,
+    id: String,
+    tx_update_request: Sender<Task>,
+    last_line: Arc<Mutex<String>>,
+    persistent: Arc<Persistent>,
+) {
+    thread::Builder::new()
+        .name("custom".into())
+        .spawn(move || {
+            let mut restart_delay = Duration::from_secs(1);
+            loop {
+                let started = Instant::now();
+                let child = die_with_parent(
+                    Command::new(&shell)
+                        .args(&["-c", &command])
+                        .stdin(Stdio::null())
+                        .stdout(Stdio::piped()),
+                )
+                .spawn();
+                match child {
+                    Ok(mut child) => {
+                        let stdout = child.stdout.take().expect("stdout is piped");
+                        {
+                            // The child is killed on shutdown, which ends its output
+                            let mut running =
+                                persistent.child.lock().expect("failed to acquire lock");
+                            if persistent.stopped.load(Ordering::SeqCst) {
+                                let _ = child.kill();
+                                let _ = child.wait();
+                                return;
+                            }
+                            if persistent.paused.load(Ordering::SeqCst) {
+                                pause_child(&child, true);
+                            }
+                            *running = Some(child);
+                        }
+                        for line in BufReader::new(stdout).lines() {
+                            let line = match line {
+                                Ok(line) => line,
+                                Err(_) => break,
+                            };
+                            if let Ok(mut last_line) = last_line.lock() {
+                                *last_line = line;
+                            }
+                            let sent = tx_update_request.send(Task {
+                                id: id.clone(),
+                                update_time: Instant::now(),
+                            });
+                            if sent.is_err() {
+                                // The bar is gone
+                                persistent.stopped.store(true, Ordering::SeqCst);
+                                break;
+                            }
+                        }
+                        let running = persistent
+                            .child
+                            .lock()
+                            .expect("failed to acquire lock")
+                            .take();
+                        if let Some(mut child) = running {
+                            let _ = child.kill();
+                            let _ = child.wait();
+                        }
+                    }
+                    Err(e) => eprintln!("custom: failed to run `{}`: {}", command, e),
+                }
+
+                if started.elapsed() > MAX_RESTART_DELAY {
+                    restart_delay = Duration::from_secs(1);
+                }
+                if persistent.stopped.load(Ordering::SeqCst) {
+                    return;
+                }
+                thread::sleep(restart_delay);
+                if persistent.stopped.load(Ordering::SeqCst) {
+                    return;
+                }
+                restart_delay = (restart_delay * 2).min(MAX_RESTART_DELAY);
+            }
+        })
+        .expect("failed to start thread for persistent custom command");
+}
+
+/// Runs the `on_click` command with the details of the click in its environment, like i3blocks
+/// does, and requests an update once it is done so that its changes are shown.
+fn run_on_click(
+    shell: &str,
+    on_click: &str,
+    event: &I3BarEvent,
+    id: String,
+    tx_update_request: Sender<Task>,
+) -> io::Result<()> {
+    let mut child = Command::new(shell)
+        .args(&["-c", on_click])
+        .env("BLOCK_BUTTON", event.button.number().to_string())
+        .env("BLOCK_X", event.x.to_string())
+        .env("BLOCK_Y", event.y.to_string())
+        .env("BLOCK_NAME", event.name.clone().unwrap_or_default())
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .spawn()?;
+    thread::Builder::new()
+        .name("subprocess".into())
+        .spawn(move || {
+            let _ = child.wait();
+            let _ = tx_update_request.send(Task {
+                id,
+                update_time: Instant::now(),
+            });
+        })?;
+    Ok(())
+}
+
+impl Custom {
+    /// Shows the output of the command, parsed as JSON in `json` mode.
+    fn set_output(&mut self, raw_output: String, command_str: &str) {
+        let output = if self.json {
+            match serde_json::from_str::<Output>(&*raw_output) {
+                Ok(output) => Some(output),
+                Err(e) => {
+                    // Show the output as it is, so that a broken script doesn't stop the bar
+                    eprintln!(
+                        "custom: error parsing JSON output of `{}`: {}",
+                        command_str, e
+                    );
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        match output {
+            Some(output) => {
+                self.output.set_icon(&output.icon);
+                // Unknown states are shown as Idle
+                self.output
+                    .set_state(output.state.parse().unwrap_or(State::Idle));
+                self.output.set_short_text(output.short_text);
+                self.is_empty = output.text.is_empty();
+                self.output.set_text(output.text);
+            }
+            None => {
+                self.output.set_icon("");
+                self.output.set_state(State::Idle);
+                self.output.set_short_text(None);
+                self.is_empty = raw_output.is_empty();
+                self.output.set_text(raw_output);
+            }
+        }
+    }
 }
 
 impl Block for Custom {
     fn update(&mut self) -> Result<Option<Update>> {
+        if let Some(ref last_line) = self.last_line {
+            let raw_output = last_line
+                .lock()
+                .block_error("custom", "failed to acquire lock")?
+                .trim()
+                .to_owned();
+            let command_str = self.command.clone().unwrap_or_default();
+            self.set_output(raw_output, &command_str);
+            // Updated whenever the command prints a line
+            return Ok(None);
+        }
+
         let command_str = self
             .cycle
-            .as_mut()
-            .map(|c| c.peek().cloned().unwrap_or_else(|| "".to_owned()))
+            .as_ref()
+            .map(|cycle| cycle.get(self.cycle_index).cloned().unwrap_or_default())
             .or_else(|| self.command.clone())
             .unwrap_or_else(|| "".to_owned());
 
-        let raw_output = Command::new(&self.shell)
+        match Command::new(&self.shell)
             .args(&["-c", &command_str])
             .output()
-            .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_owned())
-            .unwrap_or_else(|e| e.to_string());
-
-        if self.json {
-            let output: Output = match serde_json::from_str(&*raw_output) {
-                Err(e) => {
-                    return Err(BlockError(
-                        "custom".to_string(),
-                        format!("Error parsing JSON: {}", e),
-                    ));
-                }
-                Ok(s) => s,
-            };
-            self.output.set_icon(&output.icon);
-            self.output.set_state(output.state);
-            self.is_empty = output.text.is_empty();
-            self.output.set_text(output.text);
-        } else {
-            self.is_empty = raw_output.is_empty();
-            self.output.set_text(raw_output);
+        {
+            Ok(output) => self.set_output(
+                String::from_utf8_lossy(&output.stdout).trim().to_owned(),
+                &command_str,
+            ),
+            Err(e) => {
+                self.set_output(e.to_string(), &command_str);
+                self.output.set_state(State::Critical);
+            }
         }
 
         Ok(Some(self.update_interval.clone()))
@@ -200,18 +414,6 @@ impl Block for Custom {
         }
     }
 
-    fn signal(&mut self, signal: i32) -> Result<()> {
-        if let Some(sig) = self.signal {
-            if sig == signal {
-                self.tx_update_request.send(Task {
-                    id: self.id.clone(),
-                    update_time: Instant::now(),
-                })?;
-            }
-        }
-        Ok(())
-    }
-
     fn click(&mut self, event: &I3BarEvent) -> Result<()> {
         if let Some(ref name) = event.name {
             if name != &self.id {
@@ -224,12 +426,22 @@ impl Block for Custom {
         let mut update = false;
 
         if let Some(ref on_click) = self.on_click {
-            spawn_child_async(&self.shell, &["-c", on_click]).ok();
-            update = true;
+            // The update is requested once the command exits
+            let spawned = run_on_click(
+                &self.shell,
+                on_click,
+                event,
+                self.id.clone(),
+                self.tx_update_request.clone(),
+            );
+            update = spawned.is_err();
         }
 
-        if let Some(ref mut cycle) = self.cycle {
-            cycle.next();
+        if let Some(ref cycle) = self.cycle {
+            // Middle click runs the current command again
+            if event.button != MouseButton::Middle && !cycle.is_empty() {
+                self.cycle_index = (self.cycle_index + 1) % cycle.len();
+            }
             update = true;
         }
 
@@ -243,6 +455,28 @@ impl Block for Custom {
         Ok(())
     }
 
+    fn shutdown(&mut self) {
+        if let Some(ref persistent) = self.persistent {
+            persistent.stopped.store(true, Ordering::SeqCst);
+            if let Ok(mut running) = persistent.child.lock() {
+                if let Some(ref mut child) = *running {
+                    let _ = child.kill();
+                }
+            }
+        }
+    }
+
+    fn set_paused(&mut self, paused: bool) {
+        if let Some(ref persistent) = self.persistent {
+            if let Ok(running) = persistent.child.lock() {
+                persistent.paused.store(paused, Ordering::SeqCst);
+                if let Some(ref child) = *running {
+                    pause_child(child, paused);
+                }
+            }
+        }
+    }
+
     fn id(&self) -> &str {
         &self.id
     }
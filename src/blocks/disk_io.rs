@@ -0,0 +1,264 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::Sender;
+use serde_derive::Deserialize;
+
+use crate::blocks::{Block, ConfigBlock, Update};
+use crate::config::Config;
+use crate::de::{deserialize_duration, deserialize_opt_string_list};
+use crate::errors::*;
+use crate::scheduler::Task;
+use crate::util::{format_throughput, pseudo_uuid, read_file, FormatTemplate};
+use crate::widget::{I3BarWidget, State};
+use crate::widgets::text::TextWidget;
+
+/// The size of a sector in /proc/diskstats, regardless of the device.
+const SECTOR_SIZE: u64 = 512;
+
+/// The number of sectors read and written by a device, as read from /proc/diskstats.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+struct DiskStats {
+    sectors_read: u64,
+    sectors_written: u64,
+}
+
+/// Parses a line of /proc/diskstats into the device name and its counters.
+fn parse_diskstats_line(line: &str) -> Option<(&str, DiskStats)> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    Some((
+        fields.get(2)?,
+        DiskStats {
+            sectors_read: fields.get(5)?.parse().ok()?,
+            sectors_written: fields.get(9)?.parse().ok()?,
+        },
+    ))
+}
+
+/// Whether the device is a whole physical disk. Partitions aren't listed in
+/// /sys/block, and loop, ram, device mapper etc. devices live in /sys/devices/virtual.
+fn is_physical_disk(name: &str) -> bool {
+    match fs::canonicalize(Path::new("/sys/block").join(name)) {
+        Ok(path) => !path.to_string_lossy().contains("/devices/virtual/"),
+        Err(_) => false,
+    }
+}
+
+/// Sums up the counters of the given devices, or of all physical disks if none
+/// are given. Returns `None` if none of the devices could be found.
+fn read_diskstats(devices: Option<&[String]>) -> Result<Option<DiskStats>> {
+    let diskstats = read_file("disk_io", Path::new("/proc/diskstats"))?;
+    let mut total: Option<DiskStats> = None;
+    for (name, stats) in diskstats.lines().filter_map(parse_diskstats_line) {
+        let selected = match devices {
+            Some(devices) => devices.iter().any(|device| device == name),
+            None => is_physical_disk(name),
+        };
+        if selected {
+            let total = total.get_or_insert_with(DiskStats::default);
+            total.sectors_read += stats.sectors_read;
+            total.sectors_written += stats.sectors_written;
+        }
+    }
+    Ok(total)
+}
+
+/// The difference between two readings of a counter. The kernel's counters are
+/// `unsigned long`, so they wrap around at 32 bits on 32 bit systems.
+fn counter_delta(prev: u64, current: u64) -> u64 {
+    if current >= prev {
+        current - prev
+    } else if prev <= u64::from(u32::MAX) {
+        u64::from(u32::MAX) - prev + current + 1
+    } else {
+        // The counters were reset, e.g. because the device was replaced
+        0
+    }
+}
+
+pub struct DiskIo {
+    text: TextWidget,
+    id: String,
+    update_interval: Duration,
+    devices: Option<Vec<String>>,
+    format: FormatTemplate,
+    warning: Option<f64>,
+    critical: Option<f64>,
+    prev: Option<(Instant, DiskStats)>,
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct DiskIoConfig {
+    /// Update interval in seconds
+    #[serde(
+        default = "DiskIoConfig::default_interval",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub interval: Duration,
+
+    /// The devices to sum up, e.g. "sda", or a list of them. All physical
+    /// disks are summed up if not set.
+    #[serde(
+        default = "DiskIoConfig::default_device",
+        deserialize_with = "deserialize_opt_string_list"
+    )]
+    pub device: Option<Vec<String>>,
+
+    /// Format override
+    #[serde(default = "DiskIoConfig::default_format")]
+    pub format: String,
+
+    /// Throughput in MiB/s, above which state is set to warning
+    #[serde(default = "DiskIoConfig::default_warning")]
+    pub warning: Option<f64>,
+
+    /// Throughput in MiB/s, above which state is set to critical
+    #[serde(default = "DiskIoConfig::default_critical")]
+    pub critical: Option<f64>,
+
+    #[serde(default = "DiskIoConfig::default_color_overrides")]
+    pub color_overrides: Option<BTreeMap<String, String>>,
+}
+
+impl DiskIoConfig {
+    fn default_interval() -> Duration {
+        Duration::from_secs(2)
+    }
+
+    fn default_device() -> Option<Vec<String>> {
+        None
+    }
+
+    fn default_format() -> String {
+        "{read} {write}".to_owned()
+    }
+
+    fn default_warning() -> Option<f64> {
+        None
+    }
+
+    fn default_critical() -> Option<f64> {
+        None
+    }
+
+    fn default_color_overrides() -> Option<BTreeMap<String, String>> {
+        None
+    }
+}
+
+impl ConfigBlock for DiskIo {
+    type Config = DiskIoConfig;
+
+    fn new(
+        block_config: Self::Config,
+        config: Config,
+        _tx_update_request: Sender<Task>,
+    ) -> Result<Self> {
+        let id = pseudo_uuid();
+        let text = TextWidget::new(config, &id).with_icon("disk_drive");
+
+        Ok(DiskIo {
+            id,
+            update_interval: block_config.interval,
+            text,
+            devices: block_config.device,
+            format: FormatTemplate::from_string(&block_config.format)
+                .block_error("disk_io", "Invalid format specified for disk_io")?,
+            warning: block_config.warning,
+            critical: block_config.critical,
+            prev: None,
+        })
+    }
+}
+
+impl Block for DiskIo {
+    fn update(&mut self) -> Result<Option<Update>> {
+        let now = Instant::now();
+        let current = read_diskstats(self.devices.as_deref())?;
+
+        // The throughput is unknown on the first update, or while the devices are missing
+        let throughput = match (self.prev, current) {
+            (Some((prev_time, prev)), Some(current)) => {
+                let elapsed = now.duration_since(prev_time).as_secs_f64();
+                if elapsed > 0. {
+                    let read = counter_delta(prev.sectors_read, current.sectors_read);
+                    let written = counter_delta(prev.sectors_written, current.sectors_written);
+                    Some((
+                        (read * SECTOR_SIZE) as f64 / elapsed,
+                        (written * SECTOR_SIZE) as f64 / elapsed,
+                    ))
+                } else {
+                    Some((0., 0.))
+                }
+            }
+            (None, Some(_)) => Some((0., 0.)),
+            _ => None,
+        };
+        self.prev = current.map(|current| (now, current));
+
+        let (read, write) = match throughput {
+            Some((read, write)) => {
+                let max = read.max(write) / 1024. / 1024.;
+                self.text.set_state(match (self.warning, self.critical) {
+                    (_, Some(critical)) if max > critical => State::Critical,
+                    (Some(warning), _) if max > warning => State::Warning,
+                    _ => State::Idle,
+                });
+                (
+                    format_throughput(read, false),
+                    format_throughput(write, false),
+                )
+            }
+            None => {
+                self.text.set_state(State::Idle);
+                ("N/A".to_string(), "N/A".to_string())
+            }
+        };
+
+        let values = map!("{read}" => read,
+                          "{write}" => write);
+        self.text.set_text(self.format.render_static_str(&values)?);
+
+        Ok(Some(self.update_interval.into()))
+    }
+
+    fn view(&self) -> Vec<&dyn I3BarWidget> {
+        vec![&self.text]
+    }
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_diskstats_line() {
+        let line =
+            "   8       0 sda 31473 9293 2600368 11076 22507 31045 1887712 43044 0 34744 58520";
+        assert_eq!(
+            parse_diskstats_line(line),
+            Some((
+                "sda",
+                DiskStats {
+                    sectors_read: 2600368,
+                    sectors_written: 1887712,
+                }
+            ))
+        );
+        assert_eq!(parse_diskstats_line("   8       0 sda 31473"), None);
+    }
+
+    #[test]
+    fn test_counter_delta() {
+        assert_eq!(counter_delta(100, 150), 50);
+        assert_eq!(counter_delta(u64::from(u32::MAX) - 9, 10), 20);
+        assert_eq!(counter_delta(u64::from(u32::MAX) + 100, 10), 0);
+    }
+}
@@ -1,6 +1,7 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::read_to_string;
 use std::path::Path;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use crossbeam_channel::Sender;
 use nix::sys::statvfs::statvfs;
@@ -10,8 +11,10 @@ use crate::blocks::{Block, ConfigBlock, Update};
 use crate::config::Config;
 use crate::de::deserialize_duration;
 use crate::errors::*;
+use crate::input::{I3BarEvent, MouseButton};
 use crate::scheduler::Task;
-use crate::util::{format_percent_bar, pseudo_uuid, FormatTemplate};
+use crate::subprocess::spawn_child_async;
+use crate::util::{escape_shell_argument, format_percent_bar, pseudo_uuid, FormatTemplate};
 use crate::widget::{I3BarWidget, State};
 use crate::widgets::text::TextWidget;
 
@@ -49,21 +52,38 @@ pub enum InfoType {
     Used,
 }
 
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertOn {
+    Space,
+    Inodes,
+    Both,
+}
+
 pub struct DiskSpace {
     disk_space: TextWidget,
     id: String,
     update_interval: Duration,
     alias: String,
     path: String,
+    paths: Option<Vec<String>>,
+    path_format: FormatTemplate,
+    separator: String,
     unit: Unit,
     info_type: InfoType,
     warning: f64,
     alert: f64,
+    /// The levels for inodes, which are always percentages
+    inodes_warning: f64,
+    inodes_alert: f64,
     show_percentage: bool,
     show_bar: bool,
     alert_absolute: bool,
+    alert_on: AlertOn,
     format: FormatTemplate,
     icon: String,
+    on_click: Option<String>,
+    tx_update_request: Sender<Task>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -77,6 +97,18 @@ pub struct DiskSpaceConfig {
     #[serde(default = "DiskSpaceConfig::default_alias")]
     pub alias: String,
 
+    /// Several paths to show in one block, each rendered with path_format
+    #[serde(default = "DiskSpaceConfig::default_paths")]
+    pub paths: Option<Vec<String>>,
+
+    /// Format string for each of the paths
+    #[serde(default = "DiskSpaceConfig::default_path_format")]
+    pub path_format: String,
+
+    /// Separator between the paths
+    #[serde(default = "DiskSpaceConfig::default_separator")]
+    pub separator: String,
+
     /// Currently supported options are available, free, total and used
     /// Sets value used for {percentage} calculation
     /// total is the same as used, use format to set format string for output
@@ -120,6 +152,18 @@ pub struct DiskSpaceConfig {
     #[serde(default = "DiskSpaceConfig::default_alert_absolute")]
     pub alert_absolute: bool,
 
+    /// Whether warning and alert apply to disk space, inodes or whichever is worse
+    #[serde(default = "DiskSpaceConfig::default_alert_on")]
+    pub alert_on: AlertOn,
+
+    /// Inode warning level as a percentage, `warning` unless that is absolute
+    #[serde(default = "DiskSpaceConfig::default_inodes_warning")]
+    pub inodes_warning: Option<f64>,
+
+    /// Inode alert level as a percentage, `alert` unless that is absolute
+    #[serde(default = "DiskSpaceConfig::default_inodes_alert")]
+    pub inodes_alert: Option<f64>,
+
     #[serde(default = "DiskSpaceConfig::default_color_overrides")]
     pub color_overrides: Option<BTreeMap<String, String>>,
 }
@@ -133,6 +177,18 @@ impl DiskSpaceConfig {
         "/".to_owned()
     }
 
+    fn default_paths() -> Option<Vec<String>> {
+        None
+    }
+
+    fn default_path_format() -> String {
+        "{path}:{available}".to_owned()
+    }
+
+    fn default_separator() -> String {
+        " ".to_owned()
+    }
+
     fn default_info_type() -> InfoType {
         InfoType::Available
     }
@@ -171,17 +227,182 @@ impl DiskSpaceConfig {
         false
     }
 
+    fn default_alert_on() -> AlertOn {
+        AlertOn::Space
+    }
+
+    fn default_inodes_warning() -> Option<f64> {
+        None
+    }
+
+    fn default_inodes_alert() -> Option<f64> {
+        None
+    }
+
     fn default_color_overrides() -> Option<BTreeMap<String, String>> {
         None
     }
 }
 
+#[derive(Copy, Clone)]
 enum AlertType {
     Above,
     Below,
 }
 
 impl DiskSpace {
+    /// The placeholder values and the state for a single path, along with the
+    /// value the state was computed from and its warning and alert levels.
+    /// Paths that cannot be queried, e.g. because a drive was detached, are
+    /// shown as `--`, as are mount points in fstab with nothing mounted.
+    fn path_values(
+        &self,
+        path: &str,
+        alias: &str,
+        mounts: &str,
+        fstab: &str,
+    ) -> (
+        HashMap<&'static str, String>,
+        State,
+        Option<(f64, f64, f64)>,
+    ) {
+        let mut values = map!("{alias}" => alias.to_string(),
+        "{path}" => path.to_string(),
+        "{unit}" => format!("{:?}", self.unit),
+        "{icon}" => self.icon.to_string(),
+        "{fstype}" => filesystem_type(mounts, path).unwrap_or_else(|| "--".to_string()));
+
+        // The directory of an unmounted drive would report the numbers of the
+        // filesystem it is on
+        let unmounted = is_mount_point(fstab, path) && !is_mount_point(mounts, path);
+        let statvfs = match statvfs(Path::new(path)) {
+            Ok(statvfs) if !unmounted => statvfs,
+            _ => {
+                for key in &[
+                    "{percentage}",
+                    "{bar}",
+                    "{total}",
+                    "{used}",
+                    "{available}",
+                    "{free}",
+                    "{iused}",
+                    "{ifree}",
+                    "{iused_percent}",
+                    "{result}",
+                ] {
+                    values.insert(key, "--".to_string());
+                }
+                return (values, State::Idle, None);
+            }
+        };
+
+        let total = (statvfs.blocks() as u64) * (statvfs.fragment_size() as u64);
+        let used = ((statvfs.blocks() as u64) - (statvfs.blocks_free() as u64))
+            * (statvfs.fragment_size() as u64);
+        let available = (statvfs.blocks_available() as u64) * (statvfs.block_size() as u64);
+        let free = (statvfs.blocks_free() as u64) * (statvfs.block_size() as u64);
+
+        // Some filesystems, e.g. btrfs, allocate inodes dynamically and report none at all
+        let inodes_total = statvfs.files() as u64;
+        let inodes_free = statvfs.files_free() as u64;
+        let inodes_used = inodes_total - inodes_free;
+        let inodes_used_percentage = if inodes_total > 0 {
+            Some(inodes_used as f64 / inodes_total as f64 * 100.)
+        } else {
+            None
+        };
+
+        let result = match self.info_type {
+            InfoType::Available => available,
+            InfoType::Free => free,
+            InfoType::Total | InfoType::Used => used,
+        };
+        let alert_type = self.alert_type();
+
+        let percentage = (result as f32) / (total as f32) * 100f32;
+
+        values.insert("{percentage}", format!("{:.2}%", percentage));
+        values.insert("{bar}", format_percent_bar(percentage));
+        values.insert(
+            "{total}",
+            format!("{:.2}", Unit::bytes_in_unit(self.unit, total)),
+        );
+        values.insert(
+            "{used}",
+            format!("{:.2}", Unit::bytes_in_unit(self.unit, used)),
+        );
+        values.insert(
+            "{available}",
+            format!("{:.2}", Unit::bytes_in_unit(self.unit, available)),
+        );
+        values.insert(
+            "{free}",
+            format!("{:.2}", Unit::bytes_in_unit(self.unit, free)),
+        );
+        values.insert("{iused}", inodes_used.to_string());
+        values.insert("{ifree}", inodes_free.to_string());
+        values.insert(
+            "{iused_percent}",
+            format!("{:.2}%", inodes_used_percentage.unwrap_or(0.)),
+        );
+        values.insert("{result}", format!("{:.2}", result));
+
+        // Send percentage to alert check if we don't want absolute alerts
+        let alert_val = if !self.alert_absolute {
+            percentage as f64
+        } else {
+            Unit::bytes_in_unit(self.unit, result)
+        };
+
+        // Inodes are always compared as a percentage, of the used or the free
+        // inodes depending on info_type
+        let inode_val = inodes_used_percentage.map(|used| match alert_type {
+            AlertType::Above => used,
+            AlertType::Below => 100. - used,
+        });
+
+        let space_state = self.compute_state(alert_val, self.warning, self.alert, alert_type);
+        let inode_state = inode_val
+            .map(|val| self.compute_state(val, self.inodes_warning, self.inodes_alert, alert_type));
+
+        let space = (alert_val, self.warning, self.alert);
+        let (state, levels) = match (self.alert_on, inode_state, inode_val) {
+            (AlertOn::Inodes, Some(inode_state), Some(inode_val)) => (
+                inode_state,
+                (inode_val, self.inodes_warning, self.inodes_alert),
+            ),
+            (AlertOn::Both, Some(inode_state), Some(inode_val))
+                if severity(inode_state) > severity(space_state) =>
+            {
+                (
+                    inode_state,
+                    (inode_val, self.inodes_warning, self.inodes_alert),
+                )
+            }
+            _ => (space_state, space),
+        };
+
+        (values, state, Some(levels))
+    }
+
+    fn alert_type(&self) -> AlertType {
+        match self.info_type {
+            InfoType::Available | InfoType::Free => AlertType::Below,
+            InfoType::Total | InfoType::Used => AlertType::Above,
+        }
+    }
+
+    /// The values at which the colors of each state are reached when `gradient`
+    /// is set. Good is as far from `warning` as `alert` is, on the other side.
+    fn gradient_stops(warning: f64, alert: f64) -> [(f64, State); 3] {
+        let good = 2. * warning - alert;
+        [
+            (good, State::Good),
+            (warning, State::Warning),
+            (alert, State::Critical),
+        ]
+    }
+
     fn compute_state(&self, value: f64, warning: f64, alert: f64, alert_type: AlertType) -> State {
         match alert_type {
             AlertType::Above => {
@@ -206,13 +427,50 @@ impl DiskSpace {
     }
 }
 
+/// Whether a path is the mount point of an entry of a mount table, in the
+/// format of /proc/mounts or /etc/fstab.
+fn is_mount_point(table: &str, path: &str) -> bool {
+    table
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .filter_map(|line| line.split_whitespace().nth(1))
+        .any(|mount_point| Path::new(&mount_point.replace("\\040", " ")) == Path::new(path))
+}
+
+/// Looks up the type of the filesystem a path is on, given the contents of /proc/mounts.
+fn filesystem_type(mounts: &str, path: &str) -> Option<String> {
+    mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let mount_point = fields.nth(1)?.replace("\\040", " ");
+            let fstype = fields.next()?;
+            if Path::new(path).starts_with(&mount_point) {
+                Some((mount_point.len(), fstype))
+            } else {
+                None
+            }
+        })
+        // Mounts listed later shadow earlier ones at the same mount point
+        .max_by_key(|&(len, _)| len)
+        .map(|(_, fstype)| fstype.to_string())
+}
+
+fn severity(state: State) -> u8 {
+    match state {
+        State::Critical => 2,
+        State::Warning => 1,
+        _ => 0,
+    }
+}
+
 impl ConfigBlock for DiskSpace {
     type Config = DiskSpaceConfig;
 
     fn new(
         block_config: Self::Config,
         config: Config,
-        _tx_update_request: Sender<Task>,
+        tx_update_request: Sender<Task>,
     ) -> Result<Self> {
         let icon = config
             .icons
@@ -220,6 +478,17 @@ impl ConfigBlock for DiskSpace {
             .cloned()
             .unwrap_or_else(|| "".to_string());
 
+        // Absolute levels can't apply to inodes, so they use the default
+        // percentages instead
+        let (inodes_warning, inodes_alert) = if block_config.alert_absolute {
+            (
+                DiskSpaceConfig::default_warning(),
+                DiskSpaceConfig::default_alert(),
+            )
+        } else {
+            (block_config.warning, block_config.alert)
+        };
+
         let id = pseudo_uuid();
         let disk_space = TextWidget::new(config, &id);
         Ok(DiskSpace {
@@ -228,86 +497,74 @@ impl ConfigBlock for DiskSpace {
             disk_space,
             alias: block_config.alias,
             path: block_config.path,
+            paths: block_config.paths,
+            path_format: FormatTemplate::from_string(&block_config.path_format)?,
+            separator: block_config.separator,
             format: FormatTemplate::from_string(&block_config.format)?,
             info_type: block_config.info_type,
             unit: block_config.unit,
             warning: block_config.warning,
             alert: block_config.alert,
+            inodes_warning: block_config.inodes_warning.unwrap_or(inodes_warning),
+            inodes_alert: block_config.inodes_alert.unwrap_or(inodes_alert),
             show_percentage: block_config.show_percentage,
             show_bar: block_config.show_bar,
             alert_absolute: block_config.alert_absolute,
+            alert_on: block_config.alert_on,
             icon,
+            on_click: None,
+            tx_update_request,
         })
     }
+
+    fn override_on_click(&mut self) -> Option<&mut Option<String>> {
+        Some(&mut self.on_click)
+    }
 }
 
 impl Block for DiskSpace {
     fn update(&mut self) -> Result<Option<Update>> {
-        let statvfs = statvfs(Path::new(self.path.as_str()))
-            .block_error("disk_space", "failed to retrieve statvfs")?;
-
-        let result;
-        let total = (statvfs.blocks() as u64) * (statvfs.fragment_size() as u64);
-        let used = ((statvfs.blocks() as u64) - (statvfs.blocks_free() as u64))
-            * (statvfs.fragment_size() as u64);
-        let available = (statvfs.blocks_available() as u64) * (statvfs.block_size() as u64);
-        let free = (statvfs.blocks_free() as u64) * (statvfs.block_size() as u64);
-
-        let alert_type;
-        match self.info_type {
-            InfoType::Available => {
-                result = available;
-                alert_type = AlertType::Below;
-            }
-            InfoType::Free => {
-                result = free;
-                alert_type = AlertType::Below;
-            }
-            InfoType::Total => {
-                // Deprecated: Same as Used - use format string to set output format
-                // Kept for back-compatibility
-                // Use format: "{used}/{total} {unit}" for previous format
-                result = used;
-                alert_type = AlertType::Above;
-                self.format = FormatTemplate::from_string("{used}/{total} {unit}")?;
-            }
-            InfoType::Used => {
-                result = used;
-                alert_type = AlertType::Above;
-            }
+        if let InfoType::Total = self.info_type {
+            // Deprecated: Same as Used - use format string to set output format
+            // Kept for back-compatibility
+            // Use format: "{used}/{total} {unit}" for previous format
+            self.format = FormatTemplate::from_string("{used}/{total} {unit}")?;
         }
-
-        let percentage = (result as f32) / (total as f32) * 100f32;
         if self.show_percentage {
             self.format = FormatTemplate::from_string("{alias} {result} ({percentage}) {unit}")?;
         } else if self.show_bar {
             self.format = FormatTemplate::from_string("{alias} {result} {unit} {bar}")?;
         }
 
-        let values = map!("{percentage}" => format!("{:.2}%", percentage),
-        "{bar}" => format_percent_bar(percentage),
-        "{alias}" => self.alias.clone(),
-        "{unit}" => format!("{:?}", self.unit),
-        "{path}" => self.path.clone(),
-        "{total}" => format!("{:.2}", Unit::bytes_in_unit(self.unit, total)),
-        "{used}" => format!("{:.2}", Unit::bytes_in_unit(self.unit, used)),
-        "{available}" => format!("{:.2}", Unit::bytes_in_unit(self.unit, available)),
-        "{free}" => format!("{:.2}", Unit::bytes_in_unit(self.unit, free)),
-        "{icon}" => self.icon.to_string(),
-        "{result}" => format!("{:.2}", result)
-        );
-        self.disk_space
-            .set_text(self.format.render_static_str(&values)?);
-
-        // Send percentage to alert check if we don't want absolute alerts
-        let alert_val = if !self.alert_absolute {
-            percentage as f64
-        } else {
-            Unit::bytes_in_unit(self.unit, result)
+        let mounts = read_to_string("/proc/mounts").unwrap_or_default();
+        let fstab = read_to_string("/etc/fstab").unwrap_or_default();
+
+        let (text, state, alert_val) = match self.paths {
+            Some(ref paths) => {
+                let mut texts = Vec::new();
+                let mut worst = (State::Idle, None);
+                for path in paths {
+                    let (values, state, alert_val) = self.path_values(path, path, &mounts, &fstab);
+                    texts.push(self.path_format.render_static_str(&values)?);
+                    if worst.1.is_none() || severity(state) > severity(worst.0) {
+                        worst = (state, alert_val.or(worst.1));
+                    }
+                }
+                (texts.join(&self.separator), worst.0, worst.1)
+            }
+            None => {
+                let (values, state, alert_val) =
+                    self.path_values(&self.path, &self.alias, &mounts, &fstab);
+                (self.format.render_static_str(&values)?, state, alert_val)
+            }
         };
 
-        let state = self.compute_state(alert_val, self.warning, self.alert, alert_type);
+        self.disk_space.set_text(text);
         self.disk_space.set_state(state);
+        if let Some((alert_val, warning, alert)) = alert_val {
+            self.disk_space
+                .set_gradient(alert_val, &DiskSpace::gradient_stops(warning, alert));
+        }
 
         Ok(Some(self.update_interval.into()))
     }
@@ -316,7 +573,73 @@ impl Block for DiskSpace {
         vec![&self.disk_space]
     }
 
+    fn click(&mut self, event: &I3BarEvent) -> Result<()> {
+        if !event.matches_name(&self.id) {
+            return Ok(());
+        }
+
+        match event.button {
+            MouseButton::Left => {
+                if let Some(ref on_click) = self.on_click {
+                    // With several paths, the first one is used
+                    let path = match self.paths {
+                        Some(ref paths) => paths.first().unwrap_or(&self.path),
+                        None => &self.path,
+                    };
+                    let cmd = on_click.replace("{path}", &escape_shell_argument(path));
+                    spawn_child_async("sh", &["-c", &cmd])
+                        .block_error("disk_space", "could not spawn child")?;
+                }
+            }
+            MouseButton::Middle => {
+                self.tx_update_request.send(Task {
+                    id: self.id.clone(),
+                    update_time: Instant::now(),
+                })?;
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
     fn id(&self) -> &str {
         &self.id
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filesystem_type() {
+        let mounts = "/dev/sda2 / ext4 rw,relatime 0 0\n\
+                      tmpfs /tmp tmpfs rw,nosuid,nodev 0 0\n\
+                      /dev/sdb1 /run/media/My\\040Drive vfat rw 0 0\n";
+        assert_eq!(filesystem_type(mounts, "/"), Some("ext4".to_string()));
+        assert_eq!(filesystem_type(mounts, "/home"), Some("ext4".to_string()));
+        assert_eq!(
+            filesystem_type(mounts, "/tmp/foo"),
+            Some("tmpfs".to_string())
+        );
+        assert_eq!(filesystem_type(mounts, "/tmpfoo"), Some("ext4".to_string()));
+        assert_eq!(
+            filesystem_type(mounts, "/run/media/My Drive"),
+            Some("vfat".to_string())
+        );
+        assert_eq!(filesystem_type("", "/"), None);
+    }
+
+    #[test]
+    fn test_is_mount_point() {
+        let fstab = "# <file system> <dir> <type> <options> <dump> <pass>\n\
+                     UUID=1234 / ext4 rw,relatime 0 1\n\
+                     /dev/sdb1 /mnt/My\\040Drive vfat noauto 0 0\n";
+        assert!(is_mount_point(fstab, "/"));
+        assert!(is_mount_point(fstab, "/mnt/My Drive/"));
+        assert!(!is_mount_point(fstab, "/mnt"));
+        assert!(!is_mount_point(fstab, "<dir>"));
+        assert!(!is_mount_point("", "/"));
+    }
+}
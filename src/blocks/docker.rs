@@ -1,5 +1,10 @@
 use std::collections::BTreeMap;
-use std::time::Duration;
+use std::io::ErrorKind;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use crossbeam_channel::Sender;
 use serde_derive::Deserialize;
@@ -12,14 +17,26 @@ use crate::http;
 use crate::input::I3BarEvent;
 use crate::scheduler::Task;
 use crate::util::{pseudo_uuid, FormatTemplate};
-use crate::widget::I3BarWidget;
+use crate::widget::{I3BarWidget, State};
 use crate::widgets::text::TextWidget;
 
 pub struct Docker {
     text: TextWidget,
     id: String,
     format: FormatTemplate,
-    update_interval: Duration,
+    engine: Arc<Mutex<Engine>>,
+}
+
+/// What is known about the container engine.
+#[derive(Debug, Clone)]
+enum Engine {
+    /// Not queried yet, or the query failed
+    Unknown,
+    /// There is no socket, or nothing listens on it
+    Down,
+    /// The user may not connect to the socket
+    PermissionDenied,
+    Up(Status),
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -54,6 +71,10 @@ pub struct DockerConfig {
     #[serde(default = "DockerConfig::default_format")]
     pub format: String,
 
+    /// The socket of the engine's API, e.g. `/run/podman/podman.sock`
+    #[serde(default = "DockerConfig::default_socket_path")]
+    pub socket_path: PathBuf,
+
     #[serde(default = "DockerConfig::default_color_overrides")]
     pub color_overrides: Option<BTreeMap<String, String>>,
 }
@@ -67,6 +88,10 @@ impl DockerConfig {
         "{running}%".to_owned()
     }
 
+    fn default_socket_path() -> PathBuf {
+        PathBuf::from("/var/run/docker.sock")
+    }
+
     fn default_color_overrides() -> Option<BTreeMap<String, String>> {
         None
     }
@@ -75,45 +100,108 @@ impl DockerConfig {
 impl ConfigBlock for Docker {
     type Config = DockerConfig;
 
-    fn new(block_config: Self::Config, config: Config, _: Sender<Task>) -> Result<Self> {
+    fn new(
+        block_config: Self::Config,
+        config: Config,
+        tx_update_request: Sender<Task>,
+    ) -> Result<Self> {
         let id = pseudo_uuid();
         let text = TextWidget::new(config, &id)
             .with_text("N/A")
             .with_icon("docker");
+        let format = FormatTemplate::from_string(&block_config.format)
+            .block_error("docker", "Invalid format specified")?;
+        let engine = Arc::new(Mutex::new(Engine::Unknown));
+
+        let thread_engine = engine.clone();
+        let thread_id = id.clone();
+        let socket_path = block_config.socket_path;
+        let interval = block_config.interval;
+        thread::Builder::new()
+            .name("docker".into())
+            .spawn(move || loop {
+                let status = query_engine(&socket_path);
+                if let Ok(mut engine) = thread_engine.lock() {
+                    *engine = status;
+                }
+                if tx_update_request
+                    .send(Task {
+                        id: thread_id.clone(),
+                        update_time: Instant::now(),
+                    })
+                    .is_err()
+                {
+                    return;
+                }
+                thread::sleep(interval);
+            })
+            .unwrap();
+
         Ok(Docker {
             id,
             text,
-            format: FormatTemplate::from_string(&block_config.format)
-                .block_error("docker", "Invalid format specified")?,
-            update_interval: block_config.interval,
+            format,
+            engine,
         })
     }
 }
 
+/// Queries the engine's API. This may block for a few seconds if the engine
+/// is unresponsive.
+fn query_engine(socket_path: &Path) -> Engine {
+    // curl can't tell a missing socket from one the user may not access
+    match UnixStream::connect(socket_path) {
+        Ok(_) => {}
+        Err(e) if e.kind() == ErrorKind::PermissionDenied => return Engine::PermissionDenied,
+        Err(_) => return Engine::Down,
+    }
+
+    http::http_get_socket_json(
+        socket_path.to_path_buf(),
+        "http:/api/info",
+        Some(Duration::from_secs(3)),
+    )
+    .ok()
+    .and_then(|output| serde_json::from_value(output.content).ok())
+    .map_or(Engine::Unknown, Engine::Up)
+}
+
 impl Block for Docker {
     fn update(&mut self) -> Result<Option<Update>> {
-        let socket_path = std::path::PathBuf::from("/var/run/docker.sock");
-        let output = http::http_get_socket_json(socket_path, "http:/api/info");
-
-        if output.is_err() {
-            self.text.set_text("N/A".to_string());
-            return Ok(Some(self.update_interval.into()));
+        let engine = self
+            .engine
+            .lock()
+            .block_error("docker", "mutex poisoned")?
+            .clone();
+
+        match engine {
+            Engine::Up(status) => {
+                let values = map!(
+                    "{total}" => format!("{}", status.total),
+                    "{running}" => format!("{}", status.running),
+                    "{paused}" => format!("{}", status.paused),
+                    "{stopped}" => format!("{}", status.stopped),
+                    "{images}" => format!("{}", status.images)
+                );
+                self.text.set_text(self.format.render_static_str(&values)?);
+                self.text.set_state(State::Idle);
+            }
+            Engine::Down => {
+                self.text.set_text("down".to_string());
+                self.text.set_state(State::Idle);
+            }
+            Engine::PermissionDenied => {
+                self.text.set_text("permission denied".to_string());
+                self.text.set_state(State::Warning);
+            }
+            Engine::Unknown => {
+                self.text.set_text("N/A".to_string());
+                self.text.set_state(State::Idle);
+            }
         }
 
-        let status: Status = serde_json::from_value(output.unwrap().content)
-            .block_error("docker", "Failed to parse JSON response.")?;
-
-        let values = map!(
-            "{total}" => format!("{}", status.total),
-            "{running}" => format!("{}", status.running),
-            "{paused}" => format!("{}", status.paused),
-            "{stopped}" => format!("{}", status.stopped),
-            "{images}" => format!("{}", status.images)
-        );
-
-        self.text.set_text(self.format.render_static_str(&values)?);
-
-        Ok(Some(self.update_interval.into()))
+        // Updates are requested by the querying thread
+        Ok(None)
     }
 
     fn view(&self) -> Vec<&dyn I3BarWidget> {
@@ -0,0 +1,282 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crossbeam_channel::Sender;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::blocks::{Block, ConfigBlock, Update};
+use crate::config::Config;
+use crate::de::deserialize_duration;
+use crate::errors::*;
+use crate::http;
+use crate::input::{I3BarEvent, MouseButton};
+use crate::scheduler::Task;
+use crate::util::{pseudo_uuid, xdg_cache_home, FormatTemplate};
+use crate::widget::{I3BarWidget, State};
+use crate::widgets::text::TextWidget;
+
+/// The result of the last request, persisted so that restarting the bar
+/// doesn't query the endpoint more often than the update interval.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+struct IpCache {
+    /// Unix time of the last request, in seconds
+    timestamp: u64,
+    /// The last address that was successfully retrieved
+    ip: Option<String>,
+    country: Option<String>,
+    /// Whether the last request failed
+    failed: bool,
+}
+
+fn read_cache(path: &Path) -> Option<IpCache> {
+    serde_json::from_str(&fs::read_to_string(path).ok()?).ok()
+}
+
+fn write_cache(path: &Path, cache: &IpCache) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).block_error("external_ip", "failed to create cache directory")?;
+    }
+    let content =
+        serde_json::to_string(cache).internal_error("external_ip", "failed to serialize cache")?;
+    fs::write(path, content).block_error("external_ip", "failed to write cache")
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Seconds to wait until the endpoint may be queried again, if the last
+/// request was less than `interval` ago.
+fn remaining_wait(last_request: u64, now: u64, interval: Duration) -> Option<u64> {
+    let elapsed = now.saturating_sub(last_request);
+    interval
+        .as_secs()
+        .checked_sub(elapsed)
+        .filter(|&wait| wait > 0)
+}
+
+pub struct ExternalIp {
+    text: TextWidget,
+    id: String,
+    update_interval: Duration,
+    url: String,
+    geoip_url: Option<String>,
+    timeout: Duration,
+    format: FormatTemplate,
+    cache_path: PathBuf,
+    cache: Option<IpCache>,
+    force_refresh: bool,
+    tx_update_request: Sender<Task>,
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ExternalIpConfig {
+    /// Update interval in seconds
+    #[serde(
+        default = "ExternalIpConfig::default_interval",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub interval: Duration,
+
+    /// Endpoint returning a JSON object with the address in its `ip` field
+    #[serde(default = "ExternalIpConfig::default_url")]
+    pub url: String,
+
+    /// Endpoint returning a JSON object with the country in its `country`
+    /// field. `{ip}` is replaced with the address.
+    #[serde(default = "ExternalIpConfig::default_geoip_url")]
+    pub geoip_url: Option<String>,
+
+    /// Timeout of each request in seconds
+    #[serde(
+        default = "ExternalIpConfig::default_timeout",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub timeout: Duration,
+
+    /// Format override
+    #[serde(default = "ExternalIpConfig::default_format")]
+    pub format: String,
+
+    #[serde(default = "ExternalIpConfig::default_color_overrides")]
+    pub color_overrides: Option<BTreeMap<String, String>>,
+}
+
+impl ExternalIpConfig {
+    fn default_interval() -> Duration {
+        Duration::from_secs(300)
+    }
+
+    fn default_url() -> String {
+        "https://api.ipify.org?format=json".to_owned()
+    }
+
+    fn default_geoip_url() -> Option<String> {
+        None
+    }
+
+    fn default_timeout() -> Duration {
+        Duration::from_secs(3)
+    }
+
+    fn default_format() -> String {
+        "{ip}".to_owned()
+    }
+
+    fn default_color_overrides() -> Option<BTreeMap<String, String>> {
+        None
+    }
+}
+
+impl ConfigBlock for ExternalIp {
+    type Config = ExternalIpConfig;
+
+    fn new(
+        block_config: Self::Config,
+        config: Config,
+        tx_update_request: Sender<Task>,
+    ) -> Result<Self> {
+        let id = pseudo_uuid();
+        let text = TextWidget::new(config, &id);
+        let cache_path = xdg_cache_home().join("i3status-rust/external_ip.json");
+
+        Ok(ExternalIp {
+            id,
+            update_interval: block_config.interval,
+            text,
+            url: block_config.url,
+            geoip_url: block_config.geoip_url,
+            timeout: block_config.timeout,
+            format: FormatTemplate::from_string(&block_config.format)
+                .block_error("external_ip", "Invalid format specified for external_ip")?,
+            cache: read_cache(&cache_path),
+            cache_path,
+            force_refresh: false,
+            tx_update_request,
+        })
+    }
+}
+
+impl ExternalIp {
+    fn fetch(&self) -> Result<(String, Option<String>)> {
+        let response = http::http_get_json(&self.url, Some(self.timeout), vec![])?;
+        let ip = response
+            .content
+            .pointer("/ip")
+            .and_then(|ip| ip.as_str())
+            .block_error("external_ip", "no address in response")?
+            .to_string();
+
+        // The address is still worth showing if the country can't be found
+        let country = self.geoip_url.as_ref().and_then(|url| {
+            let response =
+                http::http_get_json(&url.replace("{ip}", &ip), Some(self.timeout), vec![]).ok()?;
+            Some(response.content.pointer("/country")?.as_str()?.to_string())
+        });
+
+        Ok((ip, country))
+    }
+
+    fn render(&mut self, changed: bool) -> Result<()> {
+        let cache = self.cache.clone().unwrap_or_default();
+        let values = map!("{ip}" => cache.ip.unwrap_or_else(|| "N/A".to_string()),
+                          "{country}" => cache.country.unwrap_or_default());
+        self.text.set_text(self.format.render_static_str(&values)?);
+        self.text.set_state(if cache.failed {
+            State::Idle
+        } else if changed {
+            State::Info
+        } else {
+            State::Good
+        });
+        Ok(())
+    }
+}
+
+impl Block for ExternalIp {
+    fn update(&mut self) -> Result<Option<Update>> {
+        let now = unix_time();
+
+        if !self.force_refresh {
+            if let Some(wait) = self
+                .cache
+                .as_ref()
+                .and_then(|cache| remaining_wait(cache.timestamp, now, self.update_interval))
+            {
+                self.render(false)?;
+                return Ok(Some(Duration::from_secs(wait).into()));
+            }
+        }
+        self.force_refresh = false;
+
+        let previous = self.cache.take().unwrap_or_default();
+        let (cache, changed) = match self.fetch() {
+            Ok((ip, country)) => (
+                IpCache {
+                    timestamp: now,
+                    ip: Some(ip.clone()),
+                    country,
+                    failed: false,
+                },
+                matches!(previous.ip, Some(ref previous) if *previous != ip),
+            ),
+            // Keep showing the last known address
+            Err(_) => (
+                IpCache {
+                    timestamp: now,
+                    failed: true,
+                    ..previous
+                },
+                false,
+            ),
+        };
+        // Failing to persist the cache only affects the rate limiting across
+        // restarts, which is no reason not to show the address.
+        let _ = write_cache(&self.cache_path, &cache);
+        self.cache = Some(cache);
+        self.render(changed)?;
+
+        Ok(Some(self.update_interval.into()))
+    }
+
+    fn view(&self) -> Vec<&dyn I3BarWidget> {
+        vec![&self.text]
+    }
+
+    fn click(&mut self, e: &I3BarEvent) -> Result<()> {
+        if e.matches_name(&self.id) && e.button == MouseButton::Left {
+            self.force_refresh = true;
+            self.tx_update_request.send(Task {
+                id: self.id.clone(),
+                update_time: Instant::now(),
+            })?;
+        }
+
+        Ok(())
+    }
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remaining_wait() {
+        let interval = Duration::from_secs(300);
+        assert_eq!(remaining_wait(1000, 1100, interval), Some(200));
+        assert_eq!(remaining_wait(1000, 1300, interval), None);
+        assert_eq!(remaining_wait(1000, 2000, interval), None);
+        // The clock went backwards, e.g. after a restore from hibernation
+        assert_eq!(remaining_wait(2000, 1000, interval), Some(300));
+    }
+}
@@ -1,22 +1,59 @@
 use crate::blocks::{Block, ConfigBlock, Update};
-use crate::config::Config;
+use crate::config::{Config, LogicalDirection, Scrolling};
 use crate::de::deserialize_duration;
 use crate::errors::*;
+use crate::input::{I3BarEvent, MouseButton};
 use crate::scheduler::Task;
-use crate::util::{pseudo_uuid, FormatTemplate};
-use crate::widget::{I3BarWidget, Spacing};
+use crate::util::{pseudo_uuid, read_file, FormatTemplate, FormatValue};
+use crate::widget::{I3BarWidget, Spacing, State};
 use crate::widgets::text::TextWidget;
 use crossbeam_channel::Sender;
+use regex::Regex;
 use serde_derive::Deserialize;
-use std::{collections::BTreeMap, collections::HashMap, process::Command, time::Duration};
+use std::{
+    collections::BTreeMap,
+    collections::HashMap,
+    fs::OpenOptions,
+    io::prelude::*,
+    path::{Path, PathBuf},
+    process::Command,
+    time::Duration,
+    time::Instant,
+};
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum FanDriver {
+    Sysfs,
+    Sensors,
+}
+
+impl Default for FanDriver {
+    fn default() -> Self {
+        FanDriver::Sysfs
+    }
+}
 
 pub struct Fan {
     text: TextWidget,
     id: String,
     update_interval: Duration,
     format: FormatTemplate,
+    format_alt: Option<FormatTemplate>,
+    collapsed: bool,
+    fan_placeholders: usize,
+    driver: FanDriver,
     chip: Option<String>,
     inputs: Option<Vec<String>>,
+    min_rpm: f64,
+    max_rpm: f64,
+    good: Option<i64>,
+    warning: Option<i64>,
+    critical: Option<i64>,
+    pwm_path: Option<PathBuf>,
+    scroll_step: u64,
+    scrolling: Scrolling,
+    tx_update_request: Sender<Task>,
 }
 
 #[derive(Deserialize, Debug, Default, Clone)]
@@ -33,6 +70,14 @@ pub struct FanConfig {
     #[serde(default = "FanConfig::default_format")]
     pub format: String,
 
+    /// Alternative format, toggled by clicking the block
+    #[serde(default = "FanConfig::default_format_alt")]
+    pub format_alt: Option<String>,
+
+    /// The "driver" to use for reading fan speeds. One of "sysfs" or "sensors".
+    #[serde(default)]
+    pub driver: FanDriver,
+
     /// Chip override
     #[serde(default = "FanConfig::default_chip")]
     pub chip: Option<String>,
@@ -41,6 +86,34 @@ pub struct FanConfig {
     #[serde(default = "FanConfig::default_inputs")]
     pub inputs: Option<Vec<String>>,
 
+    /// Readings below this speed are ignored
+    #[serde(default = "FanConfig::default_min_rpm")]
+    pub min_rpm: f64,
+
+    /// Readings above this speed are ignored
+    #[serde(default = "FanConfig::default_max_rpm")]
+    pub max_rpm: f64,
+
+    /// Minimum speed, above which state is set to good
+    #[serde(default)]
+    pub good: Option<i64>,
+
+    /// Minimum speed, above which state is set to warning
+    #[serde(default)]
+    pub warning: Option<i64>,
+
+    /// Minimum speed, above which state is set to critical
+    #[serde(default)]
+    pub critical: Option<i64>,
+
+    /// PWM file (e.g. `/sys/class/hwmon/hwmon2/pwm1`) to adjust by scrolling
+    #[serde(default = "FanConfig::default_pwm_path")]
+    pub pwm_path: Option<String>,
+
+    /// The step the PWM duty cycle is in/decreased by when scrolling, out of 255
+    #[serde(default = "FanConfig::default_scroll_step")]
+    pub scroll_step: u64,
+
     #[serde(default = "FanConfig::default_color_overrides")]
     pub color_overrides: Option<BTreeMap<String, String>>,
 }
@@ -50,6 +123,10 @@ impl FanConfig {
         "{average}RPM".to_owned()
     }
 
+    fn default_format_alt() -> Option<String> {
+        None
+    }
+
     fn default_interval() -> Duration {
         Duration::from_secs(15)
     }
@@ -62,6 +139,22 @@ impl FanConfig {
         None
     }
 
+    fn default_min_rpm() -> f64 {
+        0.0
+    }
+
+    fn default_max_rpm() -> f64 {
+        10000.0
+    }
+
+    fn default_pwm_path() -> Option<String> {
+        None
+    }
+
+    fn default_scroll_step() -> u64 {
+        10
+    }
+
     fn default_color_overrides() -> Option<BTreeMap<String, String>> {
         None
     }
@@ -73,10 +166,29 @@ impl ConfigBlock for Fan {
     fn new(
         block_config: Self::Config,
         config: Config,
-        _tx_update_request: Sender<Task>,
+        tx_update_request: Sender<Task>,
     ) -> Result<Self> {
         let id = pseudo_uuid();
 
+        // The highest `{fanN}` index used in the format strings
+        let fan_re = Regex::new(r"\{fan([0-9]+)\}").block_error("fan", "invalid regex")?;
+        let fan_placeholders = std::iter::once(&block_config.format)
+            .chain(block_config.format_alt.iter())
+            .flat_map(|format| fan_re.captures_iter(format))
+            .filter_map(|cap| cap[1].parse::<usize>().ok())
+            .max()
+            .unwrap_or(0);
+
+        let format_alt = match block_config.format_alt {
+            Some(ref format) => Some(
+                FormatTemplate::from_string(format)
+                    .block_error("fan", "Invalid format_alt specified")?,
+            ),
+            None => None,
+        };
+
+        let scrolling = config.scrolling;
+
         Ok(Fan {
             update_interval: block_config.interval,
             text: TextWidget::new(config, &id)
@@ -84,9 +196,22 @@ impl ConfigBlock for Fan {
                 .with_spacing(Spacing::Normal),
             id,
             format: FormatTemplate::from_string(&block_config.format)
-                .block_error("fan", "Invalid format specified for temperature")?,
+                .block_error("fan", "Invalid format specified")?,
+            format_alt,
+            collapsed: true,
+            fan_placeholders,
+            driver: block_config.driver,
             chip: block_config.chip,
             inputs: block_config.inputs,
+            min_rpm: block_config.min_rpm,
+            max_rpm: block_config.max_rpm,
+            good: block_config.good,
+            warning: block_config.warning,
+            critical: block_config.critical,
+            pwm_path: block_config.pwm_path.map(PathBuf::from),
+            scroll_step: block_config.scroll_step,
+            scrolling,
+            tx_update_request,
         })
     }
 }
@@ -94,66 +219,252 @@ impl ConfigBlock for Fan {
 type SensorsOutput = HashMap<String, HashMap<String, serde_json::Value>>;
 type InputReadings = HashMap<String, f64>;
 
-impl Block for Fan {
-    fn update(&mut self) -> Result<Option<Update>> {
-        let mut args = vec!["-j"];
-        if let Some(ref chip) = &self.chip {
-            args.push(chip);
+/// A single fan speed, along with the chip and input it was read from.
+#[derive(Debug, Clone)]
+struct FanReading {
+    chip: String,
+    input: String,
+    /// The number of the `fanN_input` file, so that fans are shown in order
+    index: u32,
+    rpm: f64,
+}
+
+/// Gets the number of a `fanN_input` file or value, or 0 if it has none.
+fn fan_index(name: &str) -> u32 {
+    name.trim_start_matches("fan")
+        .trim_end_matches("_input")
+        .parse()
+        .unwrap_or(0)
+}
+
+/// Checks a `chip` option against the `name` file of a hwmon device. Chip names
+/// in the lm_sensors format (e.g. `nct6775-isa-0290`) are matched on the part
+/// before the first dash, and `*` matches any chip.
+fn chip_matches(chip: &str, hwmon_name: &str) -> bool {
+    let prefix = chip.split('-').next().unwrap_or(chip);
+    prefix == "*" || prefix == hwmon_name
+}
+
+/// Reads all `fan*_input` files below `/sys/class/hwmon`. Readings that are
+/// missing or unparsable are skipped, since stopped fans on some laptops make
+/// their input file disappear.
+fn read_sysfs_fans(chip: &Option<String>, inputs: &Option<Vec<String>>) -> Result<Vec<FanReading>> {
+    let mut fans = Vec::new();
+    let hwmons = Path::new("/sys/class/hwmon")
+        .read_dir()
+        .block_error("fan", "Failed to read hwmon directory")?;
+
+    for hwmon in hwmons.flatten() {
+        let hwmon_path = hwmon.path();
+        let name = match read_file("fan", &hwmon_path.join("name")) {
+            Ok(name) => name.trim().to_owned(),
+            Err(_) => continue,
+        };
+        if let Some(ref chip) = chip {
+            if !chip_matches(chip, &name) {
+                continue;
+            }
         }
-        let output = Command::new("sensors")
-            .args(&args)
-            .output()
-            .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_owned())
-            .unwrap_or_else(|e| e.to_string());
 
-        let parsed: SensorsOutput = serde_json::from_str(&output)
-            .block_error("temperature", "sensors output is invalid")?;
+        let entries = match hwmon_path.read_dir() {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.flatten() {
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            if !file_name.starts_with("fan") || !file_name.ends_with("_input") {
+                continue;
+            }
 
-        let mut fans: Vec<i64> = Vec::new();
-        for (_chip, inputs) in parsed {
-            for (input_name, input_values) in inputs {
-                if let Some(ref whitelist) = self.inputs {
-                    if !whitelist.contains(&input_name) {
-                        continue;
-                    }
+            // lm_sensors names inputs by their label if there is one
+            let base = file_name.trim_end_matches("_input");
+            let label = read_file("fan", &hwmon_path.join(format!("{}_label", base)))
+                .map(|label| label.trim().to_owned())
+                .unwrap_or_else(|_| base.to_owned());
+            if let Some(ref whitelist) = inputs {
+                if !whitelist.contains(&label) {
+                    continue;
                 }
+            }
 
-                let values_parsed: InputReadings = match serde_json::from_value(input_values) {
-                    Ok(values) => values,
-                    Err(_) => continue, // probably the "Adapter" key, just ignore.
-                };
+            if let Ok(value) = read_file("fan", &entry.path()).and_then(|v| {
+                v.trim()
+                    .parse::<f64>()
+                    .block_error("fan", "invalid reading")
+            }) {
+                fans.push(FanReading {
+                    chip: name.clone(),
+                    input: label,
+                    index: fan_index(&file_name),
+                    rpm: value,
+                });
+            }
+        }
+    }
 
-                for (value_name, value) in values_parsed {
-                    if !value_name.starts_with("fan") || !value_name.ends_with("input") {
-                        continue;
-                    }
+    Ok(fans)
+}
 
-                    if (0f64..10000f64).contains(&value) {
-                        fans.push(value as i64);
-                    } else {
-                        // This error is recoverable and therefore should not stop the program
-                        eprintln!("Fan ({}) outside of range ([0, 10000])", value);
-                    }
+/// Reads the raw PWM duty cycle (0-255) from the given file.
+fn read_pwm(path: &Path) -> Result<u64> {
+    read_file("fan", path)?
+        .trim()
+        .parse::<u64>()
+        .block_error("fan", "Failed to read value from pwm file")
+}
+
+fn write_pwm(path: &Path, value: u64) -> Result<()> {
+    let mut file = OpenOptions::new().write(true).open(path).block_error(
+        "fan",
+        &format!(
+            "Failed to open {} for writing, is the fan in manual mode?",
+            path.to_string_lossy()
+        ),
+    )?;
+    file.write_fmt(format_args!("{}", value.min(255)))
+        .block_error("fan", "Failed to write into pwm file")
+}
+
+/// Runs `sensors -j` and collects all `fan*_input` readings from its output.
+fn read_sensors_fans(
+    chip: &Option<String>,
+    inputs: &Option<Vec<String>>,
+) -> Result<Vec<FanReading>> {
+    let mut args = vec!["-j"];
+    if let Some(ref chip) = chip {
+        args.push(chip);
+    }
+    let output = Command::new("sensors")
+        .args(&args)
+        .output()
+        .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_owned())
+        .block_error("fan", "sensors failed")?;
+
+    parse_sensors_output(&output, inputs)
+}
+
+/// Collects all `fan*_input` readings from the JSON output of `sensors -j`.
+fn parse_sensors_output(output: &str, inputs: &Option<Vec<String>>) -> Result<Vec<FanReading>> {
+    let parsed: SensorsOutput =
+        serde_json::from_str(output).block_error("fan", "sensors output is invalid")?;
+
+    let mut fans = Vec::new();
+    for (chip_name, chip_inputs) in parsed {
+        for (input_name, input_values) in chip_inputs {
+            if let Some(ref whitelist) = inputs {
+                if !whitelist.contains(&input_name) {
+                    continue;
+                }
+            }
+
+            let values_parsed: InputReadings = match serde_json::from_value(input_values) {
+                Ok(values) => values,
+                Err(_) => continue, // probably the "Adapter" key, just ignore.
+            };
+
+            for (value_name, value) in values_parsed {
+                if value_name.starts_with("fan") && value_name.ends_with("input") {
+                    fans.push(FanReading {
+                        chip: chip_name.clone(),
+                        input: input_name.clone(),
+                        index: fan_index(&value_name),
+                        rpm: value,
+                    });
                 }
             }
         }
+    }
+
+    Ok(fans)
+}
+
+impl Block for Fan {
+    fn update(&mut self) -> Result<Option<Update>> {
+        let readings = match self.driver {
+            FanDriver::Sysfs => read_sysfs_fans(&self.chip, &self.inputs),
+            FanDriver::Sensors => read_sensors_fans(&self.chip, &self.inputs),
+        };
+        // Show failures in the widget instead of keeping stale readings around
+        let mut readings = match readings {
+            Ok(readings) => readings,
+            Err(BlockError(block, message)) => {
+                self.text.set_text(format!("{}: {}", block, message));
+                self.text.set_state(State::Critical);
+                return Ok(Some(self.update_interval.into()));
+            }
+            Err(e) => return Err(e),
+        };
+        // Sorted by number, as fan10 would come before fan2 by name
+        readings.sort_by(|a, b| (&a.chip, a.index).cmp(&(&b.chip, b.index)));
+
+        let mut fans: Vec<i64> = Vec::new();
+        for reading in readings {
+            if (self.min_rpm..=self.max_rpm).contains(&reading.rpm) {
+                fans.push(reading.rpm as i64);
+            } else {
+                // This error is recoverable and therefore should not stop the program
+                eprintln!(
+                    "Fan {}/{} ({}) outside of range ([{}, {}])",
+                    reading.chip, reading.input, reading.rpm, self.min_rpm, self.max_rpm
+                );
+            }
+        }
 
         if !fans.is_empty() {
             let max: i64 = *fans
                 .iter()
                 .max()
-                .block_error("temperature", "failed to get max temperature")?;
+                .block_error("fan", "failed to get max speed")?;
             let min: i64 = *fans
                 .iter()
                 .min()
-                .block_error("temperature", "failed to get min temperature")?;
+                .block_error("fan", "failed to get min speed")?;
             let avg: i64 = (fans.iter().sum::<i64>() as f64 / fans.len() as f64).round() as i64;
 
-            let values = map!("{average}" => avg,
-                "{min}" => min,
-                "{max}" => max);
+            // Every `{fanN}` used in the format gets a value, even if there are
+            // fewer fans than that.
+            let fan_keys: Vec<String> = (1..=fans.len().max(self.fan_placeholders))
+                .map(|i| format!("{{fan{}}}", i))
+                .collect();
+
+            // Speeds are numbers, so that e.g. `{average:5}` keeps the width
+            let mut values: HashMap<&str, FormatValue> = map!("{average}" => avg.into(),
+            "{min}" => min.into(),
+            "{max}" => max.into(),
+            "{count}" => (fans.len() as i64).into(),
+            "{pwm}" => match self.pwm_path {
+                Some(ref path) => read_pwm(path)
+                    .map(|pwm| format!("{}%", (pwm as f64 / 255.0 * 100.0).round()))
+                    .unwrap_or_else(|_| "N/A".to_string()),
+                None => "N/A".to_string(),
+            }.into());
+            for (i, key) in fan_keys.iter().enumerate() {
+                let value = fans
+                    .get(i)
+                    .map(|&rpm| rpm.into())
+                    .unwrap_or_else(|| "N/A".into());
+                values.insert(key.as_str(), value);
+            }
+
+            let format = match self.format_alt {
+                Some(ref format_alt) if !self.collapsed => format_alt,
+                _ => &self.format,
+            };
+            self.text.set_text(format.render_values(&values)?);
 
-            self.text.set_text(self.format.render_static_str(&values)?);
+            // A stopped fan is never an alarm, e.g. on passively cooled machines
+            let state = match max {
+                0 => State::Idle,
+                m if self.critical.map_or(false, |t| m >= t) => State::Critical,
+                m if self.warning.map_or(false, |t| m >= t) => State::Warning,
+                m if self.good.map_or(false, |t| m >= t) => State::Good,
+                _ => State::Idle,
+            };
+            self.text.set_state(state);
+        } else {
+            // Clears an error shown by an earlier update
+            self.text.set_text(String::new());
+            self.text.set_state(State::Idle);
         }
 
         Ok(Some(self.update_interval.into()))
@@ -163,7 +474,104 @@ impl Block for Fan {
         vec![&self.text]
     }
 
+    fn click(&mut self, e: &I3BarEvent) -> Result<()> {
+        if e.matches_name(&self.id) && e.button == MouseButton::Left && self.format_alt.is_some() {
+            self.collapsed = !self.collapsed;
+            self.tx_update_request.send(Task {
+                id: self.id.clone(),
+                update_time: Instant::now(),
+            })?;
+        }
+
+        if let Some(ref path) = self.pwm_path {
+            if e.matches_name(&self.id) {
+                use LogicalDirection::*;
+                let step = self.scroll_step;
+                let adjusted = match self.scrolling.to_logical_direction(e.button) {
+                    Some(Up) => {
+                        read_pwm(path).and_then(|pwm| write_pwm(path, (pwm + step).min(255)))
+                    }
+                    Some(Down) => {
+                        read_pwm(path).and_then(|pwm| write_pwm(path, pwm.saturating_sub(step)))
+                    }
+                    None => return Ok(()),
+                };
+                // Fans which aren't in manual mode can't be adjusted, which
+                // isn't worth stopping the bar for
+                match adjusted {
+                    Ok(()) => self.tx_update_request.send(Task {
+                        id: self.id.clone(),
+                        update_time: Instant::now(),
+                    })?,
+                    Err(BlockError(_, message)) => {
+                        self.text.set_text(message);
+                        self.text.set_state(State::Critical);
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     fn id(&self) -> &str {
         &self.id
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::blocks::fan::{fan_index, parse_sensors_output};
+
+    #[test]
+    fn test_parse_sensors_output() {
+        let output = r#"{
+            "thinkpad-isa-0000": {
+                "Adapter": "ISA adapter",
+                "fan1": {"fan1_input": 2421.000},
+                "fan2": {"fan2_input": 0.000}
+            }
+        }"#;
+        let mut fans = parse_sensors_output(output, &None).unwrap();
+        fans.sort_by(|a, b| a.input.cmp(&b.input));
+        assert_eq!(fans.len(), 2);
+        assert_eq!(fans[0].chip, "thinkpad-isa-0000");
+        assert_eq!(fans[0].input, "fan1");
+        assert_eq!(fans[0].rpm, 2421.0);
+        assert_eq!(fans[1].rpm, 0.0);
+
+        let whitelist = Some(vec!["fan2".to_string()]);
+        let fans = parse_sensors_output(output, &whitelist).unwrap();
+        assert_eq!(fans.len(), 1);
+        assert_eq!(fans[0].input, "fan2");
+    }
+
+    #[test]
+    fn test_fan_index() {
+        assert_eq!(fan_index("fan2_input"), 2);
+        assert_eq!(fan_index("fan10_input"), 10);
+        assert_eq!(fan_index("fan_input"), 0);
+
+        let output = r#"{"chip": {"fan10": {"fan10_input": 1.0}, "fan2": {"fan2_input": 2.0}}}"#;
+        let mut fans = parse_sensors_output(output, &None).unwrap();
+        fans.sort_by_key(|fan| fan.index);
+        assert_eq!(fans[0].input, "fan2");
+        assert_eq!(fans[1].input, "fan10");
+    }
+
+    #[test]
+    fn test_parse_sensors_output_empty() {
+        assert!(parse_sensors_output("{}", &None).unwrap().is_empty());
+        assert!(parse_sensors_output("", &None).is_err());
+    }
+
+    #[test]
+    fn test_parse_sensors_output_malformed() {
+        assert!(parse_sensors_output("{\"chip\": {\"fan1\": ", &None).is_err());
+        assert!(parse_sensors_output("sensors: command not found", &None).is_err());
+        // Inputs without numeric readings are skipped rather than failing
+        let output = r#"{"chip": {"fan1": {"fan1_input": "fast"}}}"#;
+        assert!(parse_sensors_output(output, &None).unwrap().is_empty());
+    }
+}
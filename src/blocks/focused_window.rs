@@ -1,22 +1,22 @@
 use std::collections::BTreeMap;
 use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use crossbeam_channel::Sender;
 use serde_derive::Deserialize;
-use swayipc::reply::{Event, Node, WindowChange, WorkspaceChange};
+use swayipc::reply::{Event, Node, NodeType, WindowChange, WorkspaceChange};
 use swayipc::{Connection, EventType};
 
 use crate::blocks::{Block, ConfigBlock, Update};
 use crate::config::Config;
 use crate::errors::*;
 use crate::scheduler::Task;
-use crate::util::pseudo_uuid;
-use crate::widget::I3BarWidget;
+use crate::util::{escape_pango_text, pseudo_uuid, FormatTemplate};
+use crate::widget::{I3BarWidget, Markup};
 use crate::widgets::text::TextWidget;
 
-#[derive(Copy, Clone, Debug, Deserialize)]
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum MarksType {
     All,
@@ -24,12 +24,118 @@ pub enum MarksType {
     None,
 }
 
+/// The focused window, as far as the block is concerned.
+#[derive(Debug, Default, Clone, PartialEq)]
+struct Window {
+    id: Option<i64>,
+    title: String,
+    marks: Vec<String>,
+    /// The app id on Wayland, or the class on X11
+    app: String,
+}
+
+impl Window {
+    fn from_node(node: &Node) -> Self {
+        Window {
+            id: Some(node.id),
+            title: node.name.clone().unwrap_or_default(),
+            marks: node.marks.clone(),
+            app: node
+                .app_id
+                .clone()
+                .or_else(|| {
+                    node.window_properties
+                        .as_ref()
+                        .and_then(|properties| properties.class.clone())
+                })
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Finds the focused window in the tree. Nothing is returned when a workspace
+/// is focused, i.e. when it is empty.
+fn find_focused(node: &Node) -> Option<&Node> {
+    if node.focused {
+        return match node.node_type {
+            NodeType::Con | NodeType::FloatingCon => Some(node),
+            _ => None,
+        };
+    }
+    node.nodes
+        .iter()
+        .chain(node.floating_nodes.iter())
+        .find_map(find_focused)
+}
+
+/// Applies an event to the focused window, and returns whether it changed.
+fn handle_event(window: &mut Window, event: Event) -> bool {
+    let new_window = match event {
+        Event::Window(e) => match e.change {
+            WindowChange::Focus => Window::from_node(&e.container),
+            WindowChange::Title | WindowChange::Mark if e.container.focused => {
+                Window::from_node(&e.container)
+            }
+            WindowChange::Close if window.id == Some(e.container.id) => Window::default(),
+            _ => return false,
+        },
+        // Focusing an empty workspace focuses no window
+        Event::Workspace(e) => match (e.change, e.current) {
+            (WorkspaceChange::Init, _) => Window::default(),
+            (WorkspaceChange::Focus, Some(ref workspace))
+                if workspace.nodes.is_empty() && workspace.floating_nodes.is_empty() =>
+            {
+                Window::default()
+            }
+            _ => return false,
+        },
+        _ => return false,
+    };
+    let changed = *window != new_window;
+    *window = new_window;
+    changed
+}
+
+/// Follows the focused window until the connection is lost, e.g. because
+/// the window manager restarted. Returns `Ok` once the block is gone.
+fn watch_windows(
+    window: &Mutex<Window>,
+    request_update: &dyn Fn() -> bool,
+) -> std::result::Result<(), swayipc::Error> {
+    let mut conn = Connection::new()?;
+    let focused = conn
+        .get_tree()?
+        .nodes
+        .iter()
+        .find_map(find_focused)
+        .map(Window::from_node)
+        .unwrap_or_default();
+    if let Ok(mut window) = window.lock() {
+        *window = focused;
+    }
+    if !request_update() {
+        return Ok(());
+    }
+
+    for event in conn.subscribe(&[EventType::Window, EventType::Workspace])? {
+        let changed = match window.lock() {
+            Ok(mut window) => handle_event(&mut window, event?),
+            Err(_) => return Ok(()),
+        };
+        if changed && !request_update() {
+            return Ok(());
+        }
+    }
+    Ok(())
+}
+
 pub struct FocusedWindow {
     text: TextWidget,
-    title: Arc<Mutex<String>>,
-    marks: Arc<Mutex<String>>,
+    window: Arc<Mutex<Window>>,
+    format: FormatTemplate,
     show_marks: MarksType,
     max_width: usize,
+    markup: Markup,
     id: String,
 }
 
@@ -44,6 +150,10 @@ pub struct FocusedWindowConfig {
     #[serde(default = "FocusedWindowConfig::default_show_marks")]
     pub show_marks: MarksType,
 
+    /// Format override
+    #[serde(default = "FocusedWindowConfig::default_format")]
+    pub format: String,
+
     #[serde(default = "FocusedWindowConfig::default_color_overrides")]
     pub color_overrides: Option<BTreeMap<String, String>>,
 }
@@ -57,6 +167,10 @@ impl FocusedWindowConfig {
         MarksType::None
     }
 
+    fn default_format() -> String {
+        "{title}".to_owned()
+    }
+
     fn default_color_overrides() -> Option<BTreeMap<String, String>> {
         None
     }
@@ -67,160 +181,104 @@ impl ConfigBlock for FocusedWindow {
 
     fn new(block_config: Self::Config, config: Config, tx: Sender<Task>) -> Result<Self> {
         let id = pseudo_uuid();
-        let id_clone = id.clone();
-
-        let title = Arc::new(Mutex::new(String::from("")));
-        let marks = Arc::new(Mutex::new(String::from("")));
-        let marks_type = block_config.show_marks;
 
-        let update_window = {
-            let title = title.clone();
-
-            move |new_title| {
-                let mut title = title
-                    .lock()
-                    .expect("lock has been poisoned in `window` block");
-
-                let changed = *title != new_title;
-                *title = new_title;
-                changed
-            }
-        };
-
-        let close_window = {
-            let title = title.clone();
-
-            move |closed_title: String| {
-                let mut title = title
-                    .lock()
-                    .expect("lock has been poisoned in `window` block");
-
-                if *title == closed_title {
-                    *title = "".to_string();
-                    true
-                } else {
-                    false
-                }
-            }
-        };
-
-        let update_marks = {
-            let marks = marks.clone();
-
-            move |new_marks: Vec<String>| {
-                let mut new_marks_str = String::from("");
-
-                for mark in new_marks {
-                    match marks_type {
-                        MarksType::All => {
-                            new_marks_str.push_str(&format!("[{}]", mark));
-                        }
-                        MarksType::Visible if !mark.starts_with('_') => {
-                            new_marks_str.push_str(&format!("[{}]", mark));
-                        }
-                        _ => {}
-                    }
-                }
-
-                let mut marks = marks
-                    .lock()
-                    .expect("lock has been poisoned in `window` block");
+        let _test_conn =
+            Connection::new().block_error("focused_window", "failed to acquire connect to IPC")?;
 
-                let changed = *marks != new_marks_str;
-                *marks = new_marks_str;
-                changed
+        let window = Arc::new(Mutex::new(Window::default()));
+        let request_update = {
+            let id = id.clone();
+            move || {
+                tx.send(Task {
+                    id: id.clone(),
+                    update_time: Instant::now(),
+                })
+                .is_ok()
             }
         };
 
-        let _test_conn =
-            Connection::new().block_error("focused_window", "failed to acquire connect to IPC")?;
-
+        let window_copy = window.clone();
         thread::Builder::new()
             .name("focused_window".into())
-            .spawn(move || {
-                let conn = Connection::new().expect("failed to open connection with swayipc");
-
-                let events = conn
-                    .subscribe(&[EventType::Window, EventType::Workspace])
-                    .expect("could not subscribe to window events");
-
-                for event in events {
-                    let updated = match event.expect("could not read event in `window` block") {
-                        Event::Window(e) => match (e.change, e.container) {
-                            (WindowChange::Mark, Node { marks, .. }) => update_marks(marks),
-                            (WindowChange::Focus, Node { name, marks, .. }) => {
-                                let updated_for_window = name.map(&update_window).unwrap_or(false);
-                                let updated_for_marks = update_marks(marks);
-                                updated_for_window || updated_for_marks
-                            }
-                            (
-                                WindowChange::Title,
-                                Node {
-                                    focused: true,
-                                    name: Some(name),
-                                    ..
-                                },
-                            ) => update_window(name),
-                            (
-                                WindowChange::Close,
-                                Node {
-                                    name: Some(name), ..
-                                },
-                            ) => close_window(name),
-                            _ => false,
-                        },
-                        Event::Workspace(e) if e.change == WorkspaceChange::Init => {
-                            update_window("".to_string())
-                        }
-                        _ => false,
-                    };
-
-                    if updated {
-                        tx.send(Task {
-                            id: id_clone.clone(),
-                            update_time: Instant::now(),
-                        })
-                        .expect("could not communicate with channel in `window` block");
-                    }
+            .spawn(move || loop {
+                let result = watch_windows(&window_copy, &request_update);
+                // Don't show the previous window while disconnected
+                if let Ok(mut window) = window_copy.lock() {
+                    *window = Window::default();
+                }
+                if result.is_ok() || !request_update() {
+                    return;
                 }
+                // Give the window manager time to open its socket again
+                thread::sleep(Duration::from_secs(1));
             })
             .expect("failed to start watching thread for `window` block");
 
+        let markup = config.markup;
         let text = TextWidget::new(config, &id);
         Ok(FocusedWindow {
             id,
             text,
+            format: FormatTemplate::from_string(&block_config.format)
+                .block_error("focused_window", "Invalid format specified")?,
             max_width: block_config.max_width,
+            markup,
             show_marks: block_config.show_marks,
-            title,
-            marks,
+            window,
         })
     }
 }
 
+/// Shortens the text to `max_width` characters, ending in an ellipsis if
+/// anything was cut off.
+fn truncate(text: &str, max_width: usize) -> String {
+    if text.chars().count() <= max_width {
+        text.to_owned()
+    } else if max_width == 0 {
+        String::new()
+    } else {
+        let mut truncated: String = text.chars().take(max_width - 1).collect();
+        truncated.push('…');
+        truncated
+    }
+}
+
 impl Block for FocusedWindow {
     fn update(&mut self) -> Result<Option<Update>> {
-        let mut marks_string = (*self
-            .marks
-            .lock()
-            .block_error("focused_window", "failed to acquire lock")?)
-        .clone();
-        marks_string = marks_string.chars().take(self.max_width).collect();
-        let mut title_string = (*self
-            .title
+        let window = self
+            .window
             .lock()
-            .block_error("focused_window", "failed to acquire lock")?)
-        .clone();
-        title_string = title_string.chars().take(self.max_width).collect();
-        let out_str = match self.show_marks {
-            MarksType::None => title_string,
-            _ => {
-                if !marks_string.is_empty() {
-                    marks_string
-                } else {
-                    title_string
-                }
+            .block_error("focused_window", "failed to acquire lock")?
+            .clone();
+
+        let marks: String = window
+            .marks
+            .iter()
+            .filter(|mark| self.show_marks == MarksType::All || !mark.starts_with('_'))
+            .map(|mark| format!("[{}]", mark))
+            .collect();
+        let out_str = if self.show_marks != MarksType::None && !marks.is_empty() {
+            let marks = truncate(&marks, self.max_width);
+            match self.markup {
+                Markup::Pango => escape_pango_text(marks),
+                Markup::None => marks,
             }
+        } else if self.markup == Markup::Pango {
+            // Cutting the markup could break it, so only the title is shortened
+            let title = truncate(&window.title, self.max_width);
+            let values = map!(
+                "{title}" => title.as_str(),
+                "{marks}" => marks.as_str(),
+                "{app}" => window.app.as_str()
+            );
+            self.format.render_markup(&values, self.markup)?
+        } else {
+            let values = map!(
+                "{title}" => window.title.as_str(),
+                "{marks}" => marks.as_str(),
+                "{app}" => window.app.as_str()
+            );
+            truncate(&self.format.render_static_str(&values)?, self.max_width)
         };
         self.text.set_text(out_str);
 
@@ -228,12 +286,12 @@ impl Block for FocusedWindow {
     }
 
     fn view(&self) -> Vec<&dyn I3BarWidget> {
-        let title = &*self
-            .title
+        let window = &*self
+            .window
             .lock()
             .expect("lock has been poisoned in `window` block");
 
-        if title.is_empty() {
+        if window.id.is_none() {
             vec![]
         } else {
             vec![&self.text]
@@ -244,3 +302,17 @@ impl Block for FocusedWindow {
         &self.id
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("i3status-rust", 21), "i3status-rust");
+        assert_eq!(truncate("i3status-rust", 13), "i3status-rust");
+        assert_eq!(truncate("i3status-rust", 9), "i3status…");
+        assert_eq!(truncate("Überschrift", 4), "Übe…");
+        assert_eq!(truncate("i3status-rust", 0), "");
+    }
+}
@@ -1,4 +1,6 @@
+use std::cmp;
 use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
 use std::time::Duration;
 
 use crossbeam_channel::Sender;
@@ -11,14 +13,17 @@ use crate::config::Config;
 use crate::de::deserialize_duration;
 use crate::errors::*;
 use crate::http;
-use crate::input::I3BarEvent;
+use crate::input::{I3BarEvent, MouseButton};
 use crate::scheduler::Task;
-use crate::util::{pseudo_uuid, FormatTemplate};
-use crate::widget::I3BarWidget;
+use crate::subprocess::spawn_child_async;
+use crate::util::{pseudo_uuid, read_file, FormatTemplate};
+use crate::widget::{I3BarWidget, State};
 use crate::widgets::text::TextWidget;
 
 const GITHUB_TOKEN_ENV: &str = "I3RS_GITHUB_TOKEN";
 
+const NOTIFICATIONS_URL: &str = "https://github.com/notifications";
+
 pub struct Github {
     text: TextWidget,
     id: String,
@@ -26,6 +31,16 @@ pub struct Github {
     api_server: String,
     token: String,
     format: FormatTemplate,
+    hide_when_zero: bool,
+    /// The ETag of the notifications shown, to only fetch them again once
+    /// they changed
+    etag: Option<String>,
+    /// The minimum interval between polls requested by GitHub
+    poll_interval: Duration,
+    /// The total number of notifications last fetched
+    total: Option<u64>,
+    /// The text rendered for them, to restore it if a click replaced it
+    rendered: Option<String>,
 }
 
 #[derive(Deserialize, Debug, Default, Clone)]
@@ -45,6 +60,14 @@ pub struct GithubConfig {
     #[serde(default = "GithubConfig::default_format")]
     pub format: String,
 
+    /// A file to read the token from, instead of the environment
+    #[serde(default = "GithubConfig::default_token_file")]
+    pub token_file: Option<PathBuf>,
+
+    /// Hide the block while there are no notifications
+    #[serde(default = "GithubConfig::default_hide_when_zero")]
+    pub hide_when_zero: bool,
+
     #[serde(default = "GithubConfig::default_color_overrides")]
     pub color_overrides: Option<BTreeMap<String, String>>,
 }
@@ -62,6 +85,14 @@ impl GithubConfig {
         "{total}".to_owned()
     }
 
+    fn default_token_file() -> Option<PathBuf> {
+        None
+    }
+
+    fn default_hide_when_zero() -> bool {
+        false
+    }
+
     fn default_color_overrides() -> Option<BTreeMap<String, String>> {
         None
     }
@@ -71,14 +102,17 @@ impl ConfigBlock for Github {
     type Config = GithubConfig;
 
     fn new(block_config: Self::Config, config: Config, _: Sender<Task>) -> Result<Self> {
-        let token = match std::env::var(GITHUB_TOKEN_ENV).ok() {
-            Some(v) => v,
-            None => {
-                return Err(BlockError(
-                    "github".to_owned(),
-                    "missing I3RS_GITHUB_TOKEN environment variable".to_owned(),
-                ))
-            }
+        let token = match block_config.token_file {
+            Some(ref path) => read_file("github", path)?.trim().to_owned(),
+            None => match std::env::var(GITHUB_TOKEN_ENV).ok() {
+                Some(v) => v,
+                None => {
+                    return Err(BlockError(
+                        "github".to_owned(),
+                        "missing I3RS_GITHUB_TOKEN environment variable".to_owned(),
+                    ))
+                }
+            },
         };
 
         let id = pseudo_uuid();
@@ -93,13 +127,19 @@ impl ConfigBlock for Github {
             token,
             format: FormatTemplate::from_string(&block_config.format)
                 .block_error("github", "Invalid format specified")?,
+            hide_when_zero: block_config.hide_when_zero,
+            etag: None,
+            poll_interval: Duration::from_secs(0),
+            total: None,
+            rendered: None,
         })
     }
 }
 
 impl Block for Github {
     fn update(&mut self) -> Result<Option<Update>> {
-        let aggregations = match Notifications::new(&self.api_server, &self.token).try_fold(
+        let mut notifications = Notifications::new(&self.api_server, &self.token, &self.etag);
+        let aggregations = match notifications.try_fold(
             map!("total".to_owned() => 0),
             |mut acc,
              notif|
@@ -112,12 +152,30 @@ impl Block for Github {
         ) {
             Ok(v) => v,
             Err(_) => {
-                // If there is a error reported, set the value to x
-                self.text.set_text("x".to_owned());
+                // Keep showing the last count, but greyed out
+                self.text
+                    .set_text(self.rendered.clone().unwrap_or_else(|| "x".to_owned()));
+                self.text.set_state(State::Idle);
                 return Ok(Some(self.update_interval.into()));
             }
         };
 
+        if let Some(poll_interval) = notifications.poll_interval {
+            self.poll_interval = poll_interval;
+        }
+        let next_update = cmp::max(self.update_interval, self.poll_interval);
+        if notifications.not_modified {
+            if let Some(ref rendered) = self.rendered {
+                self.text.set_text(rendered.clone());
+            }
+            if let Some(total) = self.total {
+                self.text
+                    .set_state(if total > 0 { State::Info } else { State::Idle });
+            }
+            return Ok(Some(next_update.into()));
+        }
+        self.etag = notifications.etag;
+
         let default: u64 = 0;
         let values = map!(
             "{total}" => format!("{}", aggregations.get("total").unwrap_or(&default)),
@@ -136,16 +194,35 @@ impl Block for Github {
             "{team_mention}" => format!("{}", aggregations.get("team_mention").unwrap_or(&default))
         );
 
-        self.text.set_text(self.format.render_static_str(&values)?);
+        let rendered = self.format.render_static_str(&values)?;
+        self.text.set_text(rendered.clone());
+        self.rendered = Some(rendered);
 
-        Ok(Some(self.update_interval.into()))
+        let total = *aggregations.get("total").unwrap_or(&default);
+        self.text
+            .set_state(if total > 0 { State::Info } else { State::Idle });
+        self.total = Some(total);
+
+        Ok(Some(next_update.into()))
     }
 
     fn view(&self) -> Vec<&dyn I3BarWidget> {
-        vec![&self.text]
+        if self.hide_when_zero && self.total == Some(0) {
+            vec![]
+        } else {
+            vec![&self.text]
+        }
     }
 
-    fn click(&mut self, _: &I3BarEvent) -> Result<()> {
+    fn click(&mut self, event: &I3BarEvent) -> Result<()> {
+        if event.matches_name(&self.id) && event.button == MouseButton::Left {
+            // Shown until the next update, e.g. if xdg-open isn't installed
+            if let Err(e) = spawn_child_async("xdg-open", &[NOTIFICATIONS_URL]) {
+                self.text
+                    .set_text(format!("could not spawn xdg-open: {}", e));
+                self.text.set_state(State::Critical);
+            }
+        }
         Ok(())
     }
 
@@ -163,6 +240,14 @@ struct Notifications<'a> {
     notifications: <Vec<Notification> as IntoIterator>::IntoIter,
     token: &'a str,
     next_page_url: String,
+    /// Sent with the request of the first page, and replaced by the ETag of
+    /// its response
+    etag: Option<String>,
+    /// Whether the first page is unchanged since `etag` was received
+    not_modified: bool,
+    /// The value of the `X-Poll-Interval` header of the first page
+    poll_interval: Option<Duration>,
+    first_page: bool,
 }
 
 impl<'a> Iterator for Notifications<'a> {
@@ -178,11 +263,15 @@ impl<'a> Iterator for Notifications<'a> {
 }
 
 impl<'a> Notifications<'a> {
-    fn new(api_server: &'a str, token: &'a str) -> Notifications<'a> {
+    fn new(api_server: &'a str, token: &'a str, etag: &Option<String>) -> Notifications<'a> {
         Notifications {
             next_page_url: format!("{}/notifications", api_server),
             token,
             notifications: vec![].into_iter(),
+            etag: etag.clone(),
+            not_modified: false,
+            poll_interval: None,
+            first_page: true,
         }
     }
 
@@ -198,10 +287,28 @@ impl<'a> Notifications<'a> {
         }
 
         let header_value = format!("Bearer {}", self.token);
-        let headers = vec![("Authorization", header_value.as_str())];
+        let mut headers = vec![("Authorization", header_value.as_str())];
+        let first_page = self.first_page;
+        self.first_page = false;
+        if first_page {
+            if let Some(ref etag) = self.etag {
+                headers.push(("If-None-Match", etag.as_str()));
+            }
+        }
         let result =
             http::http_get_json(&self.next_page_url, Some(Duration::from_secs(3)), headers)?;
 
+        if first_page {
+            self.poll_interval = find_header(&result.headers, "X-Poll-Interval")
+                .and_then(|value| value.parse().ok())
+                .map(Duration::from_secs);
+            if result.code == 304 {
+                self.not_modified = true;
+                return Ok(None);
+            }
+            self.etag = find_header(&result.headers, "ETag").map(str::to_owned);
+        }
+
         self.next_page_url = result
             .headers
             .iter()
@@ -222,6 +329,18 @@ impl<'a> Notifications<'a> {
     }
 }
 
+/// Looks up the value of a header in the raw header lines of a response.
+fn find_header<'a>(headers: &'a [String], name: &str) -> Option<&'a str> {
+    headers.iter().find_map(|header| {
+        let mut parts = header.splitn(2, ':');
+        if parts.next()?.trim().eq_ignore_ascii_case(name) {
+            Some(parts.next()?.trim())
+        } else {
+            None
+        }
+    })
+}
+
 fn parse_links_header(raw_links: &str) -> HashMap<&str, &str> {
     lazy_static! {
         static ref LINKS_REGEX: Regex =
@@ -260,4 +379,16 @@ mod tests {
             )
         );
     }
+
+    #[test]
+    fn it_finds_headers() {
+        let headers = vec![
+            "HTTP/2 200\r\n".to_owned(),
+            "etag: W/\"61e9f87e\"\r\n".to_owned(),
+            "X-Poll-Interval: 60\r\n".to_owned(),
+        ];
+        assert_eq!(find_header(&headers, "ETag"), Some("W/\"61e9f87e\""));
+        assert_eq!(find_header(&headers, "x-poll-interval"), Some("60"));
+        assert_eq!(find_header(&headers, "Link"), None);
+    }
 }
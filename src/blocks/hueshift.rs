@@ -1,8 +1,11 @@
 use std::collections::BTreeMap;
-use std::process::Command;
-use std::time::Duration;
+use std::env;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
 
 use crossbeam_channel::Sender;
+use dbus::ffidisp::stdintf::org_freedesktop_dbus::Properties;
+use dbus::ffidisp::{BusType, Connection};
 use serde_derive::Deserialize;
 
 use crate::blocks::{Block, ConfigBlock, Update};
@@ -11,20 +14,34 @@ use crate::de::deserialize_duration;
 use crate::errors::*;
 use crate::input::{I3BarEvent, MouseButton};
 use crate::scheduler::Task;
-use crate::util::{has_command, pseudo_uuid};
-use crate::widget::I3BarWidget;
+use crate::util::{has_command, pseudo_uuid, FormatTemplate};
+use crate::widget::{I3BarWidget, State};
 use crate::widgets::button::ButtonWidget;
 
+/// The D-Bus service of wl-gammarelay
+const GAMMARELAY: &str = "rs.wl-gammarelay";
+const GAMMARELAY_INTERFACE: &str = "rs.wl.gammarelay";
+
+/// Two left clicks within this time reset the color temperature
+const DOUBLE_CLICK_TIME: Duration = Duration::from_millis(500);
+
 pub struct Hueshift {
     text: ButtonWidget,
     id: String,
     update_interval: Duration,
+    format: FormatTemplate,
     step: u16,
     current_temp: u16,
     max_temp: u16,
     min_temp: u16,
     hue_shifter: Option<HueShifter>,
     click_temp: u16,
+    default_temp: u16,
+    /// The session bus, to talk to wl-gammarelay
+    dbus_conn: Option<Connection>,
+    /// The error of the last change of the color temperature, if it failed
+    error: Option<String>,
+    last_click: Option<Instant>,
 
     //useful, but optional
     #[allow(dead_code)]
@@ -38,6 +55,10 @@ pub struct Hueshift {
 pub enum HueShifter {
     Redshift,
     Sct,
+    Gammastep,
+    /// wl-gammarelay, controlled over D-Bus
+    #[serde(rename = "wl_gammarelay")]
+    WlGammarelay,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -70,6 +91,14 @@ pub struct HueshiftConfig {
     #[serde(default = "HueshiftConfig::default_click_temp")]
     pub click_temp: u16,
 
+    /// The color temperature set by a right or double click
+    #[serde(default = "HueshiftConfig::default_default_temp")]
+    pub default_temp: u16,
+
+    /// Format override
+    #[serde(default = "HueshiftConfig::default_format")]
+    pub format: String,
+
     #[serde(default = "HueshiftConfig::default_color_overrides")]
     pub color_overrides: Option<BTreeMap<String, String>>,
 }
@@ -97,21 +126,43 @@ impl HueshiftConfig {
         100
     }
 
-    /// Prefer any installed shifter, redshift is preferred though.
+    /// Prefer a running wl-gammarelay, then any installed shifter. redshift
+    /// is preferred on X11, and gammastep on Wayland.
     fn default_hue_shifter() -> Option<HueShifter> {
-        if has_command("hueshift", "redshift").unwrap_or(false) {
-            Some(HueShifter::Redshift)
-        } else if has_command("hueshift", "sct").unwrap_or(false) {
-            Some(HueShifter::Sct)
-        } else {
-            None
+        if gammarelay_running() {
+            return Some(HueShifter::WlGammarelay);
         }
+        let commands = if env::var_os("WAYLAND_DISPLAY").is_some() {
+            [
+                ("gammastep", HueShifter::Gammastep),
+                ("redshift", HueShifter::Redshift),
+                ("sct", HueShifter::Sct),
+            ]
+        } else {
+            [
+                ("redshift", HueShifter::Redshift),
+                ("gammastep", HueShifter::Gammastep),
+                ("sct", HueShifter::Sct),
+            ]
+        };
+        commands
+            .iter()
+            .find(|(command, _)| has_command("hueshift", command).unwrap_or(false))
+            .map(|(_, hue_shifter)| hue_shifter.clone())
     }
 
     fn default_click_temp() -> u16 {
         6500
     }
 
+    fn default_default_temp() -> u16 {
+        6500
+    }
+
+    fn default_format() -> String {
+        "{temperature}".to_owned()
+    }
+
     fn default_color_overrides() -> Option<BTreeMap<String, String>> {
         None
     }
@@ -140,9 +191,18 @@ impl ConfigBlock for Hueshift {
         if block_config.min_temp < 1000 || block_config.min_temp > block_config.max_temp {
             min_temp = 1000;
         }
+        let dbus_conn = match block_config.hue_shifter {
+            Some(HueShifter::WlGammarelay) => Some(
+                Connection::get_private(BusType::Session)
+                    .block_error("hueshift", "failed to establish D-Bus connection")?,
+            ),
+            _ => None,
+        };
         Ok(Hueshift {
             id: id.clone(),
             update_interval: block_config.interval,
+            format: FormatTemplate::from_string(&block_config.format)
+                .block_error("hueshift", "Invalid format specified for hueshift")?,
             text: ButtonWidget::new(config.clone(), &id).with_text(&current_temp.to_string()),
             tx_update_request,
             step,
@@ -151,14 +211,113 @@ impl ConfigBlock for Hueshift {
             current_temp,
             hue_shifter: block_config.hue_shifter,
             click_temp: block_config.click_temp,
+            default_temp: block_config.default_temp.max(min_temp).min(max_temp),
+            dbus_conn,
+            error: None,
+            last_click: None,
             config,
         })
     }
 }
 
+/// Whether wl-gammarelay is running on the session bus.
+fn gammarelay_running() -> bool {
+    let conn = match Connection::get_private(BusType::Session) {
+        Ok(conn) => conn,
+        Err(_) => return false,
+    };
+    conn.with_path("org.freedesktop.DBus", "/", 1000)
+        .method_call("org.freedesktop.DBus", "NameHasOwner", (GAMMARELAY,))
+        .map_or(false, |(has_owner,): (bool,)| has_owner)
+}
+
+/// Runs a hue shifter, capturing its output so that failures, e.g. without a
+/// display to talk to, are shown in the block instead of on stderr.
+fn run_hue_shifter(program: &str, args: &[&str]) -> Result<()> {
+    let output = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .block_error("hueshift", &format!("{} not found", program))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        Err(BlockError(
+            "hueshift".to_owned(),
+            format!(
+                "{} failed: {}",
+                program,
+                stderr.lines().next().unwrap_or_default().trim()
+            ),
+        ))
+    }
+}
+
+impl Hueshift {
+    fn set_temp(&self, temp: u16) -> Result<()> {
+        let temp_arg = temp.to_string();
+        match self.hue_shifter {
+            Some(HueShifter::Redshift) => run_hue_shifter("redshift", &["-P", "-O", &temp_arg]),
+            Some(HueShifter::Gammastep) => run_hue_shifter("gammastep", &["-P", "-O", &temp_arg]),
+            Some(HueShifter::Sct) => run_hue_shifter("sct", &[&temp_arg]),
+            Some(HueShifter::WlGammarelay) => self
+                .dbus_conn
+                .as_ref()
+                .internal_error("hueshift", "no D-Bus connection")?
+                .with_path(GAMMARELAY, "/", 1000)
+                .set(GAMMARELAY_INTERFACE, "Temperature", temp)
+                .block_error("hueshift", "failed to set temperature with wl-gammarelay"),
+            None => Err(BlockError(
+                "hueshift".to_owned(),
+                "no supported hue shifter found".to_owned(),
+            )),
+        }
+    }
+
+    /// Changes the color temperature, and keeps the error to show it in the
+    /// block if that fails.
+    fn change_temp(&mut self, temp: u16) {
+        match self.set_temp(temp) {
+            Ok(()) => {
+                self.current_temp = temp;
+                self.error = None;
+            }
+            Err(BlockError(_, message)) | Err(InternalError(_, message, _)) => {
+                self.error = Some(message)
+            }
+            Err(ConfigurationError(message, _)) => self.error = Some(message),
+        }
+    }
+
+    fn render(&mut self) -> Result<()> {
+        match self.error {
+            Some(ref error) => {
+                self.text.set_text(error.clone());
+                self.text.set_state(State::Critical);
+            }
+            None => {
+                let values = map!("{temperature}" => self.current_temp);
+                self.text.set_text(self.format.render_static_str(&values)?);
+                self.text.set_state(State::Idle);
+            }
+        }
+        Ok(())
+    }
+}
+
 impl Block for Hueshift {
     fn update(&mut self) -> Result<Option<Update>> {
-        self.text.set_text(&self.current_temp.to_string());
+        // wl-gammarelay may be controlled by other programs as well
+        if let Some(ref conn) = self.dbus_conn {
+            if let Ok(temp) = conn
+                .with_path(GAMMARELAY, "/", 1000)
+                .get::<u16>(GAMMARELAY_INTERFACE, "Temperature")
+            {
+                self.current_temp = temp;
+            }
+        }
+        self.render()?;
         Ok(Some(self.update_interval.into()))
     }
 
@@ -171,40 +330,40 @@ impl Block for Hueshift {
             if name.as_str() == self.id {
                 match event.button {
                     MouseButton::Left => {
-                        self.current_temp = self.click_temp;
-                        update_hue(&self.hue_shifter, self.current_temp);
-                    }
-                    MouseButton::Right => {
-                        if self.max_temp > 6500 {
-                            self.current_temp = 6500;
-                            reset_hue(&self.hue_shifter);
+                        let double_click = self
+                            .last_click
+                            .map_or(false, |last| last.elapsed() < DOUBLE_CLICK_TIME);
+                        if double_click {
+                            self.last_click = None;
+                            self.change_temp(self.default_temp);
                         } else {
-                            self.current_temp = self.max_temp;
-                            update_hue(&self.hue_shifter, self.current_temp);
+                            self.last_click = Some(Instant::now());
+                            self.change_temp(self.click_temp);
                         }
                     }
+                    MouseButton::Right => {
+                        self.change_temp(self.default_temp);
+                    }
                     mb => {
                         use LogicalDirection::*;
-                        let new_temp: u16;
                         match self.config.scrolling.to_logical_direction(mb) {
                             Some(Up) => {
-                                new_temp = self.current_temp + self.step;
+                                let new_temp = self.current_temp.saturating_add(self.step);
                                 if new_temp <= self.max_temp {
-                                    update_hue(&self.hue_shifter, new_temp);
-                                    self.current_temp = new_temp;
+                                    self.change_temp(new_temp);
                                 }
                             }
                             Some(Down) => {
-                                new_temp = self.current_temp - self.step;
+                                let new_temp = self.current_temp.saturating_sub(self.step);
                                 if new_temp >= self.min_temp {
-                                    update_hue(&self.hue_shifter, new_temp);
-                                    self.current_temp = new_temp;
+                                    self.change_temp(new_temp);
                                 }
                             }
                             None => {}
                         }
                     }
                 }
+                self.render()?;
             }
         }
         Ok(())
@@ -214,44 +373,3 @@ impl Block for Hueshift {
         &self.id
     }
 }
-
-#[inline]
-fn update_hue(hue_shifter: &Option<HueShifter>, new_temp: u16) {
-    match hue_shifter {
-        Some(HueShifter::Redshift) => {
-            Command::new("sh")
-                .args(&[
-                    "-c",
-                    format!("redshift -O {} -P >/dev/null 2>&1", new_temp).as_str(),
-                ])
-                .spawn()
-                .expect("Failed to set new color temperature using redshift.");
-        }
-        Some(HueShifter::Sct) => {
-            Command::new("sh")
-                .args(&["-c", format!("sct {} >/dev/null 2>&1", new_temp).as_str()])
-                .spawn()
-                .expect("Failed to set new color temperature using sct.");
-        }
-        None => {}
-    }
-}
-
-#[inline]
-fn reset_hue(hue_shifter: &Option<HueShifter>) {
-    match hue_shifter {
-        Some(HueShifter::Redshift) => {
-            Command::new("sh")
-                .args(&["-c", "redshift -x >/dev/null 2>&1"])
-                .spawn()
-                .expect("Failed to set new color temperature using redshift.");
-        }
-        Some(HueShifter::Sct) => {
-            Command::new("sh")
-                .args(&["-c", "sct >/dev/null 2>&1"])
-                .spawn()
-                .expect("Failed to set new color temperature using sct.");
-        }
-        None => {}
-    }
-}
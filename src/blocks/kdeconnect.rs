@@ -1,33 +1,188 @@
 use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::{Duration, Instant};
 
 use crossbeam_channel::Sender;
-use dbus::arg;
 use dbus::blocking::{stdintf::org_freedesktop_dbus::Properties, Connection};
+use dbus::message::{MatchRule, MessageType};
 use dbus::Message;
 use serde_derive::Deserialize;
 
 use crate::blocks::{Block, ConfigBlock, Update};
 use crate::config::Config;
 use crate::errors::*;
-use crate::input::I3BarEvent;
+use crate::input::{I3BarEvent, MouseButton};
 use crate::scheduler::Task;
 use crate::util::{battery_level_to_icon, pseudo_uuid, FormatTemplate};
 use crate::widget::{I3BarWidget, State};
 use crate::widgets::button::ButtonWidget;
 
+const KDECONNECT: &str = "org.kde.kdeconnect";
+const DAEMON_PATH: &str = "/modules/kdeconnect";
+const DAEMON: &str = "org.kde.kdeconnect.daemon";
+const DEVICE: &str = "org.kde.kdeconnect.device";
+const BATTERY: &str = "org.kde.kdeconnect.device.battery";
+const NOTIFICATIONS: &str = "org.kde.kdeconnect.device.notifications";
+const FINDMYPHONE: &str = "org.kde.kdeconnect.device.findmyphone";
+const TIMEOUT: Duration = Duration::from_millis(5000);
+
+/// The last known state of the device.
+#[derive(Debug, Default, Clone)]
+struct Device {
+    id: Option<String>,
+    name: String,
+    charge: i32,
+    charging: bool,
+    notif_count: usize,
+    reachable: bool,
+}
+
+impl Device {
+    /// Picks the configured device, or else the first reachable paired
+    /// device, sticking to the current one as long as no other is reachable.
+    fn pick(&mut self, c: &Connection, device_id: &Option<String>) {
+        if device_id.is_some() {
+            self.id = device_id.clone();
+            return;
+        }
+        let daemon = c.with_proxy(KDECONNECT, DAEMON_PATH, TIMEOUT);
+        // method call opts: only_reachable, only_paired=true
+        let first_device = |only_reachable: bool| {
+            let devices: std::result::Result<(Vec<String>,), _> =
+                daemon.method_call(DAEMON, "devices", (only_reachable, true));
+            devices
+                .ok()
+                .and_then(|(devices,)| devices.into_iter().next())
+        };
+        if let Some(id) = first_device(true)
+            .or_else(|| self.id.clone())
+            .or_else(|| first_device(false))
+        {
+            self.id = Some(id);
+        }
+    }
+
+    /// Reads the state of the device from the daemon. The last known values
+    /// are kept while it is unreachable, e.g. while the daemon restarts.
+    fn refresh(&mut self, c: &Connection, device_id: &Option<String>) {
+        self.pick(c, device_id);
+        let id = match self.id {
+            Some(ref id) => id,
+            None => {
+                self.reachable = false;
+                return;
+            }
+        };
+        let p = c.with_proxy(
+            KDECONNECT,
+            format!("/modules/kdeconnect/devices/{}", id),
+            TIMEOUT,
+        );
+
+        self.reachable = p.get(DEVICE, "isReachable").unwrap_or(false);
+        if let Ok(name) = p.get(DEVICE, "name") {
+            self.name = name;
+        }
+        if !self.reachable {
+            return;
+        }
+
+        let charge: std::result::Result<(i32,), _> = p.method_call(BATTERY, "charge", ());
+        if let Ok((charge,)) = charge {
+            self.charge = charge;
+        }
+        let charging: std::result::Result<(bool,), _> = p.method_call(BATTERY, "isCharging", ());
+        if let Ok((charging,)) = charging {
+            self.charging = charging;
+        }
+        let notifications: std::result::Result<(Vec<String>,), _> =
+            p.method_call(NOTIFICATIONS, "activeNotifications", ());
+        if let Ok((notifications,)) = notifications {
+            self.notif_count = notifications.len();
+        }
+    }
+}
+
+/// Follows the daemon in a separate thread. Any signal of the daemon, or the
+/// daemon (re)starting, causes the state of the device to be read again, and
+/// an update of the block to be requested.
+fn monitor(device: Arc<Mutex<Device>>, device_id: Option<String>, id: String, send: Sender<Task>) {
+    thread::Builder::new()
+        .name("kdeconnect".into())
+        .spawn(move || loop {
+            let c = match Connection::new_session() {
+                Ok(c) => c,
+                Err(_) => {
+                    thread::sleep(Duration::from_secs(10));
+                    continue;
+                }
+            };
+
+            let changed = Arc::new(AtomicBool::new(true));
+            let mut daemon_rule = MatchRule::new();
+            daemon_rule.msg_type = Some(MessageType::Signal);
+            daemon_rule.sender = Some(KDECONNECT.into());
+            let daemon_changed = changed.clone();
+            let owner_rule = MatchRule::new_signal("org.freedesktop.DBus", "NameOwnerChanged");
+            let owner_changed = changed.clone();
+            let matched = c
+                .add_match(daemon_rule, move |_: (), _: &Connection, _: &Message| {
+                    daemon_changed.store(true, Ordering::SeqCst);
+                    true
+                })
+                .and_then(|_| {
+                    c.add_match(
+                        owner_rule,
+                        move |(name, _, _): (String, String, String),
+                              _: &Connection,
+                              _: &Message| {
+                            if name == KDECONNECT {
+                                owner_changed.store(true, Ordering::SeqCst);
+                            }
+                            true
+                        },
+                    )
+                });
+            if matched.is_err() {
+                thread::sleep(Duration::from_secs(10));
+                continue;
+            }
+
+            loop {
+                if changed.swap(false, Ordering::SeqCst) {
+                    let mut new_device = match device.lock() {
+                        Ok(device) => device.clone(),
+                        Err(_) => return,
+                    };
+                    new_device.refresh(&c, &device_id);
+                    if let Ok(mut device) = device.lock() {
+                        *device = new_device;
+                    }
+
+                    if send
+                        .send(Task {
+                            id: id.clone(),
+                            update_time: Instant::now(),
+                        })
+                        .is_err()
+                    {
+                        return;
+                    }
+                }
+                // The session bus is gone, connect to it again
+                if c.process(Duration::from_millis(1000)).is_err() {
+                    break;
+                }
+            }
+        })
+        .unwrap();
+}
+
 pub struct KDEConnect {
     id: String,
-    device_id: String,
-    device_name: Arc<Mutex<String>>,
-    battery_charge: Arc<Mutex<i32>>,
-    battery_state: Arc<Mutex<bool>>,
-    notif_count: Arc<Mutex<i32>>,
-    phone_reachable: Arc<Mutex<bool>>,
-    // TODO
-    //notif_text: Arc<Mutex<String>>,
+    device: Arc<Mutex<Device>>,
     bat_good: i32,
     bat_info: i32,
     bat_warning: i32,
@@ -112,353 +267,68 @@ impl ConfigBlock for KDEConnect {
     fn new(block_config: Self::Config, config: Config, send: Sender<Task>) -> Result<Self> {
         let id: String = pseudo_uuid();
 
-        let id1 = id.clone();
-        let id2 = id.clone();
-        let id3 = id.clone();
-        let id4 = id.clone();
-        let id5 = id.clone();
-        let id6 = id.clone();
-        let id7 = id.clone();
-        let send2 = send.clone();
-        let send3 = send.clone();
-        let send4 = send.clone();
-        let send5 = send.clone();
-        let send6 = send.clone();
-        let send7 = send.clone();
-
-        let c = Connection::new_session().block_error(
-            "kdeconnect",
-            &"Failed to establish D-Bus connection".to_string(),
-        )?;
-
-        let device_id = if block_config.device_id.is_none() {
-            // If none specified in block config, just grab the first device found.
-            let p1 = c.with_proxy(
-                "org.kde.kdeconnect",
-                "/modules/kdeconnect",
-                Duration::from_millis(5000),
-            );
-            // method call opts: only_reachable=false, only_paired=true
-            let (devices,): (Vec<String>,) = p1
-                .method_call("org.kde.kdeconnect.daemon", "devices", (false, true))
-                .block_error(
-                    "kdeconnect",
-                    &"Couldn't connect to KDE Connect daemon".to_string(),
-                )?;
-            if devices.is_empty() {
-                return Err(BlockError(
-                    "kdeconnect".to_owned(),
-                    "No devices found.".to_owned(),
-                ));
-            }
-            devices[0].clone()
-        } else {
-            block_config.device_id.unwrap()
-        };
-
-        let p2 = c.with_proxy(
-            "org.kde.kdeconnect",
-            format!("/modules/kdeconnect/devices/{}", device_id),
-            Duration::from_millis(5000),
-        );
-
-        let initial_name: String = p2
-            .get("org.kde.kdeconnect.device", "name")
-            .unwrap_or_else(|_| String::from(""));
-
-        let (initial_charge,): (i32,) = p2
-            .method_call("org.kde.kdeconnect.device.battery", "charge", ())
-            .unwrap_or((0,));
-
-        let (initial_charging,): (bool,) = p2
-            .method_call("org.kde.kdeconnect.device.battery", "isCharging", ())
-            .unwrap_or((false,));
-
-        let (initial_notifications,): (Vec<String>,) = p2
-            .method_call(
-                "org.kde.kdeconnect.device.notifications",
-                "activeNotifications",
-                (),
-            )
-            .unwrap_or((vec![String::from("")],));
-
-        let initial_reachable: bool = p2
-            .get("org.kde.kdeconnect.device", "isReachable")
-            .unwrap_or(false);
-
-        let device_id_copy = device_id.clone();
-        let device_name = Arc::new(Mutex::new(initial_name));
-        let device_name_copy = device_name.clone();
-        let charge = Arc::new(Mutex::new(initial_charge));
-        let charge_copy = charge.clone();
-        // TODO: revisit this lint
-        #[allow(clippy::mutex_atomic)]
-        let charging = Arc::new(Mutex::new(initial_charging));
-        let charging_copy = charging.clone();
-        let notif_count = Arc::new(Mutex::new(initial_notifications.len() as i32));
-        let notif_count_copy1 = notif_count.clone();
-        let notif_count_copy2 = notif_count.clone();
-        let notif_count_copy3 = notif_count.clone();
-        // TODO: revisit this lint
-        #[allow(clippy::mutex_atomic)]
-        let reachable = Arc::new(Mutex::new(initial_reachable));
-        let reachable_copy1 = reachable.clone();
-        let reachable_copy2 = reachable.clone();
-
-        // TODO: See if can reliably get the text and/or app of the most recent notification.
-        // Will need to see if the order of notifications is guaranteed or not.
-        // Also, need to call activeNotifications each time a notification is added/removed/updated,
-        // because the signal only gives us a 'public_id' and no other useful info
-        //let last_notif_text = if initial_notifications.get(0).is_none() {
-        //    Arc::new(Mutex::new("".to_string()))
-        //} else {
-        //    Arc::new(Mutex::new(initial_notifications.get(0).unwrap().to_string()))
-        //};
-
-        thread::Builder::new()
-            .name("kdeconnect".into())
-            .spawn(move || {
-                let c = Connection::new_session()
-                    .expect("Failed to establish D-Bus connection in thread");
-
-                let p = c.with_proxy(
-                    "org.kde.kdeconnect",
-                    format!("/modules/kdeconnect/devices/{}", device_id_copy),
-                    Duration::from_millis(5000),
-                );
-
-                let _battery_state_handler = p.match_signal(
-                    move |s: OrgKdeKdeconnectDeviceBatteryStateChanged,
-                          _: &Connection,
-                          _: &Message| {
-                        let mut charging = charging_copy.lock().unwrap();
-                        *charging = s.charging;
-
-                        // Tell block to update now.
-                        // KDEConnect emits both stateChanged and chargeChanged
-                        // whenever there is an update regardless of whether or
-                        // not they both changed. So we only need to send updates
-                        // in one of the two battery signal handlers. Hopefully
-                        // one day they add proper PropertiesChanged signals.
-                        send.send(Task {
-                            id: id1.clone(),
-                            update_time: Instant::now(),
-                        })
-                        .unwrap();
-
-                        true
-                    },
-                );
-
-                let _battery_charge_handler = p.match_signal(
-                    move |s: OrgKdeKdeconnectDeviceBatteryChargeChanged,
-                          _: &Connection,
-                          _: &Message| {
-                        let mut charge = charge_copy.lock().unwrap();
-                        *charge = s.charge;
-
-                        true
-                    },
-                );
-
-                let _device_name_handler = p.match_signal(
-                    move |s: OrgKdeKdeconnectDeviceNameChanged, _: &Connection, _: &Message| {
-                        let mut name = device_name_copy.lock().unwrap();
-                        *name = s.name;
-
-                        // Tell block to update now.
-                        send2
-                            .send(Task {
-                                id: id2.clone(),
-                                update_time: Instant::now(),
-                            })
-                            .unwrap();
-
-                        true
-                    },
-                );
-
-                let _notification_added_handler = p.match_signal(
-                    move |_s: OrgKdeKdeconnectDeviceNotificationsNotificationPosted,
-                          _: &Connection,
-                          _: &Message| {
-                        let mut notif_count = notif_count_copy1.lock().unwrap();
-                        *notif_count += 1;
-
-                        // Tell block to update now.
-                        send3
-                            .send(Task {
-                                id: id3.clone(),
-                                update_time: Instant::now(),
-                            })
-                            .unwrap();
-
-                        true
-                    },
-                );
-
-                let _notification_removed_handler = p.match_signal(
-                    move |_s: OrgKdeKdeconnectDeviceNotificationsNotificationRemoved,
-                          _: &Connection,
-                          _: &Message| {
-                        let mut notif_count = notif_count_copy2.lock().unwrap();
-                        *notif_count = if *notif_count - 1 < 0 {
-                            0
-                        } else {
-                            *notif_count - 1
-                        };
-
-                        // Tell block to update now.
-                        send4
-                            .send(Task {
-                                id: id4.clone(),
-                                update_time: Instant::now(),
-                            })
-                            .unwrap();
-
-                        true
-                    },
-                );
-
-                let _notification_all_removed_handler = p.match_signal(
-                    move |_s: OrgKdeKdeconnectDeviceNotificationsAllNotificationsRemoved,
-                          _: &Connection,
-                          _: &Message| {
-                        let mut notif_count = notif_count_copy3.lock().unwrap();
-                        *notif_count = 0;
-
-                        // Tell block to update now.
-                        send5
-                            .send(Task {
-                                id: id5.clone(),
-                                update_time: Instant::now(),
-                            })
-                            .unwrap();
-
-                        true
-                    },
-                );
-
-                //if notif_text is ever implemented this may be handy
-                //OrgKdeKdeconnectDeviceNotificationsNotificationUpdated
-
-                let _phone_reachable_handler = p.match_signal(
-                    move |s: OrgKdeKdeconnectDeviceReachableChanged,
-                          _: &Connection,
-                          _: &Message| {
-                        let mut reachable = reachable_copy1.lock().unwrap();
-                        *reachable = s.reachable;
-
-                        // Tell block to update now.
-                        // KDEConnect emits both stateChanged and chargeChanged
-                        // whenever there is an update regardless of whether or
-                        // not they both changed. So we only need to send updates
-                        // in one of the two battery signal handlers. Hopefully
-                        // one day they add proper PropertiesChanged signals.
-                        send6
-                            .send(Task {
-                                id: id6.clone(),
-                                update_time: Instant::now(),
-                            })
-                            .unwrap();
-
-                        true
-                    },
-                );
-
-                let p3 = c.with_proxy(
-                    "org.kde.kdeconnect",
-                    "/modules/kdeconnect",
-                    Duration::from_millis(5000),
-                );
-
-                let _phone_visible_handler = p3.match_signal(
-                    move |s: OrgKdeKdeconnectDaemonDeviceVisibilityChanged,
-                          _: &Connection,
-                          _: &Message| {
-                        // TODO: check if s.id matches our device? Is visible same as reachable?
-                        let mut reachable = reachable_copy2.lock().unwrap();
-                        *reachable = s.is_visible;
-
-                        // Tell block to update now.
-                        send7
-                            .send(Task {
-                                id: id7.clone(),
-                                update_time: Instant::now(),
-                            })
-                            .unwrap();
-
-                        true
-                    },
-                );
-
-                loop {
-                    c.process(Duration::from_millis(1000)).unwrap();
-                }
-            })
-            .unwrap();
+        let device = Arc::new(Mutex::new(Device::default()));
+        monitor(device.clone(), block_config.device_id, id.clone(), send);
 
         Ok(KDEConnect {
+            output: ButtonWidget::new(config.clone(), &id).with_icon("phone_disconnected"),
             id,
-            device_id,
-            device_name,
-            battery_charge: charge,
-            battery_state: charging,
-            notif_count,
-            // TODO
-            //notif_text,
-            phone_reachable: reachable,
+            device,
             bat_good: block_config.bat_good,
             bat_info: block_config.bat_info,
             bat_warning: block_config.bat_warning,
             bat_critical: block_config.bat_critical,
             format: FormatTemplate::from_string(&block_config.format)?,
             format_disconnected: FormatTemplate::from_string(&block_config.format_disconnected)?,
-            output: ButtonWidget::new(config.clone(), "kdeconnect").with_icon("phone"),
             config,
         })
     }
 }
 
+impl KDEConnect {
+    /// Makes the phone ring, to find it.
+    fn ring(&self) -> Result<()> {
+        let id = match self
+            .device
+            .lock()
+            .block_error("kdeconnect", "failed to acquire lock for `device`")?
+            .id
+            .clone()
+        {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+        let c = Connection::new_session()
+            .block_error("kdeconnect", "Failed to establish D-Bus connection")?;
+        let p = c.with_proxy(
+            KDECONNECT,
+            format!("/modules/kdeconnect/devices/{}/findmyphone", id),
+            TIMEOUT,
+        );
+        // Swallow errors, e.g. when the plugin is disabled, rather than nuke the bar.
+        let _: std::result::Result<(), _> = p.method_call(FINDMYPHONE, "ring", ());
+        Ok(())
+    }
+}
+
 impl Block for KDEConnect {
     fn id(&self) -> &str {
         &self.id
     }
 
     fn update(&mut self) -> Result<Option<Update>> {
-        let charge = (*self
-            .battery_charge
-            .lock()
-            .block_error("kdeconnect", "failed to acquire lock for `charge`")?)
-            as i32;
-
-        let charging = *self
-            .battery_state
-            .lock()
-            .block_error("kdeconnect", "failed to acquire lock for `battery_state`")?;
-
-        let notif_count = *self
-            .notif_count
-            .lock()
-            .block_error("kdeconnect", "failed to acquire lock for `notif_count`")?;
-
-        // TODO
-        //let notif_text = (*self
-        //   .notif_text
-        //   .lock()
-        //   .block_error("kdeconnect", "failed to acquire lock for `notif_text`")?)
-        //   .clone();
-
-        let phone_reachable = *self
-            .phone_reachable
-            .lock()
-            .block_error("kdeconnect", "failed to acquire lock for `phone_reachable`")?;
-
-        let name = (*self
-            .device_name
+        let Device {
+            id,
+            name,
+            charge,
+            charging,
+            notif_count,
+            reachable: phone_reachable,
+        } = self
+            .device
             .lock()
-            .block_error("kdeconnect", "failed to acquire lock for `name`")?)
-        .clone();
+            .block_error("kdeconnect", "failed to acquire lock for `device`")?
+            .clone();
 
         let bat_icon = self
             .config
@@ -476,6 +346,7 @@ impl Block for KDEConnect {
 
         let values = map!(
             "{bat_icon}" => bat_icon.trim().to_string(),
+            "{bat}" => if charge < 0 { "x".to_string() } else { charge.to_string() },
             "{bat_charge}" => if charge < 0 { "x".to_string() } else { charge.to_string() },
             "{bat_state}" => charging.to_string(),
             "{notif_icon}" => self.config.icons.get("notification").cloned().unwrap_or_else(|| "".to_string()).trim().to_string(),
@@ -483,7 +354,7 @@ impl Block for KDEConnect {
             // TODO
             //"{notif_text}" => notif_text,
             "{name}" => name,
-            "{id}" => self.device_id.to_string()
+            "{id}" => id.unwrap_or_default()
         );
 
         if (
@@ -514,7 +385,7 @@ impl Block for KDEConnect {
         }
 
         if !phone_reachable {
-            self.output.set_state(State::Critical);
+            self.output.set_state(State::Idle);
             self.output.set_icon("phone_disconnected");
             self.output
                 .set_text(self.format_disconnected.render_static_str(&values)?);
@@ -532,282 +403,10 @@ impl Block for KDEConnect {
         vec![&self.output]
     }
 
-    fn click(&mut self, _: &I3BarEvent) -> Result<()> {
+    fn click(&mut self, event: &I3BarEvent) -> Result<()> {
+        if event.matches_name(&self.id) && event.button == MouseButton::Left {
+            self.ring()?;
+        }
         Ok(())
     }
 }
-
-// Code below generated using the command below and Results changed to explcitly use std::Result
-// `dbus-codegen-rust -d org.kde.kdeconnect -p /modules/kdeconnect/devices/mydeviceid`
-#[derive(Debug)]
-pub struct OrgKdeKdeconnectDeviceNameChanged {
-    pub name: String,
-}
-
-impl arg::AppendAll for OrgKdeKdeconnectDeviceNameChanged {
-    fn append(&self, i: &mut arg::IterAppend) {
-        arg::RefArg::append(&self.name, i);
-    }
-}
-
-impl arg::ReadAll for OrgKdeKdeconnectDeviceNameChanged {
-    fn read(i: &mut arg::Iter) -> std::result::Result<Self, arg::TypeMismatchError> {
-        Ok(OrgKdeKdeconnectDeviceNameChanged { name: i.read()? })
-    }
-}
-
-impl dbus::message::SignalArgs for OrgKdeKdeconnectDeviceNameChanged {
-    const NAME: &'static str = "nameChanged";
-    const INTERFACE: &'static str = "org.kde.kdeconnect.device";
-}
-
-#[derive(Debug)]
-pub struct OrgKdeKdeconnectDeviceBatteryStateChanged {
-    pub charging: bool,
-}
-
-impl arg::AppendAll for OrgKdeKdeconnectDeviceBatteryStateChanged {
-    fn append(&self, i: &mut arg::IterAppend) {
-        arg::RefArg::append(&self.charging, i);
-    }
-}
-
-impl arg::ReadAll for OrgKdeKdeconnectDeviceBatteryStateChanged {
-    fn read(i: &mut arg::Iter) -> std::result::Result<Self, arg::TypeMismatchError> {
-        Ok(OrgKdeKdeconnectDeviceBatteryStateChanged {
-            charging: i.read()?,
-        })
-    }
-}
-
-impl dbus::message::SignalArgs for OrgKdeKdeconnectDeviceBatteryStateChanged {
-    const NAME: &'static str = "stateChanged";
-    const INTERFACE: &'static str = "org.kde.kdeconnect.device.battery";
-}
-
-#[derive(Debug)]
-pub struct OrgKdeKdeconnectDeviceBatteryChargeChanged {
-    pub charge: i32,
-}
-
-impl arg::AppendAll for OrgKdeKdeconnectDeviceBatteryChargeChanged {
-    fn append(&self, i: &mut arg::IterAppend) {
-        arg::RefArg::append(&self.charge, i);
-    }
-}
-
-impl arg::ReadAll for OrgKdeKdeconnectDeviceBatteryChargeChanged {
-    fn read(i: &mut arg::Iter) -> std::result::Result<Self, arg::TypeMismatchError> {
-        Ok(OrgKdeKdeconnectDeviceBatteryChargeChanged { charge: i.read()? })
-    }
-}
-
-impl dbus::message::SignalArgs for OrgKdeKdeconnectDeviceBatteryChargeChanged {
-    const NAME: &'static str = "chargeChanged";
-    const INTERFACE: &'static str = "org.kde.kdeconnect.device.battery";
-}
-
-#[derive(Debug)]
-pub struct OrgKdeKdeconnectDeviceNotificationsNotificationPosted {
-    pub public_id: String,
-}
-
-impl arg::AppendAll for OrgKdeKdeconnectDeviceNotificationsNotificationPosted {
-    fn append(&self, i: &mut arg::IterAppend) {
-        arg::RefArg::append(&self.public_id, i);
-    }
-}
-
-impl arg::ReadAll for OrgKdeKdeconnectDeviceNotificationsNotificationPosted {
-    fn read(i: &mut arg::Iter) -> std::result::Result<Self, arg::TypeMismatchError> {
-        Ok(OrgKdeKdeconnectDeviceNotificationsNotificationPosted {
-            public_id: i.read()?,
-        })
-    }
-}
-
-impl dbus::message::SignalArgs for OrgKdeKdeconnectDeviceNotificationsNotificationPosted {
-    const NAME: &'static str = "notificationPosted";
-    const INTERFACE: &'static str = "org.kde.kdeconnect.device.notifications";
-}
-
-#[derive(Debug)]
-pub struct OrgKdeKdeconnectDeviceNotificationsNotificationRemoved {
-    pub public_id: String,
-}
-
-impl arg::AppendAll for OrgKdeKdeconnectDeviceNotificationsNotificationRemoved {
-    fn append(&self, i: &mut arg::IterAppend) {
-        arg::RefArg::append(&self.public_id, i);
-    }
-}
-
-impl arg::ReadAll for OrgKdeKdeconnectDeviceNotificationsNotificationRemoved {
-    fn read(i: &mut arg::Iter) -> std::result::Result<Self, arg::TypeMismatchError> {
-        Ok(OrgKdeKdeconnectDeviceNotificationsNotificationRemoved {
-            public_id: i.read()?,
-        })
-    }
-}
-
-impl dbus::message::SignalArgs for OrgKdeKdeconnectDeviceNotificationsNotificationRemoved {
-    const NAME: &'static str = "notificationRemoved";
-    const INTERFACE: &'static str = "org.kde.kdeconnect.device.notifications";
-}
-
-#[derive(Debug)]
-pub struct OrgKdeKdeconnectDeviceNotificationsNotificationUpdated {
-    pub public_id: String,
-}
-
-impl arg::AppendAll for OrgKdeKdeconnectDeviceNotificationsNotificationUpdated {
-    fn append(&self, i: &mut arg::IterAppend) {
-        arg::RefArg::append(&self.public_id, i);
-    }
-}
-
-impl arg::ReadAll for OrgKdeKdeconnectDeviceNotificationsNotificationUpdated {
-    fn read(i: &mut arg::Iter) -> std::result::Result<Self, arg::TypeMismatchError> {
-        Ok(OrgKdeKdeconnectDeviceNotificationsNotificationUpdated {
-            public_id: i.read()?,
-        })
-    }
-}
-
-impl dbus::message::SignalArgs for OrgKdeKdeconnectDeviceNotificationsNotificationUpdated {
-    const NAME: &'static str = "notificationUpdated";
-    const INTERFACE: &'static str = "org.kde.kdeconnect.device.notifications";
-}
-
-#[derive(Debug)]
-pub struct OrgKdeKdeconnectDeviceNotificationsAllNotificationsRemoved {}
-
-impl arg::AppendAll for OrgKdeKdeconnectDeviceNotificationsAllNotificationsRemoved {
-    fn append(&self, _: &mut arg::IterAppend) {}
-}
-
-impl arg::ReadAll for OrgKdeKdeconnectDeviceNotificationsAllNotificationsRemoved {
-    fn read(_: &mut arg::Iter) -> std::result::Result<Self, arg::TypeMismatchError> {
-        Ok(OrgKdeKdeconnectDeviceNotificationsAllNotificationsRemoved {})
-    }
-}
-
-impl dbus::message::SignalArgs for OrgKdeKdeconnectDeviceNotificationsAllNotificationsRemoved {
-    const NAME: &'static str = "allNotificationsRemoved";
-    const INTERFACE: &'static str = "org.kde.kdeconnect.device.notifications";
-}
-
-#[derive(Debug)]
-pub struct OrgKdeKdeconnectDeviceReachableChanged {
-    pub reachable: bool,
-}
-
-impl arg::AppendAll for OrgKdeKdeconnectDeviceReachableChanged {
-    fn append(&self, i: &mut arg::IterAppend) {
-        arg::RefArg::append(&self.reachable, i);
-    }
-}
-
-impl arg::ReadAll for OrgKdeKdeconnectDeviceReachableChanged {
-    fn read(i: &mut arg::Iter) -> std::result::Result<Self, arg::TypeMismatchError> {
-        Ok(OrgKdeKdeconnectDeviceReachableChanged {
-            reachable: i.read()?,
-        })
-    }
-}
-
-impl dbus::message::SignalArgs for OrgKdeKdeconnectDeviceReachableChanged {
-    const NAME: &'static str = "reachableChanged";
-    const INTERFACE: &'static str = "org.kde.kdeconnect.device";
-}
-
-// This code was autogenerated using the command below and Results changed to explcitly use std::Result
-// `dbus-codegen-rust -d org.kde.kdeconnect -p /modules/kdeconnect`
-#[derive(Debug)]
-pub struct OrgKdeKdeconnectDaemonDeviceAdded {
-    pub id: String,
-}
-
-impl arg::AppendAll for OrgKdeKdeconnectDaemonDeviceAdded {
-    fn append(&self, i: &mut arg::IterAppend) {
-        arg::RefArg::append(&self.id, i);
-    }
-}
-
-impl arg::ReadAll for OrgKdeKdeconnectDaemonDeviceAdded {
-    fn read(i: &mut arg::Iter) -> std::result::Result<Self, arg::TypeMismatchError> {
-        Ok(OrgKdeKdeconnectDaemonDeviceAdded { id: i.read()? })
-    }
-}
-
-impl dbus::message::SignalArgs for OrgKdeKdeconnectDaemonDeviceAdded {
-    const NAME: &'static str = "deviceAdded";
-    const INTERFACE: &'static str = "org.kde.kdeconnect.daemon";
-}
-
-#[derive(Debug)]
-pub struct OrgKdeKdeconnectDaemonDeviceRemoved {
-    pub id: String,
-}
-
-impl arg::AppendAll for OrgKdeKdeconnectDaemonDeviceRemoved {
-    fn append(&self, i: &mut arg::IterAppend) {
-        arg::RefArg::append(&self.id, i);
-    }
-}
-
-impl arg::ReadAll for OrgKdeKdeconnectDaemonDeviceRemoved {
-    fn read(i: &mut arg::Iter) -> std::result::Result<Self, arg::TypeMismatchError> {
-        Ok(OrgKdeKdeconnectDaemonDeviceRemoved { id: i.read()? })
-    }
-}
-
-impl dbus::message::SignalArgs for OrgKdeKdeconnectDaemonDeviceRemoved {
-    const NAME: &'static str = "deviceRemoved";
-    const INTERFACE: &'static str = "org.kde.kdeconnect.daemon";
-}
-
-#[derive(Debug)]
-pub struct OrgKdeKdeconnectDaemonDeviceVisibilityChanged {
-    pub id: String,
-    pub is_visible: bool,
-}
-
-impl arg::AppendAll for OrgKdeKdeconnectDaemonDeviceVisibilityChanged {
-    fn append(&self, i: &mut arg::IterAppend) {
-        arg::RefArg::append(&self.id, i);
-        arg::RefArg::append(&self.is_visible, i);
-    }
-}
-
-impl arg::ReadAll for OrgKdeKdeconnectDaemonDeviceVisibilityChanged {
-    fn read(i: &mut arg::Iter) -> std::result::Result<Self, arg::TypeMismatchError> {
-        Ok(OrgKdeKdeconnectDaemonDeviceVisibilityChanged {
-            id: i.read()?,
-            is_visible: i.read()?,
-        })
-    }
-}
-
-impl dbus::message::SignalArgs for OrgKdeKdeconnectDaemonDeviceVisibilityChanged {
-    const NAME: &'static str = "deviceVisibilityChanged";
-    const INTERFACE: &'static str = "org.kde.kdeconnect.daemon";
-}
-
-#[derive(Debug)]
-pub struct OrgKdeKdeconnectDaemonDeviceListChanged {}
-
-impl arg::AppendAll for OrgKdeKdeconnectDaemonDeviceListChanged {
-    fn append(&self, _: &mut arg::IterAppend) {}
-}
-
-impl arg::ReadAll for OrgKdeKdeconnectDaemonDeviceListChanged {
-    fn read(_: &mut arg::Iter) -> std::result::Result<Self, arg::TypeMismatchError> {
-        Ok(OrgKdeKdeconnectDaemonDeviceListChanged {})
-    }
-}
-
-impl dbus::message::SignalArgs for OrgKdeKdeconnectDaemonDeviceListChanged {
-    const NAME: &'static str = "deviceListChanged";
-    const INTERFACE: &'static str = "org.kde.kdeconnect.daemon";
-}
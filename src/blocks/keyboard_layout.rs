@@ -19,6 +19,7 @@ use crate::blocks::{Block, ConfigBlock, Update};
 use crate::config::Config;
 use crate::de::deserialize_duration;
 use crate::errors::*;
+use crate::input::{I3BarEvent, MouseButton};
 use crate::scheduler::Task;
 use crate::util::{pseudo_uuid, FormatTemplate};
 use crate::widget::I3BarWidget;
@@ -31,6 +32,8 @@ pub enum KeyboardLayoutDriver {
     LocaleBus,
     KbddBus,
     Sway,
+    #[cfg(feature = "xkb")]
+    XkbEvent,
 }
 
 impl Default for KeyboardLayoutDriver {
@@ -423,6 +426,364 @@ impl KeyboardLayoutMonitor for Sway {
     }
 }
 
+// XkbEvent - listens to the events of the X11 XKB extension, so that layout
+// changes show up immediately without polling. The layouts and variants are
+// read from the same `_XKB_RULES_NAMES` property as `setxkbmap -query` does.
+#[cfg(feature = "xkb")]
+pub struct XkbEvent {
+    layout: Arc<Mutex<(String, String)>>,
+}
+
+#[cfg(feature = "xkb")]
+impl XkbEvent {
+    pub fn new() -> Result<Self> {
+        let layout = xlib::XkbDisplay::open()?.layout()?;
+
+        Ok(XkbEvent {
+            layout: Arc::new(Mutex::new(layout)),
+        })
+    }
+}
+
+#[cfg(feature = "xkb")]
+impl KeyboardLayoutMonitor for XkbEvent {
+    fn keyboard_layout(&self) -> Result<String> {
+        let layout = self
+            .layout
+            .lock()
+            .block_error("keyboard_layout", "mutex poisoned")?;
+        Ok(layout.0.clone())
+    }
+
+    fn keyboard_variant(&self) -> Result<String> {
+        let layout = self
+            .layout
+            .lock()
+            .block_error("keyboard_layout", "mutex poisoned")?;
+        Ok(layout.1.clone())
+    }
+
+    fn must_poll(&self) -> bool {
+        false
+    }
+
+    /// Wait for the XKB events of a display of its own in a separate thread
+    /// and send updates via the `update_request` channel.
+    fn monitor(&self, id: String, update_request: Sender<Task>) {
+        let arc = Arc::clone(&self.layout);
+        thread::Builder::new()
+            .name("keyboard_layout".into())
+            .spawn(move || {
+                let display = match xlib::XkbDisplay::open().and_then(|d| d.listen().map(|_| d)) {
+                    Ok(display) => display,
+                    Err(e) => {
+                        eprintln!("keyboard_layout: failed to listen to XKB events: {}", e);
+                        return;
+                    }
+                };
+                loop {
+                    display.wait_for_change();
+                    match display.layout() {
+                        Ok(layout) => {
+                            if let Ok(mut current) = arc.lock() {
+                                *current = layout;
+                            }
+                        }
+                        Err(e) => eprintln!("keyboard_layout: failed to read the layout: {}", e),
+                    }
+                    if update_request
+                        .send(Task {
+                            id: id.clone(),
+                            update_time: Instant::now(),
+                        })
+                        .is_err()
+                    {
+                        return;
+                    }
+                }
+            })
+            .unwrap();
+    }
+}
+
+/// The parts of Xlib and its XKB extension the `xkbevent` driver uses
+#[cfg(feature = "xkb")]
+mod xlib {
+    use std::ffi::CString;
+    use std::os::raw::{c_char, c_int, c_long, c_uchar, c_uint, c_ulong, c_ushort, c_void};
+    use std::ptr;
+
+    use crate::errors::*;
+
+    enum Display {}
+
+    type Atom = c_ulong;
+    type Window = c_ulong;
+
+    const SUCCESS: c_int = 0;
+    const PROPERTY_CHANGE_MASK: c_long = 1 << 22;
+    const PROPERTY_NOTIFY: c_int = 28;
+    const XKB_USE_CORE_KBD: c_uint = 0x0100;
+    const XKB_STATE_NOTIFY: c_uint = 2;
+    const XKB_GROUP_STATE_MASK: c_ulong = 1 << 4;
+
+    #[repr(C)]
+    #[derive(Default)]
+    #[allow(dead_code)]
+    struct XkbStateRec {
+        group: c_uchar,
+        locked_group: c_uchar,
+        base_group: c_ushort,
+        latched_group: c_ushort,
+        mods: c_uchar,
+        base_mods: c_uchar,
+        latched_mods: c_uchar,
+        locked_mods: c_uchar,
+        compat_state: c_uchar,
+        grab_mods: c_uchar,
+        compat_grab_mods: c_uchar,
+        lookup_mods: c_uchar,
+        compat_lookup_mods: c_uchar,
+        ptr_buttons: c_ushort,
+    }
+
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    #[allow(dead_code)]
+    struct XPropertyEvent {
+        type_: c_int,
+        serial: c_ulong,
+        send_event: c_int,
+        display: *mut Display,
+        window: Window,
+        atom: Atom,
+        time: c_ulong,
+        state: c_int,
+    }
+
+    #[repr(C)]
+    union XEvent {
+        type_: c_int,
+        property: XPropertyEvent,
+        pad: [c_long; 24],
+    }
+
+    #[link(name = "X11")]
+    extern "C" {
+        fn XOpenDisplay(display_name: *const c_char) -> *mut Display;
+        fn XCloseDisplay(display: *mut Display) -> c_int;
+        fn XDefaultRootWindow(display: *mut Display) -> Window;
+        fn XInternAtom(
+            display: *mut Display,
+            atom_name: *const c_char,
+            only_if_exists: c_int,
+        ) -> Atom;
+        fn XSelectInput(display: *mut Display, w: Window, event_mask: c_long) -> c_int;
+        fn XNextEvent(display: *mut Display, event_return: *mut XEvent) -> c_int;
+        fn XPending(display: *mut Display) -> c_int;
+        #[allow(clippy::too_many_arguments)]
+        fn XGetWindowProperty(
+            display: *mut Display,
+            w: Window,
+            property: Atom,
+            long_offset: c_long,
+            long_length: c_long,
+            delete: c_int,
+            req_type: Atom,
+            actual_type_return: *mut Atom,
+            actual_format_return: *mut c_int,
+            nitems_return: *mut c_ulong,
+            bytes_after_return: *mut c_ulong,
+            prop_return: *mut *mut c_uchar,
+        ) -> c_int;
+        fn XFree(data: *mut c_void) -> c_int;
+        fn XkbQueryExtension(
+            display: *mut Display,
+            opcode_return: *mut c_int,
+            event_base_return: *mut c_int,
+            error_base_return: *mut c_int,
+            major_in_out: *mut c_int,
+            minor_in_out: *mut c_int,
+        ) -> c_int;
+        fn XkbSelectEventDetails(
+            display: *mut Display,
+            device_id: c_uint,
+            event_type: c_uint,
+            affect: c_ulong,
+            details: c_ulong,
+        ) -> c_int;
+        fn XkbGetState(
+            display: *mut Display,
+            device_spec: c_uint,
+            state: *mut XkbStateRec,
+        ) -> c_int;
+    }
+
+    /// Picks the layout and variant of the given group from the value of the
+    /// `_XKB_RULES_NAMES` property: the rules, model, layouts, variants and
+    /// options, separated by NUL characters.
+    pub(super) fn group_layout(rules_names: &str, group: usize) -> Option<(String, String)> {
+        let mut names = rules_names.split('\0');
+        let layouts = names.nth(2)?;
+        let variants = names.next().unwrap_or("");
+        let layout = match layouts.split(',').nth(group) {
+            Some(layout) => layout,
+            // The group may briefly be out of date after the layouts changed,
+            // so all of them are shown until the next event
+            None => layouts,
+        };
+        let variant = variants.split(',').nth(group).unwrap_or("");
+        Some((layout.to_string(), variant.to_string()))
+    }
+
+    /// A connection to the X server with the XKB extension. Xlib isn't used
+    /// across threads, so every thread opens one of its own.
+    pub(super) struct XkbDisplay {
+        display: *mut Display,
+        root: Window,
+        rules_names: Atom,
+        xkb_event_type: c_int,
+    }
+
+    impl XkbDisplay {
+        pub fn open() -> Result<Self> {
+            let display = unsafe { XOpenDisplay(ptr::null()) };
+            if display.is_null() {
+                return Err(BlockError(
+                    "keyboard_layout".to_string(),
+                    "Failed to open the X display.".to_string(),
+                ));
+            }
+            // Closes the display if the XKB extension is missing
+            let mut xkb_display = XkbDisplay {
+                display,
+                root: unsafe { XDefaultRootWindow(display) },
+                rules_names: 0,
+                xkb_event_type: 0,
+            };
+
+            let (mut opcode, mut error_base) = (0, 0);
+            let (mut major, mut minor) = (1, 0);
+            let found = unsafe {
+                XkbQueryExtension(
+                    display,
+                    &mut opcode,
+                    &mut xkb_display.xkb_event_type,
+                    &mut error_base,
+                    &mut major,
+                    &mut minor,
+                )
+            };
+            if found == 0 {
+                return Err(BlockError(
+                    "keyboard_layout".to_string(),
+                    "The X server doesn't support the XKB extension.".to_string(),
+                ));
+            }
+
+            let name = CString::new("_XKB_RULES_NAMES").unwrap();
+            xkb_display.rules_names = unsafe { XInternAtom(display, name.as_ptr(), 0) };
+            Ok(xkb_display)
+        }
+
+        /// Selects the events for switching between layouts, and for changing
+        /// the layouts with e.g. `setxkbmap`.
+        pub fn listen(&self) -> Result<()> {
+            let selected = unsafe {
+                XkbSelectEventDetails(
+                    self.display,
+                    XKB_USE_CORE_KBD,
+                    XKB_STATE_NOTIFY,
+                    XKB_GROUP_STATE_MASK,
+                    XKB_GROUP_STATE_MASK,
+                )
+            };
+            if selected == 0 {
+                return Err(BlockError(
+                    "keyboard_layout".to_string(),
+                    "Failed to select the XKB events.".to_string(),
+                ));
+            }
+            unsafe { XSelectInput(self.display, self.root, PROPERTY_CHANGE_MASK) };
+            Ok(())
+        }
+
+        /// Blocks until the layout may have changed, taking all the events
+        /// which arrived in the meantime.
+        pub fn wait_for_change(&self) {
+            let mut changed = false;
+            loop {
+                let mut event = XEvent { pad: [0; 24] };
+                unsafe { XNextEvent(self.display, &mut event) };
+                changed |= unsafe {
+                    event.type_ == self.xkb_event_type
+                        || (event.type_ == PROPERTY_NOTIFY
+                            && event.property.atom == self.rules_names)
+                };
+                if changed && unsafe { XPending(self.display) } == 0 {
+                    return;
+                }
+            }
+        }
+
+        /// The layout and variant of the active group.
+        pub fn layout(&self) -> Result<(String, String)> {
+            let mut state = XkbStateRec::default();
+            if unsafe { XkbGetState(self.display, XKB_USE_CORE_KBD, &mut state) } != SUCCESS {
+                return Err(BlockError(
+                    "keyboard_layout".to_string(),
+                    "Failed to get the XKB state.".to_string(),
+                ));
+            }
+
+            let (mut actual_type, mut actual_format) = (0, 0);
+            let (mut items, mut bytes_after) = (0, 0);
+            let mut data = ptr::null_mut();
+            let status = unsafe {
+                XGetWindowProperty(
+                    self.display,
+                    self.root,
+                    self.rules_names,
+                    0,
+                    1024,
+                    0,
+                    0,
+                    &mut actual_type,
+                    &mut actual_format,
+                    &mut items,
+                    &mut bytes_after,
+                    &mut data,
+                )
+            };
+            if status != SUCCESS || data.is_null() {
+                return Err(BlockError(
+                    "keyboard_layout".to_string(),
+                    "Failed to read the _XKB_RULES_NAMES property.".to_string(),
+                ));
+            }
+            let rules_names = unsafe {
+                let bytes = std::slice::from_raw_parts(data, items as usize);
+                let rules_names = String::from_utf8_lossy(bytes).into_owned();
+                XFree(data as *mut c_void);
+                rules_names
+            };
+
+            group_layout(&rules_names, state.group as usize).ok_or_else(|| {
+                BlockError(
+                    "keyboard_layout".to_string(),
+                    "Could not find the layouts in _XKB_RULES_NAMES.".to_string(),
+                )
+            })
+        }
+    }
+
+    impl Drop for XkbDisplay {
+        fn drop(&mut self) {
+            unsafe { XCloseDisplay(self.display) };
+        }
+    }
+}
+
 #[derive(Deserialize, Debug, Default, Clone)]
 #[serde(default, deny_unknown_fields)]
 pub struct KeyboardLayoutConfig {
@@ -438,6 +799,12 @@ pub struct KeyboardLayoutConfig {
 
     sway_kb_identifier: String,
 
+    /// Short labels for layout names, e.g. `"English (US)" = "EN"`
+    mappings: Option<BTreeMap<String, String>>,
+
+    /// Shell command to switch to the next layout with on left click
+    switch_cmd: Option<String>,
+
     #[serde(default = "KeyboardLayoutConfig::default_color_overrides")]
     pub color_overrides: Option<BTreeMap<String, String>>,
 }
@@ -448,7 +815,7 @@ impl KeyboardLayoutConfig {
     }
 
     fn default_interval() -> Duration {
-        Duration::from_secs(60)
+        Duration::from_secs(1)
     }
 
     fn default_color_overrides() -> Option<BTreeMap<String, String>> {
@@ -462,6 +829,8 @@ pub struct KeyboardLayout {
     monitor: Box<dyn KeyboardLayoutMonitor>,
     update_interval: Option<Duration>,
     format: FormatTemplate,
+    mappings: Option<BTreeMap<String, String>>,
+    switch_cmd: Option<String>,
 }
 
 impl ConfigBlock for KeyboardLayout {
@@ -486,6 +855,12 @@ impl ConfigBlock for KeyboardLayout {
                 monitor.monitor(id.clone(), send);
                 Box::new(monitor)
             }
+            #[cfg(feature = "xkb")]
+            KeyboardLayoutDriver::XkbEvent => {
+                let monitor = XkbEvent::new()?;
+                monitor.monitor(id.clone(), send);
+                Box::new(monitor)
+            }
         };
         let update_interval = if monitor.must_poll() {
             Some(block_config.interval)
@@ -502,6 +877,8 @@ impl ConfigBlock for KeyboardLayout {
                 "keyboard_layout",
                 "Invalid format specified for keyboard_layout",
             )?,
+            mappings: block_config.mappings,
+            switch_cmd: block_config.switch_cmd,
         })
     }
 }
@@ -513,6 +890,10 @@ impl Block for KeyboardLayout {
 
     fn update(&mut self) -> Result<Option<Update>> {
         let layout = self.monitor.keyboard_layout()?;
+        let layout = match self.mappings {
+            Some(ref mappings) => mappings.get(&layout).cloned().unwrap_or(layout),
+            None => layout,
+        };
         let variant = self.monitor.keyboard_variant()?;
         let values = map!(
             "{layout}" => layout,
@@ -527,4 +908,43 @@ impl Block for KeyboardLayout {
     fn view(&self) -> Vec<&dyn I3BarWidget> {
         vec![&self.output]
     }
+
+    fn click(&mut self, e: &I3BarEvent) -> Result<()> {
+        if !e.matches_name(&self.id) || e.button != MouseButton::Left {
+            return Ok(());
+        }
+        if let Some(ref cmd) = self.switch_cmd {
+            // Wait for the command, so that the new layout is shown right away
+            Command::new("sh")
+                .args(["-c", cmd])
+                .status()
+                .block_error("keyboard_layout", "Failed to run switch_cmd")?;
+            self.update()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "xkb"))]
+mod tests {
+    use super::xlib::group_layout;
+
+    #[test]
+    fn test_group_layout() {
+        let rules_names = "evdev\0pc105\0us,de\0,nodeadkeys\0grp:alt_shift_toggle\0";
+        assert_eq!(
+            group_layout(rules_names, 0),
+            Some(("us".to_string(), "".to_string()))
+        );
+        assert_eq!(
+            group_layout(rules_names, 1),
+            Some(("de".to_string(), "nodeadkeys".to_string()))
+        );
+        // A group which isn't known yet shows all the layouts
+        assert_eq!(
+            group_layout(rules_names, 2),
+            Some(("us,de".to_string(), "".to_string()))
+        );
+        assert_eq!(group_layout("evdev\0pc105", 0), None);
+    }
 }
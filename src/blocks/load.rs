@@ -24,6 +24,7 @@ pub struct Load {
     minimum_info: f32,
     minimum_warning: f32,
     minimum_critical: f32,
+    per_core: bool,
 }
 
 #[derive(Deserialize, Debug, Default, Clone)]
@@ -49,6 +50,10 @@ pub struct LoadConfig {
     #[serde(default = "LoadConfig::default_critical")]
     pub critical: f32,
 
+    /// Divide the load averages by the number of logical cores
+    #[serde(default = "LoadConfig::default_per_core")]
+    pub per_core: bool,
+
     #[serde(default = "LoadConfig::default_color_overrides")]
     pub color_overrides: Option<BTreeMap<String, String>>,
 }
@@ -74,6 +79,10 @@ impl LoadConfig {
         0.9
     }
 
+    fn default_per_core() -> bool {
+        false
+    }
+
     fn default_color_overrides() -> Option<BTreeMap<String, String>> {
         None
     }
@@ -107,6 +116,7 @@ impl ConfigBlock for Load {
             minimum_info: block_config.info,
             minimum_warning: block_config.warning,
             minimum_critical: block_config.critical,
+            per_core: block_config.per_core,
             format: FormatTemplate::from_string(&block_config.format)
                 .block_error("load", "Invalid format specified for load")?,
             text,
@@ -127,16 +137,31 @@ impl Block for Load {
         f.read_to_string(&mut loadavg)
             .block_error("load", "Failed to read the load average of your system!")?;
 
-        let split: Vec<&str> = (&loadavg).split(' ').collect();
-
-        let values = map!("{1m}" => split[0],
-                          "{5m}" => split[1],
-                          "{15m}" => split[2]);
-
-        let used_perc = values["{1m}"]
-            .parse::<f32>()
-            .block_error("load", "failed to parse float percentage")?
-            / self.logical_cores as f32;
+        let mut averages = [0.0; 3];
+        for (average, value) in averages.iter_mut().zip(loadavg.split(' ')) {
+            *average = value
+                .parse::<f32>()
+                .block_error("load", "failed to parse float percentage")?;
+        }
+
+        // The thresholds always apply to the load of a single core
+        let used_perc = averages[0] / self.logical_cores as f32;
+
+        if self.per_core {
+            for average in averages.iter_mut() {
+                *average /= self.logical_cores as f32;
+            }
+        }
+
+        let load1 = format!("{:.2}", averages[0]);
+        let load5 = format!("{:.2}", averages[1]);
+        let load15 = format!("{:.2}", averages[2]);
+        let values = map!("{1m}" => &load1,
+                          "{5m}" => &load5,
+                          "{15m}" => &load15,
+                          "{load1}" => &load1,
+                          "{load5}" => &load5,
+                          "{load15}" => &load15);
 
         self.text.set_state(match used_perc {
             x if x > self.minimum_critical => State::Critical,
@@ -0,0 +1,385 @@
+use std::collections::{BTreeMap, HashMap};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::{unbounded, Receiver, RecvTimeoutError, Sender};
+use curl::easy::Easy;
+use serde_derive::Deserialize;
+
+use crate::blocks::{Block, ConfigBlock, Update};
+use crate::config::Config;
+use crate::de::deserialize_duration;
+use crate::errors::*;
+use crate::input::{I3BarEvent, MouseButton};
+use crate::scheduler::Task;
+use crate::util::{pseudo_uuid, FormatTemplate};
+use crate::widget::{I3BarWidget, State};
+use crate::widgets::button::ButtonWidget;
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct MailAccountConfig {
+    /// Makes the unread mails of this account available as `{unread_<name>}`
+    #[serde(default)]
+    pub name: Option<String>,
+
+    /// The IMAP server, which is connected to over TLS
+    pub server: String,
+
+    #[serde(default = "MailAccountConfig::default_port")]
+    pub port: u16,
+
+    pub username: String,
+
+    /// A command printing the password, e.g. `pass show mail`
+    pub password_cmd: String,
+
+    /// The folders to count the unread mails of
+    #[serde(default = "MailAccountConfig::default_folders")]
+    pub folders: Vec<String>,
+}
+
+impl MailAccountConfig {
+    fn default_port() -> u16 {
+        993
+    }
+
+    fn default_folders() -> Vec<String> {
+        vec!["INBOX".to_owned()]
+    }
+}
+
+/// Quotes a string for IMAP commands.
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Reads the number of unseen mails from the response to `STATUS folder
+/// (UNSEEN)`, e.g. `* STATUS INBOX (UNSEEN 3)`.
+fn parse_unseen(response: &str) -> Option<u32> {
+    let start = response.find("UNSEEN ")? + "UNSEEN ".len();
+    let digits: String = response[start..]
+        .chars()
+        .take_while(char::is_ascii_digit)
+        .collect();
+    digits.parse().ok()
+}
+
+fn run_password_cmd(cmd: &str) -> Result<String> {
+    let output = Command::new("sh")
+        .args(&["-c", cmd])
+        .output()
+        .block_error("mail", &format!("Failed to run password_cmd '{}'", cmd))?;
+    if !output.status.success() {
+        return Err(BlockError(
+            "mail".to_owned(),
+            format!("password_cmd '{}' failed", cmd),
+        ));
+    }
+    // Like `pass`, the password is the first line
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .to_owned())
+}
+
+struct Account {
+    config: MailAccountConfig,
+    /// The connection is kept open by curl between checks. The handle is
+    /// dropped once a check fails, so that the next check connects again and
+    /// runs `password_cmd` again.
+    handle: Option<Easy>,
+}
+
+impl Account {
+    fn connect(&self) -> Result<Easy> {
+        let password = run_password_cmd(&self.config.password_cmd)?;
+        let mut easy = Easy::new();
+        easy.url(&format!(
+            "imaps://{}:{}/",
+            self.config.server, self.config.port
+        ))?;
+        easy.username(&self.config.username)?;
+        easy.password(&password)?;
+        easy.connect_timeout(Duration::from_secs(10))?;
+        easy.timeout(Duration::from_secs(30))?;
+        Ok(easy)
+    }
+
+    fn unseen(easy: &mut Easy, folder: &str) -> Result<u32> {
+        easy.custom_request(&format!("STATUS {} (UNSEEN)", quote(folder)))?;
+        let mut response = Vec::new();
+        {
+            let mut transfer = easy.transfer();
+            transfer.write_function(|data| {
+                response.extend_from_slice(data);
+                Ok(data.len())
+            })?;
+            transfer.perform()?;
+        }
+        parse_unseen(&String::from_utf8_lossy(&response)).block_error(
+            "mail",
+            &format!("Unexpected response to STATUS of {}", folder),
+        )
+    }
+
+    /// Counts the unread mails in all folders of the account.
+    fn check(&mut self) -> Result<u32> {
+        let mut easy = match self.handle.take() {
+            Some(easy) => easy,
+            None => self.connect()?,
+        };
+        let mut unread = 0;
+        for folder in &self.config.folders {
+            unread += Account::unseen(&mut easy, folder)?;
+        }
+        self.handle = Some(easy);
+        Ok(unread)
+    }
+}
+
+/// What the worker thread found out so far.
+#[derive(Debug, Default)]
+struct Status {
+    /// The unread mails of each account by the last successful check
+    unread: Vec<Option<u32>>,
+    /// Whether the last check of any account failed
+    failed: bool,
+}
+
+/// Checks the accounts in the background, as connecting to the servers may
+/// take a while, and requests an update of the block after each check.
+fn make_thread(
+    mut accounts: Vec<Account>,
+    interval: Duration,
+    status: Arc<Mutex<Status>>,
+    rx_check: Receiver<()>,
+    id: String,
+    tx_update_request: Sender<Task>,
+) {
+    thread::Builder::new()
+        .name("mail".into())
+        .spawn(move || loop {
+            let results: Vec<Result<u32>> = accounts.iter_mut().map(Account::check).collect();
+            if let Ok(mut status) = status.lock() {
+                status.failed = results.iter().any(Result::is_err);
+                for (unread, result) in status.unread.iter_mut().zip(results) {
+                    if let Ok(count) = result {
+                        *unread = Some(count);
+                    }
+                }
+            }
+            if tx_update_request
+                .send(Task {
+                    id: id.clone(),
+                    update_time: Instant::now(),
+                })
+                .is_err()
+            {
+                return;
+            }
+
+            match rx_check.recv_timeout(interval) {
+                Ok(()) => rx_check.try_iter().for_each(drop),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        })
+        .unwrap();
+}
+
+pub struct Mail {
+    output: ButtonWidget,
+    id: String,
+    format: FormatTemplate,
+    hide_when_zero: bool,
+    /// The `{unread_<name>}` placeholder of each account, if it has a name
+    placeholders: Vec<Option<String>>,
+    status: Arc<Mutex<Status>>,
+    tx_check: Sender<()>,
+    hidden: bool,
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct MailConfig {
+    /// Update interval in seconds
+    #[serde(
+        default = "MailConfig::default_interval",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub interval: Duration,
+
+    /// Format override
+    #[serde(default = "MailConfig::default_format")]
+    pub format: String,
+
+    /// Hide the block while there are no unread mails
+    #[serde(default = "MailConfig::default_hide_when_zero")]
+    pub hide_when_zero: bool,
+
+    pub accounts: Vec<MailAccountConfig>,
+
+    #[serde(default = "MailConfig::default_color_overrides")]
+    pub color_overrides: Option<BTreeMap<String, String>>,
+}
+
+impl MailConfig {
+    fn default_interval() -> Duration {
+        Duration::from_secs(300)
+    }
+
+    fn default_format() -> String {
+        "{unread}".to_owned()
+    }
+
+    fn default_hide_when_zero() -> bool {
+        false
+    }
+
+    fn default_color_overrides() -> Option<BTreeMap<String, String>> {
+        None
+    }
+}
+
+impl ConfigBlock for Mail {
+    type Config = MailConfig;
+
+    fn new(
+        block_config: Self::Config,
+        config: Config,
+        tx_update_request: Sender<Task>,
+    ) -> Result<Self> {
+        if block_config.accounts.is_empty() {
+            return Err(BlockError(
+                "mail".to_owned(),
+                "No accounts configured".to_owned(),
+            ));
+        }
+
+        let id = pseudo_uuid();
+        let placeholders = block_config
+            .accounts
+            .iter()
+            .map(|account| {
+                account
+                    .name
+                    .as_ref()
+                    .map(|name| format!("{{unread_{}}}", name))
+            })
+            .collect();
+        let status = Arc::new(Mutex::new(Status {
+            unread: vec![None; block_config.accounts.len()],
+            failed: false,
+        }));
+        let (tx_check, rx_check) = unbounded();
+
+        make_thread(
+            block_config
+                .accounts
+                .into_iter()
+                .map(|config| Account {
+                    config,
+                    handle: None,
+                })
+                .collect(),
+            block_config.interval,
+            status.clone(),
+            rx_check,
+            id.clone(),
+            tx_update_request,
+        );
+
+        Ok(Mail {
+            output: ButtonWidget::new(config, &id).with_icon("mail"),
+            id,
+            format: FormatTemplate::from_string(&block_config.format)
+                .block_error("mail", "Invalid format specified for mail")?,
+            hide_when_zero: block_config.hide_when_zero,
+            placeholders,
+            status,
+            tx_check,
+            hidden: block_config.hide_when_zero,
+        })
+    }
+}
+
+impl Block for Mail {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn view(&self) -> Vec<&dyn I3BarWidget> {
+        if self.hidden {
+            vec![]
+        } else {
+            vec![&self.output]
+        }
+    }
+
+    fn update(&mut self) -> Result<Option<Update>> {
+        let (unread, failed) = {
+            let status = self.status.lock().block_error("mail", "mutex poisoned")?;
+            (status.unread.clone(), status.failed)
+        };
+
+        let render = |unread: Option<u32>| unread.map_or_else(|| "?".to_owned(), |n| n.to_string());
+        let total = unread.iter().copied().sum::<Option<u32>>();
+        let mut values: HashMap<&str, String> = map!("{unread}" => render(total));
+        for (placeholder, unread) in self.placeholders.iter().zip(&unread) {
+            if let Some(placeholder) = placeholder {
+                values.insert(placeholder.as_str(), render(*unread));
+            }
+        }
+        self.output
+            .set_text(self.format.render_static_str(&values)?);
+
+        let known_total: u32 = unread.iter().flatten().sum();
+        self.output.set_state(match known_total {
+            _ if failed => State::Warning,
+            0 => State::Idle,
+            _ => State::Info,
+        });
+        self.hidden = self.hide_when_zero && known_total == 0 && !failed;
+
+        // Updates are requested by the checking thread
+        Ok(None)
+    }
+
+    fn click(&mut self, event: &I3BarEvent) -> Result<()> {
+        if event.matches_name(&self.id)
+            && event.button == MouseButton::Left
+            && self.tx_check.send(()).is_err()
+        {
+            self.output.set_text("checking thread exited".to_owned());
+            self.output.set_state(State::Critical);
+            self.hidden = false;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_unseen() {
+        assert_eq!(parse_unseen("* STATUS INBOX (UNSEEN 3)\r\n"), Some(3));
+        assert_eq!(
+            parse_unseen("* STATUS \"Lists/rust\" (UNSEEN 120)\r\n"),
+            Some(120)
+        );
+        assert_eq!(parse_unseen("* STATUS INBOX ()\r\n"), None);
+    }
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote("INBOX"), "\"INBOX\"");
+        assert_eq!(quote("a \"b\" \\c"), "\"a \\\"b\\\" \\\\c\"");
+    }
+}
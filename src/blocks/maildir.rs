@@ -1,17 +1,25 @@
 use std::collections::BTreeMap;
-use std::time::Duration;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
 
 use crossbeam_channel::Sender;
+use inotify::{Inotify, WatchMask};
 use maildir::Maildir as ExtMaildir;
+use nix::errno::Errno;
+use nix::poll::{poll, PollFd, PollFlags};
 use serde_derive::Deserialize;
 
 use crate::blocks::{Block, ConfigBlock, Update};
 use crate::config::Config;
 use crate::de::deserialize_duration;
 use crate::errors::*;
-use crate::input::I3BarEvent;
+use crate::input::{I3BarEvent, MouseButton};
 use crate::scheduler::Task;
-use crate::util::pseudo_uuid;
+use crate::subprocess::spawn_child_async;
+use crate::util::{pseudo_uuid, FormatTemplate};
 use crate::widget::{I3BarWidget, State};
 use crate::widgets::text::TextWidget;
 
@@ -31,6 +39,15 @@ impl MailType {
             MailType::All => maildir.count_new() + maildir.count_cur(),
         }
     }
+
+    /// The subdirectories of a maildir holding the mails counted.
+    fn subdirs(&self) -> &'static [&'static str] {
+        match self {
+            MailType::New => &["new"],
+            MailType::Cur => &["cur"],
+            MailType::All => &["new", "cur"],
+        }
+    }
 }
 
 impl Default for MailType {
@@ -43,6 +60,13 @@ pub struct Maildir {
     text: TextWidget,
     id: String,
     update_interval: Duration,
+    format: FormatTemplate,
+    /// Whether all inboxes are watched for changes, so that there is no need
+    /// to poll them
+    watched: bool,
+    /// Dropped to stop watching the inboxes
+    stop_watching: Option<UnixStream>,
+    on_click: Option<String>,
     inboxes: Vec<String>,
     threshold_warning: usize,
     threshold_critical: usize,
@@ -59,6 +83,8 @@ pub struct MaildirConfig {
     )]
     pub interval: Duration,
     pub inboxes: Vec<String>,
+    #[serde(default = "MaildirConfig::default_format")]
+    pub format: String,
     #[serde(default = "MaildirConfig::default_threshold_warning")]
     pub threshold_warning: usize,
     #[serde(default = "MaildirConfig::default_threshold_critical")]
@@ -75,6 +101,9 @@ impl MaildirConfig {
     fn default_interval() -> Duration {
         Duration::from_secs(5)
     }
+    fn default_format() -> String {
+        "{count}".to_owned()
+    }
     fn default_threshold_warning() -> usize {
         1
     }
@@ -89,19 +118,101 @@ impl MaildirConfig {
     }
 }
 
+/// Watches the maildirs for mails arriving or being moved in a separate
+/// thread, and schedules an update of the block after each change. Returns
+/// whether all of them are watched, and a socket which stops the thread once
+/// it is dropped.
+fn watch_inboxes(
+    inboxes: &[String],
+    display_type: &MailType,
+    id: String,
+    tx_update_request: Sender<Task>,
+) -> (bool, Option<UnixStream>) {
+    let (mut notify, (stop, stopped)) = match (Inotify::init(), UnixStream::pair()) {
+        (Ok(notify), Ok(pair)) => (notify, pair),
+        _ => return (false, None),
+    };
+    let mut watched = true;
+    for inbox in inboxes {
+        for subdir in display_type.subdirs() {
+            watched &= notify
+                .add_watch(
+                    Path::new(inbox).join(subdir),
+                    WatchMask::CREATE
+                        | WatchMask::DELETE
+                        | WatchMask::MOVED_FROM
+                        | WatchMask::MOVED_TO,
+                )
+                .is_ok();
+        }
+    }
+
+    thread::Builder::new()
+        .name("maildir".into())
+        .spawn(move || {
+            let mut buffer = [0; 1024];
+            let mut fds = [
+                PollFd::new(notify.as_raw_fd(), PollFlags::POLLIN),
+                PollFd::new(stopped.as_raw_fd(), PollFlags::POLLIN),
+            ];
+            loop {
+                match poll(&mut fds, -1) {
+                    Ok(_) => {}
+                    Err(nix::Error::Sys(Errno::EINTR)) => continue,
+                    Err(_) => return,
+                }
+                // The other end hangs up once the block is shut down, and
+                // the watches are dropped along with the thread
+                if fds[1].revents().map_or(true, |events| !events.is_empty()) {
+                    return;
+                }
+                if notify.read_events(&mut buffer).is_err() {
+                    return;
+                }
+
+                if tx_update_request
+                    .send(Task {
+                        id: id.clone(),
+                        update_time: Instant::now(),
+                    })
+                    .is_err()
+                {
+                    return;
+                }
+
+                // Avoid update spam while a whole batch of mails is synced.
+                thread::sleep(Duration::from_millis(250))
+            }
+        })
+        .unwrap();
+
+    (watched, Some(stop))
+}
+
 impl ConfigBlock for Maildir {
     type Config = MaildirConfig;
 
     fn new(
         block_config: Self::Config,
         config: Config,
-        _tx_update_request: Sender<Task>,
+        tx_update_request: Sender<Task>,
     ) -> Result<Self> {
         let id = pseudo_uuid();
         let widget = TextWidget::new(config, &id).with_text("");
+        let (watched, stop_watching) = watch_inboxes(
+            &block_config.inboxes,
+            &block_config.display_type,
+            id.clone(),
+            tx_update_request,
+        );
         Ok(Maildir {
             id,
             update_interval: block_config.interval,
+            format: FormatTemplate::from_string(&block_config.format)
+                .block_error("maildir", "Invalid format specified for maildir")?,
+            watched,
+            stop_watching,
+            on_click: None,
             text: if block_config.icon {
                 widget.with_icon("mail")
             } else {
@@ -113,6 +224,10 @@ impl ConfigBlock for Maildir {
             display_type: block_config.display_type,
         })
     }
+
+    fn override_on_click(&mut self) -> Option<&mut Option<String>> {
+        Some(&mut self.on_click)
+    }
 }
 
 impl Block for Maildir {
@@ -130,18 +245,40 @@ impl Block for Maildir {
             state = State::Warning;
         }
         self.text.set_state(state);
-        self.text.set_text(format!("{}", newmails));
-        Ok(Some(self.update_interval.into()))
+        let values = map!("{count}" => newmails);
+        self.text.set_text(self.format.render_static_str(&values)?);
+
+        // Fall back to polling if the maildirs can't be watched, e.g. because
+        // one doesn't exist yet.
+        if self.watched {
+            Ok(None)
+        } else {
+            Ok(Some(self.update_interval.into()))
+        }
     }
 
     fn view(&self) -> Vec<&dyn I3BarWidget> {
         vec![&self.text]
     }
 
-    fn click(&mut self, _: &I3BarEvent) -> Result<()> {
+    fn click(&mut self, event: &I3BarEvent) -> Result<()> {
+        if event.matches_name(&self.id) && event.button == MouseButton::Left {
+            if let Some(ref cmd) = self.on_click {
+                // Shown until the next update, e.g. if the mail client is missing
+                if let Err(e) = spawn_child_async("sh", &["-c", cmd]) {
+                    self.text
+                        .set_text(format!("could not spawn '{}': {}", cmd, e));
+                    self.text.set_state(State::Critical);
+                }
+            }
+        }
         Ok(())
     }
 
+    fn shutdown(&mut self) {
+        self.stop_watching = None;
+    }
+
     fn id(&self) -> &str {
         &self.id
     }
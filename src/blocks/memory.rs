@@ -2,6 +2,7 @@ use std::collections::BTreeMap;
 use std::fmt;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
+use std::path::Path;
 use std::str::FromStr;
 use std::time::{Duration, Instant};
 
@@ -154,6 +155,66 @@ impl Memstate {
     }
 }
 
+/// Sizes in KiB of the data stored on zram devices, and of the zram devices used as swap.
+#[derive(Clone, Copy, Debug, Default)]
+struct Zramstate {
+    original: u64,
+    compressed: u64,
+    swap_total: u64,
+    swap_used: u64,
+}
+
+impl Zramstate {
+    /// Sums up all zram devices. Systems without zram simply report zeros.
+    fn read() -> Self {
+        let mut state = Zramstate::default();
+
+        if let Ok(entries) = Path::new("/sys/block").read_dir() {
+            for entry in entries.filter_map(|entry| entry.ok()) {
+                if !entry.file_name().to_string_lossy().starts_with("zram") {
+                    continue;
+                }
+                let mm_stat = match read_file("memory", &entry.path().join("mm_stat")) {
+                    Ok(mm_stat) => mm_stat,
+                    Err(_) => continue,
+                };
+                // The first two fields are orig_data_size and compr_data_size, in bytes
+                let mut fields = mm_stat
+                    .split_whitespace()
+                    .map(|field| field.parse::<u64>().unwrap_or(0));
+                state.original += fields.next().unwrap_or(0) / 1024;
+                state.compressed += fields.next().unwrap_or(0) / 1024;
+            }
+        }
+
+        if let Ok(f) = File::open("/proc/swaps") {
+            // Filename Type Size Used Priority, with sizes in KiB
+            for line in BufReader::new(f)
+                .lines()
+                .skip(1)
+                .filter_map(|line| line.ok())
+            {
+                let fields = line.split_whitespace().collect::<Vec<&str>>();
+                if fields.len() < 4 || !fields[0].starts_with("/dev/zram") {
+                    continue;
+                }
+                state.swap_total += u64::from_str(fields[2]).unwrap_or(0);
+                state.swap_used += u64::from_str(fields[3]).unwrap_or(0);
+            }
+        }
+
+        state
+    }
+
+    fn ratio(&self) -> f32 {
+        if self.compressed == 0 {
+            0f32
+        } else {
+            self.original as f32 / self.compressed as f32
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Memory {
     id: String,
@@ -165,6 +226,7 @@ pub struct Memory {
     tx_update_request: Sender<Task>,
     warning: (f64, f64),
     critical: (f64, f64),
+    exclude_zram: bool,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -178,7 +240,7 @@ pub struct MemoryConfig {
     pub format_swap: String,
 
     /// Default view displayed on startup. Options are <br/> memory, swap
-    #[serde(default = "MemoryConfig::default_display_type")]
+    #[serde(default = "MemoryConfig::default_display_type", alias = "display_mode")]
     pub display_type: Memtype,
 
     /// Whether the format string should be prepended with Icons. Options are <br/> true, false
@@ -212,6 +274,10 @@ pub struct MemoryConfig {
     #[serde(default = "MemoryConfig::default_critical_swap")]
     pub critical_swap: f64,
 
+    /// Whether zram devices should be left out of the swap values
+    #[serde(default = "MemoryConfig::default_exclude_zram")]
+    pub exclude_zram: bool,
+
     #[serde(default = "MemoryConfig::default_color_overrides")]
     pub color_overrides: Option<BTreeMap<String, String>>,
 }
@@ -257,18 +323,38 @@ impl MemoryConfig {
         95.0
     }
 
+    fn default_exclude_zram() -> bool {
+        false
+    }
+
     fn default_color_overrides() -> Option<BTreeMap<String, String>> {
         None
     }
 }
 
 impl Memory {
-    fn format_insert_values(&mut self, mem_state: Memstate) -> Result<String> {
+    fn format_insert_values(
+        &mut self,
+        mem_state: Memstate,
+        zram_state: Zramstate,
+    ) -> Result<String> {
         let mem_total = Unit::KiB(mem_state.mem_total());
         let mem_free = Unit::KiB(mem_state.mem_free());
-        let swap_total = Unit::KiB(mem_state.swap_total());
-        let swap_free = Unit::KiB(mem_state.swap_free());
-        let swap_used = Unit::KiB(mem_state.swap_total() - mem_state.swap_free());
+        let (swap_total, swap_free) = if self.exclude_zram {
+            let zram_free = zram_state.swap_total.saturating_sub(zram_state.swap_used);
+            (
+                Unit::KiB(mem_state.swap_total().saturating_sub(zram_state.swap_total)),
+                Unit::KiB(mem_state.swap_free().saturating_sub(zram_free)),
+            )
+        } else {
+            (
+                Unit::KiB(mem_state.swap_total()),
+                Unit::KiB(mem_state.swap_free()),
+            )
+        };
+        let swap_used = Unit::KiB(swap_total.n().saturating_sub(swap_free.n()));
+        let zram_original = Unit::KiB(zram_state.original);
+        let zram_compressed = Unit::KiB(zram_state.compressed);
         let mem_total_used = Unit::KiB(mem_total.n() - mem_free.n());
         let buffers = Unit::KiB(mem_state.buffers());
         let cached = Unit::KiB(mem_state.cached() + mem_state.s_reclaimable() - mem_state.shmem());
@@ -319,7 +405,10 @@ impl Memory {
             "{Cm}" => format!("{}", cached.mib()),
             "{Cp}" => format!("{:.2}", cached.percent(mem_total)),
             "{Cpi}" => format!("{:02}", cached.percent(mem_total) as i32),
-            "{Cpb}" => format_percent_bar(cached.percent(mem_total)));
+            "{Cpb}" => format_percent_bar(cached.percent(mem_total)),
+            "{zram_original}" => format!("{}", zram_original.mib()),
+            "{zram_compressed}" => format!("{}", zram_compressed.mib()),
+            "{zram_ratio}" => format!("{:.1}", zram_state.ratio()));
 
         match self.memtype {
             Memtype::Memory => self.output.0.set_state(match mem_used.percent(mem_total) {
@@ -379,6 +468,7 @@ impl ConfigBlock for Memory {
             tx_update_request: tx,
             warning: (block_config.warning_mem, block_config.warning_swap),
             critical: (block_config.critical_mem, block_config.critical_swap),
+            exclude_zram: block_config.exclude_zram,
         })
     }
 }
@@ -475,7 +565,7 @@ impl Block for Memory {
         }
 
         // Now, create the string to be shown
-        let output_text = self.format_insert_values(mem_state)?;
+        let output_text = self.format_insert_values(mem_state, Zramstate::read())?;
 
         match self.memtype {
             Memtype::Memory => self.output.0.set_text(output_text),
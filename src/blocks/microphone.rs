@@ -0,0 +1,170 @@
+use std::collections::BTreeMap;
+
+use crossbeam_channel::Sender;
+use serde_derive::Deserialize;
+
+use crate::blocks::sound::{Sound, SoundConfig};
+use crate::blocks::sound_device::{DeviceKind, SoundDriver};
+use crate::blocks::{Block, ConfigBlock, Update};
+use crate::config::Config;
+use crate::errors::*;
+use crate::input::I3BarEvent;
+use crate::scheduler::Task;
+use crate::widget::{I3BarWidget, State};
+
+/// The sound block for the default source, with its own defaults.
+pub struct Microphone {
+    sound: Sound,
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct MicrophoneConfig {
+    /// ALSA / PulseAudio sound device name
+    #[serde(default)]
+    pub driver: SoundDriver,
+
+    /// PulseAudio source name, or
+    /// ALSA control name as listed in the output of `amixer -D yourdevice scontrols` (default is "Capture")
+    #[serde(default = "MicrophoneConfig::default_name")]
+    pub name: Option<String>,
+
+    /// ALSA device name, usually in the form "hw:#" where # is the number of the card desired (default is "default")
+    #[serde(default = "MicrophoneConfig::default_device")]
+    pub device: Option<String>,
+
+    /// Use the mapped volume for evaluating the percentage representation like alsamixer, to be more natural for human ear
+    #[serde(default = "MicrophoneConfig::default_natural_mapping")]
+    pub natural_mapping: bool,
+
+    /// The steps volume is in/decreased for the selected audio device (When greater than 50 it gets limited to 50)
+    #[serde(default = "MicrophoneConfig::default_step_width", alias = "step")]
+    pub step_width: u32,
+
+    /// Format string for displaying microphone information.
+    /// placeholders: {volume}
+    #[serde(default = "MicrophoneConfig::default_format")]
+    pub format: String,
+
+    #[serde(default = "MicrophoneConfig::default_show_volume_when_muted")]
+    pub show_volume_when_muted: bool,
+
+    /// Show volume as bar instead of percent
+    #[serde(default = "MicrophoneConfig::default_bar")]
+    pub bar: bool,
+
+    #[serde(default = "MicrophoneConfig::default_max_vol")]
+    pub max_vol: Option<u32>,
+
+    /// Set the state to critical while the microphone is not muted
+    #[serde(default = "MicrophoneConfig::default_on_air")]
+    pub on_air: bool,
+
+    #[serde(default = "MicrophoneConfig::default_color_overrides")]
+    pub color_overrides: Option<BTreeMap<String, String>>,
+}
+
+impl MicrophoneConfig {
+    fn default_name() -> Option<String> {
+        None
+    }
+
+    fn default_device() -> Option<String> {
+        None
+    }
+
+    fn default_natural_mapping() -> bool {
+        false
+    }
+
+    fn default_step_width() -> u32 {
+        5
+    }
+
+    fn default_format() -> String {
+        "{volume}%".into()
+    }
+
+    fn default_show_volume_when_muted() -> bool {
+        false
+    }
+
+    fn default_bar() -> bool {
+        false
+    }
+
+    fn default_max_vol() -> Option<u32> {
+        None
+    }
+
+    fn default_on_air() -> bool {
+        true
+    }
+
+    fn default_color_overrides() -> Option<BTreeMap<String, String>> {
+        None
+    }
+}
+
+impl ConfigBlock for Microphone {
+    type Config = MicrophoneConfig;
+
+    fn new(
+        block_config: Self::Config,
+        config: Config,
+        tx_update_request: Sender<Task>,
+    ) -> Result<Self> {
+        let unmuted_state = if block_config.on_air {
+            State::Critical
+        } else {
+            State::Idle
+        };
+
+        let sound_config = SoundConfig {
+            driver: block_config.driver,
+            name: block_config.name,
+            device: block_config.device,
+            device_kind: DeviceKind::Source,
+            natural_mapping: block_config.natural_mapping,
+            step_width: block_config.step_width,
+            format: block_config.format,
+            show_volume_when_muted: block_config.show_volume_when_muted,
+            bar: block_config.bar,
+            mappings: None,
+            max_vol: block_config.max_vol,
+            color_overrides: block_config.color_overrides,
+        };
+
+        Ok(Microphone {
+            sound: Sound::with_states(
+                sound_config,
+                config,
+                tx_update_request,
+                State::Idle,
+                unmuted_state,
+            )?,
+        })
+    }
+
+    fn override_on_click(&mut self) -> Option<&mut Option<String>> {
+        self.sound.override_on_click()
+    }
+}
+
+impl Block for Microphone {
+    fn update(&mut self) -> Result<Option<Update>> {
+        self.sound.update()
+    }
+
+    fn view(&self) -> Vec<&dyn I3BarWidget> {
+        self.sound.view()
+    }
+
+    fn click(&mut self, e: &I3BarEvent) -> Result<()> {
+        self.sound.click(e)
+    }
+
+    fn id(&self) -> &str {
+        self.sound.id()
+    }
+}
@@ -1,6 +1,7 @@
 use std::boxed::Box;
 use std::collections::BTreeMap;
 use std::result;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::{Duration, Instant};
@@ -35,6 +36,10 @@ struct Player {
     playback_status: PlaybackStatus,
     artist: Option<String>,
     title: Option<String>,
+    /// Length of the current track in microseconds
+    length: Option<i64>,
+    /// When the player last started or paused playback
+    last_active: Option<Instant>,
     //TODO
     //volume: u32,
 }
@@ -53,27 +58,49 @@ impl Default for PlaybackStatus {
     }
 }
 
+/// How long the D-Bus listeners wait for messages before checking whether the
+/// block was shut down, in milliseconds
+const LISTEN_TIMEOUT_MS: u32 = 1_000;
+
+/// How often the playback position is polled while it is shown
+const POSITION_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Blocks a listening thread while the bar is paused. The messages received in
+/// the meantime are handled once it resumes.
+fn wait_while_paused(paused: &AtomicBool, stopped: &AtomicBool) {
+    while paused.load(Ordering::SeqCst) && !stopped.load(Ordering::SeqCst) {
+        thread::sleep(Duration::from_millis(LISTEN_TIMEOUT_MS.into()));
+    }
+}
+
 pub struct Music {
     id: String,
     current_song_widget: RotatingTextWidget,
-    prev: Option<ButtonWidget>,
-    play: Option<ButtonWidget>,
-    next: Option<ButtonWidget>,
+    /// The control buttons in the configured order, with their names
+    buttons: Vec<(String, ButtonWidget)>,
     on_collapsed_click_widget: ButtonWidget,
     on_collapsed_click: Option<String>,
     on_click: Option<String>,
     dbus_conn: Connection,
     marquee: bool,
-    marquee_interval: Duration,
     smart_trim: bool,
     max_width: usize,
     separator: String,
     seek_step: i64,
+    show_position: bool,
     config: Config,
     players: Arc<Mutex<Vec<Player>>>,
+    cycle_on_scroll: bool,
     hide_when_empty: bool,
     send: Sender<Task>,
     format: FormatTemplate,
+    /// The shown text without the position
+    static_text: String,
+    /// The last polled playback position, in microseconds
+    position: Option<i64>,
+    next_position_poll: Option<Instant>,
+    stopped: Arc<AtomicBool>,
+    paused: Arc<AtomicBool>,
 }
 
 impl Music {
@@ -192,7 +219,7 @@ pub struct MusicConfig {
     #[serde(default = "MusicConfig::default_separator")]
     pub separator: String,
 
-    /// Array of control buttons to be displayed. Options are prev (previous title),
+    /// Array of control buttons to be displayed, in this order. Options are prev (previous title),
     /// play (play/pause) and next (next title).
     #[serde(default = "MusicConfig::default_buttons")]
     pub buttons: Vec<String>,
@@ -200,7 +227,7 @@ pub struct MusicConfig {
     #[serde(default = "MusicConfig::default_on_collapsed_click")]
     pub on_collapsed_click: Option<String>,
 
-    // Number of microseconds to seek forward/backward when scrolling on the bar.
+    /// Number of milliseconds to seek forward/backward when scrolling on the bar.
     #[serde(default = "MusicConfig::default_seek_step")]
     pub seek_step: i64,
 
@@ -208,6 +235,7 @@ pub struct MusicConfig {
     #[serde(default = "MusicConfig::default_interface_name_exclude_patterns")]
     pub interface_name_exclude: Vec<String>,
 
+    /// Hide the block while no player is available or playing a song.
     #[serde(default = "MusicConfig::default_hide_when_empty")]
     pub hide_when_empty: bool,
 
@@ -287,6 +315,7 @@ impl ConfigBlock for Music {
         let id_copy3 = id.clone();
         let send2 = send.clone();
         let send3 = send.clone();
+        let format = FormatTemplate::from_string(&block_config.format)?;
 
         let c = Connection::get_private(BusType::Session)
             .block_error("music", "failed to establish D-Bus connection")?;
@@ -319,46 +348,26 @@ impl ConfigBlock for Music {
 
             // Get bus connection name
             // TODO: possibly could get this info from the sender field of the Metadata call below?
-            let m = Message::new_method_call(
-                "org.freedesktop.DBus",
-                "/",
-                "org.freedesktop.DBus",
-                "GetNameOwner",
-            )
-            .unwrap();
-            let r = c.send_with_reply_and_block(m.append1(name), 500).unwrap();
-            let bn: &str = r.read1().ok().unwrap();
+            // The player may have gone away since the names were listed.
+            let bn = match get_name_owner(&c, name) {
+                Some(bn) => bn,
+                None => continue,
+            };
 
             if !initial_players.iter().any(|p| p.bus_name == bn) {
-                // Get current media info, if any
-                let p = c.with_path(name, "/org/mpris/MediaPlayer2", 500);
-                let data = p.get("org.mpris.MediaPlayer2.Player", "Metadata");
-                let (title, artist) = match data {
-                    Err(_) => (String::new(), String::new()),
-                    Ok(data) => {
-                        extract_from_metadata(&data).unwrap_or((String::new(), String::new()))
-                    }
-                };
-
-                // Get current playback status
-                let data = p.get("org.mpris.MediaPlayer2.Player", "PlaybackStatus");
-                let status = match data {
-                    Err(_) => PlaybackStatus::Unknown,
-                    Ok(data) => {
-                        let data: Box<dyn RefArg> = data;
-                        extract_playback_status(&data)
-                    }
-                };
-
-                initial_players.push(Player {
-                    bus_name: bn.to_string(),
-                    interface_name: name.to_string(),
-                    playback_status: status,
-                    artist: Some(artist),
-                    title: Some(title),
-                });
+                initial_players.push(fetch_player(&c, name, &bn));
             }
         }
+        promote_active_player(&mut initial_players);
+
+        // Stops the threads listening on D-Bus when the block is shut down
+        let stopped = Arc::new(AtomicBool::new(false));
+        let stopped_copy = stopped.clone();
+        let stopped_copy2 = stopped.clone();
+        // Holds back the messages from D-Bus while the bar is paused
+        let paused = Arc::new(AtomicBool::new(false));
+        let paused_copy = paused.clone();
+        let paused_copy2 = paused.clone();
 
         let players_original = Arc::new(Mutex::new(initial_players));
         let players_copy = players_original.clone();
@@ -367,22 +376,25 @@ impl ConfigBlock for Music {
         thread::Builder::new().name("music".into()).spawn(move || {
             let c = Connection::get_private(BusType::Session).unwrap();
             c.add_match("interface='org.freedesktop.DBus.Properties',member='PropertiesChanged',path='/org/mpris/MediaPlayer2'").unwrap();
-            loop {
-                for msg in c.incoming(100_000) {
+            while !stopped_copy.load(Ordering::SeqCst) {
+                wait_while_paused(&paused_copy, &stopped_copy);
+                for msg in c.incoming(LISTEN_TIMEOUT_MS) {
                     // We are listening to events from all players on org.mpris.MediaPlayer2,
                     // but we only want to update for our currently selected player (either
                     // set by the user in the config file, or autodiscovered by us).
                     if msg.sender().is_some() {
                         if let Some(signal) = PropertiesPropertiesChanged::from_message(&msg) {
                             let mut players = players_copy2.lock().expect("failed to acquire lock for `players`");
-                            let player = players.iter_mut().find(|p| p.bus_name == msg.sender().unwrap().to_string());
-                            if player.is_none() {
+                            let index = players.iter().position(|p| p.bus_name == msg.sender().unwrap().to_string());
+                            if index.is_none() {
                                 // Ignoring update since could not find player in the array.
                                 // This shouldn't actually occur as long as the other thread updates the array in time.
                                 continue;
                             }
-                            let p = player.unwrap();
+                            let index = index.unwrap();
+                            let p = &mut players[index];
                             let mut updated = false;
+                            let mut status_changed = false;
                             let raw_metadata = signal.changed_properties.get("Metadata");
                             if let Some(data) = raw_metadata {
                                 let (title, artist) =
@@ -395,12 +407,18 @@ impl ConfigBlock for Music {
                                     p.title = Some(title);
                                     updated = true;
                                 }
+                                let length = extract_length(&data.0);
+                                if p.length != length {
+                                    p.length = length;
+                                    updated = true;
+                                }
                             };
                             let raw_metadata = signal.changed_properties.get("PlaybackStatus");
                             if let Some(data) = raw_metadata {
                                 let new_status = extract_playback_status(&data.0);
                                 if p.playback_status != new_status {
                                     p.playback_status = new_status;
+                                    status_changed = true;
                                     updated = true;
                                 }
                             };
@@ -419,15 +437,34 @@ impl ConfigBlock for Music {
                                 if playerctl_playerlist.peek().is_none() {
                                     p.artist = None;
                                     p.title = None;
+                                    p.length = None;
                                     updated = true;
                                 }
                             };
+                            if status_changed {
+                                // Show the player that started playing, or
+                                // another one if the one shown stopped.
+                                let p = &mut players[index];
+                                if p.playback_status == PlaybackStatus::Playing {
+                                    p.last_active = Some(Instant::now());
+                                    let p = players.remove(index);
+                                    players.insert(0, p);
+                                } else {
+                                    if p.playback_status == PlaybackStatus::Paused {
+                                        p.last_active = Some(Instant::now());
+                                    }
+                                    promote_active_player(&mut players);
+                                }
+                            }
                             if updated {
-                                send.send(Task {
+                                let sent = send.send(Task {
                                     id: id.clone(),
                                     update_time: Instant::now(),
-                                })
-                                .unwrap();
+                                });
+                                if sent.is_err() {
+                                    // The bar is gone, or the config was reloaded
+                                    return;
+                                }
                             }
                         }
                     }
@@ -445,70 +482,60 @@ impl ConfigBlock for Music {
             // Skip the NameAcquired event.
             c.incoming(10_000).next();
             loop {
-                for ci in c.iter(100_000) {
+                // Yields `Nothing` whenever the timeout passes without a message
+                for ci in c.iter(LISTEN_TIMEOUT_MS as i32) {
+                    if stopped_copy2.load(Ordering::SeqCst) {
+                        return;
+                    }
+                    wait_while_paused(&paused_copy2, &stopped_copy2);
                     if let ConnectionItem::Signal(x) = ci {
-                         let (name, old_owner, new_owner): (&str, &str, &str) = x.read3().unwrap();
-                         let mut players = players_copy3.lock().expect("failed to acquire lock for `players`");
-                         if !old_owner.is_empty() && new_owner.is_empty() {
-                             if let Some(pos) = players.iter().position(|p| p.bus_name == old_owner) {
-                                 players.remove(pos);
-                                 send2.send(Task {
-                                     id: id_copy3.clone(),
-                                     update_time: Instant::now(),
-                                 })
-                                 .unwrap();
-                             }
-                         } else if old_owner.is_empty() && !new_owner.is_empty() && !ignored_player(name, &interface_name_exclude_regexps, preferred_player.clone()) && !players.iter().any(|p| p.bus_name == new_owner) {
-                         players.push(Player {
-                             bus_name: new_owner.to_string(),
-                             interface_name: name.to_string(),
-                             playback_status: PlaybackStatus::Unknown,
-                             artist: None,
-                             title: None,
-                         });
-                         send2.send(Task {
-                             id: id_copy3.clone(),
-                             update_time: Instant::now(),
-                         })
-                         .unwrap();
-                         }
+                        let (name, old_owner, new_owner): (&str, &str, &str) = match x.read3() {
+                            Ok(args) => args,
+                            Err(_) => continue,
+                        };
+                        let mut players = players_copy3.lock().expect("failed to acquire lock for `players`");
+                        let mut updated = false;
+                        if !old_owner.is_empty() {
+                            if let Some(pos) = players.iter().position(|p| p.bus_name == old_owner) {
+                                players.remove(pos);
+                                if pos == 0 {
+                                    promote_active_player(&mut players);
+                                }
+                                updated = true;
+                            }
+                        }
+                        if !new_owner.is_empty() && !ignored_player(name, &interface_name_exclude_regexps, preferred_player.clone()) && !players.iter().any(|p| p.bus_name == new_owner) {
+                            // Don't hold the lock while waiting for the player to reply.
+                            drop(players);
+                            let player = fetch_player(&c, name, new_owner);
+                            players = players_copy3.lock().expect("failed to acquire lock for `players`");
+                            if player.playback_status == PlaybackStatus::Playing {
+                                players.insert(0, player);
+                            } else {
+                                players.push(player);
+                            }
+                            updated = true;
+                        }
+                        if updated {
+                            let sent = send2.send(Task {
+                                id: id_copy3.clone(),
+                                update_time: Instant::now(),
+                            });
+                            if sent.is_err() {
+                                return;
+                            }
+                        }
                     }
                 }
             }
         }).unwrap();
 
-        let mut play: Option<ButtonWidget> = None;
-        let mut prev: Option<ButtonWidget> = None;
-        let mut next: Option<ButtonWidget> = None;
+        let mut buttons = Vec::new();
         for button in block_config.buttons {
-            match &*button {
-                "play" => {
-                    let button_id = format!("{}_PLAY", id_copy);
-                    play = Some(
-                        ButtonWidget::new(config.clone(), &button_id)
-                            .with_icon("music_play")
-                            .with_state(State::Info)
-                            .with_spacing(Spacing::Inline),
-                    )
-                }
-                "next" => {
-                    let button_id = format!("{}_NEXT", id_copy);
-                    next = Some(
-                        ButtonWidget::new(config.clone(), &button_id)
-                            .with_icon("music_next")
-                            .with_state(State::Info)
-                            .with_spacing(Spacing::Inline),
-                    )
-                }
-                "prev" => {
-                    let button_id = format!("{}_PREV", id_copy);
-                    prev = Some(
-                        ButtonWidget::new(config.clone(), &button_id)
-                            .with_icon("music_prev")
-                            .with_state(State::Info)
-                            .with_spacing(Spacing::Inline),
-                    )
-                }
+            let (suffix, icon) = match &*button {
+                "play" => ("PLAY", "music_play"),
+                "next" => ("NEXT", "music_next"),
+                "prev" => ("PREV", "music_prev"),
                 x => {
                     return Err(BlockError(
                         "music".to_owned(),
@@ -516,6 +543,12 @@ impl ConfigBlock for Music {
                     ))
                 }
             };
+            let button_id = format!("{}_{}", id_copy, suffix);
+            let widget = ButtonWidget::new(config.clone(), &button_id)
+                .with_icon(icon)
+                .with_state(State::Info)
+                .with_spacing(Spacing::Inline);
+            buttons.push((button, widget));
         }
 
         fn compile_regexps(patterns: Vec<String>) -> result::Result<Vec<Regex>, regex::Error> {
@@ -523,11 +556,12 @@ impl ConfigBlock for Music {
         }
 
         let id_collapsed = format!("{}_COLLAPSED", id_copy);
+        let cycle_on_scroll = block_config.player.is_none();
         Ok(Music {
             id: id_copy,
             current_song_widget: RotatingTextWidget::new(
-                Duration::new(block_config.marquee_interval.as_secs(), 0),
-                Duration::new(0, block_config.marquee_speed.subsec_nanos()),
+                block_config.marquee_interval,
+                block_config.marquee_speed,
                 block_config.max_width,
                 block_config.dynamic_width,
                 config.clone(),
@@ -535,9 +569,7 @@ impl ConfigBlock for Music {
             )
             .with_icon("music")
             .with_state(State::Info),
-            prev,
-            play,
-            next,
+            buttons,
             on_click: None,
             on_collapsed_click_widget: ButtonWidget::new(config.clone(), &id_collapsed)
                 .with_icon("music")
@@ -547,16 +579,22 @@ impl ConfigBlock for Music {
             dbus_conn: Connection::get_private(BusType::Session)
                 .block_error("music", "failed to establish D-Bus connection")?,
             marquee: block_config.marquee,
-            marquee_interval: block_config.marquee_interval,
             smart_trim: block_config.smart_trim,
             max_width: block_config.max_width,
             separator: block_config.separator,
             seek_step: block_config.seek_step,
+            show_position: format.placeholders().contains(&"{position}"),
             config,
             players: players_copy,
+            cycle_on_scroll,
             hide_when_empty: block_config.hide_when_empty,
             send: send3,
-            format: FormatTemplate::from_string(&block_config.format)?,
+            format,
+            static_text: String::new(),
+            position: None,
+            next_position_poll: None,
+            stopped,
+            paused,
         })
     }
 
@@ -570,12 +608,20 @@ impl Block for Music {
         &self.id
     }
 
+    fn shutdown(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+
+    fn set_paused(&mut self, paused: bool) {
+        self.paused.store(paused, Ordering::SeqCst);
+    }
+
     fn update(&mut self) -> Result<Option<Update>> {
-        let (rotation_in_progress, time_to_next_rotation) = if self.marquee {
-            self.current_song_widget.next()?
-        } else {
-            (false, None)
-        };
+        // The players are refreshed on every update, including the ones for
+        // the rotation, as that only reads what the D-Bus threads stored
+        if self.marquee {
+            self.current_song_widget.next()?;
+        }
 
         let players = self
             .players
@@ -585,13 +631,13 @@ impl Block for Music {
             Some(m) => m,
             None => {
                 self.current_song_widget.set_text(String::from(""));
+                self.static_text.clear();
                 return Ok(None);
             }
         };
 
         let interface_name = metadata.clone().interface_name;
-        let split: Vec<&str> = interface_name.split('.').collect();
-        let player_name = split[3].to_string();
+        let player_name = interface_name.split('.').nth(3).unwrap_or("").to_string();
         let artist = metadata.clone().artist.unwrap_or_else(|| String::from(""));
         let title = metadata.clone().title.unwrap_or_else(|| String::from(""));
         let combo =
@@ -604,26 +650,57 @@ impl Block for Music {
                 self.smart_trim(artist.clone(), title.clone())
             };
 
-        let values = map!(
+        let mut values = map!(
             "{artist}" => artist.clone(),
             "{title}" => title.clone(),
             "{combo}" => combo,
             //TODO
             //"{vol}" => volume,
             "{player}" => player_name,
+            "{position}" => String::new(),
+            "{duration}" => metadata.length.map(format_time).unwrap_or_default(),
             "{avail}" => players.len().to_string()
         );
 
-        if !(rotation_in_progress) {
-            if title.is_empty() && artist.is_empty() {
-                self.current_song_widget.set_text(String::new());
+        // The text without the position tells whether anything but the
+        // position changed, which restarts the rotation
+        let static_text = if title.is_empty() && artist.is_empty() {
+            String::new()
+        } else {
+            self.format.render_markup(&values, self.config.markup)?
+        };
+        let song_changed = static_text != self.static_text;
+
+        // Only query the position if it is shown, as it has to be polled. It
+        // is polled on its own schedule, as the rotation updates more often.
+        let now = Instant::now();
+        if !self.show_position || metadata.playback_status == PlaybackStatus::Stopped {
+            self.position = None;
+            self.next_position_poll = None;
+        } else if song_changed || self.next_position_poll.map_or(true, |t| t <= now) {
+            self.position = get_position(&self.dbus_conn, &metadata.interface_name);
+            self.next_position_poll = Some(now + POSITION_POLL_INTERVAL);
+        }
+
+        if static_text.is_empty() {
+            self.current_song_widget.set_text(String::new());
+        } else {
+            let text = match self.position {
+                Some(position) => {
+                    values.insert("{position}", format_time(position));
+                    self.format.render_markup(&values, self.config.markup)?
+                }
+                None => static_text.clone(),
+            };
+            if song_changed {
+                self.current_song_widget.set_text(text);
             } else {
-                self.current_song_widget
-                    .set_text(self.format.render_static_str(&values)?);
+                self.current_song_widget.set_text_keeping_rotation(text);
             }
         }
+        self.static_text = static_text;
 
-        if let Some(ref mut play) = self.play {
+        for (_, play) in self.buttons.iter_mut().filter(|(name, _)| name == "play") {
             play.set_icon(match metadata.playback_status {
                 PlaybackStatus::Playing => "music_pause",
                 PlaybackStatus::Paused => "music_play",
@@ -632,18 +709,26 @@ impl Block for Music {
             })
         }
 
-        // If `marquee` is enabled then we need to schedule an update for the text rotation.
-        // (time_to_next_rotation is always None if marquee is disabled)
-        if let Some(t) = time_to_next_rotation {
-            Ok(Some(Update::Every(t)))
-        // We just finished a rotation so we wait before starting again
-        } else if self.marquee {
-            Ok(Some(self.marquee_interval.into()))
-        // Otherwise we do not need to schedule anything as the block will auto-update itself after
-        // seeing a PropertiesChanged signal for the MPRIS interface it is monitoring.
+        // If `marquee` is enabled and the text is too long, we need to schedule an update
+        // for the text rotation. Otherwise we do not need to schedule anything as the block
+        // will auto-update itself after seeing a PropertiesChanged signal for the MPRIS
+        // interface it is monitoring.
+        let next_update = if self.marquee {
+            self.current_song_widget.time_to_next_rotation()
         } else {
-            Ok(None)
-        }
+            None
+        };
+
+        // Players don't signal changes of the position, so it is polled while playing
+        let next_update = match self.next_position_poll {
+            Some(poll) if metadata.playback_status == PlaybackStatus::Playing => {
+                let until_poll = poll.saturating_duration_since(now);
+                Some(next_update.map_or(until_poll, |t| t.min(until_poll)))
+            }
+            _ => next_update,
+        };
+
+        Ok(next_update.map(Update::Every))
     }
 
     fn click(&mut self, event: &I3BarEvent) -> Result<()> {
@@ -686,6 +771,18 @@ impl Block for Music {
                         if let Some(ref cmd) = self.on_click {
                             spawn_child_async("sh", &["-c", cmd])
                                 .block_error("music", "could not spawn child")?;
+                        } else if let Some(metadata) = players.first() {
+                            // Bring the player's window to the front
+                            let m = Message::new_method_call(
+                                metadata.interface_name.clone(),
+                                "/org/mpris/MediaPlayer2",
+                                "org.mpris.MediaPlayer2",
+                                "Raise",
+                            )
+                            .block_error("music", "failed to create D-Bus method call")?;
+                            self.dbus_conn
+                                .send(m)
+                                .block_error("music", "failed to call method via D-Bus")?;
                         }
                     }
                 }
@@ -706,6 +803,22 @@ impl Block for Music {
                         })?;
                     }
                 }
+                // Without a `player` set, scrolling cycles through the players
+                // if there are several of them.
+                _ if self.cycle_on_scroll && players.len() > 1 => {
+                    if name.as_str() == self.id || name == &collapsed_id {
+                        use LogicalDirection::*;
+                        match self.config.scrolling.to_logical_direction(event.button) {
+                            Some(Up) => players.rotate_left(1),
+                            Some(Down) => players.rotate_right(1),
+                            None => return Ok(()),
+                        }
+                        self.send.send(Task {
+                            id: self.id.clone(),
+                            update_time: Instant::now(),
+                        })?;
+                    }
+                }
                 _ => {
                     if name.as_str() == self.id && players.len() > 0 {
                         let metadata = players.first().unwrap();
@@ -743,19 +856,21 @@ impl Block for Music {
             .players
             .lock()
             .expect("failed to acquire lock for `players`");
-        if players.len() == 1 && self.current_song_widget.is_empty() && self.hide_when_empty {
+        // Nothing is shown without a player, or while it isn't playing anything
+        let empty = match players.first() {
+            Some(player) => {
+                player.playback_status == PlaybackStatus::Stopped
+                    || self.current_song_widget.is_empty()
+            }
+            None => true,
+        };
+        if empty && self.hide_when_empty {
             vec![]
         } else if players.len() > 0 && !self.current_song_widget.is_empty() {
             let mut elements: Vec<&dyn I3BarWidget> = Vec::new();
             elements.push(&self.current_song_widget);
-            if let Some(ref prev) = self.prev {
-                elements.push(prev);
-            }
-            if let Some(ref play) = self.play {
-                elements.push(play);
-            }
-            if let Some(ref next) = self.next {
-                elements.push(next);
+            for (_, button) in &self.buttons {
+                elements.push(button);
             }
             elements
         } else if self.current_song_widget.is_empty() {
@@ -766,6 +881,97 @@ impl Block for Music {
     }
 }
 
+/// Looks up the unique bus name of the owner of a well-known name.
+fn get_name_owner(c: &Connection, name: &str) -> Option<String> {
+    let m = Message::new_method_call(
+        "org.freedesktop.DBus",
+        "/",
+        "org.freedesktop.DBus",
+        "GetNameOwner",
+    )
+    .ok()?;
+    let r = c.send_with_reply_and_block(m.append1(name), 500).ok()?;
+    r.read1::<&str>().ok().map(String::from)
+}
+
+/// Queries the current media info and playback status of a player.
+fn fetch_player(c: &Connection, name: &str, bus_name: &str) -> Player {
+    // Get current media info, if any
+    let p = c.with_path(name, "/org/mpris/MediaPlayer2", 500);
+    let data = p.get("org.mpris.MediaPlayer2.Player", "Metadata");
+    let (title, artist, length) = match data {
+        Err(_) => (String::new(), String::new(), None),
+        Ok(data) => {
+            let (title, artist) =
+                extract_from_metadata(&data).unwrap_or((String::new(), String::new()));
+            (title, artist, extract_length(&data))
+        }
+    };
+
+    // Get current playback status
+    let data = p.get("org.mpris.MediaPlayer2.Player", "PlaybackStatus");
+    let status = match data {
+        Err(_) => PlaybackStatus::Unknown,
+        Ok(data) => {
+            let data: Box<dyn RefArg> = data;
+            extract_playback_status(&data)
+        }
+    };
+
+    Player {
+        bus_name: bus_name.to_string(),
+        interface_name: name.to_string(),
+        last_active: match status {
+            PlaybackStatus::Playing | PlaybackStatus::Paused => Some(Instant::now()),
+            _ => None,
+        },
+        playback_status: status,
+        artist: Some(artist),
+        title: Some(title),
+        length,
+    }
+}
+
+/// Queries the playback position of a player in microseconds. Not all
+/// players implement this.
+fn get_position(c: &Connection, name: &str) -> Option<i64> {
+    c.with_path(name, "/org/mpris/MediaPlayer2", 500)
+        .get("org.mpris.MediaPlayer2.Player", "Position")
+        .ok()
+}
+
+/// Formats a time in microseconds as M:SS.
+fn format_time(us: i64) -> String {
+    let secs = us.max(0) / 1_000_000;
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
+
+/// The index of the player to show: the most recently active one that is
+/// playing, or failing that, the most recently active paused one.
+fn active_player(players: &[Player]) -> Option<usize> {
+    let most_recent = |status: PlaybackStatus| {
+        players
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| p.playback_status == status)
+            .max_by_key(|(_, p)| p.last_active)
+            .map(|(i, _)| i)
+    };
+    most_recent(PlaybackStatus::Playing).or_else(|| most_recent(PlaybackStatus::Paused))
+}
+
+/// Moves the player to show to the front of the list, unless the one in front
+/// is playing already.
+fn promote_active_player(players: &mut Vec<Player>) {
+    if matches!(players.first(), Some(p) if p.playback_status == PlaybackStatus::Playing) {
+        return;
+    }
+    if let Some(index) = active_player(players) {
+        let player = players.remove(index);
+        players.insert(0, player);
+    }
+}
+
 fn extract_playback_status(value: &dyn RefArg) -> PlaybackStatus {
     if let Some(status) = value.as_str() {
         match status {
@@ -793,6 +999,22 @@ fn extract_artist_from_value(value: &dyn RefArg) -> Result<&str> {
     }
 }
 
+#[allow(clippy::borrowed_box)] // TODO: remove clippy workaround
+fn extract_length(metadata: &Box<dyn RefArg>) -> Option<i64> {
+    let mut iter = metadata.as_iter()?;
+    while let Some(key) = iter.next() {
+        let value = iter.next()?;
+        if key.as_str()? == "mpris:length" {
+            // Should be an int64, but some players send an uint64
+            return value
+                .as_i64()
+                .or_else(|| value.as_u64().map(|length| length as i64))
+                .filter(|&length| length > 0);
+        }
+    }
+    None
+}
+
 #[allow(clippy::borrowed_box)] // TODO: remove clippy workaround
 fn extract_from_metadata(metadata: &Box<dyn RefArg>) -> Result<(String, String)> {
     let mut title = String::new();
@@ -849,3 +1071,53 @@ fn ignored_player(
 
     false
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_time() {
+        assert_eq!(format_time(0), "0:00");
+        assert_eq!(format_time(61_500_000), "1:01");
+        assert_eq!(format_time(3_723_000_000), "62:03");
+    }
+
+    fn player(name: &str, status: PlaybackStatus, last_active: Option<Instant>) -> Player {
+        Player {
+            bus_name: format!(":1.{}", name.len()),
+            interface_name: format!("org.mpris.MediaPlayer2.{}", name),
+            playback_status: status,
+            artist: None,
+            title: None,
+            length: None,
+            last_active,
+        }
+    }
+
+    #[test]
+    fn test_promote_active_player() {
+        let earlier = Instant::now();
+        let later = earlier + Duration::from_secs(1);
+        let mut players = vec![
+            player("mpv", PlaybackStatus::Stopped, None),
+            player("firefox", PlaybackStatus::Paused, Some(later)),
+            player("spotify", PlaybackStatus::Paused, Some(earlier)),
+        ];
+        promote_active_player(&mut players);
+        assert_eq!(players[0].interface_name, "org.mpris.MediaPlayer2.firefox");
+
+        players[2].playback_status = PlaybackStatus::Playing;
+        promote_active_player(&mut players);
+        assert_eq!(players[0].interface_name, "org.mpris.MediaPlayer2.spotify");
+
+        // A playing player in front is kept
+        players[1].playback_status = PlaybackStatus::Playing;
+        promote_active_player(&mut players);
+        assert_eq!(players[0].interface_name, "org.mpris.MediaPlayer2.spotify");
+
+        let mut players = vec![player("mpv", PlaybackStatus::Stopped, None)];
+        promote_active_player(&mut players);
+        assert_eq!(players[0].interface_name, "org.mpris.MediaPlayer2.mpv");
+    }
+}
@@ -1,4 +1,4 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, VecDeque};
 use std::ffi::OsStr;
 use std::fmt;
 use std::fs::{read_to_string, OpenOptions};
@@ -18,14 +18,13 @@ use crate::de::deserialize_duration;
 use crate::errors::*;
 use crate::scheduler::Task;
 use crate::util::{
-    escape_pango_text, format_number, format_percent_bar, format_vec_to_bar_graph, pseudo_uuid,
+    format_number, format_percent_bar, format_sparkline, format_throughput, pseudo_uuid,
     FormatTemplate,
 };
-use crate::widget::{I3BarWidget, Spacing};
+use crate::widget::{I3BarWidget, Spacing, State};
 use crate::widgets::button::ButtonWidget;
 
 lazy_static! {
-    static ref DEFAULT_DEV_REGEX: Regex = Regex::new("default.*dev (\\w*).*").unwrap();
     static ref WHITESPACE_REGEX: Regex = Regex::new("\\s+").unwrap();
     static ref ETHTOOL_SPEED_REGEX: Regex = Regex::new("Speed: (\\d+\\w\\w/s)").unwrap();
     static ref IW_SSID_REGEX: Regex = Regex::new("SSID: (.*)").unwrap();
@@ -83,25 +82,11 @@ impl NetworkDevice {
         self.device.clone()
     }
 
-    /// Grab the name of the 'default' device.
-    /// A default device is usually selected by the network manager
-    /// and will change when the status of devices change.
+    /// Grab the name of the 'default' device, i.e. the interface of the
+    /// default route. A default device is usually selected by the network
+    /// manager and will change when the status of devices change.
     pub fn default_device() -> Option<String> {
-        String::from_utf8(
-            Command::new("ip")
-                .args(&["route", "show", "default"])
-                .output()
-                .ok()
-                .and_then(|o| {
-                    let mut captures = DEFAULT_DEV_REGEX.captures_iter(&o.stdout);
-                    if let Some(cap) = captures.next() {
-                        cap.get(1).map(|x| x.as_bytes().to_vec())
-                    } else {
-                        None
-                    }
-                })?,
-        )
-        .ok()
+        parse_default_route(&read_to_string("/proc/net/route").ok()?)
     }
 
     /// Check whether the device exists.
@@ -217,30 +202,6 @@ impl NetworkDevice {
         }
     }
 
-    fn relative_signal_strength(&self) -> Result<Option<u32>> {
-        let xbm = if let Some(xbm) = self.absolute_signal_strength()? {
-            xbm as f64
-        } else {
-            return Ok(None);
-        };
-
-        // Code inspired by https://github.com/NetworkManager/NetworkManager/blob/master/src/platform/wifi/nm-wifi-utils-nl80211.c
-        const NOISE_FLOOR_DBM: f64 = -90.;
-        const SIGNAL_MAX_DBM: f64 = -20.;
-
-        let xbm = if xbm < NOISE_FLOOR_DBM {
-            NOISE_FLOOR_DBM
-        } else if xbm > SIGNAL_MAX_DBM {
-            SIGNAL_MAX_DBM
-        } else {
-            xbm
-        };
-
-        let result = 100. - 70. * ((SIGNAL_MAX_DBM - xbm) / (SIGNAL_MAX_DBM - NOISE_FLOOR_DBM));
-        let result = result as u32;
-        Ok(Some(result))
-    }
-
     /// Queries the inet IP of this device (using `ip`).
     pub fn ip_addr(&self) -> Result<Option<String>> {
         if !self.is_up()? {
@@ -276,8 +237,9 @@ impl NetworkDevice {
         })
     }
 
-    /// Queries the inet IPv6 of this device (using `ip`).
-    pub fn ipv6_addr(&self) -> Result<Option<String>> {
+    /// Queries the global inet IPv6 of this device (using `ip`). Temporary
+    /// privacy addresses are only used if `prefer_temporary` is set.
+    pub fn ipv6_addr(&self, prefer_temporary: bool) -> Result<Option<String>> {
         if !self.is_up()? {
             return Ok(None);
         }
@@ -297,18 +259,15 @@ impl NetworkDevice {
             return Ok(Some("".to_string()));
         }
 
-        let ip = ip_devs
+        let addrs: Vec<&IpAddrInfo> = ip_devs
             .iter()
-            .filter(|dev| dev.addr_info.is_some())
-            .flat_map(|dev| &dev.addr_info)
+            .filter_map(|dev| dev.addr_info.as_ref())
             .flatten()
-            .filter_map(|addr| addr.local.clone())
-            .next();
+            .collect();
 
-        Ok(match ip {
-            Some(addr) => Some(addr),
-            _ => Some("".to_string()),
-        })
+        Ok(Some(
+            select_ipv6_addr(&addrs, prefer_temporary).unwrap_or_default(),
+        ))
     }
 
     /// Queries the bitrate of this device
@@ -358,6 +317,7 @@ impl NetworkDevice {
 
 pub struct Net {
     format: FormatTemplate,
+    short_format: Option<FormatTemplate>,
     output: ButtonWidget,
     config: Config,
     network: ButtonWidget,
@@ -365,8 +325,13 @@ pub struct Net {
     max_ssid_width: usize,
     signal_strength: Option<String>,
     signal_strength_bar: Option<String>,
+    signal_strength_dbm: Option<String>,
+    signal_good: Option<u32>,
+    signal_warning: Option<u32>,
+    signal_critical: Option<u32>,
     ip_addr: Option<String>,
     ipv6_addr: Option<String>,
+    prefer_temporary: bool,
     bitrate: Option<String>,
     output_tx: Option<String>,
     graph_tx: Option<String>,
@@ -376,18 +341,39 @@ pub struct Net {
     update_interval: Duration,
     device: NetworkDevice,
     auto_device: bool,
-    tx_buff: Vec<u64>,
-    rx_buff: Vec<u64>,
+    has_route: bool,
+    disconnected_text: String,
+    tx_buff: VecDeque<f64>,
+    rx_buff: VecDeque<f64>,
+    graph_width: usize,
     tx_bytes: u64,
     rx_bytes: u64,
     use_bits: bool,
     speed_min_unit: Unit,
     speed_digits: usize,
+    speed_scaling: SpeedScaling,
     active: bool,
     exists: bool,
     hide_inactive: bool,
     hide_missing: bool,
-    last_update: Instant,
+    /// When the SSID and IP address were last updated, `None` to update them
+    /// on the next update
+    last_update: Option<Instant>,
+}
+
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SpeedScaling {
+    /// Scale according to `speed_digits` and `speed_min_unit`
+    Digits,
+    /// One decimal with the unit scaled to the magnitude, padded to a fixed width
+    Fixed,
+}
+
+impl Default for SpeedScaling {
+    fn default() -> Self {
+        SpeedScaling::Digits
+    }
 }
 
 #[derive(Copy, Clone, Debug, Deserialize)]
@@ -424,9 +410,18 @@ pub struct NetConfig {
     #[serde(default = "NetConfig::default_format")]
     pub format: String,
 
-    /// Which interface in /sys/class/net/ to read from.
+    /// Format shown instead of `format` when the bar is too narrow
+    #[serde(default = "NetConfig::default_short_format")]
+    pub short_format: Option<String>,
+
+    /// Which interface in /sys/class/net/ to read from, or "auto" to follow
+    /// the default route.
     pub device: Option<String>,
 
+    /// Text to show while there is no default route.
+    #[serde(default = "NetConfig::default_disconnected_text")]
+    pub disconnected_text: String,
+
     /// Whether to show the SSID of active wireless networks.
     #[serde(default = "NetConfig::default_ssid")]
     pub ssid: bool,
@@ -443,6 +438,18 @@ pub struct NetConfig {
     #[serde(default = "NetConfig::default_signal_strength_bar")]
     pub signal_strength_bar: bool,
 
+    /// Minimum signal strength in percent, where state is set to good.
+    #[serde(default = "NetConfig::default_signal_threshold")]
+    pub signal_good: Option<u32>,
+
+    /// Signal strength in percent, at or below which state is set to warning.
+    #[serde(default = "NetConfig::default_signal_threshold")]
+    pub signal_warning: Option<u32>,
+
+    /// Signal strength in percent, at or below which state is set to critical.
+    #[serde(default = "NetConfig::default_signal_threshold")]
+    pub signal_critical: Option<u32>,
+
     /// Whether to show the bitrate of active wireless networks.
     #[serde(default = "NetConfig::default_bitrate")]
     pub bitrate: bool,
@@ -455,6 +462,10 @@ pub struct NetConfig {
     #[serde(default = "NetConfig::default_ipv6")]
     pub ipv6: bool,
 
+    /// Whether to show temporary privacy IPv6 addresses rather than stable ones.
+    #[serde(default = "NetConfig::default_prefer_temporary")]
+    pub prefer_temporary: bool,
+
     /// Whether to hide networks that are down/inactive completely.
     #[serde(default = "NetConfig::default_hide_inactive")]
     pub hide_inactive: bool,
@@ -479,6 +490,10 @@ pub struct NetConfig {
     #[serde(default = "NetConfig::default_speed_min_unit")]
     pub speed_min_unit: Unit,
 
+    /// How to scale the throughput indicators.
+    #[serde(default)]
+    pub speed_scaling: SpeedScaling,
+
     /// Whether to show the download throughput indicator of active networks.
     #[serde(default = "NetConfig::default_speed_down")]
     pub speed_down: bool,
@@ -491,6 +506,10 @@ pub struct NetConfig {
     #[serde(default = "NetConfig::default_graph_down")]
     pub graph_down: bool,
 
+    /// Number of samples shown by the throughput graphs.
+    #[serde(default = "NetConfig::default_graph_width")]
+    pub graph_width: usize,
+
     #[serde(default = "NetConfig::default_color_overrides")]
     pub color_overrides: Option<BTreeMap<String, String>>,
 }
@@ -504,6 +523,14 @@ impl NetConfig {
         "{speed_up} {speed_down}".to_owned()
     }
 
+    fn default_short_format() -> Option<String> {
+        None
+    }
+
+    fn default_disconnected_text() -> String {
+        "×".to_string()
+    }
+
     fn default_hide_inactive() -> bool {
         false
     }
@@ -540,6 +567,10 @@ impl NetConfig {
         false
     }
 
+    fn default_prefer_temporary() -> bool {
+        false
+    }
+
     fn default_speed_up() -> bool {
         true
     }
@@ -548,6 +579,10 @@ impl NetConfig {
         true
     }
 
+    fn default_signal_threshold() -> Option<u32> {
+        None
+    }
+
     fn default_graph_up() -> bool {
         false
     }
@@ -556,6 +591,10 @@ impl NetConfig {
         false
     }
 
+    fn default_graph_width() -> usize {
+        10
+    }
+
     fn default_use_bits() -> bool {
         false
     }
@@ -581,13 +620,12 @@ impl ConfigBlock for Net {
         config: Config,
         _tx_update_request: Sender<Task>,
     ) -> Result<Self> {
-        let default_device = match NetworkDevice::default_device() {
-            Some(ref s) if !s.is_empty() => s.to_string(),
-            _ => "lo".to_string(),
-        };
+        let auto_device = matches!(block_config.device.as_deref(), None | Some("auto"));
+        let default_device = NetworkDevice::default_device();
+        let has_route = default_device.is_some();
         let device = match block_config.device.clone() {
-            Some(d) => NetworkDevice::from_device(d),
-            _ => NetworkDevice::from_device(default_device),
+            Some(d) if !auto_device => NetworkDevice::from_device(d),
+            _ => NetworkDevice::from_device(default_device.unwrap_or_else(|| "lo".to_string())),
         };
         let init_rx_bytes = device.rx_bytes().unwrap_or(0);
         let init_tx_bytes = device.tx_bytes().unwrap_or(0);
@@ -611,19 +649,34 @@ impl ConfigBlock for Net {
             // Default format
             block_config.format
         };
+        let short_format_str = block_config.short_format.unwrap_or_default();
+        let short_format = if short_format_str.is_empty() {
+            None
+        } else {
+            Some(
+                FormatTemplate::from_string(&short_format_str)
+                    .block_error("net", "Invalid short_format specified")?,
+            )
+        };
+        // Placeholders are only filled in if one of the formats uses them
+        let used = |placeholder: &str| {
+            format.contains(placeholder) || short_format_str.contains(placeholder)
+        };
 
         Ok(Net {
             id: id.clone(),
             update_interval: block_config.interval,
             format: FormatTemplate::from_string(&format)
                 .block_error("net", "Invalid format specified")?,
-            output: ButtonWidget::new(config.clone(), "")
+            short_format,
+            output: ButtonWidget::new(config.clone(), &id)
                 .with_text("")
                 .with_spacing(Spacing::Inline),
             config: config.clone(),
             use_bits: block_config.use_bits,
             speed_min_unit: block_config.speed_min_unit,
             speed_digits: block_config.speed_digits,
+            speed_scaling: block_config.speed_scaling,
             network: ButtonWidget::new(config, &id).with_icon(if wireless {
                 "net_wireless"
             } else if vpn {
@@ -633,55 +686,67 @@ impl ConfigBlock for Net {
             } else {
                 "net_wired"
             }),
-            // Might want to signal an error if the user wants the SSID of a
-            // wired connection instead.
-            ssid: if wireless && format.contains("{ssid}") {
-                Some(" ".to_string())
+            // These are left empty while the device is wired, so that the
+            // same format works when the default device changes.
+            ssid: if used("{ssid}") {
+                Some("".to_string())
             } else {
                 None
             },
             max_ssid_width: block_config.max_ssid_width,
-            signal_strength: if wireless && format.contains("{signal_strength}") {
-                Some(0.to_string())
+            signal_strength: if used("{signal_strength}") {
+                Some("".to_string())
+            } else {
+                None
+            },
+            signal_strength_bar: if used("{signal_strength_bar}") {
+                Some("".to_string())
             } else {
                 None
             },
-            signal_strength_bar: if wireless && format.contains("{signal_strength_bar}") {
+            signal_strength_dbm: if used("{signal_strength_dbm}") {
                 Some("".to_string())
             } else {
                 None
             },
+            signal_good: block_config.signal_good,
+            signal_warning: block_config.signal_warning,
+            signal_critical: block_config.signal_critical,
             // TODO: a better way to deal with this?
-            bitrate: if format.contains("{bitrate}") {
+            bitrate: if used("{bitrate}") {
                 Some("".to_string())
             } else {
                 None
             },
-            ip_addr: if format.contains("{ip}") {
+            ip_addr: if used("{ip}") {
                 Some("".to_string())
             } else {
                 None
             },
-            ipv6_addr: if format.contains("{ipv6}") {
+            ipv6_addr: if used("{ipv6}") {
                 Some("".to_string())
             } else {
                 None
             },
+            prefer_temporary: block_config.prefer_temporary,
             output_tx: Some("".to_string()),
             output_rx: Some("".to_string()),
             graph_tx: Some("".to_string()),
             graph_rx: Some("".to_string()),
             device,
-            auto_device: block_config.device.is_none(),
-            rx_buff: vec![0; 10],
-            tx_buff: vec![0; 10],
+            auto_device,
+            has_route,
+            disconnected_text: block_config.disconnected_text,
+            rx_buff: VecDeque::with_capacity(block_config.graph_width + 1),
+            tx_buff: VecDeque::with_capacity(block_config.graph_width + 1),
+            graph_width: block_config.graph_width,
             rx_bytes: init_rx_bytes,
             tx_bytes: init_tx_bytes,
             active: true,
             exists: true,
             hide_inactive: block_config.hide_inactive,
             hide_missing: block_config.hide_missing,
-            last_update: Instant::now() - Duration::from_secs(30),
+            last_update: None,
         })
     }
 }
@@ -736,13 +801,15 @@ fn read_file(path: &Path) -> Result<String> {
 
 impl Net {
     fn update_device(&mut self) {
-        if self.auto_device {
-            let dev = match NetworkDevice::default_device() {
-                Some(ref s) if !s.is_empty() => s.to_string(),
-                _ => "lo".to_string(),
-            };
+        if !self.auto_device {
+            return;
+        }
 
-            if self.device.device() != dev {
+        let dev = NetworkDevice::default_device();
+        let reconnected = dev.is_some() && !self.has_route;
+        self.has_route = dev.is_some();
+        match dev {
+            Some(dev) if self.device.device() != dev => {
                 self.device = NetworkDevice::from_device(dev);
                 self.network.set_icon(if self.device.is_wireless() {
                     "net_wireless"
@@ -753,8 +820,27 @@ impl Net {
                 } else {
                     "net_wired"
                 });
+                self.rx_bytes = self.device.rx_bytes().unwrap_or(0);
+                self.tx_bytes = self.device.tx_bytes().unwrap_or(0);
+                self.reset_connection_info();
             }
+            _ if reconnected => self.reset_connection_info(),
+            _ => (),
+        }
+    }
+
+    /// Makes the next update refresh the SSID, signal strength and the IP
+    /// addresses, e.g. because the default device changed.
+    fn reset_connection_info(&mut self) {
+        for string in [&mut self.ip_addr, &mut self.ipv6_addr]
+            .iter_mut()
+            .filter_map(|string| string.as_mut())
+        {
+            string.clear();
         }
+        self.network.set_state(State::Idle);
+        self.output.set_state(State::Idle);
+        self.last_update = None;
     }
 
     fn update_bitrate(&mut self) -> Result<()> {
@@ -769,32 +855,87 @@ impl Net {
 
     fn update_ssid(&mut self) -> Result<()> {
         if let Some(ref mut ssid_string) = self.ssid {
+            if !self.device.is_wireless() {
+                ssid_string.clear();
+                return Ok(());
+            }
+
             let ssid = self.device.ssid()?;
             if let Some(s) = ssid {
-                let mut truncated = s;
-                truncated.truncate(self.max_ssid_width);
-                // SSID names can contain chars that need escaping
-                *ssid_string = escape_pango_text(truncated);
+                if s.is_empty() {
+                    *ssid_string = "(hidden)".to_string();
+                } else {
+                    let mut truncated = s;
+                    truncated.truncate(self.max_ssid_width);
+                    *ssid_string = truncated;
+                }
             }
         }
         Ok(())
     }
 
     fn update_signal_strength(&mut self) -> Result<()> {
-        if self.signal_strength.is_some() || self.signal_strength_bar.is_some() {
-            let value = self.device.relative_signal_strength()?;
-            if let Some(ref mut signal_strength_string) = self.signal_strength {
-                if let Some(v) = value {
-                    *signal_strength_string = format!("{}%", v);
-                };
-            }
+        let wanted = self.signal_strength.is_some()
+            || self.signal_strength_bar.is_some()
+            || self.signal_strength_dbm.is_some()
+            || self.signal_good.is_some()
+            || self.signal_warning.is_some()
+            || self.signal_critical.is_some();
+        if !wanted {
+            return Ok(());
+        }
 
-            if let Some(ref mut signal_strength_bar_string) = self.signal_strength_bar {
-                if let Some(v) = value {
-                    *signal_strength_bar_string = format_percent_bar(v as f32);
-                };
+        let dbm = if self.device.is_wireless() {
+            self.device.absolute_signal_strength()?
+        } else {
+            None
+        };
+        let value = dbm.map(signal_strength_percent);
+
+        if !self.device.is_wireless() {
+            for string in [
+                &mut self.signal_strength,
+                &mut self.signal_strength_bar,
+                &mut self.signal_strength_dbm,
+            ]
+            .iter_mut()
+            .filter_map(|string| string.as_mut())
+            {
+                string.clear();
             }
         }
+
+        if let Some(ref mut signal_strength_string) = self.signal_strength {
+            if let Some(v) = value {
+                *signal_strength_string = format!("{}%", v);
+            };
+        }
+
+        if let Some(ref mut signal_strength_bar_string) = self.signal_strength_bar {
+            if let Some(v) = value {
+                *signal_strength_bar_string = format_percent_bar(v as f32);
+            };
+        }
+
+        if let Some(ref mut signal_strength_dbm_string) = self.signal_strength_dbm {
+            if let Some(v) = dbm {
+                *signal_strength_dbm_string = format!("{}dBm", v);
+            };
+        }
+
+        let state = match value {
+            Some(v) if matches!(self.signal_critical, Some(critical) if v <= critical) => {
+                State::Critical
+            }
+            Some(v) if matches!(self.signal_warning, Some(warning) if v <= warning) => {
+                State::Warning
+            }
+            Some(v) if matches!(self.signal_good, Some(good) if v >= good) => State::Good,
+            _ => State::Idle,
+        };
+        self.network.set_state(state);
+        self.output.set_state(state);
+
         Ok(())
     }
 
@@ -806,7 +947,7 @@ impl Net {
             }
         }
         if let Some(ref mut ipv6_addr_string) = self.ipv6_addr {
-            let ipv6_addr = self.device.ipv6_addr()?;
+            let ipv6_addr = self.device.ipv6_addr(self.prefer_temporary)?;
             if let Some(ip) = ipv6_addr {
                 *ipv6_addr_string = ip;
             }
@@ -829,22 +970,27 @@ impl Net {
             self.tx_bytes = current_tx;
 
             if let Some(ref mut tx) = self.output_tx {
-                *tx = format_number(
-                    if self.use_bits {
-                        tx_bytes * 8
-                    } else {
-                        tx_bytes
-                    } as f64,
-                    self.speed_digits,
-                    &self.speed_min_unit.to_string(),
-                    if self.use_bits { "b" } else { "B" },
-                );
+                *tx = match self.speed_scaling {
+                    SpeedScaling::Fixed => format_throughput(tx_bytes as f64, self.use_bits),
+                    SpeedScaling::Digits => format_number(
+                        if self.use_bits {
+                            tx_bytes * 8
+                        } else {
+                            tx_bytes
+                        } as f64,
+                        self.speed_digits,
+                        &self.speed_min_unit.to_string(),
+                        if self.use_bits { "b" } else { "B" },
+                    ),
+                };
             };
 
             if let Some(ref mut graph_tx) = self.graph_tx {
-                self.tx_buff.remove(0);
-                self.tx_buff.push(tx_bytes);
-                *graph_tx = format_vec_to_bar_graph(&self.tx_buff, None, None);
+                self.tx_buff.push_back(tx_bytes as f64);
+                if self.tx_buff.len() > self.graph_width {
+                    self.tx_buff.pop_front();
+                }
+                *graph_tx = format_sparkline(&self.tx_buff, self.graph_width);
             }
         }
         if self.output_rx.is_some() || self.graph_rx.is_some() {
@@ -857,22 +1003,27 @@ impl Net {
             self.rx_bytes = current_rx;
 
             if let Some(ref mut rx) = self.output_rx {
-                *rx = format_number(
-                    if self.use_bits {
-                        rx_bytes * 8
-                    } else {
-                        rx_bytes
-                    } as f64,
-                    self.speed_digits,
-                    &self.speed_min_unit.to_string(),
-                    if self.use_bits { "b" } else { "B" },
-                );
+                *rx = match self.speed_scaling {
+                    SpeedScaling::Fixed => format_throughput(rx_bytes as f64, self.use_bits),
+                    SpeedScaling::Digits => format_number(
+                        if self.use_bits {
+                            rx_bytes * 8
+                        } else {
+                            rx_bytes
+                        } as f64,
+                        self.speed_digits,
+                        &self.speed_min_unit.to_string(),
+                        if self.use_bits { "b" } else { "B" },
+                    ),
+                };
             };
 
             if let Some(ref mut graph_rx) = self.graph_rx {
-                self.rx_buff.remove(0);
-                self.rx_buff.push(rx_bytes);
-                *graph_rx = format_vec_to_bar_graph(&self.rx_buff, None, None);
+                self.rx_buff.push_back(rx_bytes as f64);
+                if self.rx_buff.len() > self.graph_width {
+                    self.rx_buff.pop_front();
+                }
+                *graph_rx = format_sparkline(&self.rx_buff, self.graph_width);
             }
         }
         Ok(())
@@ -883,6 +1034,14 @@ impl Block for Net {
     fn update(&mut self) -> Result<Option<Update>> {
         self.update_device();
 
+        if self.auto_device && !self.has_route {
+            self.exists = true;
+            self.active = false;
+            self.network.set_text(self.disconnected_text.clone());
+            self.network.set_state(State::Critical);
+            return Ok(Some(self.update_interval.into()));
+        }
+
         // skip updating if device is not up.
         self.exists = self.device.exists()?;
         self.active = self.exists && self.device.is_up()?;
@@ -902,7 +1061,10 @@ impl Block for Net {
 
         // Update SSID and IP address every 30s and the bitrate every 10s
         let now = Instant::now();
-        if now.duration_since(self.last_update).as_secs() % 10 == 0 {
+        let elapsed = self
+            .last_update
+            .map(|last_update| now.duration_since(last_update).as_secs());
+        if elapsed.map_or(true, |elapsed| elapsed % 10 == 0) {
             self.update_bitrate()?;
         }
 
@@ -918,14 +1080,11 @@ impl Block for Net {
             Some(_) => false,
         };
 
-        if (now.duration_since(self.last_update).as_secs() > 30)
-            || waiting_for_ip
-            || waiting_for_ipv6
-        {
+        if elapsed.map_or(true, |elapsed| elapsed > 30) || waiting_for_ip || waiting_for_ipv6 {
             self.update_ssid()?;
             self.update_signal_strength()?;
             self.update_ip_addr()?;
-            self.last_update = now;
+            self.last_update = Some(now);
         }
 
         self.update_tx_rx()?;
@@ -950,10 +1109,13 @@ impl Block for Net {
             self.output_rx.as_ref().unwrap_or(&empty_string)
         );
 
+        let device = self.device.device();
         let values = map!(
+            "{device}" => &device,
             "{ssid}" => self.ssid.as_ref().unwrap_or(&empty_string),
             "{signal_strength}" => self.signal_strength.as_ref().unwrap_or(&empty_string),
             "{signal_strength_bar}" => self.signal_strength_bar.as_ref().unwrap_or(&empty_string),
+            "{signal_strength_dbm}" => self.signal_strength_dbm.as_ref().unwrap_or(&empty_string),
             "{bitrate}" =>  self.bitrate.as_ref().unwrap_or(&empty_string),
             "{ip}" =>  self.ip_addr.as_ref().unwrap_or(&empty_string),
             "{ipv6}" =>  self.ipv6_addr.as_ref().unwrap_or(&empty_string),
@@ -964,7 +1126,14 @@ impl Block for Net {
         );
 
         self.output
-            .set_text(self.format.render_static_str(&values)?);
+            .set_text(self.format.render_markup(&values, self.config.markup)?);
+        let short_text = match self.short_format {
+            Some(ref short_format) => {
+                Some(short_format.render_markup(&values, self.config.markup)?)
+            }
+            None => None,
+        };
+        self.output.set_short_text(short_text);
 
         Ok(Some(self.update_interval.into()))
     }
@@ -984,14 +1153,77 @@ impl Block for Net {
     }
 }
 
+/// Finds the interface of the default route with the lowest metric in the
+/// contents of /proc/net/route.
+fn parse_default_route(routes: &str) -> Option<String> {
+    // Routes that are up have the RTF_UP flag set
+    const RTF_UP: u32 = 0x1;
+
+    routes
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let flags = u32::from_str_radix(fields.get(3)?, 16).ok()?;
+            let metric = fields.get(6)?.parse::<u32>().ok()?;
+            if *fields.get(1)? == "00000000" && *fields.get(7)? == "00000000" && flags & RTF_UP != 0
+            {
+                Some((metric, fields[0]))
+            } else {
+                None
+            }
+        })
+        .min_by_key(|(metric, _)| *metric)
+        .map(|(_, device)| device.to_string())
+}
+
+/// Converts a signal strength in dBm to an approximate percentage.
+fn signal_strength_percent(dbm: i32) -> u32 {
+    let xbm = dbm as f64;
+
+    // Code inspired by https://github.com/NetworkManager/NetworkManager/blob/master/src/platform/wifi/nm-wifi-utils-nl80211.c
+    const NOISE_FLOOR_DBM: f64 = -90.;
+    const SIGNAL_MAX_DBM: f64 = -20.;
+
+    let xbm = if xbm < NOISE_FLOOR_DBM {
+        NOISE_FLOOR_DBM
+    } else if xbm > SIGNAL_MAX_DBM {
+        SIGNAL_MAX_DBM
+    } else {
+        xbm
+    };
+
+    let result = 100. - 70. * ((SIGNAL_MAX_DBM - xbm) / (SIGNAL_MAX_DBM - NOISE_FLOOR_DBM));
+    result as u32
+}
+
 #[derive(Deserialize)]
 struct IpDev {
     addr_info: Option<Vec<IpAddrInfo>>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Debug, Default)]
 struct IpAddrInfo {
     local: Option<String>,
+    #[serde(default)]
+    scope: String,
+    #[serde(default)]
+    temporary: bool,
+    #[serde(default)]
+    deprecated: bool,
+}
+
+/// Picks the IPv6 address to show out of the addresses of a device, as listed
+/// by `ip`. Only global addresses are considered, and non-deprecated ones and
+/// (unless `prefer_temporary` is set) non-temporary ones come first. Within
+/// a scope, the kernel lists the most recently added address first, so the
+/// first match wins.
+fn select_ipv6_addr(addrs: &[&IpAddrInfo], prefer_temporary: bool) -> Option<String> {
+    addrs
+        .iter()
+        .filter(|addr| addr.scope == "global" && addr.local.is_some())
+        .min_by_key(|addr| (addr.deprecated, addr.temporary != prefer_temporary))
+        .and_then(|addr| addr.local.clone())
 }
 
 fn get_ssid(dev: &NetworkDevice) -> Result<Option<String>> {
@@ -1168,7 +1400,58 @@ fn decode_escaped_unicode(raw: &[u8]) -> Vec<u8> {
 
 #[cfg(test)]
 mod tests {
-    use crate::blocks::net::maybe_ssid_convert;
+    use crate::blocks::net::{
+        maybe_ssid_convert, parse_default_route, select_ipv6_addr, IpAddrInfo,
+    };
+
+    fn ipv6(local: &str, scope: &str, temporary: bool, deprecated: bool) -> IpAddrInfo {
+        IpAddrInfo {
+            local: Some(local.to_string()),
+            scope: scope.to_string(),
+            temporary,
+            deprecated,
+        }
+    }
+
+    #[test]
+    fn test_select_ipv6_addr() {
+        let link_local = ipv6("fe80::1", "link", false, false);
+        let temporary = ipv6("2001:db8::1234", "global", true, false);
+        let stable = ipv6("2001:db8::1", "global", false, false);
+        let older_stable = ipv6("2001:db8::2", "global", false, false);
+        let addrs = [&link_local, &temporary, &stable, &older_stable];
+
+        assert_eq!(
+            select_ipv6_addr(&addrs, false),
+            Some("2001:db8::1".to_string())
+        );
+        assert_eq!(
+            select_ipv6_addr(&addrs, true),
+            Some("2001:db8::1234".to_string())
+        );
+        assert_eq!(select_ipv6_addr(&[&link_local], false), None);
+
+        let deprecated = ipv6("2001:db8::3", "global", false, true);
+        assert_eq!(
+            select_ipv6_addr(&[&deprecated, &temporary], false),
+            Some("2001:db8::1234".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_default_route() {
+        let routes =
+            "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT\n\
+                      wlan0\t00000000\t0102A8C0\t0003\t0\t0\t600\t00000000\t0\t0\t0\n\
+                      enp0s31f6\t00000000\t0100A8C0\t0003\t0\t0\t100\t00000000\t0\t0\t0\n\
+                      enp0s31f6\t0000A8C0\t00000000\t0001\t0\t0\t100\t00FFFFFF\t0\t0\t0\n";
+        assert_eq!(parse_default_route(routes), Some("enp0s31f6".to_string()));
+
+        let routes =
+            "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT\n\
+                      wlan0\t0002A8C0\t00000000\t0001\t0\t0\t600\t00FFFFFF\t0\t0\t0\n";
+        assert_eq!(parse_default_route(routes), None);
+    }
 
     #[test]
     fn test_ssid_decode_escaped_unicode() {
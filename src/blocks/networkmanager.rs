@@ -3,7 +3,7 @@ use std::fmt;
 use std::net::Ipv4Addr;
 use std::result;
 use std::thread;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use crossbeam_channel::Sender;
 use dbus::arg::{Array, Iter, Variant};
@@ -17,6 +17,7 @@ use serde_derive::Deserialize;
 
 use crate::blocks::{Block, ConfigBlock, Update};
 use crate::config::Config;
+use crate::de::deserialize_opt_duration;
 use crate::errors::*;
 use crate::scheduler::Task;
 use crate::util::{pseudo_uuid, FormatTemplate};
@@ -50,6 +51,40 @@ impl From<u32> for NetworkState {
     }
 }
 
+#[derive(Clone, Copy, PartialEq)]
+enum ConnectivityState {
+    Unknown,
+    None,
+    Portal,
+    Limited,
+    Full,
+}
+
+impl From<u32> for ConnectivityState {
+    fn from(id: u32) -> Self {
+        match id {
+            // https://developer.gnome.org/NetworkManager/stable/nm-dbus-types.html#NMConnectivityState
+            1 => ConnectivityState::None,
+            2 => ConnectivityState::Portal,
+            3 => ConnectivityState::Limited,
+            4 => ConnectivityState::Full,
+            _ => ConnectivityState::Unknown,
+        }
+    }
+}
+
+impl ConnectivityState {
+    fn as_str(self) -> &'static str {
+        match self {
+            ConnectivityState::Unknown => "unknown",
+            ConnectivityState::None => "none",
+            ConnectivityState::Portal => "portal",
+            ConnectivityState::Limited => "limited",
+            ConnectivityState::Full => "full",
+        }
+    }
+}
+
 enum ActiveConnectionState {
     Unknown,
     Activating,
@@ -205,6 +240,17 @@ impl ConnectionManager {
         Ok(NetworkState::from(state.0))
     }
 
+    pub fn connectivity(&self, c: &Connection) -> Result<ConnectivityState> {
+        let m = Self::get_property(c, "Connectivity")
+            .block_error("networkmanager", "Failed to retrieve connectivity")?;
+
+        let connectivity: Variant<u32> = m
+            .get1()
+            .block_error("networkmanager", "Failed to read property")?;
+
+        Ok(ConnectivityState::from(connectivity.0))
+    }
+
     pub fn primary_connection(&self, c: &Connection) -> Result<NmConnection> {
         let m = Self::get_property(c, "PrimaryConnection")
             .block_error("networkmanager", "Failed to retrieve primary connection")?;
@@ -455,11 +501,16 @@ pub struct NetworkManager {
     connection_format: FormatTemplate,
     interface_name_exclude_regexps: Vec<Regex>,
     interface_name_include_regexps: Vec<Regex>,
+    update_interval: Option<Duration>,
 }
 
 #[derive(Deserialize, Debug, Default, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct NetworkManagerConfig {
+    /// Update interval in seconds, in addition to the updates on changes.
+    #[serde(default, deserialize_with = "deserialize_opt_duration")]
+    pub interval: Option<Duration>,
+
     /// Whether to only show the primary connection, or all active connections.
     #[serde(default = "NetworkManagerConfig::default_primary_only")]
     pub primary_only: bool,
@@ -540,12 +591,26 @@ impl ConfigBlock for NetworkManager {
             .name("networkmanager".into())
             .spawn(move || {
                 let c = Connection::get_private(BusType::System).unwrap();
-                let rule = "type='signal',\
+                let rules = [
+                    "type='signal',\
                         path='/org/freedesktop/NetworkManager',\
                         interface='org.freedesktop.NetworkManager',\
-                        member='PropertiesChanged'";
-
-                c.add_match(&rule).unwrap();
+                        member='PropertiesChanged'",
+                    "type='signal',\
+                        path='/org/freedesktop/NetworkManager',\
+                        interface='org.freedesktop.NetworkManager',\
+                        member='StateChanged'",
+                    // Notices NetworkManager being restarted, so that the
+                    // block doesn't keep showing it as being gone.
+                    "type='signal',\
+                        interface='org.freedesktop.DBus',\
+                        member='NameOwnerChanged',\
+                        arg0='org.freedesktop.NetworkManager'",
+                ];
+
+                for rule in rules.iter() {
+                    c.add_match(rule).unwrap();
+                }
 
                 loop {
                     let timeout = 300_000;
@@ -585,6 +650,7 @@ impl ConfigBlock for NetworkManager {
                 .block_error("networkmanager", "failed to parse exclude patterns")?,
             interface_name_include_regexps: compile_regexps(block_config.interface_name_include)
                 .block_error("networkmanager", "failed to parse include patterns")?,
+            update_interval: block_config.interval,
         })
     }
 }
@@ -596,8 +662,15 @@ impl Block for NetworkManager {
 
     fn update(&mut self) -> Result<Option<Update>> {
         let state = self.manager.state(&self.dbus_conn);
+        // Older versions of NetworkManager don't check connectivity
+        let connectivity = self
+            .manager
+            .connectivity(&self.dbus_conn)
+            .unwrap_or(ConnectivityState::Unknown);
+        let captive_portal = connectivity == ConnectivityState::Portal;
 
         self.indicator.set_state(match state {
+            _ if captive_portal => State::Warning,
             Ok(NetworkState::ConnectedGlobal) => State::Good,
             Ok(NetworkState::ConnectedSite) => State::Info,
             Ok(NetworkState::ConnectedLocal) => State::Idle,
@@ -620,6 +693,7 @@ impl Block for NetworkManager {
 
             _ => {
                 let good_state = match state {
+                    _ if captive_portal => State::Warning,
                     Ok(NetworkState::ConnectedGlobal) => State::Good,
                     Ok(NetworkState::ConnectedSite) => State::Info,
                     _ => State::Idle,
@@ -779,7 +853,8 @@ impl Block for NetworkManager {
                         };
 
                         let values = map!("{devices}" => devicevec.join(" "),
-                                          "{id}" => id);
+                                          "{id}" => id,
+                                          "{connectivity}" => connectivity.as_str().to_string());
 
                         if let Ok(s) = self.connection_format.render_static_str(&values) {
                             widget.set_text(s);
@@ -797,7 +872,7 @@ impl Block for NetworkManager {
             }
         };
 
-        Ok(None)
+        Ok(self.update_interval.map(|d| d.into()))
     }
 
     fn view(&self) -> Vec<&dyn I3BarWidget> {
@@ -1,7 +1,7 @@
 use std::collections::BTreeMap;
-use std::sync::{Arc, Mutex};
+use std::process::Command;
 use std::thread;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use crossbeam_channel::Sender;
 use dbus::ffidisp::stdintf::org_freedesktop_dbus::{Properties, PropertiesPropertiesChanged};
@@ -11,40 +11,182 @@ use serde_derive::Deserialize;
 
 use crate::blocks::{Block, ConfigBlock, Update};
 use crate::config::Config;
+use crate::de::deserialize_duration;
 use crate::errors::*;
 use crate::input::{I3BarEvent, MouseButton};
 use crate::scheduler::Task;
-use crate::util::{pseudo_uuid, FormatTemplate};
-use crate::widget::I3BarWidget;
+use crate::util::{has_command, pseudo_uuid, FormatTemplate};
+use crate::widget::{I3BarWidget, State};
 use crate::widgets::button::ButtonWidget;
 
-// TODO
-// Add driver option so can choose between dunst, mako, etc.
+const NOTIFICATIONS: &str = "org.freedesktop.Notifications";
+const NOTIFICATIONS_PATH: &str = "/org/freedesktop/Notifications";
+/// The interface dunst extends the notification server with
+const DUNST: &str = "org.dunstproject.cmd0";
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum NotifyDriver {
+    Dunst,
+    Mako,
+}
+
+/// The state of the notification daemon.
+#[derive(Debug, Clone, Copy)]
+struct DaemonState {
+    paused: bool,
+    /// The notifications held back while paused
+    count: usize,
+}
+
+fn dunst_state(con: &Connection) -> Result<DaemonState> {
+    let p = con.with_path(NOTIFICATIONS, NOTIFICATIONS_PATH, 1000);
+    let paused = p
+        .get(DUNST, "paused")
+        .block_error("notify", "Failed to get dunst state. Is it running?")?;
+    // Only recent versions of dunst count the waiting notifications
+    let count: u32 = p.get(DUNST, "waitingLength").unwrap_or(0);
+    Ok(DaemonState {
+        paused,
+        count: count as usize,
+    })
+}
+
+fn makoctl(args: &[&str]) -> Result<String> {
+    let output = Command::new("makoctl")
+        .args(args)
+        .output()
+        .block_error("notify", "Failed to run makoctl")?;
+    if !output.status.success() {
+        return Err(BlockError(
+            "notify".to_owned(),
+            "makoctl failed. Is mako running?".to_owned(),
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Counts the notifications in the output of `makoctl list`, e.g.
+/// `{"type": "aa{sv}", "data": [[{...}, {...}]]}`.
+fn parse_mako_list(list: &str) -> Option<usize> {
+    let list: serde_json::Value = serde_json::from_str(list).ok()?;
+    Some(list.get("data")?.get(0)?.as_array()?.len())
+}
+
+fn mako_state(mode: &str) -> Result<DaemonState> {
+    // `makoctl mode` prints the active modes, one per line
+    let paused = makoctl(&["mode"])?.lines().any(|line| line.trim() == mode);
+    let count = if paused {
+        makoctl(&["list"])
+            .ok()
+            .and_then(|list| parse_mako_list(&list))
+            .unwrap_or(0)
+    } else {
+        0
+    };
+    Ok(DaemonState { paused, count })
+}
+
+/// Requests an update whenever dunst is paused or resumed, or (re)started.
+fn monitor_dunst(id: String, send: Sender<Task>) {
+    thread::Builder::new()
+        .name("notify".into())
+        .spawn(move || {
+            let c = match Connection::get_private(BusType::Session) {
+                Ok(c) => c,
+                Err(_) => return,
+            };
+            let matched_signal =
+                PropertiesPropertiesChanged::match_str(Some(&NOTIFICATIONS.into()), None);
+            let owner_rule = format!(
+                "type='signal',\
+                 interface='org.freedesktop.DBus',\
+                 member='NameOwnerChanged',\
+                 arg0='{}'",
+                NOTIFICATIONS
+            );
+            if c.add_match(&matched_signal).is_err() || c.add_match(&owner_rule).is_err() {
+                return;
+            }
+            loop {
+                for msg in c.incoming(1000) {
+                    let owner_changed = msg
+                        .member()
+                        .map_or(false, |member| &*member == "NameOwnerChanged");
+                    if (owner_changed || PropertiesPropertiesChanged::from_message(&msg).is_some())
+                        && send
+                            .send(Task {
+                                id: id.clone(),
+                                update_time: Instant::now(),
+                            })
+                            .is_err()
+                    {
+                        return;
+                    }
+                }
+            }
+        })
+        .unwrap();
+}
 
 pub struct Notify {
     id: String,
-    paused: Arc<Mutex<i64>>,
-    format: FormatTemplate,
     output: ButtonWidget,
+    format: FormatTemplate,
+    update_interval: Duration,
+    /// The daemon in use, if a supported one was found
+    driver: Option<NotifyDriver>,
+    con: Option<Connection>,
+    mako_mode: String,
+    state: Option<DaemonState>,
+    send: Sender<Task>,
+    monitoring: bool,
 }
 
-#[derive(Deserialize, Debug, Default, Clone)]
+#[derive(Deserialize, Debug, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct NotifyConfig {
-    /// Format string for displaying phone information.
+    /// Notification daemon to control, detected if not set
+    #[serde(default = "NotifyConfig::default_driver")]
+    pub driver: Option<NotifyDriver>,
+
+    /// Format string for displaying the state of the notification daemon.
     #[serde(default = "NotifyConfig::default_format")]
     pub format: String,
 
+    /// Update interval in seconds, for daemons which don't signal changes
+    #[serde(
+        default = "NotifyConfig::default_interval",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub interval: Duration,
+
+    /// The mode of mako which hides notifications
+    #[serde(default = "NotifyConfig::default_mako_mode")]
+    pub mako_mode: String,
+
     #[serde(default = "NotifyConfig::default_color_overrides")]
     pub color_overrides: Option<BTreeMap<String, String>>,
 }
 
 impl NotifyConfig {
+    fn default_driver() -> Option<NotifyDriver> {
+        None
+    }
+
     fn default_format() -> String {
         // display just the bell icon
         "".into()
     }
 
+    fn default_interval() -> Duration {
+        Duration::from_secs(5)
+    }
+
+    fn default_mako_mode() -> String {
+        "do-not-disturb".into()
+    }
+
     fn default_color_overrides() -> Option<BTreeMap<String, String>> {
         None
     }
@@ -55,67 +197,72 @@ impl ConfigBlock for Notify {
 
     fn new(block_config: Self::Config, config: Config, send: Sender<Task>) -> Result<Self> {
         let id: String = pseudo_uuid();
-        let id1 = id.clone();
 
-        let c = Connection::get_private(BusType::Session).block_error(
-            "notify",
-            &"Failed to establish D-Bus connection".to_string(),
-        )?;
+        let mut notify = Notify {
+            output: ButtonWidget::new(config, &id).with_icon("bell"),
+            id,
+            format: FormatTemplate::from_string(&block_config.format)
+                .block_error("notify", "Invalid format specified for notify")?,
+            update_interval: block_config.interval,
+            driver: block_config.driver,
+            con: Connection::get_private(BusType::Session).ok(),
+            mako_mode: block_config.mako_mode,
+            state: None,
+            send,
+            monitoring: false,
+        };
+        if notify.driver.is_none() {
+            notify.driver = notify.detect();
+        }
+        Ok(notify)
+    }
+}
+
+impl Notify {
+    /// Finds a running daemon, dunst is preferred.
+    fn detect(&self) -> Option<NotifyDriver> {
+        if self
+            .con
+            .as_ref()
+            .map_or(false, |con| dunst_state(con).is_ok())
+        {
+            Some(NotifyDriver::Dunst)
+        } else if has_command("notify", "makoctl").unwrap_or(false)
+            && mako_state(&self.mako_mode).is_ok()
+        {
+            Some(NotifyDriver::Mako)
+        } else {
+            None
+        }
+    }
 
-        let p = c.with_path(
-            "org.freedesktop.Notifications",
-            "/org/freedesktop/Notifications",
-            5000,
-        );
-        let initial_state: bool = p.get("org.dunstproject.cmd0", "paused").block_error(
-            "notify",
-            &"Failed to get dunst state. Is it running?".to_string(),
-        )?;
-
-        let icon = if initial_state { "bell-slash" } else { "bell" };
-
-        // TODO: revisit this lint
-        #[allow(clippy::mutex_atomic)]
-        let state = Arc::new(Mutex::new(initial_state as i64));
-        let state_copy = state.clone();
-
-        thread::Builder::new()
-            .name("notify".into())
-            .spawn(move || {
-                let c = Connection::get_private(BusType::Session)
-                    .expect("Failed to establish D-Bus connection in thread");
-
-                let matched_signal = PropertiesPropertiesChanged::match_str(
-                    Some(&"org.freedesktop.Notifications".into()),
-                    None,
-                );
-                c.add_match(&matched_signal).unwrap();
-                loop {
-                    for msg in c.incoming(1000) {
-                        if let Some(signal) = PropertiesPropertiesChanged::from_message(&msg) {
-                            let value = signal.changed_properties.get("paused").unwrap();
-                            let status = &value.0.as_i64().unwrap();
-                            let mut paused = state_copy.lock().unwrap();
-                            *paused = *status;
-
-                            // Tell block to update now.
-                            send.send(Task {
-                                id: id1.clone(),
-                                update_time: Instant::now(),
-                            })
-                            .unwrap();
-                        }
-                    }
-                }
-            })
-            .unwrap();
+    fn read_state(&self) -> Result<DaemonState> {
+        match self.driver {
+            Some(NotifyDriver::Dunst) => dunst_state(
+                self.con
+                    .as_ref()
+                    .block_error("notify", "Failed to establish D-Bus connection")?,
+            ),
+            Some(NotifyDriver::Mako) => mako_state(&self.mako_mode),
+            None => Err(BlockError(
+                "notify".to_owned(),
+                "unsupported notification daemon".to_owned(),
+            )),
+        }
+    }
 
-        Ok(Notify {
-            id,
-            paused: state,
-            format: FormatTemplate::from_string(&block_config.format)?,
-            output: ButtonWidget::new(config, "notify").with_icon(icon),
-        })
+    fn toggle(&self, paused: bool) -> Result<()> {
+        match self.driver {
+            Some(NotifyDriver::Dunst) => self
+                .con
+                .as_ref()
+                .block_error("notify", "Failed to establish D-Bus connection")?
+                .with_path(NOTIFICATIONS, NOTIFICATIONS_PATH, 1000)
+                .set(DUNST, "paused", !paused)
+                .block_error("notify", "Failed to query D-Bus"),
+            Some(NotifyDriver::Mako) => makoctl(&["mode", "-t", &self.mako_mode]).map(drop),
+            None => Ok(()),
+        }
     }
 }
 
@@ -125,22 +272,46 @@ impl Block for Notify {
     }
 
     fn update(&mut self) -> Result<Option<Update>> {
-        let paused = *self
-            .paused
-            .lock()
-            .block_error("notify", "failed to acquire lock for `state`")?;
-
-        let values = map!(
-            "{state}" => paused.to_string()
-        );
-
-        self.output
-            .set_text(self.format.render_static_str(&values)?);
+        // The daemon may be started after the bar
+        if self.driver.is_none() {
+            self.driver = self.detect();
+        }
+        if self.driver == Some(NotifyDriver::Dunst) && !self.monitoring {
+            monitor_dunst(self.id.clone(), self.send.clone());
+            self.monitoring = true;
+        }
 
-        let icon = if paused == 1 { "bell-slash" } else { "bell" };
-        self.output.set_icon(icon);
+        match self.read_state() {
+            Ok(state) => {
+                let values = map!(
+                    "{state}" => (state.paused as i64).to_string(),
+                    "{count}" => if state.paused && state.count > 0 {
+                        state.count.to_string()
+                    } else {
+                        String::new()
+                    }
+                );
+                self.output
+                    .set_text(self.format.render_static_str(&values)?);
+                self.output
+                    .set_icon(if state.paused { "bell-slash" } else { "bell" });
+                self.output.set_state(State::Idle);
+                self.state = Some(state);
+            }
+            Err(BlockError(_, message)) => {
+                self.output.set_text(message);
+                self.output.set_state(State::Critical);
+                self.state = None;
+            }
+            Err(e) => return Err(e),
+        }
 
-        Ok(None)
+        // dunst signals changes, as long as it is monitored, once it could
+        // be read
+        match (self.driver, self.state) {
+            (Some(NotifyDriver::Dunst), Some(_)) => Ok(None),
+            _ => Ok(Some(self.update_interval.into())),
+        }
     }
 
     // Returns the view of the block, comprised of widgets.
@@ -149,34 +320,38 @@ impl Block for Notify {
     }
 
     fn click(&mut self, e: &I3BarEvent) -> Result<()> {
-        if e.name.as_ref().map(|s| s == "notify").unwrap_or(false) && e.button == MouseButton::Left
-        {
-            let c = Connection::get_private(BusType::Session).block_error(
-                "notify",
-                &"Failed to establish D-Bus connection".to_string(),
-            )?;
-
-            let p = c.with_path(
-                "org.freedesktop.Notifications",
-                "/org/freedesktop/Notifications",
-                5000,
-            );
-
-            let paused = *self
-                .paused
-                .lock()
-                .block_error("notify", "failed to acquire lock")?;
-
-            if paused == 1 {
-                p.set("org.dunstproject.cmd0", "paused", false)
-                    .block_error("notify", &"Failed to query D-Bus".to_string())?;
-            } else {
-                p.set("org.dunstproject.cmd0", "paused", true)
-                    .block_error("notify", &"Failed to query D-Bus".to_string())?;
+        if e.matches_name(&self.id) && e.button == MouseButton::Left {
+            let toggled = match self.state {
+                Some(state) => self.toggle(state.paused),
+                None => Ok(()),
+            };
+            // Read the state back, so that the icon shows what the daemon did
+            self.update()?;
+            if let Err(BlockError(_, message)) = toggled {
+                self.output.set_text(message);
+                self.output.set_state(State::Critical);
             }
-
-            // block will auto-update due to monitoring the bus
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mako_list() {
+        assert_eq!(
+            parse_mako_list(r#"{"type": "aa{sv}", "data": [[]]}"#),
+            Some(0)
+        );
+        assert_eq!(
+            parse_mako_list(
+                r#"{"type": "aa{sv}", "data": [[{"id": {"type": "u", "data": 1}}, {"id": {"type": "u", "data": 2}}]]}"#
+            ),
+            Some(2)
+        );
+        assert_eq!(parse_mako_list("no notifications"), None);
+    }
+}
@@ -11,19 +11,181 @@ use crate::de::deserialize_duration;
 use crate::errors::*;
 use crate::input::{I3BarEvent, MouseButton};
 use crate::scheduler::Task;
-use crate::util::pseudo_uuid;
+use crate::util::{pseudo_uuid, FormatTemplate};
 use crate::widget::{I3BarWidget, Spacing, State};
 use crate::widgets::button::ButtonWidget;
 use crate::widgets::text::TextWidget;
 
+#[cfg(feature = "nvml")]
+use nvml_wrapper::enum_wrappers::device::{Clock, TemperatureSensor};
+#[cfg(feature = "nvml")]
+use nvml_wrapper::NVML;
+
+/// What is known about the GPU. Values the GPU doesn't report are missing.
+#[derive(Debug, Default, Clone, PartialEq)]
+struct GpuInfo {
+    name: String,
+    /// In percent
+    utilization: Option<u64>,
+    /// In MiB
+    memory_used: Option<u64>,
+    /// In MiB
+    memory_total: Option<u64>,
+    /// In degrees C
+    temperature: Option<u64>,
+    /// In percent
+    fan_speed: Option<u64>,
+    /// In MHz
+    clocks: Option<u64>,
+    /// In W
+    power: Option<f64>,
+}
+
+const SMI_QUERY: &str = "name,memory.total,utilization.gpu,memory.used,temperature.gpu,fan.speed,clocks.current.graphics,power.draw";
+
+/// Parses a line of `nvidia-smi --query-gpu=<SMI_QUERY> --format=csv,noheader,nounits`.
+/// Values the GPU doesn't support are reported as `[Not Supported]` or `[N/A]`.
+fn parse_smi_output(output: &str) -> Option<GpuInfo> {
+    let fields: Vec<&str> = output.trim().split(", ").collect();
+    if fields.len() != 8 {
+        return None;
+    }
+    let number = |i: usize| fields[i].parse::<f64>().ok();
+    Some(GpuInfo {
+        name: fields[0].to_string(),
+        memory_total: number(1).map(|n| n as u64),
+        utilization: number(2).map(|n| n as u64),
+        memory_used: number(3).map(|n| n as u64),
+        temperature: number(4).map(|n| n as u64),
+        fan_speed: number(5).map(|n| n as u64),
+        clocks: number(6).map(|n| n as u64),
+        power: number(7),
+    })
+}
+
+/// Where the information about the GPU comes from.
+enum Backend {
+    #[cfg(feature = "nvml")]
+    Nvml(NVML),
+    NvidiaSmi,
+}
+
+impl Backend {
+    /// Prefers NVML, and falls back to `nvidia-smi`. Fails if neither can
+    /// talk to the NVIDIA driver.
+    fn new(gpu_id: u64) -> Result<Self> {
+        #[cfg(feature = "nvml")]
+        {
+            if let Ok(nvml) = NVML::init() {
+                if nvml.device_by_index(gpu_id as u32).is_ok() {
+                    return Ok(Backend::Nvml(nvml));
+                }
+            }
+        }
+
+        let output = Command::new("nvidia-smi")
+            .args(&[
+                "-i",
+                &gpu_id.to_string(),
+                "--query-gpu=name",
+                "--format=csv,noheader",
+            ])
+            .output()
+            .block_error(
+                "nvidia_gpu",
+                "Failed to execute nvidia-smi. Is the NVIDIA driver installed?",
+            )?;
+        match output.status.code() {
+            // The GPU is disabled, but may be enabled later
+            Some(0) | Some(9) => Ok(Backend::NvidiaSmi),
+            _ => Err(BlockError(
+                "nvidia_gpu".to_string(),
+                format!(
+                    "GPU {} not found: {}",
+                    gpu_id,
+                    String::from_utf8_lossy(&output.stdout).trim()
+                ),
+            )),
+        }
+    }
+
+    /// Queries the GPU, or returns `None` if it is disabled.
+    fn query(&self, gpu_id: u64) -> Result<Option<GpuInfo>> {
+        match self {
+            #[cfg(feature = "nvml")]
+            Backend::Nvml(nvml) => {
+                let device = nvml
+                    .device_by_index(gpu_id as u32)
+                    .block_error("nvidia_gpu", "Failed to get the GPU from NVML")?;
+                let memory = device.memory_info().ok();
+                Ok(Some(GpuInfo {
+                    name: device.name().unwrap_or_default(),
+                    utilization: device
+                        .utilization_rates()
+                        .ok()
+                        .map(|rates| rates.gpu as u64),
+                    memory_used: memory.as_ref().map(|memory| memory.used / 1024 / 1024),
+                    memory_total: memory.as_ref().map(|memory| memory.total / 1024 / 1024),
+                    temperature: device
+                        .temperature(TemperatureSensor::Gpu)
+                        .ok()
+                        .map(u64::from),
+                    fan_speed: device.fan_speed(0).ok().map(u64::from),
+                    clocks: device.clock_info(Clock::Graphics).ok().map(u64::from),
+                    power: device
+                        .power_usage()
+                        .ok()
+                        .map(|milliwatts| f64::from(milliwatts) / 1000.0),
+                }))
+            }
+            Backend::NvidiaSmi => {
+                let output = Command::new("nvidia-smi")
+                    .args(&[
+                        "-i",
+                        &gpu_id.to_string(),
+                        &format!("--query-gpu={}", SMI_QUERY),
+                        "--format=csv,noheader,nounits",
+                    ])
+                    .output()
+                    .block_error("nvidia_gpu", "Failed to execute nvidia-smi.")?;
+
+                match output.status.code() {
+                    Some(0) => parse_smi_output(&String::from_utf8_lossy(&output.stdout))
+                        .block_error("nvidia_gpu", "Unexpected output of nvidia-smi")
+                        .map(Some),
+                    Some(9) => Ok(None),
+                    Some(code) => Err(BlockError(
+                        "nvidia_gpu".to_string(),
+                        format!("nvidia-smi error code {}", code),
+                    )),
+                    None => Err(BlockError(
+                        "nvidia_gpu".to_string(),
+                        "nvidia-smi terminated by signal".to_string(),
+                    )),
+                }
+            }
+        }
+    }
+}
+
+fn format_value<T: std::fmt::Display>(value: Option<T>, unit: &str) -> String {
+    match value {
+        Some(value) => format!("{:02}{}", value, unit),
+        None => "N/A".to_string(),
+    }
+}
+
 pub struct NvidiaGpu {
     id: String,
     id_fans: String,
     id_memory: String,
     update_interval: Duration,
 
+    backend: Backend,
     gpu_enabled: bool,
     gpu_id: u64,
+    /// Shows everything in the name widget instead of separate widgets
+    format: Option<FormatTemplate>,
 
     name_widget: ButtonWidget,
     name_widget_mode: NameWidgetMode,
@@ -36,7 +198,9 @@ pub struct NvidiaGpu {
     show_temperature: Option<TextWidget>,
 
     show_fan: Option<ButtonWidget>,
-    fan_speed: u64,
+    /// The last fan speed the GPU reported, if any
+    fan_speed: Option<u64>,
+    fan_control: bool,
     fan_speed_controlled: bool,
     scrolling: Scrolling,
 
@@ -91,10 +255,18 @@ pub struct NvidiaGpuConfig {
     #[serde(default = "NvidiaGpuConfig::default_show_fan_speed")]
     pub show_fan_speed: bool,
 
+    /// Control the fans by clicking and scrolling
+    #[serde(default = "NvidiaGpuConfig::default_fan_control")]
+    pub fan_control: bool,
+
     /// GPU clocks. In percents.
     #[serde(default = "NvidiaGpuConfig::default_show_clocks")]
     pub show_clocks: bool,
 
+    /// Format override, replacing the `show_*` widgets
+    #[serde(default = "NvidiaGpuConfig::default_format")]
+    pub format: Option<String>,
+
     /// Maximum temperature, below which state is set to idle
     #[serde(default = "NvidiaGpuConfig::default_idle")]
     pub idle: u64,
@@ -144,10 +316,18 @@ impl NvidiaGpuConfig {
         false
     }
 
+    fn default_fan_control() -> bool {
+        false
+    }
+
     fn default_show_clocks() -> bool {
         false
     }
 
+    fn default_format() -> Option<String> {
+        None
+    }
+
     fn default_idle() -> u64 {
         50
     }
@@ -186,8 +366,16 @@ impl ConfigBlock for NvidiaGpu {
             id_fans: id_fans.clone(),
             id_memory: id_memory.clone(),
             update_interval: block_config.interval,
+            backend: Backend::new(block_config.gpu_id)?,
             gpu_enabled: false,
             gpu_id: block_config.gpu_id,
+            format: match block_config.format {
+                Some(ref format) => Some(
+                    FormatTemplate::from_string(format)
+                        .block_error("nvidia_gpu", "Invalid format specified for nvidia_gpu")?,
+                ),
+                None => None,
+            },
 
             name_widget: ButtonWidget::new(config.clone(), &id)
                 .with_icon("gpu")
@@ -227,7 +415,8 @@ impl ConfigBlock for NvidiaGpu {
             } else {
                 None
             },
-            fan_speed: 0,
+            fan_speed: None,
+            fan_control: block_config.fan_control,
             fan_speed_controlled: false,
             scrolling: config.scrolling,
 
@@ -245,65 +434,83 @@ impl ConfigBlock for NvidiaGpu {
     }
 }
 
-impl Block for NvidiaGpu {
-    fn update(&mut self) -> Result<Option<Update>> {
-        let mut params = String::from("name,memory.total,");
-        if self.show_utilization.is_some() {
-            params += "utilization.gpu,";
-        }
-        if self.show_memory.is_some() {
-            params += "memory.used,";
-        }
-        if self.show_temperature.is_some() {
-            params += "temperature.gpu,";
-        }
-        if self.show_fan.is_some() {
-            params += "fan.speed,";
-        }
-        if self.show_clocks.is_some() {
-            params += "clocks.current.graphics,";
+impl NvidiaGpu {
+    /// Takes over control of the fans, starting at the current speed, or
+    /// hands it back to the driver.
+    fn set_fan_control(&self, controlled: bool, fan_speed: u64) -> Result<()> {
+        let mut args = vec![
+            "-a".to_string(),
+            format!(
+                "[gpu:{}]/GPUFanControlState={}",
+                self.gpu_id, controlled as u8
+            ),
+        ];
+        if controlled {
+            args.push("-a".to_string());
+            args.push(format!(
+                "[fan:{}]/GPUTargetFanSpeed={}",
+                self.gpu_id, fan_speed
+            ));
         }
+        Command::new("nvidia-settings")
+            .args(&args)
+            .output()
+            .block_error("gpu", "Failed to execute nvidia-settings.")?;
+        Ok(())
+    }
 
-        let handle = Command::new("nvidia-smi")
+    fn set_fan_speed(&self, fan_speed: u64) -> Result<()> {
+        Command::new("nvidia-settings")
             .args(&[
-                "-i",
-                &self.gpu_id.to_string(),
-                &format!("--query-gpu={}", params),
-                "--format=csv,noheader,nounits",
+                "-a",
+                &format!("[fan:{}]/GPUTargetFanSpeed={}", self.gpu_id, fan_speed),
             ])
             .output()
-            .block_error("gpu", "Failed to execute nvidia-smi.")?;
-
-        self.gpu_enabled = match handle.status.code() {
-            Some(0) => true,
-            Some(9) => false,
-            Some(code) => {
-                return Err(BlockError(
-                    "nvidia_gpu".to_string(),
-                    format!("nvidia-smi error code {}", code),
-                ))
+            .block_error("gpu", "Failed to execute nvidia-settings.")?;
+        Ok(())
+    }
+}
+
+impl Block for NvidiaGpu {
+    fn update(&mut self) -> Result<Option<Update>> {
+        let info = self.backend.query(self.gpu_id)?;
+        self.gpu_enabled = info.is_some();
+
+        if let Some(info) = info {
+            let temperature_state = match info.temperature.unwrap_or(0) {
+                t if t <= self.maximum_idle => State::Idle,
+                t if t <= self.maximum_good => State::Good,
+                t if t <= self.maximum_info => State::Info,
+                t if t <= self.maximum_warning => State::Warning,
+                _ => State::Critical,
+            };
+            if info.fan_speed.is_some() {
+                self.fan_speed = info.fan_speed;
             }
-            None => {
-                return Err(BlockError(
-                    "nvidia_gpu".to_string(),
-                    "nvidia-smi terminated by signal".to_string(),
-                ))
+            let name = match self.name_widget_mode {
+                NameWidgetMode::ShowDefaultName => info.name.clone(),
+                NameWidgetMode::ShowLabel => self.label.to_string(),
+            };
+
+            if let Some(ref format) = self.format {
+                let values = map!(
+                    "{name}" => name,
+                    "{utilization}" => format_value(info.utilization, "%"),
+                    "{memory_used}" => format_value(info.memory_used, "MB"),
+                    "{memory_total}" => format_value(info.memory_total, "MB"),
+                    "{temperature}" => format_value(info.temperature, "°C"),
+                    "{fan_speed}" => format_value(info.fan_speed, "%"),
+                    "{clocks}" => format_value(info.clocks, "MHz"),
+                    "{power}" => format_value(info.power.map(|power| power.round() as u64), "W")
+                );
+                self.name_widget
+                    .set_text(format.render_static_str(&values)?);
+                self.name_widget.set_state(temperature_state);
+                return Ok(Some(self.update_interval.into()));
             }
-        };
-
-        if self.gpu_enabled {
-            let mut output = handle.stdout;
-            output.pop(); // Remove trailing newline.
-            let result_str = String::from_utf8(output).unwrap();
-            // TODO: Change to 'retain' in rust 1.26
-            let result: Vec<&str> = result_str.split(", ").collect();
-
-            let gpu_name = result[0].to_string();
-            let memory_total = result[1].to_string();
 
             match self.name_widget_mode {
                 NameWidgetMode::ShowDefaultName => {
-                    self.name_widget.set_text(gpu_name);
                     self.name_widget.set_spacing(Spacing::Inline);
                 }
                 NameWidgetMode::ShowLabel => {
@@ -312,45 +519,37 @@ impl Block for NvidiaGpu {
                     } else {
                         self.name_widget.set_spacing(Spacing::Inline);
                     }
-                    self.name_widget.set_text(self.label.to_string());
                 }
             }
+            self.name_widget.set_text(name);
 
-            let mut count: usize = 2;
             if let Some(ref mut utilization_widget) = self.show_utilization {
-                utilization_widget.set_text(format!("{:02}%", result[count]));
-                count += 1;
+                utilization_widget.set_text(format_value(info.utilization, "%"));
             }
             if let Some(ref mut memory_widget) = self.show_memory {
                 match self.memory_widget_mode {
                     MemoryWidgetMode::ShowUsedMemory => {
-                        memory_widget.set_text(format!("{}MB", result[count]));
+                        memory_widget.set_text(format_value(info.memory_used, "MB"));
                     }
                     MemoryWidgetMode::ShowTotalMemory => {
-                        memory_widget.set_text(format!("{}MB", memory_total));
+                        memory_widget.set_text(format_value(info.memory_total, "MB"));
                     }
                 }
-                count += 1;
             }
             if let Some(ref mut temperature_widget) = self.show_temperature {
-                let temp = result[count].parse::<u64>().unwrap_or(0);
-                temperature_widget.set_state(match temp {
-                    t if t <= self.maximum_idle => State::Idle,
-                    t if t <= self.maximum_good => State::Good,
-                    t if t <= self.maximum_info => State::Info,
-                    t if t <= self.maximum_warning => State::Warning,
-                    _ => State::Critical,
-                });
-                temperature_widget.set_text(format!("{:02}°C", temp));
-                count += 1;
+                temperature_widget.set_state(temperature_state);
+                temperature_widget.set_text(format_value(info.temperature, "°C"));
             }
             if let Some(ref mut fan_widget) = self.show_fan {
-                self.fan_speed = result[count].parse::<u64>().unwrap_or(0);
-                fan_widget.set_text(format!("{:02}%", self.fan_speed));
-                count += 1;
+                fan_widget.set_text(format_value(info.fan_speed, "%"));
+                fan_widget.set_state(if self.fan_speed_controlled {
+                    State::Warning
+                } else {
+                    State::Idle
+                });
             }
             if let Some(ref mut clocks_widget) = self.show_clocks {
-                clocks_widget.set_text(format!("{}MHz", result[count]));
+                clocks_widget.set_text(format_value(info.clocks, "MHz"));
             }
         } else {
             self.name_widget.set_text("DISABLED".to_string());
@@ -363,7 +562,7 @@ impl Block for NvidiaGpu {
         let mut widgets: Vec<&dyn I3BarWidget> = Vec::new();
         widgets.push(&self.name_widget);
 
-        if self.gpu_enabled {
+        if self.gpu_enabled && self.format.is_none() {
             if let Some(ref utilization_widget) = self.show_utilization {
                 widgets.push(utilization_widget);
             }
@@ -387,7 +586,11 @@ impl Block for NvidiaGpu {
         if let Some(ref name) = e.name {
             let event_name = name.as_str();
 
-            if event_name == self.id {
+            // With a format, the whole block controls the fans
+            let fan_event = self.fan_control
+                && (event_name == self.id_fans || (self.format.is_some() && event_name == self.id));
+
+            if event_name == self.id && self.format.is_none() {
                 if let MouseButton::Left = e.button {
                     match self.name_widget_mode {
                         NameWidgetMode::ShowDefaultName => {
@@ -415,9 +618,23 @@ impl Block for NvidiaGpu {
                 }
             }
 
-            if event_name == self.id_fans {
+            if fan_event {
+                // Without a known speed to start at, the fans are left to the
+                // driver until the next update reports one
+                let fan_speed = match self.fan_speed {
+                    Some(fan_speed) => fan_speed,
+                    None => {
+                        let widget = match self.show_fan {
+                            Some(ref mut fan_widget) if self.format.is_none() => fan_widget,
+                            _ => &mut self.name_widget,
+                        };
+                        widget.set_text("fan speed unknown".to_string());
+                        widget.set_state(State::Critical);
+                        return Ok(());
+                    }
+                };
                 let mut controlled_changed = false;
-                let mut new_fan_speed = self.fan_speed;
+                let mut new_fan_speed = fan_speed;
                 match e.button {
                     MouseButton::Left => {
                         self.fan_speed_controlled = !self.fan_speed_controlled;
@@ -427,12 +644,12 @@ impl Block for NvidiaGpu {
                         use LogicalDirection::*;
                         match self.scrolling.to_logical_direction(e.button) {
                             Some(Up) => {
-                                if self.fan_speed < 100 && self.fan_speed_controlled {
+                                if fan_speed < 100 && self.fan_speed_controlled {
                                     new_fan_speed += 1;
                                 }
                             }
                             Some(Down) => {
-                                if self.fan_speed > 0 && self.fan_speed_controlled {
+                                if fan_speed > 0 && self.fan_speed_controlled {
                                     new_fan_speed -= 1;
                                 }
                             }
@@ -441,46 +658,21 @@ impl Block for NvidiaGpu {
                     }
                 };
 
+                if controlled_changed {
+                    self.set_fan_control(self.fan_speed_controlled, fan_speed)?;
+                } else if self.fan_speed_controlled {
+                    self.set_fan_speed(new_fan_speed)?;
+                    self.fan_speed = Some(new_fan_speed);
+                }
+
                 if let Some(ref mut fan_widget) = self.show_fan {
-                    if controlled_changed {
-                        if self.fan_speed_controlled {
-                            Command::new("nvidia-settings")
-                                .args(&[
-                                    "-a",
-                                    &format!("[gpu:{}]/GPUFanControlState=1", self.gpu_id),
-                                    "-a",
-                                    &format!(
-                                        "[fan:{}]/GPUTargetFanSpeed={}",
-                                        self.gpu_id, self.fan_speed
-                                    ),
-                                ])
-                                .output()
-                                .block_error("gpu", "Failed to execute nvidia-settings.")?;
-                            fan_widget.set_text(format!("{:02}%", self.fan_speed));
-                            fan_widget.set_state(State::Warning);
-                        } else {
-                            Command::new("nvidia-settings")
-                                .args(&[
-                                    "-a",
-                                    &format!("[gpu:{}]/GPUFanControlState=0", self.gpu_id),
-                                ])
-                                .output()
-                                .block_error("gpu", "Failed to execute nvidia-settings.")?;
-                            fan_widget.set_state(State::Idle);
-                        }
-                    } else if self.fan_speed_controlled {
-                        Command::new("nvidia-settings")
-                            .args(&[
-                                "-a",
-                                &format!(
-                                    "[fan:{}]/GPUTargetFanSpeed={}",
-                                    self.gpu_id, new_fan_speed
-                                ),
-                            ])
-                            .output()
-                            .block_error("gpu", "Failed to execute nvidia-settings.")?;
-                        self.fan_speed = new_fan_speed;
+                    if self.format.is_none() {
                         fan_widget.set_text(format!("{:02}%", new_fan_speed));
+                        fan_widget.set_state(if self.fan_speed_controlled {
+                            State::Warning
+                        } else {
+                            State::Idle
+                        });
                     }
                 }
             }
@@ -493,3 +685,41 @@ impl Block for NvidiaGpu {
         &self.id
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_smi_output() {
+        assert_eq!(
+            parse_smi_output("GeForce GT 1030, 2001, 3, 260, 35, 32, 139, 7.81\n"),
+            Some(GpuInfo {
+                name: "GeForce GT 1030".to_string(),
+                utilization: Some(3),
+                memory_used: Some(260),
+                memory_total: Some(2001),
+                temperature: Some(35),
+                fan_speed: Some(32),
+                clocks: Some(139),
+                power: Some(7.81),
+            })
+        );
+        assert_eq!(
+            parse_smi_output(
+                "Quadro P1000, 4040, 0, 5, 41, [Not Supported], 139, [Not Supported]\n"
+            ),
+            Some(GpuInfo {
+                name: "Quadro P1000".to_string(),
+                utilization: Some(0),
+                memory_used: Some(5),
+                memory_total: Some(4040),
+                temperature: Some(41),
+                fan_speed: None,
+                clocks: Some(139),
+                power: None,
+            })
+        );
+        assert_eq!(parse_smi_output("No devices were found"), None);
+    }
+}
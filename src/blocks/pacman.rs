@@ -1,4 +1,5 @@
-use std::collections::BTreeMap;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
 use std::env;
 use std::ffi::OsString;
 use std::fs;
@@ -15,6 +16,7 @@ use crate::blocks::{Block, ConfigBlock, Update};
 use crate::config::Config;
 use crate::de::deserialize_duration;
 use crate::errors::*;
+use crate::http;
 use crate::input::{I3BarEvent, MouseButton};
 use crate::scheduler::Task;
 use crate::util::{has_command, pseudo_uuid, FormatTemplate};
@@ -33,15 +35,17 @@ pub struct Pacman {
     watched: Watched,
     uptodate: bool,
     hide_when_uptodate: bool,
+    /// The AUR updates found by the last successful query of the AUR RPC
+    aur_cache: Option<String>,
 }
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum Watched {
     Pacman,
-    /// cf `Pacman::aur_command`
-    AUR(String),
-    /// cf `Pacman::aur_command`
-    Both(String),
+    /// cf `Pacman::aur_command`, the AUR RPC is queried if it is not set
+    AUR(Option<String>),
+    /// cf `Pacman::aur_command`, the AUR RPC is queried if it is not set
+    Both(Option<String>),
 }
 
 #[derive(Deserialize, Debug, Default, Clone)]
@@ -77,7 +81,8 @@ pub struct PacmanConfig {
     #[serde(default = "PacmanConfig::default_critical_updates_regex")]
     pub critical_updates_regex: Option<String>,
 
-    /// Optional AUR command, listing available updates
+    /// Optional AUR command, listing available updates. If not set, the
+    /// versions of foreign packages are looked up with the AUR RPC
     #[serde()]
     pub aur_command: Option<String>,
 
@@ -125,18 +130,10 @@ impl PacmanConfig {
             || concatenated_format_str.contains(pacman_deprecated_format);
         let both = concatenated_format_str.contains(both_format);
         if both || (pacman && aur) {
-            let aur_command = aur_command.block_error(
-                "pacman",
-                "{aur} or {both} found in format string but no aur_command supplied",
-            )?;
             Ok(Watched::Both(aur_command))
         } else if pacman && !aur {
             Ok(Watched::Pacman)
         } else if !pacman && aur {
-            let aur_command = aur_command.block_error(
-                "pacman",
-                "{aur} found in format string but no aur_command supplied",
-            )?;
             Ok(Watched::AUR(aur_command))
         } else {
             // most likely a mistake: {count}, {pacman}, {aur}, {both} not found in format string
@@ -221,6 +218,7 @@ impl ConfigBlock for Pacman {
             )?,
             uptodate: false,
             hide_when_uptodate: block_config.hide_when_uptodate,
+            aur_cache: None,
         })
     }
 }
@@ -239,7 +237,7 @@ fn has_fake_root() -> Result<bool> {
     has_command("pacman", "fakeroot")
 }
 
-fn check_fakeroot_command_exists() -> Result<()> {
+pub(super) fn check_fakeroot_command_exists() -> Result<()> {
     if !has_fake_root()? {
         Err(BlockError(
             "pacman".to_string(),
@@ -265,7 +263,7 @@ fn get_updates_db_dir() -> Result<String> {
         .block_error("pacman", "There's a problem with your $CHECKUPDATES_DB")
 }
 
-fn get_pacman_available_updates() -> Result<String> {
+pub(super) fn get_pacman_available_updates() -> Result<String> {
     let updates_db = get_updates_db_dir()?;
 
     // Determine pacman database path
@@ -324,7 +322,238 @@ fn get_aur_available_updates(aur_command: &str) -> Result<String> {
     )
 }
 
-fn get_update_count(updates: &str) -> usize {
+const AUR_RPC_URL: &str = "https://aur.archlinux.org/rpc/?v=5&type=info";
+
+/// The AUR RPC rejects requests for too many packages at once
+const AUR_RPC_BATCH_SIZE: usize = 200;
+
+const AUR_RPC_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Lists the installed packages which are not found in the sync databases as
+/// `(name, version)`, most of which come from the AUR.
+fn get_foreign_packages() -> Result<Vec<(String, String)>> {
+    let output = Command::new("pacman")
+        .env("LC_ALL", "C")
+        .arg("-Qm")
+        .output()
+        .block_error("pacman", "Failed to list foreign packages")?;
+    // pacman fails if there are no foreign packages at all
+    Ok(parse_foreign_packages(&String::from_utf8_lossy(
+        &output.stdout,
+    )))
+}
+
+fn parse_foreign_packages(output: &str) -> Vec<(String, String)> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            Some((parts.next()?.to_string(), parts.next()?.to_string()))
+        })
+        .collect()
+}
+
+/// Percent-encodes everything but the unreserved characters of RFC 3986.
+fn url_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Looks up the versions of the given packages in the AUR, with one request
+/// per `AUR_RPC_BATCH_SIZE` packages. Packages unknown to the AUR are missing
+/// from the result.
+fn get_aur_versions(names: &[&str]) -> Result<HashMap<String, String>> {
+    let mut versions = HashMap::new();
+    for batch in names.chunks(AUR_RPC_BATCH_SIZE) {
+        let url = batch.iter().fold(AUR_RPC_URL.to_string(), |url, name| {
+            url + "&arg[]=" + &url_encode(name)
+        });
+        let response = http::http_get_json(&url, Some(AUR_RPC_TIMEOUT), vec![])?;
+        if response.code != 200 {
+            return Err(BlockError(
+                "pacman".to_string(),
+                format!("AUR RPC returned status {}", response.code),
+            ));
+        }
+        if let Some(error) = response.content.get("error").and_then(|e| e.as_str()) {
+            return Err(BlockError(
+                "pacman".to_string(),
+                format!("AUR RPC error: {}", error),
+            ));
+        }
+        let results = response
+            .content
+            .get("results")
+            .and_then(|r| r.as_array())
+            .block_error("pacman", "Malformed response of the AUR RPC")?;
+        for result in results {
+            if let (Some(name), Some(version)) = (
+                result.get("Name").and_then(|n| n.as_str()),
+                result.get("Version").and_then(|v| v.as_str()),
+            ) {
+                versions.insert(name.to_string(), version.to_string());
+            }
+        }
+    }
+    Ok(versions)
+}
+
+/// Lists the foreign packages with a newer version in the AUR, in the same
+/// format as `pacman -Qu`.
+fn get_aur_rpc_updates() -> Result<String> {
+    let packages = get_foreign_packages()?;
+    if packages.is_empty() {
+        return Ok(String::new());
+    }
+    let names: Vec<&str> = packages.iter().map(|(name, _)| name.as_str()).collect();
+    let aur_versions = get_aur_versions(&names)?;
+
+    Ok(packages
+        .iter()
+        .filter_map(|(name, version)| {
+            let aur_version = aur_versions.get(name)?;
+            if vercmp(version, aur_version) == Ordering::Less {
+                Some(format!("{} {} -> {}\n", name, version, aur_version))
+            } else {
+                None
+            }
+        })
+        .collect())
+}
+
+/// Splits a version into epoch, version and release, like pacman's
+/// `parseEVR`.
+fn parse_evr(evr: &str) -> (&str, &str, Option<&str>) {
+    let digits = evr.bytes().take_while(u8::is_ascii_digit).count();
+    let (epoch, rest) = if evr[digits..].starts_with(':') {
+        match &evr[..digits] {
+            "" => ("0", &evr[digits + 1..]),
+            epoch => (epoch, &evr[digits + 1..]),
+        }
+    } else {
+        ("0", evr)
+    };
+    match rest.rfind('-') {
+        Some(i) => (epoch, &rest[..i], Some(&rest[i + 1..])),
+        None => (epoch, rest, None),
+    }
+}
+
+/// Compares two version segments the way `rpmvercmp` does: alternating runs
+/// of digits and letters are compared numerically and lexically respectively.
+fn rpmvercmp(a: &str, b: &str) -> Ordering {
+    if a == b {
+        return Ordering::Equal;
+    }
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    let (mut one, mut two) = (0, 0);
+
+    while one < a.len() && two < b.len() {
+        let (sep1, sep2) = (one, two);
+        while one < a.len() && !a[one].is_ascii_alphanumeric() {
+            one += 1;
+        }
+        while two < b.len() && !b[two].is_ascii_alphanumeric() {
+            two += 1;
+        }
+        if one == a.len() || two == b.len() {
+            break;
+        }
+        // The version with the longer separator is newer
+        if one - sep1 != two - sep2 {
+            return (one - sep1).cmp(&(two - sep2));
+        }
+
+        let is_num = a[one].is_ascii_digit();
+        let in_segment = |c: u8| {
+            if is_num {
+                c.is_ascii_digit()
+            } else {
+                c.is_ascii_alphabetic()
+            }
+        };
+        let end1 = one + a[one..].iter().take_while(|&&c| in_segment(c)).count();
+        let end2 = two + b[two..].iter().take_while(|&&c| in_segment(c)).count();
+
+        // Numeric segments are newer than alphabetic ones
+        if end2 == two {
+            return if is_num {
+                Ordering::Greater
+            } else {
+                Ordering::Less
+            };
+        }
+
+        let (mut seg1, mut seg2) = (&a[one..end1], &b[two..end2]);
+        if is_num {
+            while seg1.len() > 1 && seg1[0] == b'0' {
+                seg1 = &seg1[1..];
+            }
+            while seg2.len() > 1 && seg2[0] == b'0' {
+                seg2 = &seg2[1..];
+            }
+            match seg1.len().cmp(&seg2.len()) {
+                Ordering::Equal => {}
+                ordering => return ordering,
+            }
+        }
+        match seg1.cmp(seg2) {
+            Ordering::Equal => {}
+            ordering => return ordering,
+        }
+
+        one = end1;
+        two = end2;
+    }
+
+    match (a.get(one), b.get(two)) {
+        (None, None) => Ordering::Equal,
+        // A remaining alphabetic segment means a pre-release, e.g. 1.0alpha < 1.0
+        (None, Some(c)) if !c.is_ascii_alphabetic() => Ordering::Less,
+        (Some(c), _) if c.is_ascii_alphabetic() => Ordering::Less,
+        _ => Ordering::Greater,
+    }
+}
+
+/// Compares two package versions with the semantics of `vercmp`.
+fn vercmp(a: &str, b: &str) -> Ordering {
+    if a == b {
+        return Ordering::Equal;
+    }
+    let (epoch1, version1, release1) = parse_evr(a);
+    let (epoch2, version2, release2) = parse_evr(b);
+
+    rpmvercmp(epoch1, epoch2)
+        .then_with(|| rpmvercmp(version1, version2))
+        .then_with(|| match (release1, release2) {
+            (Some(release1), Some(release2)) => rpmvercmp(release1, release2),
+            _ => Ordering::Equal,
+        })
+}
+
+/// Lists the available AUR updates with `aur_command`, or with the AUR RPC if
+/// it is not set. Failures of the latter fall back to the last known updates,
+/// if there are any.
+fn get_aur_updates(aur_command: &Option<String>, cache: &mut Option<String>) -> Result<String> {
+    match aur_command {
+        Some(aur_command) => get_aur_available_updates(aur_command),
+        None => match get_aur_rpc_updates() {
+            Ok(updates) => {
+                *cache = Some(updates.clone());
+                Ok(updates)
+            }
+            Err(e) => cache.clone().ok_or(e),
+        },
+    }
+}
+
+pub(super) fn get_update_count(updates: &str) -> usize {
     updates
         .lines()
         .filter(|line| !line.contains("[ignored]"))
@@ -353,6 +582,26 @@ impl Block for Pacman {
     }
 
     fn update(&mut self) -> Result<Option<Update>> {
+        let aur_available_updates = match &self.watched {
+            Watched::Pacman => String::new(),
+            Watched::AUR(aur_command) | Watched::Both(aur_command) => {
+                match get_aur_updates(aur_command, &mut self.aur_cache) {
+                    Ok(updates) => updates,
+                    // No count is better than a wrong one, until the AUR RPC
+                    // answered once
+                    Err(e) if aur_command.is_none() => {
+                        self.output.set_text(match e {
+                            BlockError(_, message) => message,
+                            _ => "AUR RPC can't be reached".to_owned(),
+                        });
+                        self.output.set_state(State::Critical);
+                        self.uptodate = false;
+                        return Ok(Some(self.update_interval.into()));
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+        };
         let (formatting_map, warning, critical, cum_count) = match &self.watched {
             Watched::Pacman => {
                 check_fakeroot_command_exists()?;
@@ -369,8 +618,7 @@ impl Block for Pacman {
 
                 (formatting_map, warning, critical, pacman_count)
             }
-            Watched::AUR(aur_command) => {
-                let aur_available_updates = get_aur_available_updates(&aur_command)?;
+            Watched::AUR(_) => {
                 let aur_count = get_update_count(&aur_available_updates);
                 let formatting_map = map!("{aur}" => aur_count);
 
@@ -383,10 +631,9 @@ impl Block for Pacman {
 
                 (formatting_map, warning, critical, aur_count)
             }
-            Watched::Both(aur_command) => {
+            Watched::Both(_) => {
                 check_fakeroot_command_exists()?;
                 let pacman_available_updates = get_pacman_available_updates()?;
-                let aur_available_updates = get_aur_available_updates(&aur_command)?;
                 let pacman_count = get_update_count(&pacman_available_updates);
                 let aur_count = get_update_count(&aur_available_updates);
                 let formatting_map = map!("{count}" => pacman_count, "{pacman}" => pacman_count, "{aur}" => aur_count, "{both}" => pacman_count + aur_count);
@@ -437,8 +684,11 @@ impl Block for Pacman {
 
 #[cfg(test)]
 mod tests {
+    use std::cmp::Ordering;
+
     use crate::blocks::pacman::{
-        get_aur_available_updates, get_update_count, PacmanConfig, Watched,
+        get_aur_available_updates, get_update_count, parse_foreign_packages, url_encode, vercmp,
+        PacmanConfig, Watched,
     };
 
     #[test]
@@ -462,6 +712,9 @@ mod tests {
         assert_eq!(watched.unwrap(), Watched::Pacman);
         let watched = PacmanConfig::watched("foo bar", "foo bar", "", None);
         assert!(watched.is_err()); // missing formatter
+        let watched = PacmanConfig::watched("foo {aur} bar", "foo {aur} bar", "", None);
+        assert!(watched.is_ok());
+        assert_eq!(watched.unwrap(), Watched::AUR(None));
         let watched = PacmanConfig::watched("foo bar", "foo bar", "", Some("aur cmd".to_string()));
         assert!(watched.is_err()); // missing formatter
         let watched = PacmanConfig::watched(
@@ -471,7 +724,7 @@ mod tests {
             Some("aur cmd".to_string()),
         );
         assert!(watched.is_ok());
-        assert_eq!(watched.unwrap(), Watched::AUR("aur cmd".to_string()));
+        assert_eq!(watched.unwrap(), Watched::AUR(Some("aur cmd".to_string())));
         let watched = PacmanConfig::watched(
             "foo {pacman} {aur} bar",
             "foo {pacman} {aur} bar",
@@ -479,12 +732,14 @@ mod tests {
             Some("aur cmd".to_string()),
         );
         assert!(watched.is_ok());
-        assert_eq!(watched.unwrap(), Watched::Both("aur cmd".to_string()));
+        assert_eq!(watched.unwrap(), Watched::Both(Some("aur cmd".to_string())));
         let watched =
             PacmanConfig::watched("foo {pacman} {aur} bar", "foo {pacman} {aur} bar", "", None);
-        assert!(watched.is_err()); // missing aur command
+        assert!(watched.is_ok());
+        assert_eq!(watched.unwrap(), Watched::Both(None));
         let watched = PacmanConfig::watched("foo {both} bar", "foo {both} bar", "", None);
-        assert!(watched.is_err()); // missing aur command
+        assert!(watched.is_ok());
+        assert_eq!(watched.unwrap(), Watched::Both(None));
         let watched = PacmanConfig::watched(
             "foo {both} bar",
             "foo {both} bar",
@@ -492,7 +747,7 @@ mod tests {
             Some("aur cmd".to_string()),
         );
         assert!(watched.is_ok());
-        assert_eq!(watched.unwrap(), Watched::Both("aur cmd".to_string()));
+        assert_eq!(watched.unwrap(), Watched::Both(Some("aur cmd".to_string())));
     }
 
     #[test]
@@ -504,4 +759,41 @@ mod tests {
         assert!(available_updates.is_ok());
         assert_eq!(available_updates.unwrap(), updates);
     }
+
+    #[test]
+    fn test_parse_foreign_packages() {
+        let output = "google-chrome 89.0.4389.114-1\nyay-bin 10.1.2-1\n";
+        assert_eq!(
+            parse_foreign_packages(output),
+            vec![
+                ("google-chrome".to_string(), "89.0.4389.114-1".to_string()),
+                ("yay-bin".to_string(), "10.1.2-1".to_string())
+            ]
+        );
+        assert!(parse_foreign_packages("").is_empty());
+    }
+
+    #[test]
+    fn test_url_encode() {
+        assert_eq!(url_encode("python-foo_bar.2"), "python-foo_bar.2");
+        assert_eq!(url_encode("libc++"), "libc%2B%2B");
+        assert_eq!(url_encode("foo@bar"), "foo%40bar");
+    }
+
+    #[test]
+    fn test_vercmp() {
+        assert_eq!(vercmp("1.0-1", "1.0-1"), Ordering::Equal);
+        assert_eq!(vercmp("1.0-1", "1.0-2"), Ordering::Less);
+        assert_eq!(vercmp("1.0-1", "1.1-1"), Ordering::Less);
+        assert_eq!(vercmp("1.10-1", "1.9-1"), Ordering::Greater);
+        assert_eq!(vercmp("1.0", "1.0-5"), Ordering::Equal);
+        assert_eq!(vercmp("1:1.0-1", "2.0-1"), Ordering::Greater);
+        assert_eq!(vercmp("0:1.0-1", "1.0-1"), Ordering::Equal);
+        assert_eq!(vercmp("1.0alpha-1", "1.0-1"), Ordering::Less);
+        assert_eq!(vercmp("1.0a-1", "1.0b-1"), Ordering::Less);
+        assert_eq!(vercmp("1.0.1-1", "1.0-1"), Ordering::Greater);
+        assert_eq!(vercmp("1.0a", "1.0.1"), Ordering::Less);
+        assert_eq!(vercmp("1.001", "1.1"), Ordering::Equal);
+        assert_eq!(vercmp("r100.abc", "r99.def"), Ordering::Greater);
+    }
 }
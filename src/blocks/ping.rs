@@ -0,0 +1,314 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::Sender;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde_derive::Deserialize;
+
+use crate::blocks::{Block, ConfigBlock, Update};
+use crate::config::Config;
+use crate::de::deserialize_duration;
+use crate::errors::*;
+use crate::scheduler::Task;
+use crate::util::{pseudo_uuid, FormatTemplate};
+use crate::widget::{I3BarWidget, State};
+use crate::widgets::text::TextWidget;
+
+lazy_static! {
+    static ref PING_TIME_REGEX: Regex = Regex::new(r"time[=<]([0-9.]+) ?ms").unwrap();
+}
+
+/// Parses the round-trip time in milliseconds out of the output of `ping`.
+fn parse_ping_output(output: &str) -> Option<f64> {
+    PING_TIME_REGEX
+        .captures(output)?
+        .get(1)?
+        .as_str()
+        .parse()
+        .ok()
+}
+
+/// Sends a single ICMP echo request using the system's `ping`, which has the
+/// privileges needed for raw sockets. Returns `None` if `ping` could not be
+/// used at all, e.g. because it is missing or lacks CAP_NET_RAW.
+fn probe_icmp(host: &str, timeout: Duration) -> Option<Option<f64>> {
+    // Older versions of ping only accept whole seconds
+    let timeout = timeout.as_secs_f64().ceil().max(1.).to_string();
+    let output = Command::new("ping")
+        .args(["-n", "-c", "1", "-W", &timeout, host])
+        .output()
+        .ok()?;
+    match output.status.code() {
+        Some(0) => Some(parse_ping_output(&String::from_utf8_lossy(&output.stdout))),
+        // No reply
+        Some(1) => Some(None),
+        _ => None,
+    }
+}
+
+/// Measures the time it takes to open a TCP connection to the host.
+fn probe_tcp(host: &str, port: u16, timeout: Duration) -> Option<f64> {
+    let addr = (host, port).to_socket_addrs().ok()?.next()?;
+    let start = Instant::now();
+    TcpStream::connect_timeout(&addr, timeout).ok()?;
+    Some(start.elapsed().as_secs_f64() * 1000.)
+}
+
+/// The share of the probes that got no reply, in percent.
+fn packet_loss(history: &VecDeque<Option<f64>>) -> f64 {
+    if history.is_empty() {
+        0.
+    } else {
+        let lost = history.iter().filter(|rtt| rtt.is_none()).count();
+        100. * lost as f64 / history.len() as f64
+    }
+}
+
+pub struct Ping {
+    text: TextWidget,
+    id: String,
+    /// The results of the last probes, the most recent one last. A probe
+    /// without reply is `None`.
+    history: Arc<Mutex<VecDeque<Option<f64>>>>,
+    format: FormatTemplate,
+    unreachable_format: FormatTemplate,
+    warning: f64,
+    critical: f64,
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PingConfig {
+    /// Update interval in seconds
+    #[serde(
+        default = "PingConfig::default_interval",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub interval: Duration,
+
+    /// The host to ping
+    pub host: String,
+
+    /// Time in seconds to wait for a reply
+    #[serde(
+        default = "PingConfig::default_timeout",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub timeout: Duration,
+
+    /// Port to connect to if ICMP can't be used
+    #[serde(default = "PingConfig::default_tcp_port")]
+    pub tcp_port: u16,
+
+    /// Number of probes the packet loss is computed over
+    #[serde(default = "PingConfig::default_loss_window")]
+    pub loss_window: usize,
+
+    /// Format override
+    #[serde(default = "PingConfig::default_format")]
+    pub format: String,
+
+    /// Format override while the host is unreachable
+    #[serde(default = "PingConfig::default_unreachable_format")]
+    pub unreachable_format: String,
+
+    /// Round-trip time in milliseconds, above which state is set to warning
+    #[serde(default = "PingConfig::default_warning")]
+    pub warning: f64,
+
+    /// Round-trip time in milliseconds, above which state is set to critical
+    #[serde(default = "PingConfig::default_critical")]
+    pub critical: f64,
+
+    #[serde(default = "PingConfig::default_color_overrides")]
+    pub color_overrides: Option<BTreeMap<String, String>>,
+}
+
+impl PingConfig {
+    fn default_interval() -> Duration {
+        Duration::from_secs(10)
+    }
+
+    fn default_timeout() -> Duration {
+        Duration::from_secs(5)
+    }
+
+    fn default_tcp_port() -> u16 {
+        443
+    }
+
+    fn default_loss_window() -> usize {
+        10
+    }
+
+    fn default_format() -> String {
+        "{rtt}ms".to_owned()
+    }
+
+    fn default_unreachable_format() -> String {
+        "×".to_owned()
+    }
+
+    fn default_warning() -> f64 {
+        100.
+    }
+
+    fn default_critical() -> f64 {
+        250.
+    }
+
+    fn default_color_overrides() -> Option<BTreeMap<String, String>> {
+        None
+    }
+}
+
+/// Probes the host in the background, so that waiting for a reply doesn't
+/// hold up the other blocks, and requests an update after each probe.
+fn make_thread(
+    history: Arc<Mutex<VecDeque<Option<f64>>>>,
+    config: PingConfig,
+    id: String,
+    done: Sender<Task>,
+) {
+    thread::Builder::new()
+        .name("ping".into())
+        .spawn(move || loop {
+            let start = Instant::now();
+            let rtt = match probe_icmp(&config.host, config.timeout) {
+                Some(rtt) => rtt,
+                None => probe_tcp(&config.host, config.tcp_port, config.timeout),
+            };
+
+            {
+                let mut history = history
+                    .lock()
+                    .expect("main thread paniced while holding ping history mutex");
+                history.push_back(rtt);
+                while history.len() > config.loss_window.max(1) {
+                    history.pop_front();
+                }
+            }
+
+            if done
+                .send(Task {
+                    id: id.clone(),
+                    update_time: Instant::now(),
+                })
+                .is_err()
+            {
+                break;
+            }
+
+            if let Some(remaining) = config.interval.checked_sub(start.elapsed()) {
+                thread::sleep(remaining);
+            }
+        })
+        .unwrap();
+}
+
+impl ConfigBlock for Ping {
+    type Config = PingConfig;
+
+    fn new(block_config: Self::Config, config: Config, done: Sender<Task>) -> Result<Self> {
+        let id = pseudo_uuid();
+        let history = Arc::new(Mutex::new(VecDeque::new()));
+
+        let format = FormatTemplate::from_string(&block_config.format)
+            .block_error("ping", "Invalid format specified for ping")?;
+        let unreachable_format = FormatTemplate::from_string(&block_config.unreachable_format)
+            .block_error("ping", "Invalid unreachable_format specified for ping")?;
+        let warning = block_config.warning;
+        let critical = block_config.critical;
+
+        make_thread(history.clone(), block_config, id.clone(), done);
+
+        Ok(Ping {
+            text: TextWidget::new(config, &id).with_icon("ping"),
+            id,
+            history,
+            format,
+            unreachable_format,
+            warning,
+            critical,
+        })
+    }
+}
+
+impl Block for Ping {
+    fn update(&mut self) -> Result<Option<Update>> {
+        let (last, loss) = {
+            let history = self.history.lock().block_error("ping", "mutex poisoned")?;
+            (history.back().cloned(), packet_loss(&history))
+        };
+
+        let loss = format!("{:.0}", loss);
+        match last {
+            // The first probe hasn't finished yet
+            None => {
+                let values = map!("{rtt}" => "N/A".to_string(),
+                                  "{loss}" => loss);
+                self.text.set_text(self.format.render_static_str(&values)?);
+                self.text.set_state(State::Idle);
+            }
+            Some(Some(rtt)) => {
+                let values = map!("{rtt}" => format!("{:.0}", rtt),
+                                  "{loss}" => loss);
+                self.text.set_text(self.format.render_static_str(&values)?);
+                self.text.set_state(if rtt > self.critical {
+                    State::Critical
+                } else if rtt > self.warning {
+                    State::Warning
+                } else {
+                    State::Idle
+                });
+            }
+            Some(None) => {
+                let values = map!("{rtt}" => "N/A".to_string(),
+                                  "{loss}" => loss);
+                self.text
+                    .set_text(self.unreachable_format.render_static_str(&values)?);
+                self.text.set_state(State::Critical);
+            }
+        }
+
+        // Updates are requested by the probing thread
+        Ok(None)
+    }
+
+    fn view(&self) -> Vec<&dyn I3BarWidget> {
+        vec![&self.text]
+    }
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ping_output() {
+        let output = "PING 1.1.1.1 (1.1.1.1) 56(84) bytes of data.\n\
+                      64 bytes from 1.1.1.1: icmp_seq=1 ttl=58 time=12.4 ms\n";
+        assert_eq!(parse_ping_output(output), Some(12.4));
+        // busybox
+        let output = "64 bytes from 1.1.1.1: seq=0 ttl=58 time=9.870 ms\n";
+        assert_eq!(parse_ping_output(output), Some(9.87));
+        assert_eq!(parse_ping_output("1 packets transmitted, 0 received"), None);
+    }
+
+    #[test]
+    fn test_packet_loss() {
+        let history: VecDeque<Option<f64>> =
+            vec![Some(10.), None, Some(12.), None].into_iter().collect();
+        assert_eq!(packet_loss(&history), 50.);
+        assert_eq!(packet_loss(&VecDeque::new()), 0.);
+    }
+}
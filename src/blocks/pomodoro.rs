@@ -1,9 +1,10 @@
 use std::collections::BTreeMap;
-use std::fmt;
-use std::time::{Duration, Instant};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
 
 use crossbeam_channel::Sender;
-use serde_derive::Deserialize;
+use serde_derive::{Deserialize, Serialize};
 
 use crate::blocks::{Block, ConfigBlock, Update};
 use crate::config::Config;
@@ -11,98 +12,203 @@ use crate::errors::*;
 use crate::input::{I3BarEvent, MouseButton};
 use crate::scheduler::Task;
 use crate::subprocess::spawn_child_async;
-use crate::util::pseudo_uuid;
-use crate::widget::I3BarWidget;
+use crate::util::{pseudo_uuid, xdg_cache_home, FormatTemplate};
+use crate::widget::{I3BarWidget, State as WidgetState};
 use crate::widgets::button::ButtonWidget;
 
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+enum Phase {
+    Work,
+    Break,
+}
+
+/// The state of the timer. The end of a running timer is a point in wall
+/// clock time, so that it keeps running while the bar is restarted.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
 enum State {
-    Started(Instant),
     Stopped,
-    Paused(Duration),
-    OnBreak(Instant),
+    Running {
+        phase: Phase,
+        end: SystemTime,
+    },
+    Paused {
+        phase: Phase,
+        remaining: Duration,
+    },
+    /// The work timer expired and the break hasn't been started yet
+    WorkOver,
 }
 
 impl State {
-    fn elapsed(&self) -> Duration {
+    /// The time left on the timer, if it is running or paused.
+    fn remaining(&self, now: SystemTime) -> Option<Duration> {
+        match *self {
+            State::Running { end, .. } => Some(end.duration_since(now).unwrap_or_default()),
+            State::Paused { remaining, .. } => Some(remaining),
+            _ => None,
+        }
+    }
+
+    /// The phase of a running timer that has expired by `now`.
+    fn expired(&self, now: SystemTime) -> Option<Phase> {
+        match *self {
+            State::Running { phase, end } if end <= now => Some(phase),
+            _ => None,
+        }
+    }
+
+    /// Freezes a running timer, or resumes a paused one.
+    fn toggle_pause(self, now: SystemTime) -> State {
         match self {
-            State::Started(start) => Instant::now().duration_since(start.to_owned()),
-            State::Stopped => unreachable!(),
-            State::Paused(duration) => duration.to_owned(),
-            State::OnBreak(start) => Instant::now().duration_since(start.to_owned()),
+            State::Running { phase, .. } => State::Paused {
+                phase,
+                remaining: self.remaining(now).unwrap_or_default(),
+            },
+            State::Paused { phase, remaining } => State::Running {
+                phase,
+                end: now + remaining,
+            },
+            state => state,
         }
     }
-}
 
-impl fmt::Display for State {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    fn status(&self) -> &'static str {
         match self {
-            State::Stopped => write!(f, "\u{25a0} 0:00"),
-            State::Started(_) => write!(
-                f,
-                "\u{f04b} {}:{:02}",
-                self.elapsed().as_secs() / 60,
-                self.elapsed().as_secs() % 60
-            ),
-            State::OnBreak(_) => write!(
-                f,
-                "\u{2615} {}:{:02}",
-                self.elapsed().as_secs() / 60,
-                self.elapsed().as_secs() % 60
-            ),
-            State::Paused(duration) => write!(
-                f,
-                "\u{f04c} {}:{:02}",
-                duration.as_secs() / 60,
-                duration.as_secs() % 60
-            ),
+            State::Stopped => "\u{25a0}",
+            State::Running {
+                phase: Phase::Work, ..
+            } => "\u{f04b}",
+            State::Running {
+                phase: Phase::Break,
+                ..
+            } => "\u{2615}",
+            State::Paused { .. } => "\u{f04c}",
+            State::WorkOver => "\u{23f0}",
         }
     }
 }
 
+/// What is written to the cache to restore the timer after a restart.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+struct SavedState {
+    state: State,
+    cycle: usize,
+}
+
+fn read_state(path: &Path) -> Option<SavedState> {
+    serde_json::from_str(&fs::read_to_string(path).ok()?).ok()
+}
+
+fn write_state(path: &Path, state: &SavedState) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).block_error("pomodoro", "failed to create cache directory")?;
+    }
+    let content =
+        serde_json::to_string(state).internal_error("pomodoro", "failed to serialize state")?;
+    fs::write(path, content).block_error("pomodoro", "failed to write state")
+}
+
+/// Formats a duration as M:SS, rounding up so that the timer shows 0:00 only
+/// once it has expired.
+fn format_remaining(remaining: Duration) -> String {
+    let secs = (remaining.as_millis() + 999) / 1000;
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
+
 pub struct Pomodoro {
     id: String,
     time: ButtonWidget,
     state: State,
     length: Duration,
     break_length: Duration,
-    update_interval: Duration,
+    format: FormatTemplate,
     message: String,
     break_message: String,
+    notify_cmd: Option<String>,
     count: usize,
     use_nag: bool,
-    nag_path: std::path::PathBuf,
+    nag_path: PathBuf,
+    state_path: PathBuf,
+    tx_update_request: Sender<Task>,
 }
 
 impl Pomodoro {
-    fn set_text(&mut self) {
-        self.time
-            .set_text(format!("{} | {}", self.count, self.state));
+    fn set_text(&mut self) -> Result<()> {
+        let remaining = match self.state {
+            State::Stopped => self.length,
+            state => state.remaining(SystemTime::now()).unwrap_or_default(),
+        };
+        let values = map!("{cycle}" => self.count.to_string(),
+                          "{remaining}" => format_remaining(remaining),
+                          "{status}" => self.state.status().to_string());
+        self.time.set_text(self.format.render_static_str(&values)?);
+        self.time.set_state(match self.state {
+            State::Stopped => WidgetState::Idle,
+            State::Running {
+                phase: Phase::Work, ..
+            } => WidgetState::Info,
+            State::Running {
+                phase: Phase::Break,
+                ..
+            } => WidgetState::Good,
+            State::Paused { .. } => WidgetState::Warning,
+            State::WorkOver => WidgetState::Critical,
+        });
+        Ok(())
+    }
+
+    /// Failures are only logged, as the timer should keep running without
+    /// notifications
+    fn notify(&self, message: &str, level: &str) {
+        if self.use_nag {
+            let nag_path = self.nag_path.to_string_lossy();
+            if let Err(e) = spawn_child_async(&nag_path, &["-t", level, "-m", message]) {
+                eprintln!("pomodoro: failed to start `{}`: {}", nag_path, e);
+            }
+        }
+        if let Some(ref cmd) = self.notify_cmd {
+            if let Err(e) = spawn_child_async("sh", &["-c", &cmd.replace("{message}", message)]) {
+                eprintln!("pomodoro: failed to run notify_cmd `{}`: {}", cmd, e);
+            }
+        }
     }
 
-    fn nag(&self, message: &str, level: &str) {
-        spawn_child_async(
-            self.nag_path.to_str().unwrap(),
-            &["-t", level, "-m", message],
-        )
-        .expect("Failed to start i3-nagbar");
+    fn save(&self) {
+        // Failing to persist the state only matters if the bar is restarted
+        let _ = write_state(
+            &self.state_path,
+            &SavedState {
+                state: self.state,
+                cycle: self.count,
+            },
+        );
     }
 }
 
 #[derive(Deserialize, Debug, Default, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct PomodoroConfig {
+    /// Length of the work timer in minutes
     #[serde(default = "PomodoroConfig::default_length")]
     pub length: u64,
+    /// Length of the break timer in minutes
     #[serde(default = "PomodoroConfig::default_break_length")]
     pub break_length: u64,
+    /// Format override
+    #[serde(default = "PomodoroConfig::default_format")]
+    pub format: String,
     #[serde(default = "PomodoroConfig::default_message")]
     pub message: String,
     #[serde(default = "PomodoroConfig::default_break_message")]
     pub break_message: String,
+    /// Shell command to run when a timer expires. `{message}` is replaced
+    /// with `message` or `break_message`.
+    #[serde(default = "PomodoroConfig::default_notify_cmd")]
+    pub notify_cmd: Option<String>,
     #[serde(default = "PomodoroConfig::default_use_nag")]
     pub use_nag: bool,
     #[serde(default = "PomodoroConfig::default_nag_path")]
-    pub nag_path: std::path::PathBuf,
+    pub nag_path: PathBuf,
     #[serde(default = "PomodoroConfig::default_color_overrides")]
     pub color_overrides: Option<BTreeMap<String, String>>,
 }
@@ -116,6 +222,10 @@ impl PomodoroConfig {
         5
     }
 
+    fn default_format() -> String {
+        "{cycle} | {status} {remaining}".to_owned()
+    }
+
     fn default_message() -> String {
         "Pomodoro over! Take a break!".to_owned()
     }
@@ -124,12 +234,16 @@ impl PomodoroConfig {
         "Break over! Time to work!".to_owned()
     }
 
+    fn default_notify_cmd() -> Option<String> {
+        None
+    }
+
     fn default_use_nag() -> bool {
         false
     }
 
-    fn default_nag_path() -> std::path::PathBuf {
-        std::path::PathBuf::from("i3-nagbar")
+    fn default_nag_path() -> PathBuf {
+        PathBuf::from("i3-nagbar")
     }
 
     fn default_color_overrides() -> Option<BTreeMap<String, String>> {
@@ -140,21 +254,34 @@ impl PomodoroConfig {
 impl ConfigBlock for Pomodoro {
     type Config = PomodoroConfig;
 
-    fn new(block_config: Self::Config, config: Config, _send: Sender<Task>) -> Result<Self> {
+    fn new(
+        block_config: Self::Config,
+        config: Config,
+        tx_update_request: Sender<Task>,
+    ) -> Result<Self> {
         let id: String = pseudo_uuid();
+        let state_path = xdg_cache_home().join("i3status-rust/pomodoro.json");
+        let saved = read_state(&state_path).unwrap_or(SavedState {
+            state: State::Stopped,
+            cycle: 0,
+        });
 
         Ok(Pomodoro {
             id: id.clone(),
             time: ButtonWidget::new(config, &id).with_icon("pomodoro"),
-            state: State::Stopped,
+            state: saved.state,
             length: Duration::from_secs(block_config.length * 60), // convert to minutes
             break_length: Duration::from_secs(block_config.break_length * 60), // convert to minutes
-            update_interval: Duration::from_millis(1000),
+            format: FormatTemplate::from_string(&block_config.format)
+                .block_error("pomodoro", "Invalid format specified for pomodoro")?,
             message: block_config.message,
             break_message: block_config.break_message,
+            notify_cmd: block_config.notify_cmd,
             use_nag: block_config.use_nag,
-            count: 0,
+            count: saved.cycle,
             nag_path: block_config.nag_path,
+            state_path,
+            tx_update_request,
         })
     }
 }
@@ -165,61 +292,63 @@ impl Block for Pomodoro {
     }
 
     fn update(&mut self) -> Result<Option<Update>> {
-        self.set_text();
-        match &self.state {
-            State::Started(_) => {
-                if self.state.elapsed() >= self.length {
-                    if self.use_nag {
-                        self.nag(&self.message, "error");
-                    }
-
-                    self.state = State::OnBreak(Instant::now());
-                }
+        match self.state.expired(SystemTime::now()) {
+            Some(Phase::Work) => {
+                self.state = State::WorkOver;
+                self.save();
+                self.notify(&self.message, "error");
             }
-            State::OnBreak(_) => {
-                if self.state.elapsed() >= self.break_length {
-                    if self.use_nag {
-                        self.nag(&self.break_message, "warning");
-                    }
-                    self.state = State::Stopped;
-                    self.count += 1;
-                }
+            Some(Phase::Break) => {
+                self.state = State::Stopped;
+                self.count += 1;
+                self.save();
+                self.notify(&self.break_message, "warning");
             }
-            _ => {}
+            None => {}
         }
+        self.set_text()?;
 
-        Ok(Some(self.update_interval.into()))
+        // Only a running timer needs to be updated
+        Ok(match self.state {
+            State::Running { .. } => Some(Duration::from_secs(1).into()),
+            _ => None,
+        })
     }
 
     fn click(&mut self, event: &I3BarEvent) -> Result<()> {
-        if let Some(ref name) = event.name {
-            if name.as_str() == self.id {
-                match event.button {
-                    MouseButton::Right => {
-                        self.state = State::Stopped;
-                        self.count = 0;
-                    }
-                    _ => match &self.state {
-                        State::Stopped => {
-                            self.state = State::Started(Instant::now());
-                        }
-                        State::Started(_) => {
-                            self.state = State::Paused(self.state.elapsed());
-                        }
-                        State::Paused(duration) => {
-                            self.state = State::Started(
-                                Instant::now().checked_sub(duration.to_owned()).unwrap(),
-                            );
-                        }
-                        State::OnBreak(_) => {
-                            self.state = State::Started(Instant::now());
-                        }
-                    },
-                }
-            }
+        if !event.matches_name(&self.id) {
+            return Ok(());
         }
 
-        self.set_text();
+        let now = SystemTime::now();
+        self.state = match event.button {
+            MouseButton::Left => match self.state {
+                State::Stopped => State::Running {
+                    phase: Phase::Work,
+                    end: now + self.length,
+                },
+                State::WorkOver => State::Running {
+                    phase: Phase::Break,
+                    end: now + self.break_length,
+                },
+                state @ State::Paused { .. } => state.toggle_pause(now),
+                state => state,
+            },
+            MouseButton::Right => self.state.toggle_pause(now),
+            MouseButton::Middle => {
+                self.count = 0;
+                State::Stopped
+            }
+            _ => return Ok(()),
+        };
+        self.save();
+
+        // Restart the updates if the timer was started or resumed
+        self.tx_update_request.send(Task {
+            id: self.id.clone(),
+            update_time: Instant::now(),
+        })?;
+
         Ok(())
     }
 
@@ -227,3 +356,42 @@ impl Block for Pomodoro {
         vec![&self.time]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pause_keeps_remaining_time() {
+        let start = SystemTime::now();
+        let running = State::Running {
+            phase: Phase::Work,
+            end: start + Duration::from_secs(600),
+        };
+        let paused = running.toggle_pause(start + Duration::from_secs(100));
+        assert_eq!(
+            paused,
+            State::Paused {
+                phase: Phase::Work,
+                remaining: Duration::from_secs(500),
+            }
+        );
+
+        // A long pause doesn't count towards the timer
+        let later = start + Duration::from_secs(3600);
+        let resumed = paused.toggle_pause(later);
+        assert_eq!(resumed.remaining(later), Some(Duration::from_secs(500)));
+        assert_eq!(resumed.expired(later), None);
+        assert_eq!(
+            resumed.expired(later + Duration::from_secs(500)),
+            Some(Phase::Work)
+        );
+    }
+
+    #[test]
+    fn test_format_remaining() {
+        assert_eq!(format_remaining(Duration::from_secs(1500)), "25:00");
+        assert_eq!(format_remaining(Duration::from_millis(59_001)), "1:00");
+        assert_eq!(format_remaining(Duration::from_secs(0)), "0:00");
+    }
+}
@@ -0,0 +1,552 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::Sender;
+use serde_derive::Deserialize;
+
+use crate::blocks::{Block, ConfigBlock, Update};
+use crate::config::Config;
+use crate::de::deserialize_duration;
+use crate::errors::*;
+use crate::input::{I3BarEvent, MouseButton};
+use crate::scheduler::Task;
+use crate::util::{pseudo_uuid, FormatTemplate};
+use crate::widget::{I3BarWidget, State};
+use crate::widgets::button::ButtonWidget;
+
+#[cfg(feature = "obs")]
+use std::net::{TcpStream, ToSocketAddrs};
+#[cfg(feature = "obs")]
+use tungstenite::{Message, WebSocket};
+
+/// How many polls in a row have to fail before the state is unknown, so that
+/// a single hiccup doesn't flap the block.
+const MAX_FAILURES: u32 = 2;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordingDriver {
+    Obs,
+    Process,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Status {
+    Inactive,
+    /// Recording or streaming, for the given time
+    Active(Duration),
+    /// The recorder couldn't be reached
+    Unknown,
+}
+
+/// Keeps the last status until polling failed `MAX_FAILURES` times in a row.
+#[derive(Debug)]
+struct Tracker {
+    status: Status,
+    failures: u32,
+}
+
+impl Tracker {
+    fn new() -> Self {
+        Tracker {
+            status: Status::Unknown,
+            failures: 0,
+        }
+    }
+
+    fn record(&mut self, polled: Option<Status>) {
+        match polled {
+            Some(status) => {
+                self.status = status;
+                self.failures = 0;
+            }
+            None => {
+                self.failures += 1;
+                if self.failures >= MAX_FAILURES {
+                    self.status = Status::Unknown;
+                }
+            }
+        }
+    }
+}
+
+/// Formats the elapsed time as `12:34`, or `1:02:03` after an hour.
+fn format_elapsed(elapsed: Duration) -> String {
+    let seconds = elapsed.as_secs();
+    if seconds >= 3600 {
+        format!(
+            "{}:{:02}:{:02}",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        )
+    } else {
+        format!("{:02}:{:02}", seconds / 60, seconds % 60)
+    }
+}
+
+/// Parses a timecode of obs-websocket, e.g. `01:02:03.456`.
+#[cfg(any(feature = "obs", test))]
+fn parse_timecode(timecode: &str) -> Option<Duration> {
+    let mut parts = timecode.split('.').next()?.split(':');
+    let hours: u64 = parts.next()?.parse().ok()?;
+    let minutes: u64 = parts.next()?.parse().ok()?;
+    let seconds: u64 = parts.next()?.parse().ok()?;
+    Some(Duration::from_secs(hours * 3600 + minutes * 60 + seconds))
+}
+
+/// The `auth` of an obs-websocket `Authenticate` request.
+#[cfg(feature = "obs")]
+fn obs_auth(password: &str, salt: &str, challenge: &str) -> String {
+    use sha2::{Digest, Sha256};
+
+    let secret = base64::encode(Sha256::digest(format!("{}{}", password, salt).as_bytes()));
+    base64::encode(Sha256::digest(
+        format!("{}{}", secret, challenge).as_bytes(),
+    ))
+}
+
+/// A client of obs-websocket 4.x, which reconnects after errors.
+#[cfg(feature = "obs")]
+struct Obs {
+    url: String,
+    password: Option<String>,
+    socket: Option<WebSocket<TcpStream>>,
+    message_id: u64,
+    /// Why obs-websocket refused the last connection, if it did
+    auth_error: Option<String>,
+}
+
+#[cfg(feature = "obs")]
+impl Obs {
+    fn connect(&mut self) -> Result<WebSocket<TcpStream>> {
+        // ws://localhost:4444/ -> localhost:4444
+        let address = self
+            .url
+            .trim_start_matches("ws://")
+            .split('/')
+            .next()
+            .unwrap_or_default()
+            .to_socket_addrs()
+            .ok()
+            .and_then(|mut addresses| addresses.next())
+            .block_error("recording", &format!("Invalid OBS url '{}'", self.url))?;
+        let stream = TcpStream::connect_timeout(&address, Duration::from_secs(1))
+            .block_error("recording", "Failed to connect to OBS")?;
+        stream
+            .set_read_timeout(Some(Duration::from_secs(1)))
+            .and_then(|_| stream.set_write_timeout(Some(Duration::from_secs(1))))
+            .block_error("recording", "Failed to connect to OBS")?;
+        let (mut socket, _) = tungstenite::client(self.url.as_str(), stream)
+            .block_error("recording", "Failed to connect to obs-websocket")?;
+
+        let auth = self.request(&mut socket, "GetAuthRequired", serde_json::json!({}))?;
+        if auth["authRequired"].as_bool().unwrap_or(false) {
+            let authenticated = match self.password.clone() {
+                Some(password) => {
+                    let auth = obs_auth(
+                        &password,
+                        auth["salt"].as_str().unwrap_or_default(),
+                        auth["challenge"].as_str().unwrap_or_default(),
+                    );
+                    self.request(
+                        &mut socket,
+                        "Authenticate",
+                        serde_json::json!({ "auth": auth }),
+                    )
+                    .map(drop)
+                }
+                None => Err(BlockError(
+                    "recording".to_owned(),
+                    "obs-websocket requires a password".to_owned(),
+                )),
+            };
+            // Unlike OBS not running, this won't go away by itself
+            if let Err(BlockError(_, ref message)) = authenticated {
+                self.auth_error = Some(message.clone());
+            }
+            authenticated?;
+        }
+        self.auth_error = None;
+        Ok(socket)
+    }
+
+    fn request(
+        &mut self,
+        socket: &mut WebSocket<TcpStream>,
+        request_type: &str,
+        mut fields: serde_json::Value,
+    ) -> Result<serde_json::Value> {
+        self.message_id += 1;
+        let message_id = self.message_id.to_string();
+        fields["request-type"] = request_type.into();
+        fields["message-id"] = message_id.as_str().into();
+        socket
+            .write_message(Message::Text(fields.to_string()))
+            .block_error("recording", "Failed to send a request to OBS")?;
+
+        // Skip the events OBS sends in between
+        loop {
+            let message = socket
+                .read_message()
+                .block_error("recording", "Failed to read a response from OBS")?;
+            let response: serde_json::Value = match message {
+                Message::Text(text) => serde_json::from_str(&text)
+                    .block_error("recording", "Invalid response from OBS")?,
+                Message::Close(_) => {
+                    return Err(BlockError(
+                        "recording".to_owned(),
+                        "OBS closed the connection".to_owned(),
+                    ))
+                }
+                _ => continue,
+            };
+            if response["message-id"] != message_id.as_str() {
+                continue;
+            }
+            return if response["status"] == "ok" {
+                Ok(response)
+            } else {
+                Err(BlockError(
+                    "recording".to_owned(),
+                    format!(
+                        "{} failed: {}",
+                        request_type,
+                        response["error"].as_str().unwrap_or("unknown error")
+                    ),
+                ))
+            };
+        }
+    }
+
+    fn call(&mut self, request_type: &str) -> Result<serde_json::Value> {
+        let mut socket = match self.socket.take() {
+            Some(socket) => socket,
+            None => self.connect()?,
+        };
+        let response = self.request(&mut socket, request_type, serde_json::json!({}))?;
+        // The connection is dropped after errors, and made again next time
+        self.socket = Some(socket);
+        Ok(response)
+    }
+
+    fn poll(&mut self) -> Result<Status> {
+        let status = self.call("GetStreamingStatus")?;
+        let recording = status["recording"].as_bool().unwrap_or(false);
+        let streaming = status["streaming"].as_bool().unwrap_or(false);
+        let timecode = if recording {
+            &status["rec-timecode"]
+        } else {
+            &status["stream-timecode"]
+        };
+        Ok(if recording || streaming {
+            Status::Active(
+                timecode
+                    .as_str()
+                    .and_then(parse_timecode)
+                    .unwrap_or_default(),
+            )
+        } else {
+            Status::Inactive
+        })
+    }
+}
+
+/// Whether a process with the PID in the file is running.
+fn pidfile_running(pidfile: &Path) -> bool {
+    fs::read_to_string(pidfile)
+        .ok()
+        .and_then(|pid| pid.trim().parse::<u32>().ok())
+        .map_or(false, |pid| Path::new(&format!("/proc/{}", pid)).exists())
+}
+
+/// Whether a process with the name is running. Like `pgrep`, the name is
+/// compared to the first 15 characters of the executable name.
+fn process_running(name: &str) -> bool {
+    let name: String = name.chars().take(15).collect();
+    fs::read_dir("/proc").map_or(false, |entries| {
+        entries.flatten().any(|entry| {
+            fs::read_to_string(entry.path().join("comm"))
+                .map_or(false, |comm| comm.trim_end_matches('\n') == name)
+        })
+    })
+}
+
+enum Driver {
+    #[cfg(feature = "obs")]
+    Obs(Obs),
+    Process {
+        pidfile: Option<PathBuf>,
+        name: Option<String>,
+        /// When the process was first seen running
+        since: Option<Instant>,
+    },
+}
+
+impl Driver {
+    fn poll(&mut self) -> Result<Status> {
+        match self {
+            #[cfg(feature = "obs")]
+            Driver::Obs(obs) => obs.poll(),
+            Driver::Process {
+                pidfile,
+                name,
+                since,
+            } => {
+                let running = pidfile.as_ref().map_or(false, |p| pidfile_running(p))
+                    || name.as_ref().map_or(false, |n| process_running(n));
+                *since = match (*since, running) {
+                    (Some(since), true) => Some(since),
+                    (None, true) => Some(Instant::now()),
+                    (_, false) => None,
+                };
+                Ok(since.map_or(Status::Inactive, |since| Status::Active(since.elapsed())))
+            }
+        }
+    }
+
+    fn toggle(&mut self) -> Result<()> {
+        match self {
+            #[cfg(feature = "obs")]
+            Driver::Obs(obs) => obs.call("StartStopRecording").map(drop),
+            Driver::Process { .. } => Ok(()),
+        }
+    }
+
+    /// Why the recorder refused the connection, if it did.
+    fn auth_error(&self) -> Option<&str> {
+        match self {
+            #[cfg(feature = "obs")]
+            Driver::Obs(obs) => obs.auth_error.as_deref(),
+            Driver::Process { .. } => None,
+        }
+    }
+}
+
+pub struct Recording {
+    id: String,
+    output: ButtonWidget,
+    format: FormatTemplate,
+    update_interval: Duration,
+    hide_inactive: bool,
+    driver: Driver,
+    tracker: Tracker,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RecordingConfig {
+    /// How to find out whether recording is active
+    #[serde(default = "RecordingConfig::default_driver")]
+    pub driver: RecordingDriver,
+
+    /// The URL of obs-websocket
+    #[serde(default = "RecordingConfig::default_url")]
+    pub url: String,
+
+    /// The password of obs-websocket
+    #[serde(default = "RecordingConfig::default_password")]
+    pub password: Option<String>,
+
+    /// A file containing the PID of the recorder, for the process driver
+    #[serde(default = "RecordingConfig::default_pidfile")]
+    pub pidfile: Option<PathBuf>,
+
+    /// The name of the recorder process, for the process driver
+    #[serde(default = "RecordingConfig::default_process")]
+    pub process: Option<String>,
+
+    /// Update interval in seconds
+    #[serde(
+        default = "RecordingConfig::default_interval",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub interval: Duration,
+
+    /// Format override
+    #[serde(default = "RecordingConfig::default_format")]
+    pub format: String,
+
+    /// Hide the block while not recording
+    #[serde(default = "RecordingConfig::default_hide_inactive")]
+    pub hide_inactive: bool,
+
+    #[serde(default = "RecordingConfig::default_color_overrides")]
+    pub color_overrides: Option<BTreeMap<String, String>>,
+}
+
+impl RecordingConfig {
+    #[cfg(feature = "obs")]
+    fn default_driver() -> RecordingDriver {
+        RecordingDriver::Obs
+    }
+
+    #[cfg(not(feature = "obs"))]
+    fn default_driver() -> RecordingDriver {
+        RecordingDriver::Process
+    }
+
+    fn default_url() -> String {
+        "ws://localhost:4444".to_owned()
+    }
+
+    fn default_password() -> Option<String> {
+        None
+    }
+
+    fn default_pidfile() -> Option<PathBuf> {
+        None
+    }
+
+    fn default_process() -> Option<String> {
+        None
+    }
+
+    fn default_interval() -> Duration {
+        Duration::from_secs(1)
+    }
+
+    fn default_format() -> String {
+        "{elapsed}".to_owned()
+    }
+
+    fn default_hide_inactive() -> bool {
+        false
+    }
+
+    fn default_color_overrides() -> Option<BTreeMap<String, String>> {
+        None
+    }
+}
+
+impl ConfigBlock for Recording {
+    type Config = RecordingConfig;
+
+    fn new(block_config: Self::Config, config: Config, _send: Sender<Task>) -> Result<Self> {
+        let id = pseudo_uuid();
+
+        let driver = match block_config.driver {
+            #[cfg(feature = "obs")]
+            RecordingDriver::Obs => Driver::Obs(Obs {
+                url: block_config.url,
+                password: block_config.password,
+                socket: None,
+                message_id: 0,
+                auth_error: None,
+            }),
+            #[cfg(not(feature = "obs"))]
+            RecordingDriver::Obs => {
+                return Err(BlockError(
+                    "recording".to_owned(),
+                    "The obs driver requires building with `--features=obs`".to_owned(),
+                ))
+            }
+            RecordingDriver::Process => {
+                if block_config.pidfile.is_none() && block_config.process.is_none() {
+                    return Err(BlockError(
+                        "recording".to_owned(),
+                        "The process driver requires `pidfile` or `process`".to_owned(),
+                    ));
+                }
+                Driver::Process {
+                    pidfile: block_config.pidfile,
+                    name: block_config.process,
+                    since: None,
+                }
+            }
+        };
+
+        Ok(Recording {
+            output: ButtonWidget::new(config, &id).with_icon("recording"),
+            id,
+            format: FormatTemplate::from_string(&block_config.format)
+                .block_error("recording", "Invalid format specified for recording")?,
+            update_interval: block_config.interval,
+            hide_inactive: block_config.hide_inactive,
+            driver,
+            tracker: Tracker::new(),
+        })
+    }
+}
+
+impl Block for Recording {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn update(&mut self) -> Result<Option<Update>> {
+        let polled = self.driver.poll().ok();
+        self.tracker.record(polled);
+
+        let (elapsed, state) = match self.tracker.status {
+            Status::Active(elapsed) => (format_elapsed(elapsed), State::Critical),
+            Status::Inactive => (String::new(), State::Idle),
+            Status::Unknown => ("?".to_owned(), State::Idle),
+        };
+        let values = map!("{elapsed}" => elapsed);
+        match self.driver.auth_error() {
+            Some(auth_error) if self.tracker.status == Status::Unknown => {
+                self.output.set_text(auth_error.to_owned());
+                self.output.set_state(State::Critical);
+            }
+            _ => {
+                self.output
+                    .set_text(self.format.render_static_str(&values)?);
+                self.output.set_state(state);
+            }
+        }
+
+        Ok(Some(self.update_interval.into()))
+    }
+
+    fn view(&self) -> Vec<&dyn I3BarWidget> {
+        if self.hide_inactive && self.tracker.status == Status::Inactive {
+            vec![]
+        } else {
+            vec![&self.output]
+        }
+    }
+
+    fn click(&mut self, event: &I3BarEvent) -> Result<()> {
+        if event.matches_name(&self.id) && event.button == MouseButton::Left {
+            let toggled = self.driver.toggle();
+            self.update()?;
+            if let Err(BlockError(_, message)) = toggled {
+                self.output.set_text(message);
+                self.output.set_state(State::Critical);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tracker() {
+        let mut tracker = Tracker::new();
+        tracker.record(Some(Status::Active(Duration::from_secs(5))));
+        tracker.record(None);
+        assert_eq!(tracker.status, Status::Active(Duration::from_secs(5)));
+        tracker.record(Some(Status::Inactive));
+        tracker.record(None);
+        assert_eq!(tracker.status, Status::Inactive);
+        tracker.record(None);
+        assert_eq!(tracker.status, Status::Unknown);
+    }
+
+    #[test]
+    fn test_elapsed() {
+        assert_eq!(
+            parse_timecode("01:02:03.456"),
+            Some(Duration::from_secs(3723))
+        );
+        assert_eq!(parse_timecode("garbage"), None);
+        assert_eq!(format_elapsed(Duration::from_secs(754)), "12:34");
+        assert_eq!(format_elapsed(Duration::from_secs(3723)), "1:02:03");
+    }
+}
@@ -0,0 +1,353 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+use crossbeam_channel::Sender;
+use serde_derive::Deserialize;
+
+use crate::blocks::{Block, ConfigBlock, Update};
+use crate::config::Config;
+use crate::de::deserialize_duration;
+use crate::errors::*;
+use crate::scheduler::Task;
+use crate::util::{pseudo_uuid, read_file, FormatTemplate};
+use crate::widget::{I3BarWidget, State};
+use crate::widgets::text::TextWidget;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Health {
+    Passed,
+    Failed,
+    Unknown,
+}
+
+impl Health {
+    fn as_str(self) -> &'static str {
+        match self {
+            Health::Passed => "PASSED",
+            Health::Failed => "FAILED",
+            Health::Unknown => "N/A",
+        }
+    }
+}
+
+/// The health of a single drive.
+#[derive(Debug, Clone, PartialEq)]
+struct DriveStatus {
+    /// Temperature in degrees celsius
+    temp: Option<f64>,
+    health: Health,
+}
+
+/// The parts of `smartctl -j` output we are interested in.
+#[derive(Deserialize, Debug, Default)]
+struct SmartctlOutput {
+    #[serde(default)]
+    smartctl: SmartctlInfo,
+    smart_status: Option<SmartctlStatus>,
+    temperature: Option<SmartctlTemperature>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct SmartctlInfo {
+    #[serde(default)]
+    messages: Vec<SmartctlMessage>,
+}
+
+#[derive(Deserialize, Debug)]
+struct SmartctlMessage {
+    string: String,
+    #[serde(default)]
+    severity: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct SmartctlStatus {
+    passed: bool,
+}
+
+#[derive(Deserialize, Debug)]
+struct SmartctlTemperature {
+    current: f64,
+}
+
+/// Lists the NVMe controllers, e.g. `nvme0`.
+fn nvme_devices() -> Vec<String> {
+    let mut devices: Vec<String> = match Path::new("/sys/class/nvme").read_dir() {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    devices.sort();
+    devices
+}
+
+/// Finds the hwmon directory of an NVMe controller. Depending on the kernel
+/// version it is placed either next to or below the `device` directory.
+fn nvme_hwmon(device: &str) -> Option<PathBuf> {
+    let base = Path::new("/sys/class/nvme").join(device);
+    [base.clone(), base.join("device")]
+        .iter()
+        .filter_map(|dir| dir.read_dir().ok())
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .find(|entry| entry.file_name().to_string_lossy().starts_with("hwmon"))
+        .map(|entry| entry.path())
+}
+
+fn read_nvme_temp(device: &str) -> Option<f64> {
+    let hwmon = nvme_hwmon(device)?;
+    let millidegrees = read_file("smart", &hwmon.join("temp1_input"))
+        .ok()?
+        .trim()
+        .parse::<f64>()
+        .ok()?;
+    Some(millidegrees / 1000.)
+}
+
+fn parse_smartctl_output(output: &str) -> Result<DriveStatus> {
+    let parsed: SmartctlOutput =
+        serde_json::from_str(output).block_error("smart", "smartctl output is invalid")?;
+
+    if parsed.smart_status.is_none() && parsed.temperature.is_none() {
+        // smartctl reports errors like missing permissions in its own output
+        let message = parsed
+            .smartctl
+            .messages
+            .iter()
+            .find(|message| message.severity == "error")
+            .map(|message| message.string.as_str())
+            .unwrap_or("no SMART data");
+        let message = if message.contains("Permission denied") {
+            "permission denied"
+        } else {
+            message
+        };
+        return Err(BlockError("smart".to_string(), message.to_string()));
+    }
+
+    Ok(DriveStatus {
+        temp: parsed.temperature.map(|temperature| temperature.current),
+        health: match parsed.smart_status {
+            Some(SmartctlStatus { passed: true }) => Health::Passed,
+            Some(SmartctlStatus { passed: false }) => Health::Failed,
+            None => Health::Unknown,
+        },
+    })
+}
+
+fn read_smartctl(device: &str) -> Result<DriveStatus> {
+    let path = format!("/dev/{}", device);
+    let output = Command::new("smartctl")
+        .args(["-j", "-H", "-A", &path])
+        .output()
+        .block_error("smart", "failed to execute smartctl")?;
+    parse_smartctl_output(&String::from_utf8_lossy(&output.stdout))
+}
+
+pub struct Smart {
+    text: TextWidget,
+    id: String,
+    update_interval: Duration,
+    devices: Vec<String>,
+    use_smartctl: bool,
+    format: FormatTemplate,
+    critical_temp: f64,
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SmartConfig {
+    /// Update interval in seconds
+    #[serde(
+        default = "SmartConfig::default_interval",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub interval: Duration,
+
+    /// The drives to show, e.g. "nvme0" or "sda". Defaults to all NVMe drives.
+    #[serde(default = "SmartConfig::default_devices")]
+    pub devices: Option<Vec<String>>,
+
+    /// Whether to ask smartctl for the health and temperature
+    #[serde(default = "SmartConfig::default_use_smartctl")]
+    pub use_smartctl: bool,
+
+    /// Format override, applied to each of the drives
+    #[serde(default = "SmartConfig::default_format")]
+    pub format: String,
+
+    /// Temperature in degrees celsius, above which state is set to critical
+    #[serde(default = "SmartConfig::default_critical_temp")]
+    pub critical_temp: f64,
+
+    #[serde(default = "SmartConfig::default_color_overrides")]
+    pub color_overrides: Option<BTreeMap<String, String>>,
+}
+
+impl SmartConfig {
+    fn default_interval() -> Duration {
+        Duration::from_secs(60)
+    }
+
+    fn default_devices() -> Option<Vec<String>> {
+        None
+    }
+
+    fn default_use_smartctl() -> bool {
+        false
+    }
+
+    fn default_format() -> String {
+        "{device} {temp}°".to_owned()
+    }
+
+    fn default_critical_temp() -> f64 {
+        70.
+    }
+
+    fn default_color_overrides() -> Option<BTreeMap<String, String>> {
+        None
+    }
+}
+
+impl ConfigBlock for Smart {
+    type Config = SmartConfig;
+
+    fn new(
+        block_config: Self::Config,
+        config: Config,
+        _tx_update_request: Sender<Task>,
+    ) -> Result<Self> {
+        let id = pseudo_uuid();
+        let text = TextWidget::new(config, &id).with_icon("disk_drive");
+
+        Ok(Smart {
+            id,
+            update_interval: block_config.interval,
+            text,
+            devices: block_config.devices.unwrap_or_else(nvme_devices),
+            use_smartctl: block_config.use_smartctl,
+            format: FormatTemplate::from_string(&block_config.format)
+                .block_error("smart", "Invalid format specified for smart")?,
+            critical_temp: block_config.critical_temp,
+        })
+    }
+}
+
+impl Smart {
+    fn read_status(&self, device: &str) -> Result<DriveStatus> {
+        if self.use_smartctl {
+            let mut status = read_smartctl(device)?;
+            if status.temp.is_none() {
+                status.temp = read_nvme_temp(device);
+            }
+            Ok(status)
+        } else {
+            Ok(DriveStatus {
+                temp: read_nvme_temp(device),
+                health: Health::Unknown,
+            })
+        }
+    }
+}
+
+impl Block for Smart {
+    fn update(&mut self) -> Result<Option<Update>> {
+        let mut texts = Vec::new();
+        let mut critical = false;
+
+        for device in &self.devices {
+            match self.read_status(device) {
+                Ok(status) => {
+                    if status.health == Health::Failed
+                        || matches!(status.temp, Some(temp) if temp > self.critical_temp)
+                    {
+                        critical = true;
+                    }
+
+                    let temp = match status.temp {
+                        Some(temp) => format!("{:.0}", temp),
+                        None => "N/A".to_string(),
+                    };
+                    let values = map!("{device}" => device.as_str(),
+                                      "{temp}" => &temp,
+                                      "{health}" => status.health.as_str());
+                    texts.push(self.format.render_static_str(&values)?);
+                }
+                // Show errors like missing permissions inline, since the
+                // other drives may still be fine.
+                Err(BlockError(_, message)) => {
+                    critical = true;
+                    texts.push(format!("{}: {}", device, message));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        self.text.set_text(texts.join(" "));
+        self.text.set_state(if critical {
+            State::Critical
+        } else {
+            State::Idle
+        });
+
+        Ok(Some(self.update_interval.into()))
+    }
+
+    fn view(&self) -> Vec<&dyn I3BarWidget> {
+        vec![&self.text]
+    }
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_smartctl_output() {
+        let output = r#"{
+            "smartctl": {"version": [7, 1], "exit_status": 0},
+            "smart_status": {"passed": true},
+            "temperature": {"current": 34}
+        }"#;
+        assert_eq!(
+            parse_smartctl_output(output).unwrap(),
+            DriveStatus {
+                temp: Some(34.),
+                health: Health::Passed,
+            }
+        );
+
+        let output = r#"{"smart_status": {"passed": false}}"#;
+        assert_eq!(
+            parse_smartctl_output(output).unwrap(),
+            DriveStatus {
+                temp: None,
+                health: Health::Failed,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_smartctl_permission_denied() {
+        let output = r#"{
+            "smartctl": {
+                "messages": [
+                    {"string": "Smartctl open device: /dev/sda failed: Permission denied", "severity": "error"}
+                ],
+                "exit_status": 2
+            }
+        }"#;
+        match parse_smartctl_output(output) {
+            Err(BlockError(_, message)) => assert_eq!(message, "permission denied"),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,948 @@
+#[cfg(feature = "pulseaudio")]
+use {
+    crate::pulse::callbacks::ListResult,
+    crate::pulse::context::{
+        flags, introspect::ServerInfo, introspect::SinkInfo, introspect::SourceInfo,
+        subscribe::subscription_masks, subscribe::Facility,
+        subscribe::Operation as SubscribeOperation, Context, State as PulseState,
+    },
+    crate::pulse::mainloop::standard::IterateResult,
+    crate::pulse::mainloop::standard::Mainloop,
+    crate::pulse::proplist::{properties, Proplist},
+    crate::pulse::volume::{ChannelVolumes, VOLUME_MAX, VOLUME_NORM},
+    crossbeam_channel::unbounded,
+    lazy_static::lazy_static,
+    std::cell::RefCell,
+    std::collections::HashMap,
+    std::convert::{TryFrom, TryInto},
+    std::ops::Deref,
+    std::rc::Rc,
+    std::sync::Mutex,
+};
+
+use std::cmp::{max, min};
+use std::io::Read;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::Sender;
+use serde_derive::Deserialize;
+
+use crate::errors::*;
+use crate::scheduler::Task;
+
+/// Where the audio of a device is going to, as far as it can be told.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(super) enum OutputForm {
+    Speakers,
+    Headphones,
+    Hdmi,
+    Bluetooth,
+}
+
+impl OutputForm {
+    /// Guesses the form from the name of the active port and the bus and
+    /// form factor properties of a PulseAudio device.
+    fn guess(port: Option<&str>, bus: Option<&str>, form_factor: Option<&str>) -> Self {
+        let port = port.unwrap_or("").to_lowercase();
+        if bus == Some("bluetooth") {
+            OutputForm::Bluetooth
+        } else if port.contains("hdmi") || port.contains("displayport") {
+            OutputForm::Hdmi
+        } else if port.contains("headphone")
+            || port.contains("headset")
+            || matches!(form_factor, Some("headphone") | Some("headset"))
+        {
+            OutputForm::Headphones
+        } else {
+            OutputForm::Speakers
+        }
+    }
+
+    /// The icon to use instead of the speaker icons, if any.
+    pub(super) fn icon(self) -> Option<&'static str> {
+        match self {
+            OutputForm::Speakers => None,
+            OutputForm::Headphones => Some("volume_headphones"),
+            OutputForm::Hdmi => Some("volume_hdmi"),
+            OutputForm::Bluetooth => Some("volume_bt"),
+        }
+    }
+}
+
+pub(super) trait SoundDevice {
+    fn volume(&self) -> u32;
+    fn muted(&self) -> bool;
+    fn output_name(&self) -> String;
+    fn output_description(&self) -> Option<String> {
+        None
+    }
+    /// The description of the active port, e.g. "Headphones"
+    fn output_port(&self) -> Option<String> {
+        None
+    }
+    fn output_form(&self) -> OutputForm {
+        OutputForm::Speakers
+    }
+
+    fn get_info(&mut self) -> Result<()>;
+    fn set_volume(&mut self, step: i32, max_vol: Option<u32>) -> Result<()>;
+    fn toggle(&mut self) -> Result<()>;
+    fn monitor(&mut self, id: String, tx_update_request: Sender<Task>) -> Result<()>;
+}
+
+struct AlsaSoundDevice {
+    name: String,
+    device: String,
+    natural_mapping: bool,
+    volume: u32,
+    muted: bool,
+    headphones: bool,
+}
+
+impl AlsaSoundDevice {
+    fn new(name: String, device: String, natural_mapping: bool) -> Result<Self> {
+        let mut sd = AlsaSoundDevice {
+            name,
+            device,
+            natural_mapping,
+            volume: 0,
+            muted: false,
+            headphones: false,
+        };
+        sd.get_info()?;
+
+        Ok(sd)
+    }
+}
+
+impl SoundDevice for AlsaSoundDevice {
+    fn volume(&self) -> u32 {
+        self.volume
+    }
+    fn muted(&self) -> bool {
+        self.muted
+    }
+    fn output_name(&self) -> String {
+        self.name.clone()
+    }
+    fn output_port(&self) -> Option<String> {
+        if self.headphones {
+            Some("Headphones".to_string())
+        } else {
+            None
+        }
+    }
+    fn output_form(&self) -> OutputForm {
+        OutputForm::guess(self.output_port().as_deref(), None, None)
+    }
+
+    fn get_info(&mut self) -> Result<()> {
+        let mut args = Vec::new();
+        if self.natural_mapping {
+            args.push("-M")
+        };
+        args.extend(&["-D", &self.device, "get", &self.name]);
+
+        // amixer's output is translated in some locales
+        let output = Command::new("amixer")
+            .args(&args)
+            .env("LC_ALL", "C")
+            .output()
+            .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_owned())
+            .block_error("sound", "could not run amixer to get sound info")?;
+
+        let last_line = &output
+            .lines()
+            .last()
+            .block_error("sound", "could not get sound info")?;
+
+        let last = last_line
+            .split_whitespace()
+            .filter(|x| x.starts_with('[') && !x.contains("dB"))
+            .map(|s| s.trim_matches(FILTER))
+            .collect::<Vec<&str>>();
+
+        self.volume = last
+            .get(0)
+            .block_error("sound", "could not get volume")?
+            .parse::<u32>()
+            .block_error("sound", "could not parse volume to u32")?;
+
+        self.muted = last.get(1).map(|muted| *muted == "off").unwrap_or(false);
+
+        // Cards with jack detection report whether headphones are plugged in.
+        // Other devices, like the PulseAudio plugin, don't have this control.
+        self.headphones = Command::new("amixer")
+            .args(["-D", &self.device, "cget", "iface=CARD,name=Headphone Jack"])
+            .env("LC_ALL", "C")
+            .output()
+            .map(|o| String::from_utf8_lossy(&o.stdout).contains(": values=on"))
+            .unwrap_or(false);
+
+        Ok(())
+    }
+
+    fn set_volume(&mut self, step: i32, max_vol: Option<u32>) -> Result<()> {
+        let new_vol = max(0, self.volume as i32 + step) as u32;
+        let capped_volume = if let Some(vol_cap) = max_vol {
+            min(new_vol, vol_cap)
+        } else {
+            new_vol
+        };
+        let mut args = Vec::new();
+        if self.natural_mapping {
+            args.push("-M")
+        };
+        let vol_str = &format!("{}%", capped_volume);
+        args.extend(&["-D", &self.device, "set", &self.name, &vol_str]);
+
+        Command::new("amixer")
+            .args(&args)
+            .output()
+            .block_error("sound", "failed to set volume")?;
+
+        self.volume = capped_volume;
+
+        Ok(())
+    }
+
+    fn toggle(&mut self) -> Result<()> {
+        let mut args = Vec::new();
+        if self.natural_mapping {
+            args.push("-M")
+        };
+        args.extend(&["-D", &self.device, "set", &self.name, "toggle"]);
+
+        Command::new("amixer")
+            .args(&args)
+            .output()
+            .block_error("sound", "failed to toggle mute")?;
+
+        self.muted = !self.muted;
+
+        Ok(())
+    }
+
+    fn monitor(&mut self, id: String, tx_update_request: Sender<Task>) -> Result<()> {
+        // Monitor volume changes in a separate thread.
+        thread::Builder::new()
+            .name("sound_alsa".into())
+            .spawn(move || {
+                // Line-buffer to reduce noise.
+                let mut monitor = Command::new("stdbuf")
+                    .args(&["-oL", "alsactl", "monitor"])
+                    .stdout(Stdio::piped())
+                    .spawn()
+                    .expect("Failed to start alsactl monitor")
+                    .stdout
+                    .expect("Failed to pipe alsactl monitor output");
+
+                let mut buffer = [0; 1024]; // Should be more than enough.
+                loop {
+                    // Block until we get some output. Doesn't really matter what
+                    // the output actually is -- these are events -- we just update
+                    // the sound information if *something* happens.
+                    if monitor.read(&mut buffer).is_ok() {
+                        tx_update_request
+                            .send(Task {
+                                id: id.clone(),
+                                update_time: Instant::now(),
+                            })
+                            .unwrap();
+                    }
+                    // Don't update too often. Wait 1/4 second, fast enough for
+                    // volume button mashing but slow enough to skip event spam.
+                    thread::sleep(Duration::new(0, 250_000_000))
+                }
+            })
+            .unwrap();
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "pulseaudio")]
+struct PulseAudioConnection {
+    mainloop: Rc<RefCell<Mainloop>>,
+    context: Rc<RefCell<Context>>,
+}
+
+#[cfg(feature = "pulseaudio")]
+struct PulseAudioClient {
+    sender: Sender<PulseAudioClientRequest>,
+}
+
+#[cfg(feature = "pulseaudio")]
+struct PulseAudioSoundDevice {
+    name: Option<String>,
+    device_kind: DeviceKind,
+    volume: Option<ChannelVolumes>,
+    volume_avg: u32,
+    muted: bool,
+    description: Option<String>,
+    port: Option<String>,
+    form: OutputForm,
+}
+
+#[cfg(feature = "pulseaudio")]
+#[derive(Debug)]
+struct PulseAudioVolInfo {
+    volume: ChannelVolumes,
+    mute: bool,
+    name: String,
+    description: Option<String>,
+    port: Option<String>,
+    form: OutputForm,
+}
+
+#[cfg(feature = "pulseaudio")]
+impl TryFrom<&SourceInfo<'_>> for PulseAudioVolInfo {
+    type Error = ();
+
+    fn try_from(source_info: &SourceInfo) -> std::result::Result<Self, Self::Error> {
+        match source_info.name.as_ref() {
+            None => Err(()),
+            Some(name) => Ok(PulseAudioVolInfo {
+                volume: source_info.volume,
+                mute: source_info.mute,
+                name: name.to_string(),
+                description: source_info.description.as_ref().map(|d| d.to_string()),
+                port: source_info
+                    .active_port
+                    .as_ref()
+                    .and_then(|port| port.description.as_ref())
+                    .map(|d| d.to_string()),
+                form: OutputForm::guess(
+                    source_info
+                        .active_port
+                        .as_ref()
+                        .and_then(|port| port.name.as_deref()),
+                    source_info
+                        .proplist
+                        .get_str(properties::DEVICE_BUS)
+                        .as_deref(),
+                    source_info
+                        .proplist
+                        .get_str(properties::DEVICE_FORM_FACTOR)
+                        .as_deref(),
+                ),
+            }),
+        }
+    }
+}
+
+#[cfg(feature = "pulseaudio")]
+impl TryFrom<&SinkInfo<'_>> for PulseAudioVolInfo {
+    type Error = ();
+
+    fn try_from(sink_info: &SinkInfo) -> std::result::Result<Self, Self::Error> {
+        match sink_info.name.as_ref() {
+            None => Err(()),
+            Some(name) => Ok(PulseAudioVolInfo {
+                volume: sink_info.volume,
+                mute: sink_info.mute,
+                name: name.to_string(),
+                description: sink_info.description.as_ref().map(|d| d.to_string()),
+                port: sink_info
+                    .active_port
+                    .as_ref()
+                    .and_then(|port| port.description.as_ref())
+                    .map(|d| d.to_string()),
+                form: OutputForm::guess(
+                    sink_info
+                        .active_port
+                        .as_ref()
+                        .and_then(|port| port.name.as_deref()),
+                    sink_info
+                        .proplist
+                        .get_str(properties::DEVICE_BUS)
+                        .as_deref(),
+                    sink_info
+                        .proplist
+                        .get_str(properties::DEVICE_FORM_FACTOR)
+                        .as_deref(),
+                ),
+            }),
+        }
+    }
+}
+
+#[cfg(feature = "pulseaudio")]
+#[derive(Debug)]
+enum PulseAudioClientRequest {
+    GetDefaultDevice,
+    GetInfoByIndex(DeviceKind, u32),
+    GetInfoByName(DeviceKind, String),
+    SetVolumeByName(DeviceKind, String, ChannelVolumes),
+    SetMuteByName(DeviceKind, String, bool),
+}
+
+#[cfg(feature = "pulseaudio")]
+lazy_static! {
+    static ref PULSEAUDIO_CLIENT: Result<PulseAudioClient> = PulseAudioClient::new();
+    static ref PULSEAUDIO_EVENT_LISTENER: Mutex<HashMap<String, Sender<Task>>> =
+        Mutex::new(HashMap::new());
+
+    // Default device names
+    static ref PULSEAUDIO_DEFAULT_SOURCE: Mutex<String> = Mutex::new("@DEFAULT_SOURCE@".into());
+    static ref PULSEAUDIO_DEFAULT_SINK: Mutex<String> = Mutex::new("@DEFAULT_SINK@".into());
+
+    // State for each device
+    static ref PULSEAUDIO_DEVICES: Mutex<HashMap<(DeviceKind, String), PulseAudioVolInfo>> =
+        Mutex::new(HashMap::new());
+}
+
+#[cfg(feature = "pulseaudio")]
+impl PulseAudioConnection {
+    fn new() -> Result<Self> {
+        let mut proplist = Proplist::new().unwrap();
+        proplist
+            .set_str(properties::APPLICATION_NAME, "i3status-rs")
+            .block_error(
+                "sound",
+                "could not set pulseaudio APPLICATION_NAME property",
+            )?;
+
+        let mainloop = Rc::new(RefCell::new(
+            Mainloop::new().block_error("sound", "failed to create pulseaudio mainloop")?,
+        ));
+
+        let context = Rc::new(RefCell::new(
+            Context::new_with_proplist(mainloop.borrow().deref(), "i3status-rs_context", &proplist)
+                .block_error("sound", "failed to create new pulseaudio context")?,
+        ));
+
+        context
+            .borrow_mut()
+            .connect(None, flags::NOFLAGS, None)
+            .block_error("sound", "failed to connect to pulseaudio context")?;
+
+        let mut connection = PulseAudioConnection { mainloop, context };
+
+        // Wait for context to be ready
+        loop {
+            connection.iterate(false)?;
+            match connection.context.borrow().get_state() {
+                PulseState::Ready => {
+                    break;
+                }
+                PulseState::Failed | PulseState::Terminated => {
+                    return Err(BlockError(
+                        "sound".into(),
+                        "pulseaudio context state failed/terminated".into(),
+                    ))
+                }
+                _ => {}
+            }
+        }
+
+        Ok(connection)
+    }
+
+    fn iterate(&mut self, blocking: bool) -> Result<()> {
+        match self.mainloop.borrow_mut().iterate(blocking) {
+            IterateResult::Quit(_) | IterateResult::Err(_) => Err(BlockError(
+                "sound".into(),
+                "failed to iterate pulseaudio state".into(),
+            )),
+            IterateResult::Success(_) => Ok(()),
+        }
+    }
+}
+
+#[cfg(feature = "pulseaudio")]
+impl PulseAudioClient {
+    fn new() -> Result<PulseAudioClient> {
+        let (send_req, recv_req) = unbounded();
+        let (send_result, recv_result) = unbounded();
+        let send_result2 = send_result.clone();
+        let new_connection = |sender: Sender<Result<()>>| -> PulseAudioConnection {
+            let conn = PulseAudioConnection::new();
+            match conn {
+                Ok(conn) => {
+                    sender.send(Ok(())).unwrap();
+                    conn
+                }
+                Err(err) => {
+                    sender.send(Err(err)).unwrap();
+                    panic!("failed to create pulseaudio connection");
+                }
+            }
+        };
+        let thread_result = || -> Result<()> {
+            match recv_result.recv() {
+                Err(_) => Err(BlockError(
+                    "sound".into(),
+                    "failed to receive from pulseaudio thread channel".into(),
+                )),
+                Ok(result) => result,
+            }
+        };
+
+        // requests
+        thread::Builder::new()
+            .name("sound_pulseaudio_req".into())
+            .spawn(move || {
+                let mut connection = new_connection(send_result);
+
+                loop {
+                    // make sure mainloop dispatched everything
+                    for _ in 0..10 {
+                        connection.iterate(false).unwrap();
+                    }
+
+                    match recv_req.recv() {
+                        Err(_) => {}
+                        Ok(req) => {
+                            use PulseAudioClientRequest::*;
+                            let mut introspector = connection.context.borrow_mut().introspect();
+
+                            match req {
+                                GetDefaultDevice => {
+                                    introspector
+                                        .get_server_info(PulseAudioClient::server_info_callback);
+                                }
+                                GetInfoByIndex(DeviceKind::Sink, index) => {
+                                    introspector.get_sink_info_by_index(
+                                        index,
+                                        PulseAudioClient::sink_info_callback,
+                                    );
+                                }
+                                GetInfoByIndex(DeviceKind::Source, index) => {
+                                    introspector.get_source_info_by_index(
+                                        index,
+                                        PulseAudioClient::source_info_callback,
+                                    );
+                                }
+                                GetInfoByName(DeviceKind::Sink, name) => {
+                                    introspector.get_sink_info_by_name(
+                                        &name,
+                                        PulseAudioClient::sink_info_callback,
+                                    );
+                                }
+                                GetInfoByName(DeviceKind::Source, name) => {
+                                    introspector.get_source_info_by_name(
+                                        &name,
+                                        PulseAudioClient::source_info_callback,
+                                    );
+                                }
+                                SetVolumeByName(DeviceKind::Sink, name, volumes) => {
+                                    introspector.set_sink_volume_by_name(&name, &volumes, None);
+                                }
+                                SetVolumeByName(DeviceKind::Source, name, volumes) => {
+                                    introspector.set_source_volume_by_name(&name, &volumes, None);
+                                }
+                                SetMuteByName(DeviceKind::Sink, name, mute) => {
+                                    introspector.set_sink_mute_by_name(&name, mute, None);
+                                }
+                                SetMuteByName(DeviceKind::Source, name, mute) => {
+                                    introspector.set_source_mute_by_name(&name, mute, None);
+                                }
+                            };
+
+                            // send request and receive response
+                            connection.iterate(true).unwrap();
+                            connection.iterate(true).unwrap();
+                        }
+                    }
+                }
+            })
+            .unwrap();
+        thread_result()?;
+
+        // subscribe
+        thread::Builder::new()
+            .name("sound_pulseaudio_sub".into())
+            .spawn(move || {
+                let connection = new_connection(send_result2);
+
+                // subcribe for events
+                connection
+                    .context
+                    .borrow_mut()
+                    .set_subscribe_callback(Some(Box::new(PulseAudioClient::subscribe_callback)));
+                connection.context.borrow_mut().subscribe(
+                    subscription_masks::SERVER
+                        | subscription_masks::SINK
+                        | subscription_masks::SOURCE,
+                    |_| {},
+                );
+
+                connection.mainloop.borrow_mut().run().unwrap();
+            })
+            .unwrap();
+        thread_result()?;
+
+        Ok(PulseAudioClient { sender: send_req })
+    }
+
+    fn send(request: PulseAudioClientRequest) -> Result<()> {
+        match PULSEAUDIO_CLIENT.as_ref() {
+            Ok(client) => {
+                client.sender.send(request).unwrap();
+                Ok(())
+            }
+            Err(err) => Err(BlockError(
+                "sound".into(),
+                format!("pulseaudio connection failed with error: {}", err),
+            )),
+        }
+    }
+
+    fn server_info_callback(server_info: &ServerInfo) {
+        if let Some(default_sink) = server_info.default_sink_name.as_ref() {
+            *PULSEAUDIO_DEFAULT_SINK.lock().unwrap() = default_sink.to_string();
+        }
+
+        if let Some(default_source) = server_info.default_source_name.as_ref() {
+            *PULSEAUDIO_DEFAULT_SOURCE.lock().unwrap() = default_source.to_string();
+        }
+
+        PulseAudioClient::send_update_event();
+    }
+
+    fn get_info_callback<I: TryInto<PulseAudioVolInfo>>(
+        result: ListResult<I>,
+    ) -> Option<PulseAudioVolInfo> {
+        match result {
+            ListResult::End | ListResult::Error => None,
+            ListResult::Item(info) => info.try_into().ok(),
+        }
+    }
+
+    fn sink_info_callback(result: ListResult<&SinkInfo>) {
+        if let Some(vol_info) = Self::get_info_callback(result) {
+            PULSEAUDIO_DEVICES
+                .lock()
+                .unwrap()
+                .insert((DeviceKind::Sink, vol_info.name.to_string()), vol_info);
+
+            PulseAudioClient::send_update_event();
+        }
+    }
+
+    fn source_info_callback(result: ListResult<&SourceInfo>) {
+        if let Some(vol_info) = Self::get_info_callback(result) {
+            PULSEAUDIO_DEVICES
+                .lock()
+                .unwrap()
+                .insert((DeviceKind::Source, vol_info.name.to_string()), vol_info);
+
+            PulseAudioClient::send_update_event();
+        }
+    }
+
+    fn subscribe_callback(
+        facility: Option<Facility>,
+        _operation: Option<SubscribeOperation>,
+        index: u32,
+    ) {
+        match facility {
+            None => {}
+            Some(facility) => match facility {
+                Facility::Server => {
+                    PulseAudioClient::send(PulseAudioClientRequest::GetDefaultDevice).ok();
+                }
+                Facility::Sink => {
+                    PulseAudioClient::send(PulseAudioClientRequest::GetInfoByIndex(
+                        DeviceKind::Sink,
+                        index,
+                    ))
+                    .ok();
+                }
+                Facility::Source => {
+                    PulseAudioClient::send(PulseAudioClientRequest::GetInfoByIndex(
+                        DeviceKind::Source,
+                        index,
+                    ))
+                    .ok();
+                }
+                _ => {}
+            },
+        }
+    }
+
+    fn send_update_event() {
+        for (id, tx_update_request) in &*PULSEAUDIO_EVENT_LISTENER.lock().unwrap() {
+            tx_update_request
+                .send(Task {
+                    id: id.clone(),
+                    update_time: Instant::now(),
+                })
+                .unwrap();
+        }
+    }
+}
+
+#[cfg(feature = "pulseaudio")]
+impl PulseAudioSoundDevice {
+    fn new(device_kind: DeviceKind) -> Result<Self> {
+        PulseAudioClient::send(PulseAudioClientRequest::GetDefaultDevice)?;
+
+        let device = PulseAudioSoundDevice {
+            name: None,
+            device_kind,
+            volume: None,
+            volume_avg: 0,
+            muted: false,
+            description: None,
+            port: None,
+            form: OutputForm::Speakers,
+        };
+
+        PulseAudioClient::send(PulseAudioClientRequest::GetInfoByName(
+            device_kind,
+            device.name(),
+        ))?;
+
+        Ok(device)
+    }
+
+    fn with_name(mut self, name: String) -> Self {
+        self.name = Some(name);
+        self
+    }
+
+    fn name(&self) -> String {
+        self.name
+            .clone()
+            .unwrap_or_else(|| self.device_kind.default_name())
+    }
+
+    fn volume(&mut self, volume: ChannelVolumes) {
+        self.volume = Some(volume);
+        self.volume_avg = (volume.avg().0 as f32 / VOLUME_NORM.0 as f32 * 100.0).round() as u32;
+    }
+}
+
+#[cfg(feature = "pulseaudio")]
+impl SoundDevice for PulseAudioSoundDevice {
+    fn volume(&self) -> u32 {
+        self.volume_avg
+    }
+
+    fn muted(&self) -> bool {
+        self.muted
+    }
+
+    fn output_name(&self) -> String {
+        self.name()
+    }
+
+    fn output_description(&self) -> Option<String> {
+        self.description.clone()
+    }
+
+    fn output_port(&self) -> Option<String> {
+        self.port.clone()
+    }
+
+    fn output_form(&self) -> OutputForm {
+        self.form
+    }
+
+    fn get_info(&mut self) -> Result<()> {
+        let devices = PULSEAUDIO_DEVICES.lock().unwrap();
+
+        if let Some(info) = devices.get(&(self.device_kind, self.name())) {
+            self.volume(info.volume);
+            self.muted = info.mute;
+            self.description = info.description.clone();
+            self.port = info.port.clone();
+            self.form = info.form;
+        }
+
+        Ok(())
+    }
+
+    fn set_volume(&mut self, step: i32, max_vol: Option<u32>) -> Result<()> {
+        let mut volume = match self.volume {
+            Some(volume) => volume,
+            None => return Err(BlockError("sound".into(), "volume unknown".into())),
+        };
+
+        // apply step to volumes
+        let step = (step as f32 * VOLUME_NORM.0 as f32 / 100.0).round() as i32;
+        for vol in volume.get_mut().iter_mut() {
+            let uncapped_vol = max(0, vol.0 as i32 + step) as u32;
+            let capped_vol = if let Some(vol_cap) = max_vol {
+                min(
+                    uncapped_vol,
+                    (vol_cap as f32 * VOLUME_NORM.0 as f32 / 100.0).round() as u32,
+                )
+            } else {
+                uncapped_vol
+            };
+            vol.0 = min(capped_vol, VOLUME_MAX.0);
+        }
+
+        // update volumes
+        self.volume(volume);
+        // Also update the known state of the device, so that the next step of
+        // fast scrolling starts from this volume rather than one PulseAudio
+        // hasn't reported back yet
+        if let Some(info) = PULSEAUDIO_DEVICES
+            .lock()
+            .unwrap()
+            .get_mut(&(self.device_kind, self.name()))
+        {
+            info.volume = volume;
+        }
+        PulseAudioClient::send(PulseAudioClientRequest::SetVolumeByName(
+            self.device_kind,
+            self.name(),
+            volume,
+        ))?;
+
+        Ok(())
+    }
+
+    fn toggle(&mut self) -> Result<()> {
+        self.muted = !self.muted;
+        if let Some(info) = PULSEAUDIO_DEVICES
+            .lock()
+            .unwrap()
+            .get_mut(&(self.device_kind, self.name()))
+        {
+            info.mute = self.muted;
+        }
+
+        PulseAudioClient::send(PulseAudioClientRequest::SetMuteByName(
+            self.device_kind,
+            self.name(),
+            self.muted,
+        ))?;
+
+        Ok(())
+    }
+
+    fn monitor(&mut self, id: String, tx_update_request: Sender<Task>) -> Result<()> {
+        PULSEAUDIO_EVENT_LISTENER
+            .lock()
+            .unwrap()
+            .insert(id, tx_update_request);
+        Ok(())
+    }
+}
+
+// TODO: Use the alsa control bindings to implement push updates
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DeviceKind {
+    Sink,
+    Source,
+}
+
+#[cfg(feature = "pulseaudio")]
+impl DeviceKind {
+    pub fn default_name(self) -> String {
+        match self {
+            Self::Sink => PULSEAUDIO_DEFAULT_SINK.lock().unwrap().to_string(),
+            Self::Source => PULSEAUDIO_DEFAULT_SOURCE.lock().unwrap().to_string(),
+        }
+    }
+}
+
+impl DeviceKind {
+    /// The ALSA simple control used if no name is configured
+    fn default_alsa_control(self) -> &'static str {
+        match self {
+            Self::Sink => "Master",
+            Self::Source => "Capture",
+        }
+    }
+}
+
+impl Default for DeviceKind {
+    fn default() -> Self {
+        Self::Sink
+    }
+}
+
+#[derive(Deserialize, Copy, Clone, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum SoundDriver {
+    Auto,
+    Alsa,
+    #[cfg(feature = "pulseaudio")]
+    PulseAudio,
+}
+
+impl Default for SoundDriver {
+    fn default() -> Self {
+        SoundDriver::Auto
+    }
+}
+
+/// Creates the device for the block: PulseAudio if the feature is enabled and
+/// `driver != "alsa"`, falling back to ALSA.
+pub(super) fn make_device(
+    driver: SoundDriver,
+    device_kind: DeviceKind,
+    name: Option<String>,
+    device: Option<String>,
+    natural_mapping: bool,
+) -> Result<Box<dyn SoundDevice>> {
+    #[cfg(not(feature = "pulseaudio"))]
+    type PulseAudioSoundDevice = AlsaSoundDevice;
+
+    let pulseaudio_device: Result<PulseAudioSoundDevice> = match driver {
+        #[cfg(feature = "pulseaudio")]
+        SoundDriver::Auto | SoundDriver::PulseAudio => {
+            let sound_device = PulseAudioSoundDevice::new(device_kind);
+
+            match name.as_ref() {
+                None => sound_device,
+                Some(name) => sound_device.map(|device| device.with_name(name.to_string())),
+            }
+        }
+        _ => Err(BlockError(
+            "sound".into(),
+            "PulseAudio feature or driver disabled".into(),
+        )),
+    };
+
+    Ok(match pulseaudio_device {
+        Ok(dev) => Box::new(dev),
+        Err(_) => Box::new(AlsaSoundDevice::new(
+            name.unwrap_or_else(|| device_kind.default_alsa_control().into()),
+            device.unwrap_or_else(|| "default".into()),
+            natural_mapping,
+        )?),
+    })
+}
+
+// To filter [100%] output from amixer into 100
+const FILTER: &[char] = &['[', ']', '%'];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_output_form_guess() {
+        assert_eq!(
+            OutputForm::guess(Some("analog-output-speaker"), Some("pci"), Some("internal")),
+            OutputForm::Speakers
+        );
+        assert_eq!(
+            OutputForm::guess(Some("analog-output-headphones"), Some("pci"), None),
+            OutputForm::Headphones
+        );
+        assert_eq!(
+            OutputForm::guess(Some("hdmi-output-0"), Some("pci"), None),
+            OutputForm::Hdmi
+        );
+        assert_eq!(
+            OutputForm::guess(Some("headset-output"), Some("bluetooth"), Some("headset")),
+            OutputForm::Bluetooth
+        );
+        assert_eq!(
+            OutputForm::guess(None, Some("usb"), Some("headset")),
+            OutputForm::Headphones
+        );
+    }
+}
@@ -0,0 +1,290 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::Sender;
+use dbus::ffidisp::stdintf::org_freedesktop_dbus::Properties;
+use dbus::ffidisp::{BusType, Connection};
+use serde_derive::Deserialize;
+
+use crate::blocks::{Block, ConfigBlock, Update};
+use crate::config::Config;
+use crate::de::deserialize_duration;
+use crate::errors::*;
+use crate::scheduler::Task;
+use crate::util::{pseudo_uuid, FormatTemplate};
+use crate::widget::{I3BarWidget, State};
+use crate::widgets::button::ButtonWidget;
+
+const SYSTEMD: &str = "org.freedesktop.systemd1";
+const MANAGER_PATH: &str = "/org/freedesktop/systemd1";
+const MANAGER: &str = "org.freedesktop.systemd1.Manager";
+
+/// A systemd manager, either of the system or of the user.
+struct Manager {
+    bus: BusType,
+    /// Dropped once a call fails, and established again on the next one
+    con: Option<Connection>,
+}
+
+impl Manager {
+    fn new(bus: BusType) -> Self {
+        Manager { bus, con: None }
+    }
+
+    /// The number of failed units, if the manager can be reached.
+    fn failed_units(&mut self) -> Option<u32> {
+        if !self.con.as_ref().map_or(false, Connection::is_connected) {
+            self.con = Connection::get_private(self.bus).ok();
+        }
+        let failed = self
+            .con
+            .as_ref()?
+            .with_path(SYSTEMD, MANAGER_PATH, 1000)
+            .get(MANAGER, "NFailedUnits")
+            .ok();
+        if failed.is_none() {
+            self.con = None;
+        }
+        failed
+    }
+}
+
+/// Asks the manager to emit signals and requests an update whenever a job
+/// finishes or a unit changes, setting `subscribed` once the signals arrive.
+/// Returns `false` once the block is gone, or `true` when the connection to
+/// the bus is lost.
+fn watch_manager(
+    con: &Connection,
+    subscribed: &AtomicBool,
+    request_update: &dyn Fn() -> bool,
+) -> bool {
+    let subscription: std::result::Result<(), dbus::Error> = con
+        .with_path(SYSTEMD, MANAGER_PATH, 1000)
+        .method_call(MANAGER, "Subscribe", ());
+    let rules = [
+        "type='signal',\
+         sender='org.freedesktop.systemd1',\
+         interface='org.freedesktop.systemd1.Manager',\
+         member='JobRemoved'",
+        "type='signal',\
+         sender='org.freedesktop.systemd1',\
+         interface='org.freedesktop.systemd1.Manager',\
+         member='UnitRemoved'",
+        "type='signal',\
+         sender='org.freedesktop.systemd1',\
+         interface='org.freedesktop.DBus.Properties',\
+         member='PropertiesChanged',\
+         arg0='org.freedesktop.systemd1.Unit'",
+    ];
+    if subscription.is_err() || rules.iter().any(|rule| con.add_match(rule).is_err()) {
+        return true;
+    }
+    subscribed.store(true, Ordering::SeqCst);
+
+    while con.is_connected() {
+        if con.incoming(10_000).next().is_some() && !request_update() {
+            return false;
+        }
+    }
+    true
+}
+
+/// Monitors the manager in a separate thread. While the bus can't be
+/// reached, the block falls back to polling and the connection is retried
+/// every `retry_interval`. Returns whether the manager is subscribed to.
+fn monitor(
+    bus: BusType,
+    retry_interval: Duration,
+    id: String,
+    update_request: Sender<Task>,
+) -> Arc<AtomicBool> {
+    let subscribed = Arc::new(AtomicBool::new(false));
+    let thread_subscribed = subscribed.clone();
+    let request_update = move || {
+        update_request
+            .send(Task {
+                id: id.clone(),
+                update_time: Instant::now(),
+            })
+            .is_ok()
+    };
+
+    thread::Builder::new()
+        .name("systemd".into())
+        .spawn(move || loop {
+            if let Ok(con) = Connection::get_private(bus) {
+                if !watch_manager(&con, &thread_subscribed, &request_update) {
+                    return;
+                }
+            }
+            // The block isn't updated on its own while subscribed, so it has
+            // to start polling again
+            if thread_subscribed.swap(false, Ordering::SeqCst) && !request_update() {
+                return;
+            }
+            thread::sleep(retry_interval);
+        })
+        .unwrap();
+    subscribed
+}
+
+pub struct Systemd {
+    id: String,
+    output: ButtonWidget,
+    update_interval: Duration,
+    format: FormatTemplate,
+    hide_when_zero: bool,
+    system: Manager,
+    user: Option<Manager>,
+    failed: Option<u32>,
+    failed_user: Option<u32>,
+    /// Whether each monitored manager is subscribed to
+    subscribed: Vec<Arc<AtomicBool>>,
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SystemdConfig {
+    /// Update interval in seconds, used while the bus can't be reached
+    #[serde(
+        default = "SystemdConfig::default_interval",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub interval: Duration,
+
+    /// Format override
+    #[serde(default = "SystemdConfig::default_format")]
+    pub format: String,
+
+    /// Also count the failed units of the user's manager
+    #[serde(default = "SystemdConfig::default_user")]
+    pub user: bool,
+
+    /// Hide the block while no units failed
+    #[serde(default = "SystemdConfig::default_hide_when_zero")]
+    pub hide_when_zero: bool,
+
+    #[serde(default = "SystemdConfig::default_color_overrides")]
+    pub color_overrides: Option<BTreeMap<String, String>>,
+}
+
+impl SystemdConfig {
+    fn default_interval() -> Duration {
+        Duration::from_secs(60)
+    }
+
+    fn default_format() -> String {
+        "{failed}".to_owned()
+    }
+
+    fn default_user() -> bool {
+        false
+    }
+
+    fn default_hide_when_zero() -> bool {
+        false
+    }
+
+    fn default_color_overrides() -> Option<BTreeMap<String, String>> {
+        None
+    }
+}
+
+impl ConfigBlock for Systemd {
+    type Config = SystemdConfig;
+
+    fn new(block_config: Self::Config, config: Config, send: Sender<Task>) -> Result<Self> {
+        let id = pseudo_uuid();
+
+        let mut subscribed = vec![monitor(
+            BusType::System,
+            block_config.interval,
+            id.clone(),
+            send.clone(),
+        )];
+        if block_config.user {
+            subscribed.push(monitor(
+                BusType::Session,
+                block_config.interval,
+                id.clone(),
+                send,
+            ));
+        }
+
+        Ok(Systemd {
+            output: ButtonWidget::new(config, &id).with_icon("cogs"),
+            id,
+            update_interval: block_config.interval,
+            format: FormatTemplate::from_string(&block_config.format)
+                .block_error("systemd", "Invalid format specified for systemd")?,
+            hide_when_zero: block_config.hide_when_zero,
+            system: Manager::new(BusType::System),
+            user: if block_config.user {
+                Some(Manager::new(BusType::Session))
+            } else {
+                None
+            },
+            failed: None,
+            // Nothing is shown as failed for the user unless asked to check
+            failed_user: if block_config.user { None } else { Some(0) },
+            subscribed,
+        })
+    }
+}
+
+impl Block for Systemd {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn update(&mut self) -> Result<Option<Update>> {
+        // Keep the last known counts while a manager can't be reached
+        if let Some(failed) = self.system.failed_units() {
+            self.failed = Some(failed);
+        }
+        if let Some(failed) = self.user.as_mut().and_then(Manager::failed_units) {
+            self.failed_user = Some(failed);
+        }
+
+        let render =
+            |failed: Option<u32>| failed.map_or_else(|| "?".to_string(), |n| n.to_string());
+        let values = map!(
+            "{failed}" => render(self.failed),
+            "{failed_user}" => render(self.failed_user)
+        );
+        self.output
+            .set_text(self.format.render_static_str(&values)?);
+
+        let total = self.failed.unwrap_or(0) + self.failed_user.unwrap_or(0);
+        self.output.set_state(if total > 0 {
+            State::Critical
+        } else if self.failed.is_none() {
+            State::Idle
+        } else {
+            State::Good
+        });
+
+        // Only poll while the signals of a manager don't arrive
+        if self
+            .subscribed
+            .iter()
+            .all(|subscribed| subscribed.load(Ordering::SeqCst))
+        {
+            Ok(None)
+        } else {
+            Ok(Some(self.update_interval.into()))
+        }
+    }
+
+    fn view(&self) -> Vec<&dyn I3BarWidget> {
+        let total = self.failed.unwrap_or(0) + self.failed_user.unwrap_or(0);
+        if self.hide_when_zero && self.failed.is_some() && total == 0 {
+            vec![]
+        } else {
+            vec![&self.output]
+        }
+    }
+}
@@ -1,8 +1,10 @@
 use std::collections::BTreeMap;
 use std::process::Command;
-use std::time::Duration;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 
-use crossbeam_channel::Sender;
+use crossbeam_channel::{unbounded, Receiver, RecvTimeoutError, Sender};
 use serde_derive::Deserialize;
 
 use crate::blocks::{Block, ConfigBlock, Update};
@@ -11,27 +13,40 @@ use crate::de::deserialize_duration;
 use crate::errors::*;
 use crate::input::{I3BarEvent, MouseButton};
 use crate::scheduler::Task;
-use crate::util::{pseudo_uuid, FormatTemplate};
+use crate::util::{has_command, pseudo_uuid, FormatTemplate};
 use crate::widget::{I3BarWidget, State};
 use crate::widgets::button::ButtonWidget;
 
 pub struct Taskwarrior {
     output: ButtonWidget,
     id: String,
-    update_interval: Duration,
     warning_threshold: u32,
     critical_threshold: u32,
-    filter_tags: Vec<String>,
-    block_mode: TaskwarriorBlockMode,
+    filters: Vec<TaskwarriorFilter>,
+    /// The index of the filter which is shown
+    current_filter: usize,
     format: FormatTemplate,
     format_singular: FormatTemplate,
     format_everything_done: FormatTemplate,
+    status: Arc<Mutex<Status>>,
+    tx_refresh: Sender<()>,
+}
 
-    //useful, but optional
-    #[allow(dead_code)]
-    config: Config,
-    #[allow(dead_code)]
-    tx_update_request: Sender<Task>,
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TaskwarriorFilter {
+    /// Shown as `{filter_name}`
+    pub name: String,
+
+    /// A taskwarrior filter, e.g. `status:pending due:today`
+    #[serde(default = "TaskwarriorFilter::default_filter")]
+    pub filter: String,
+}
+
+impl TaskwarriorFilter {
+    fn default_filter() -> String {
+        "status:pending".to_owned()
+    }
 }
 
 #[derive(Deserialize, Debug, Default, Clone)]
@@ -56,6 +71,10 @@ pub struct TaskwarriorConfig {
     #[serde(default = "TaskwarriorConfig::default_filter_tags")]
     pub filter_tags: Vec<String>,
 
+    /// Named filters, which are cycled through by right click
+    #[serde(default = "TaskwarriorConfig::default_filters")]
+    pub filters: Vec<TaskwarriorFilter>,
+
     /// Format override
     #[serde(default = "TaskwarriorConfig::default_format")]
     pub format: String,
@@ -72,13 +91,6 @@ pub struct TaskwarriorConfig {
     pub color_overrides: Option<BTreeMap<String, String>>,
 }
 
-enum TaskwarriorBlockMode {
-    // Show only the tasks which are filtered by the set tags and which are not completed.
-    OnlyFilteredPendingTasks,
-    // Show all pending tasks and ignore the filtering tags.
-    AllPendingTasks,
-}
-
 impl TaskwarriorConfig {
     fn default_interval() -> Duration {
         Duration::from_secs(600)
@@ -96,6 +108,10 @@ impl TaskwarriorConfig {
         vec![]
     }
 
+    fn default_filters() -> Vec<TaskwarriorFilter> {
+        vec![]
+    }
+
     fn default_format() -> String {
         "{count}".to_owned()
     }
@@ -103,6 +119,128 @@ impl TaskwarriorConfig {
     fn default_color_overrides() -> Option<BTreeMap<String, String>> {
         None
     }
+
+    /// The configured filters. Without any, the pending tasks are counted,
+    /// and the `filter_tags` can be toggled.
+    fn filters(&self) -> Vec<TaskwarriorFilter> {
+        if !self.filters.is_empty() {
+            return self.filters.clone();
+        }
+        let all = TaskwarriorFilter {
+            name: "all".to_owned(),
+            filter: TaskwarriorFilter::default_filter(),
+        };
+        if self.filter_tags.is_empty() {
+            vec![all]
+        } else {
+            let filtered = TaskwarriorFilter {
+                name: "filtered".to_owned(),
+                filter: format!("{} {}", all.filter, tags_to_filter(&self.filter_tags)),
+            };
+            vec![filtered, all]
+        }
+    }
+}
+
+fn tags_to_filter(tags: &[String]) -> String {
+    tags.iter()
+        .map(|element| format!("+{}", element))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+fn get_number_of_tasks(filter: &str) -> Result<u32> {
+    let output = Command::new("sh")
+        .args(&["-c", &format!("task rc.gc=off {} count", filter)])
+        .output()
+        .block_error(
+            "taskwarrior",
+            "failed to run taskwarrior for getting the number of tasks",
+        )?;
+    if !output.status.success() {
+        return Err(BlockError(
+            "taskwarrior".to_owned(),
+            format!("taskwarrior failed for filter '{}'", filter),
+        ));
+    }
+    String::from_utf8(output.stdout)
+        .block_error(
+            "taskwarrior",
+            "failed to get the number of tasks from taskwarrior",
+        )?
+        .trim()
+        .parse::<u32>()
+        .block_error("taskwarrior", "could not parse the result of taskwarrior")
+}
+
+/// What the worker thread found out so far.
+#[derive(Debug, Default)]
+struct Status {
+    /// The number of tasks matching each filter, once counted
+    counts: Vec<Option<u32>>,
+    /// Why the tasks couldn't be counted
+    error: Option<String>,
+}
+
+/// Counts the tasks in the background, as taskwarrior may take a while with
+/// many tasks, and requests an update of the block after each count.
+fn make_thread(
+    filters: Vec<String>,
+    interval: Duration,
+    status: Arc<Mutex<Status>>,
+    rx_refresh: Receiver<()>,
+    id: String,
+    tx_update_request: Sender<Task>,
+) {
+    thread::Builder::new()
+        .name("taskwarrior".into())
+        .spawn(move || {
+            let request_update = || {
+                tx_update_request
+                    .send(Task {
+                        id: id.clone(),
+                        update_time: Instant::now(),
+                    })
+                    .is_ok()
+            };
+
+            // Checked once, instead of failing on every count
+            if !has_command("taskwarrior", "task").unwrap_or(false) {
+                if let Ok(mut status) = status.lock() {
+                    status.error = Some("taskwarrior is not installed".to_owned());
+                }
+                request_update();
+                return;
+            }
+
+            loop {
+                let results: Vec<Result<u32>> = filters
+                    .iter()
+                    .map(|filter| get_number_of_tasks(filter))
+                    .collect();
+                if let Ok(mut status) = status.lock() {
+                    let status = &mut *status;
+                    status.error = None;
+                    for (count, result) in status.counts.iter_mut().zip(results) {
+                        match result {
+                            Ok(n) => *count = Some(n),
+                            Err(BlockError(_, message)) => status.error = Some(message),
+                            Err(_) => {}
+                        }
+                    }
+                }
+                if !request_update() {
+                    return;
+                }
+
+                match rx_refresh.recv_timeout(interval) {
+                    Ok(()) => rx_refresh.try_iter().for_each(drop),
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => return,
+                }
+            }
+        })
+        .unwrap();
 }
 
 impl ConfigBlock for Taskwarrior {
@@ -114,17 +252,31 @@ impl ConfigBlock for Taskwarrior {
         tx_update_request: Sender<Task>,
     ) -> Result<Self> {
         let id = pseudo_uuid();
-        let output = ButtonWidget::new(config.clone(), &id)
+        let output = ButtonWidget::new(config, &id)
             .with_icon("tasks")
             .with_text("-");
 
+        let filters = block_config.filters();
+        let status = Arc::new(Mutex::new(Status {
+            counts: vec![None; filters.len()],
+            error: None,
+        }));
+        let (tx_refresh, rx_refresh) = unbounded();
+        make_thread(
+            filters.iter().map(|filter| filter.filter.clone()).collect(),
+            block_config.interval,
+            status.clone(),
+            rx_refresh,
+            id.clone(),
+            tx_update_request,
+        );
+
         Ok(Taskwarrior {
-            id: pseudo_uuid(),
-            update_interval: block_config.interval,
+            id,
             warning_threshold: block_config.warning_threshold,
             critical_threshold: block_config.critical_threshold,
-            filter_tags: block_config.filter_tags,
-            block_mode: TaskwarriorBlockMode::OnlyFilteredPendingTasks,
+            filters,
+            current_filter: 0,
             output,
             format: FormatTemplate::from_string(&block_config.format).block_error(
                 "taskwarrior",
@@ -142,88 +294,50 @@ impl ConfigBlock for Taskwarrior {
                 "taskwarrior",
                 "Invalid format specified for taskwarrior::format_everything_done",
             )?,
-            tx_update_request,
-            config,
+            status,
+            tx_refresh,
         })
     }
 }
 
-fn has_taskwarrior() -> Result<bool> {
-    Ok(String::from_utf8(
-        Command::new("sh")
-            .args(&["-c", "type -P task"])
-            .output()
-            .block_error(
-                "taskwarrior",
-                "failed to start command to check for taskwarrior",
-            )?
-            .stdout,
-    )
-    .block_error("taskwarrior", "failed to check for taskwarrior")?
-    .trim()
-        != "")
-}
-
-fn tags_to_filter(tags: &[String]) -> String {
-    tags.iter()
-        .map(|element| format!("+{}", element))
-        .collect::<Vec<String>>()
-        .join(" ")
-}
-
-fn get_number_of_pending_tasks(tags: &[String]) -> Result<u32> {
-    String::from_utf8(
-        Command::new("sh")
-            .args(&[
-                "-c",
-                &format!(
-                    "task rc.gc=off -COMPLETED -DELETED {} count",
-                    tags_to_filter(tags)
-                ),
-            ])
-            .output()
-            .block_error(
-                "taskwarrior",
-                "failed to run taskwarrior for getting the number of pending tasks",
-            )?
-            .stdout,
-    )
-    .block_error(
-        "taskwarrior",
-        "failed to get the number of pending tasks from taskwarrior",
-    )?
-    .trim()
-    .parse::<u32>()
-    .block_error("taskwarrior", "could not parse the result of taskwarrior")
-}
-
 impl Block for Taskwarrior {
     fn update(&mut self) -> Result<Option<Update>> {
-        if !has_taskwarrior()? {
-            self.output.set_text("?")
-        } else {
-            let filter_tags = match self.block_mode {
-                TaskwarriorBlockMode::OnlyFilteredPendingTasks => self.filter_tags.clone(),
-                TaskwarriorBlockMode::AllPendingTasks => vec![],
-            };
-            let number_of_pending_tasks = get_number_of_pending_tasks(&filter_tags)?;
-            let values = map!("{count}" => number_of_pending_tasks);
-            self.output.set_text(match number_of_pending_tasks {
-                0 => self.format_everything_done.render_static_str(&values)?,
-                1 => self.format_singular.render_static_str(&values)?,
-                _ => self.format.render_static_str(&values)?,
-            });
-            if number_of_pending_tasks >= self.critical_threshold {
+        let (count, error) = {
+            let status = self
+                .status
+                .lock()
+                .block_error("taskwarrior", "mutex poisoned")?;
+            (status.counts[self.current_filter], status.error.clone())
+        };
+
+        match (count, error) {
+            (_, Some(error)) => {
+                self.output.set_text(error);
                 self.output.set_state(State::Critical);
-            } else if number_of_pending_tasks >= self.warning_threshold {
-                self.output.set_state(State::Warning);
-            } else {
-                self.output.set_state(State::Idle);
+            }
+            (None, None) => self.output.set_text("-"),
+            (Some(number_of_tasks), None) => {
+                let values = map!(
+                    "{count}" => number_of_tasks.to_string(),
+                    "{filter_name}" => self.filters[self.current_filter].name.clone()
+                );
+                self.output.set_text(match number_of_tasks {
+                    0 => self.format_everything_done.render_static_str(&values)?,
+                    1 => self.format_singular.render_static_str(&values)?,
+                    _ => self.format.render_static_str(&values)?,
+                });
+                if number_of_tasks >= self.critical_threshold {
+                    self.output.set_state(State::Critical);
+                } else if number_of_tasks >= self.warning_threshold {
+                    self.output.set_state(State::Warning);
+                } else {
+                    self.output.set_state(State::Idle);
+                }
             }
         }
 
-        // continue updating the block in the configured interval
-        Ok(Some(self.update_interval.into()))
+        // Updates are requested by the counting thread
+        Ok(None)
     }
 
     fn view(&self) -> Vec<&dyn I3BarWidget> {
@@ -231,20 +345,25 @@ impl Block for Taskwarrior {
     }
 
     fn click(&mut self, event: &I3BarEvent) -> Result<()> {
-        if event.name.as_ref().map(|s| s == &self.id).unwrap_or(false) {
+        if event.matches_name(&self.id) {
             match event.button {
                 MouseButton::Left => {
-                    self.update()?;
+                    // The thread exits if taskwarrior isn't installed, which
+                    // it left as the error to show
+                    if self.tx_refresh.send(()).is_err() {
+                        let error = self
+                            .status
+                            .lock()
+                            .block_error("taskwarrior", "mutex poisoned")?
+                            .error
+                            .clone();
+                        self.output
+                            .set_text(error.unwrap_or_else(|| "counting thread exited".to_owned()));
+                        self.output.set_state(State::Critical);
+                    }
                 }
                 MouseButton::Right => {
-                    match self.block_mode {
-                        TaskwarriorBlockMode::OnlyFilteredPendingTasks => {
-                            self.block_mode = TaskwarriorBlockMode::AllPendingTasks
-                        }
-                        TaskwarriorBlockMode::AllPendingTasks => {
-                            self.block_mode = TaskwarriorBlockMode::OnlyFilteredPendingTasks
-                        }
-                    }
+                    self.current_filter = (self.current_filter + 1) % self.filters.len();
                     self.update()?;
                 }
                 _ => {}
@@ -258,3 +377,17 @@ impl Block for Taskwarrior {
         &self.id
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tags_to_filter() {
+        assert_eq!(tags_to_filter(&[]), "");
+        assert_eq!(
+            tags_to_filter(&["work".to_owned(), "important".to_owned()]),
+            "+work +important"
+        );
+    }
+}
@@ -1,17 +1,18 @@
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
 use std::process::Command;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use crossbeam_channel::Sender;
 use serde_derive::Deserialize;
 
 use crate::blocks::{Block, ConfigBlock, Update};
 use crate::config::Config;
-use crate::de::deserialize_duration;
+use crate::de::{deserialize_duration, deserialize_opt_duration};
 use crate::errors::*;
 use crate::input::{I3BarEvent, MouseButton};
 use crate::scheduler::Task;
-use crate::util::{pseudo_uuid, FormatTemplate};
+use crate::util::{pseudo_uuid, read_file, FormatTemplate};
 use crate::widget::{I3BarWidget, Spacing, State};
 use crate::widgets::button::ButtonWidget;
 
@@ -28,10 +29,42 @@ impl Default for TemperatureScale {
     }
 }
 
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TemperatureDriver {
+    Sensors,
+    Sysfs,
+}
+
+impl Default for TemperatureDriver {
+    fn default() -> Self {
+        Self::Sensors
+    }
+}
+
+impl TemperatureScale {
+    /// Converts a temperature in degrees Celsius to this scale.
+    pub fn from_celsius(self, celsius: f64) -> f64 {
+        match self {
+            Self::Celsius => celsius,
+            Self::Fahrenheit => celsius * 9.0 / 5.0 + 32.0,
+        }
+    }
+
+    pub fn unit(self) -> &'static str {
+        match self {
+            Self::Celsius => "°C",
+            Self::Fahrenheit => "°F",
+        }
+    }
+}
+
 pub struct Temperature {
     text: ButtonWidget,
     output: String,
     collapsed: bool,
+    expand_duration: Option<Duration>,
+    expanded_until: Option<Instant>,
     id: String,
     update_interval: Duration,
     scale: TemperatureScale,
@@ -39,9 +72,16 @@ pub struct Temperature {
     maximum_idle: i64,
     maximum_info: i64,
     maximum_warning: i64,
+    min_valid: f64,
+    max_valid: f64,
+    ignore_chips: Vec<String>,
+    reported_chips: HashSet<String>,
     format: FormatTemplate,
+    reported_missing: bool,
+    driver: TemperatureDriver,
     chip: Option<String>,
     inputs: Option<Vec<String>>,
+    tx_update_request: Sender<Task>,
 }
 
 #[derive(Deserialize, Debug, Default, Clone)]
@@ -58,6 +98,13 @@ pub struct TemperatureConfig {
     #[serde(default = "TemperatureConfig::default_collapsed")]
     pub collapsed: bool,
 
+    /// How long the block stays expanded after clicking it, if collapsed by default
+    #[serde(
+        default = "TemperatureConfig::default_expand_duration",
+        deserialize_with = "deserialize_opt_duration"
+    )]
+    pub expand_duration: Option<Duration>,
+
     /// The temperature scale to use for display and thresholds
     #[serde(default)]
     pub scale: TemperatureScale,
@@ -78,10 +125,26 @@ pub struct TemperatureConfig {
     #[serde(default)]
     pub warning: Option<i64>,
 
+    /// Readings below this temperature are ignored
+    #[serde(default)]
+    pub min_valid: Option<f64>,
+
+    /// Readings above this temperature are ignored
+    #[serde(default)]
+    pub max_valid: Option<f64>,
+
+    /// Chips to ignore entirely
+    #[serde(default = "TemperatureConfig::default_ignore_chips")]
+    pub ignore_chips: Vec<String>,
+
     /// Format override
     #[serde(default = "TemperatureConfig::default_format")]
     pub format: String,
 
+    /// The "driver" to use for reading temperatures. One of "sensors" or "sysfs".
+    #[serde(default)]
+    pub driver: TemperatureDriver,
+
     /// Chip override
     #[serde(default = "TemperatureConfig::default_chip")]
     pub chip: Option<String>,
@@ -107,6 +170,10 @@ impl TemperatureConfig {
         true
     }
 
+    fn default_expand_duration() -> Option<Duration> {
+        None
+    }
+
     fn default_chip() -> Option<String> {
         None
     }
@@ -115,6 +182,10 @@ impl TemperatureConfig {
         None
     }
 
+    fn default_ignore_chips() -> Vec<String> {
+        Vec::new()
+    }
+
     fn default_color_overrides() -> Option<BTreeMap<String, String>> {
         None
     }
@@ -126,10 +197,10 @@ impl ConfigBlock for Temperature {
     fn new(
         block_config: Self::Config,
         config: Config,
-        _tx_update_request: Sender<Task>,
+        tx_update_request: Sender<Task>,
     ) -> Result<Self> {
         let id = pseudo_uuid();
-        Ok(Temperature {
+        let temperature = Temperature {
             update_interval: block_config.interval,
             text: ButtonWidget::new(config, &id)
                 .with_icon("thermometer")
@@ -140,6 +211,8 @@ impl ConfigBlock for Temperature {
                 }),
             output: String::new(),
             collapsed: block_config.collapsed,
+            expand_duration: block_config.expand_duration,
+            expanded_until: None,
             id,
             scale: block_config.scale,
             maximum_good: block_config
@@ -166,79 +239,363 @@ impl ConfigBlock for Temperature {
                     TemperatureScale::Celsius => 80,
                     TemperatureScale::Fahrenheit => 176,
                 }),
+            min_valid: block_config
+                .min_valid
+                .unwrap_or_else(|| block_config.scale.from_celsius(-100.0)),
+            max_valid: block_config
+                .max_valid
+                .unwrap_or_else(|| block_config.scale.from_celsius(150.0)),
+            ignore_chips: block_config.ignore_chips,
+            reported_chips: HashSet::new(),
             format: FormatTemplate::from_string(&block_config.format)
                 .block_error("temperature", "Invalid format specified for temperature")?,
+            reported_missing: false,
+            driver: block_config.driver,
             chip: block_config.chip,
             inputs: block_config.inputs,
-        })
+            tx_update_request,
+        };
+
+        // Tells users which sensors they can put in the format
+        if let Ok(readings) = temperature.read_temperatures() {
+            let mut available: Vec<String> = readings
+                .iter()
+                .map(TemperatureReading::placeholder)
+                .collect();
+            available.sort();
+            available.dedup();
+            eprintln!("temperature: available sensors: {:?}", available);
+        }
+
+        Ok(temperature)
     }
 }
 
 type SensorsOutput = HashMap<String, HashMap<String, serde_json::Value>>;
 type InputReadings = HashMap<String, f64>;
 
-impl Block for Temperature {
-    fn update(&mut self) -> Result<Option<Update>> {
-        let mut args = vec!["-j"];
-        if let TemperatureScale::Fahrenheit = self.scale {
-            args.push("-f");
+/// A single temperature in degrees Celsius, along with the chip and input it
+/// was read from.
+#[derive(Debug, Clone)]
+struct TemperatureReading {
+    chip: String,
+    input: String,
+    celsius: f64,
+}
+
+impl TemperatureReading {
+    /// The placeholder of the reading in the format, e.g.
+    /// `{coretemp-isa-0000_Package_id_0}`. Characters placeholders can't
+    /// contain are replaced with underscores.
+    fn placeholder(&self) -> String {
+        let name: String = format!("{}_{}", self.chip, self.input)
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        format!("{{{}}}", name)
+    }
+}
+
+/// Checks a `chip` option against the name of a hwmon device or the type of a
+/// thermal zone. Chip names in the lm_sensors format (e.g. `coretemp-isa-0000`)
+/// are matched on the part before the first dash, and `*` matches any chip.
+fn chip_matches(chip: &str, name: &str) -> bool {
+    let prefix = chip.split('-').next().unwrap_or(chip);
+    prefix == "*" || prefix == name
+}
+
+/// Checks an entry of `ignore_chips` against a chip name. `*` matches any
+/// sequence of characters. Patterns in the lm_sensors format also match the
+/// shorter names used by sysfs, e.g. `nvme-pci-*` matches `nvme`.
+fn chip_ignored(pattern: &str, chip: &str) -> bool {
+    fn wildcard_match(pattern: &[u8], name: &[u8]) -> bool {
+        match (pattern.first(), name.first()) {
+            (None, None) => true,
+            (Some(b'*'), _) => {
+                wildcard_match(&pattern[1..], name)
+                    || (!name.is_empty() && wildcard_match(pattern, &name[1..]))
+            }
+            (Some(p), Some(n)) if p == n => wildcard_match(&pattern[1..], &name[1..]),
+            _ => false,
         }
-        if let Some(ref chip) = &self.chip {
-            args.push(chip);
+    }
+
+    wildcard_match(pattern.as_bytes(), chip.as_bytes())
+        || (!chip.contains('-') && chip_matches(pattern, chip))
+}
+
+/// Reads a sysfs temperature file, which contains millidegrees Celsius.
+fn read_millidegrees(path: &Path) -> Option<f64> {
+    read_file("temperature", path)
+        .ok()
+        .and_then(|value| value.trim().parse::<f64>().ok())
+        .map(|millidegrees| millidegrees / 1000.0)
+}
+
+/// Reads all `temp*_input` files below `/sys/class/hwmon`, followed by the
+/// thermal zones in `/sys/class/thermal` that are not already exposed through
+/// hwmon. Missing or unreadable inputs are skipped.
+fn read_sysfs_temperatures(
+    chip: &Option<String>,
+    inputs: &Option<Vec<String>>,
+) -> Result<Vec<TemperatureReading>> {
+    let mut temperatures = Vec::new();
+    let mut hwmon_names = Vec::new();
+
+    let hwmons = Path::new("/sys/class/hwmon")
+        .read_dir()
+        .block_error("temperature", "Failed to read hwmon directory")?;
+    for hwmon in hwmons.flatten() {
+        let hwmon_path = hwmon.path();
+        let name = match read_file("temperature", &hwmon_path.join("name")) {
+            Ok(name) => name.trim().to_owned(),
+            Err(_) => continue,
+        };
+        hwmon_names.push(name.clone());
+        if let Some(ref chip) = chip {
+            if !chip_matches(chip, &name) {
+                continue;
+            }
         }
-        let output = Command::new("sensors")
-            .args(&args)
-            .output()
-            .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_owned())
-            .unwrap_or_else(|e| e.to_string());
-
-        let parsed: SensorsOutput = serde_json::from_str(&output)
-            .block_error("temperature", "sensors output is invalid")?;
-
-        let mut temperatures: Vec<i64> = Vec::new();
-        for (_chip, inputs) in parsed {
-            for (input_name, input_values) in inputs {
-                if let Some(ref whitelist) = self.inputs {
-                    if !whitelist.contains(&input_name) {
-                        continue;
-                    }
+
+        let entries = match hwmon_path.read_dir() {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.flatten() {
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            if !file_name.starts_with("temp") || !file_name.ends_with("_input") {
+                continue;
+            }
+
+            // lm_sensors names inputs by their label if there is one
+            let base = file_name.trim_end_matches("_input");
+            let label = read_file("temperature", &hwmon_path.join(format!("{}_label", base)))
+                .map(|label| label.trim().to_owned())
+                .unwrap_or_else(|_| base.to_owned());
+            if let Some(ref whitelist) = inputs {
+                if !whitelist.contains(&label) {
+                    continue;
+                }
+            }
+
+            if let Some(celsius) = read_millidegrees(&entry.path()) {
+                temperatures.push(TemperatureReading {
+                    chip: name.clone(),
+                    input: label,
+                    celsius,
+                });
+            }
+        }
+    }
+
+    // Not every system has this directory, so it is fine if it can't be read
+    if let Ok(zones) = Path::new("/sys/class/thermal").read_dir() {
+        for zone in zones.flatten() {
+            if !zone
+                .file_name()
+                .to_string_lossy()
+                .starts_with("thermal_zone")
+            {
+                continue;
+            }
+            let zone_path = zone.path();
+            let zone_type = match read_file("temperature", &zone_path.join("type")) {
+                Ok(zone_type) => zone_type.trim().to_owned(),
+                Err(_) => continue,
+            };
+            if hwmon_names.contains(&zone_type) {
+                continue;
+            }
+            if let Some(ref chip) = chip {
+                if !chip_matches(chip, &zone_type) {
+                    continue;
+                }
+            }
+            if let Some(ref whitelist) = inputs {
+                if !whitelist.contains(&zone_type) {
+                    continue;
                 }
+            }
 
-                let values_parsed: InputReadings = match serde_json::from_value(input_values) {
-                    Ok(values) => values,
-                    Err(_) => continue, // probably the "Adapter" key, just ignore.
-                };
+            if let Some(celsius) = read_millidegrees(&zone_path.join("temp")) {
+                temperatures.push(TemperatureReading {
+                    chip: zone_type.clone(),
+                    input: zone_type,
+                    celsius,
+                });
+            }
+        }
+    }
 
-                for (value_name, value) in values_parsed {
-                    if !value_name.starts_with("temp") || !value_name.ends_with("input") {
-                        continue;
-                    }
+    Ok(temperatures)
+}
 
-                    if value > -101f64 && value < 151f64 {
-                        temperatures.push(value as i64);
-                    } else {
-                        // This error is recoverable and therefore should not stop the program
-                        eprintln!("Temperature ({}) outside of range ([-100, 150])", value);
-                    }
+/// Runs `sensors -j` and collects all `temp*_input` readings from its output.
+fn read_sensors_temperatures(
+    chip: &Option<String>,
+    inputs: &Option<Vec<String>>,
+) -> Result<Vec<TemperatureReading>> {
+    let mut args = vec!["-j"];
+    if let Some(ref chip) = chip {
+        args.push(chip);
+    }
+    let output = Command::new("sensors")
+        .args(&args)
+        .output()
+        .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_owned())
+        .unwrap_or_else(|e| e.to_string());
+
+    let parsed: SensorsOutput =
+        serde_json::from_str(&output).block_error("temperature", "sensors output is invalid")?;
+
+    let mut temperatures = Vec::new();
+    for (chip_name, chip_inputs) in parsed {
+        for (input_name, input_values) in chip_inputs {
+            if let Some(ref whitelist) = inputs {
+                if !whitelist.contains(&input_name) {
+                    continue;
+                }
+            }
+
+            let values_parsed: InputReadings = match serde_json::from_value(input_values) {
+                Ok(values) => values,
+                Err(_) => continue, // probably the "Adapter" key, just ignore.
+            };
+
+            for (value_name, value) in values_parsed {
+                if value_name.starts_with("temp") && value_name.ends_with("input") {
+                    temperatures.push(TemperatureReading {
+                        chip: chip_name.clone(),
+                        input: input_name.clone(),
+                        celsius: value,
+                    });
                 }
             }
         }
+    }
+
+    Ok(temperatures)
+}
+
+impl Temperature {
+    /// The readings of all chips that aren't ignored.
+    fn read_temperatures(&self) -> Result<Vec<TemperatureReading>> {
+        let readings = match self.driver {
+            TemperatureDriver::Sensors => read_sensors_temperatures(&self.chip, &self.inputs)?,
+            TemperatureDriver::Sysfs => read_sysfs_temperatures(&self.chip, &self.inputs)?,
+        };
+        Ok(readings
+            .into_iter()
+            .filter(|reading| {
+                !self
+                    .ignore_chips
+                    .iter()
+                    .any(|pattern| chip_ignored(pattern, &reading.chip))
+            })
+            .collect())
+    }
+
+    fn set_collapsed(&mut self, collapsed: bool) {
+        self.collapsed = collapsed;
+        if self.collapsed {
+            self.text.set_text(String::new());
+            self.text.set_spacing(Spacing::Hidden);
+        } else {
+            self.text.set_text(self.output.clone());
+            self.text.set_spacing(Spacing::Normal);
+        }
+    }
+}
+
+impl Block for Temperature {
+    fn update(&mut self) -> Result<Option<Update>> {
+        if let Some(expanded_until) = self.expanded_until {
+            if Instant::now() >= expanded_until {
+                self.expanded_until = None;
+                self.set_collapsed(true);
+            }
+        }
+
+        let readings = self.read_temperatures()?;
+
+        let mut temperatures: Vec<f64> = Vec::new();
+        let mut labeled: HashMap<String, f64> = HashMap::new();
+        for reading in readings {
+            // Also filters out bogus values like -273°C from unused thermal zones
+            if (self.min_valid..=self.max_valid).contains(&self.scale.from_celsius(reading.celsius))
+            {
+                temperatures.push(reading.celsius);
+                labeled
+                    .entry(reading.placeholder())
+                    .or_insert(reading.celsius);
+            } else if self.reported_chips.insert(reading.chip.clone()) {
+                // This error is recoverable and therefore should not stop the program.
+                // Only report it once per chip to not flood the log.
+                eprintln!(
+                    "Temperature {}/{} ({}) outside of range ([{}, {}]), ignoring",
+                    reading.chip,
+                    reading.input,
+                    self.scale.from_celsius(reading.celsius),
+                    self.min_valid,
+                    self.max_valid
+                );
+            }
+        }
 
         if !temperatures.is_empty() {
-            let max: i64 = *temperatures
+            // Convert before rounding, so the displayed value doesn't depend on the scale
+            let temperatures: Vec<f64> = temperatures
+                .into_iter()
+                .map(|t| self.scale.from_celsius(t))
+                .collect();
+            let max: i64 = temperatures
                 .iter()
-                .max()
-                .block_error("temperature", "failed to get max temperature")?;
-            let min: i64 = *temperatures
+                .cloned()
+                .fold(f64::NEG_INFINITY, f64::max)
+                .round() as i64;
+            let min: i64 = temperatures
                 .iter()
-                .min()
-                .block_error("temperature", "failed to get min temperature")?;
-            let avg: i64 = (temperatures.iter().sum::<i64>() as f64 / temperatures.len() as f64)
+                .cloned()
+                .fold(f64::INFINITY, f64::min)
                 .round() as i64;
-
-            let values = map!("{average}" => avg,
-                              "{min}" => min,
-                              "{max}" => max);
+            let avg: i64 =
+                (temperatures.iter().sum::<f64>() / temperatures.len() as f64).round() as i64;
+
+            let mut values = map!("{average}" => avg.to_string(),
+                              "{min}" => min.to_string(),
+                              "{max}" => max.to_string(),
+                              "{unit}" => self.scale.unit().to_string());
+
+            // Any other placeholder refers to a sensor by its chip and label
+            for placeholder in self.format.placeholders() {
+                if values.contains_key(placeholder) {
+                    continue;
+                }
+                let value = match labeled.get(placeholder) {
+                    Some(&celsius) => (self.scale.from_celsius(celsius).round() as i64).to_string(),
+                    None => {
+                        if !self.reported_missing {
+                            // This error is recoverable and therefore should not stop the program
+                            let mut available: Vec<&String> = labeled.keys().collect();
+                            available.sort();
+                            eprintln!(
+                                "Temperature sensor {} not found, available sensors: {:?}",
+                                placeholder, available
+                            );
+                            self.reported_missing = true;
+                        }
+                        "N/A".to_string()
+                    }
+                };
+                values.insert(placeholder, value);
+            }
 
             self.output = self.format.render_static_str(&values)?;
             if !self.collapsed {
@@ -254,9 +611,21 @@ impl Block for Temperature {
             };
 
             self.text.set_state(state);
+            self.text.set_gradient(
+                max as f64,
+                &[
+                    (self.maximum_good as f64, State::Good),
+                    (self.maximum_info as f64, State::Warning),
+                    (self.maximum_warning as f64, State::Critical),
+                ],
+            );
         }
 
-        Ok(Some(self.update_interval.into()))
+        // Collapse again on time, unless the next regular update comes first
+        Ok(Some(match self.expanded_until {
+            Some(until) if Instant::now() + self.update_interval > until => Update::At(until),
+            _ => self.update_interval.into(),
+        }))
     }
 
     fn view(&self) -> Vec<&dyn I3BarWidget> {
@@ -266,13 +635,22 @@ impl Block for Temperature {
     fn click(&mut self, e: &I3BarEvent) -> Result<()> {
         if let Some(ref name) = e.name {
             if name.as_str() == self.id && e.button == MouseButton::Left {
-                self.collapsed = !self.collapsed;
-                if self.collapsed {
-                    self.text.set_text(String::new());
-                    self.text.set_spacing(Spacing::Hidden);
-                } else {
-                    self.text.set_text(self.output.clone());
-                    self.text.set_spacing(Spacing::Normal);
+                let collapsed = !self.collapsed;
+                self.set_collapsed(collapsed);
+
+                match self.expand_duration {
+                    Some(duration) if !collapsed => {
+                        // The requested update schedules the one that
+                        // collapses the block again
+                        self.expanded_until = Some(Instant::now() + duration);
+                        self.tx_update_request
+                            .send(Task {
+                                id: self.id.clone(),
+                                update_time: Instant::now(),
+                            })
+                            .block_error("temperature", "failed to request an update")?;
+                    }
+                    _ => self.expanded_until = None,
                 }
             }
         }
@@ -284,3 +662,35 @@ impl Block for Temperature {
         &self.id
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::blocks::temperature::{chip_ignored, TemperatureReading, TemperatureScale};
+
+    #[test]
+    fn test_chip_ignored() {
+        assert!(chip_ignored("nvme-pci-*", "nvme-pci-0100"));
+        assert!(chip_ignored("nvme-pci-*", "nvme"));
+        assert!(chip_ignored("*", "coretemp-isa-0000"));
+        assert!(chip_ignored("coretemp-isa-0000", "coretemp-isa-0000"));
+        assert!(!chip_ignored("nvme-pci-*", "coretemp-isa-0000"));
+        assert!(!chip_ignored("nvme-pci-*", "coretemp"));
+    }
+
+    #[test]
+    fn test_placeholder() {
+        let reading = TemperatureReading {
+            chip: "coretemp-isa-0000".to_string(),
+            input: "Package id 0".to_string(),
+            celsius: 40.0,
+        };
+        assert_eq!(reading.placeholder(), "{coretemp-isa-0000_Package_id_0}");
+    }
+
+    #[test]
+    fn test_fahrenheit_rounds_after_conversion() {
+        let fahrenheit = TemperatureScale::Fahrenheit.from_celsius(37.6);
+        assert_eq!(fahrenheit.round() as i64, 100);
+        assert_eq!(TemperatureScale::Celsius.from_celsius(37.6), 37.6);
+    }
+}
@@ -11,21 +11,31 @@ use crossbeam_channel::Sender;
 use serde_derive::Deserialize;
 
 use crate::blocks::{Block, ConfigBlock, Update};
-use crate::config::Config;
+use crate::config::{Config, LogicalDirection};
 use crate::de::deserialize_duration;
 use crate::errors::*;
+use crate::input::{I3BarEvent, MouseButton};
 use crate::scheduler::Task;
 use crate::util::pseudo_uuid;
 use crate::widget::I3BarWidget;
 use crate::widgets::button::ButtonWidget;
 
+/// strftime specifiers that show the seconds
+const SECONDS_SPECIFIERS: &[&str] = &["%S", "%T", "%X", "%r", "%s", "%c", "%+"];
+
 pub struct Time {
     time: ButtonWidget,
     id: String,
     update_interval: Duration,
     format: String,
-    timezone: Option<Tz>,
+    short_format: Option<String>,
+    /// The timezones to cycle through, `None` being the local timezone
+    timezones: Vec<Option<Tz>>,
+    labels: Vec<String>,
+    /// The index of the timezone shown
+    current: usize,
     locale: Option<String>,
+    config: Config,
 }
 
 #[derive(Deserialize, Debug, Default, Clone)]
@@ -35,6 +45,10 @@ pub struct TimeConfig {
     #[serde(default = "TimeConfig::default_format")]
     pub format: String,
 
+    /// Format string shown when the bar is too narrow
+    #[serde(default = "TimeConfig::default_short_format")]
+    pub short_format: Option<String>,
+
     /// Update interval in seconds
     #[serde(
         default = "TimeConfig::default_interval",
@@ -45,6 +59,14 @@ pub struct TimeConfig {
     #[serde(default = "TimeConfig::default_timezone")]
     pub timezone: Option<Tz>,
 
+    /// Timezones to cycle through by clicking or scrolling, instead of a single `timezone`
+    #[serde(default = "TimeConfig::default_timezones")]
+    pub timezones: Option<Vec<Tz>>,
+
+    /// Labels of the timezones, as shown by `{label}`
+    #[serde(default = "TimeConfig::default_labels")]
+    pub labels: Option<Vec<String>>,
+
     #[serde(default = "TimeConfig::default_locale")]
     pub locale: Option<String>,
 
@@ -57,6 +79,10 @@ impl TimeConfig {
         "%a %d/%m %R".to_owned()
     }
 
+    fn default_short_format() -> Option<String> {
+        None
+    }
+
     fn default_interval() -> Duration {
         Duration::from_secs(5)
     }
@@ -65,6 +91,14 @@ impl TimeConfig {
         None
     }
 
+    fn default_timezones() -> Option<Vec<Tz>> {
+        None
+    }
+
+    fn default_labels() -> Option<Vec<String>> {
+        None
+    }
+
     fn default_locale() -> Option<String> {
         None
     }
@@ -83,47 +117,141 @@ impl ConfigBlock for Time {
         _tx_update_request: Sender<Task>,
     ) -> Result<Self> {
         let i = pseudo_uuid();
+
+        let timezones: Vec<Option<Tz>> = match block_config.timezones {
+            Some(timezones) => {
+                if block_config.timezone.is_some() {
+                    return Err(BlockError(
+                        "time".to_owned(),
+                        "`timezone` and `timezones` can't be used together".to_owned(),
+                    ));
+                }
+                if timezones.is_empty() {
+                    return Err(BlockError(
+                        "time".to_owned(),
+                        "`timezones` must not be empty".to_owned(),
+                    ));
+                }
+                timezones.into_iter().map(Some).collect()
+            }
+            None => vec![block_config.timezone],
+        };
+
+        let labels = match block_config.labels {
+            Some(labels) => {
+                if labels.len() != timezones.len() {
+                    return Err(BlockError(
+                        "time".to_owned(),
+                        "`labels` must have one entry per timezone".to_owned(),
+                    ));
+                }
+                labels
+            }
+            None => timezones
+                .iter()
+                .map(|tz| tz.map(|tz| tz.name().to_string()).unwrap_or_default())
+                .collect(),
+        };
+
+        // Showing seconds needs an update every second to not skip any
+        let format = block_config.format;
+        let short_format = block_config.short_format;
+        let update_interval = if SECONDS_SPECIFIERS.iter().any(|specifier| {
+            format.contains(specifier)
+                || short_format
+                    .as_ref()
+                    .map_or(false, |short_format| short_format.contains(specifier))
+        }) {
+            block_config.interval.min(Duration::from_secs(1))
+        } else {
+            block_config.interval
+        };
+
         Ok(Time {
             id: i.clone(),
-            format: block_config.format,
-            time: ButtonWidget::new(config, i.as_str())
+            format,
+            short_format,
+            time: ButtonWidget::new(config.clone(), i.as_str())
                 .with_text("")
                 .with_icon("time"),
-            update_interval: block_config.interval,
-            timezone: block_config.timezone,
+            update_interval,
+            timezones,
+            labels,
+            current: 0,
             locale: block_config.locale,
+            config,
         })
     }
 }
 
-impl Block for Time {
-    fn update(&mut self) -> Result<Option<Update>> {
+impl Time {
+    /// Formats the current time in the selected timezone.
+    fn format_time(&self, format: &str) -> Result<String> {
+        let timezone = self.timezones[self.current];
         let time = match &self.locale {
             Some(l) => {
                 let locale: Locale = l
                     .as_str()
                     .try_into()
                     .block_error("time", "invalid locale")?;
-                match self.timezone {
+                match timezone {
                     Some(tz) => Utc::now()
                         .with_timezone(&tz)
-                        .format_localized(&self.format, locale),
-                    None => Local::now().format_localized(&self.format, locale),
+                        .format_localized(format, locale),
+                    None => Local::now().format_localized(format, locale),
                 }
             }
-            None => match self.timezone {
-                Some(tz) => Utc::now().with_timezone(&tz).format(&self.format),
-                None => Local::now().format(&self.format),
+            None => match timezone {
+                Some(tz) => Utc::now().with_timezone(&tz).format(format),
+                None => Local::now().format(format),
             },
         };
-        self.time.set_text(format!("{}", time));
-        Ok(Some(self.update_interval.into()))
+        Ok(format!("{}", time).replace("{label}", &self.labels[self.current]))
+    }
+}
+
+impl Block for Time {
+    fn update(&mut self) -> Result<Option<Update>> {
+        self.time.set_text(self.format_time(&self.format)?);
+        let short_text = match self.short_format {
+            Some(ref short_format) => Some(self.format_time(short_format)?),
+            None => None,
+        };
+        self.time.set_short_text(short_text);
+
+        Ok(Some(Update::Aligned(self.update_interval)))
     }
 
     fn view(&self) -> Vec<&dyn I3BarWidget> {
         vec![&self.time]
     }
 
+    fn click(&mut self, e: &I3BarEvent) -> Result<()> {
+        if !e.matches_name(&self.id) || self.timezones.len() < 2 {
+            return Ok(());
+        }
+
+        use LogicalDirection::*;
+        let forward = match e.button {
+            MouseButton::Left | MouseButton::WheelRight => true,
+            MouseButton::Right | MouseButton::WheelLeft => false,
+            button => match self.config.scrolling.to_logical_direction(button) {
+                Some(Up) => true,
+                Some(Down) => false,
+                None => return Ok(()),
+            },
+        };
+        let count = self.timezones.len();
+        self.current = if forward {
+            (self.current + 1) % count
+        } else {
+            (self.current + count - 1) % count
+        };
+        self.update()?;
+
+        Ok(())
+    }
+
     fn id(&self) -> &str {
         &self.id
     }
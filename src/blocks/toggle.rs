@@ -1,7 +1,7 @@
 use std::collections::BTreeMap;
 use std::env;
 use std::process::Command;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use crossbeam_channel::Sender;
 use serde_derive::Deserialize;
@@ -25,9 +25,15 @@ pub struct Toggle {
     icon_off: String,
     update_interval: Option<Duration>,
     toggled: bool,
+    /// Show a warning until then, after a toggle command failed
+    warning_until: Option<Instant>,
+    tx_update_request: Sender<Task>,
     id: String,
 }
 
+/// How long a failed toggle command is shown
+const WARNING_DURATION: Duration = Duration::from_secs(3);
+
 #[derive(Deserialize, Debug, Default, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct ToggleConfig {
@@ -41,7 +47,7 @@ pub struct ToggleConfig {
     /// Shell Command to disable the toggle
     pub command_off: String,
 
-    /// Shell Command to determine toggle state. <br/>Empty output => off. Any output => on.
+    /// Shell Command to determine toggle state. <br/>Empty output, "off" or a non-zero exit status => off. Any other output => on.
     pub command_state: String,
 
     /// Icon ID when toggled on (default is "toggle_on")
@@ -77,7 +83,7 @@ impl ConfigBlock for Toggle {
     fn new(
         block_config: Self::Config,
         config: Config,
-        _tx_update_request: Sender<Task>,
+        tx_update_request: Sender<Task>,
     ) -> Result<Self> {
         let id = pseudo_uuid();
         Ok(Toggle {
@@ -89,33 +95,51 @@ impl ConfigBlock for Toggle {
             icon_off: block_config.icon_off,
             id,
             toggled: false,
+            warning_until: None,
+            tx_update_request,
             update_interval: block_config.interval,
         })
     }
 }
 
+/// Interprets the result of `command_state`.
+fn is_on(success: bool, output: &str) -> bool {
+    match output.trim() {
+        _ if !success => false,
+        "" | "off" => false,
+        _ => true,
+    }
+}
+
 impl Block for Toggle {
     fn update(&mut self) -> Result<Option<Update>> {
-        let output = Command::new(env::var("SHELL").unwrap_or_else(|_| "sh".to_owned()))
+        self.toggled = Command::new(env::var("SHELL").unwrap_or_else(|_| "sh".to_owned()))
             .args(&["-c", &self.command_state])
             .output()
-            .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_owned())
-            .unwrap_or_else(|e| e.to_string());
+            .map(|o| is_on(o.status.success(), &String::from_utf8_lossy(&o.stdout)))
+            .unwrap_or(false);
 
-        self.text.set_icon(match output.trim_start() {
-            "" => {
-                self.toggled = false;
-                self.icon_off.as_str()
-            }
-            _ => {
-                self.toggled = true;
-                self.icon_on.as_str()
-            }
+        self.text.set_icon(if self.toggled {
+            self.icon_on.as_str()
+        } else {
+            self.icon_off.as_str()
         });
 
-        self.text.set_state(State::Idle);
+        let now = Instant::now();
+        let warning_until = self.warning_until.filter(|&until| now < until);
+        self.text.set_state(if warning_until.is_some() {
+            State::Warning
+        } else {
+            State::Idle
+        });
 
-        Ok(self.update_interval.map(|d| d.into()))
+        // Update once more when the warning is over, unless the next regular
+        // update comes first
+        Ok(match (warning_until, self.update_interval) {
+            (Some(until), Some(interval)) if now + interval < until => Some(interval.into()),
+            (Some(until), _) => Some(Update::At(until)),
+            (None, interval) => interval.map(|d| d.into()),
+        })
     }
 
     fn view(&self) -> Vec<&dyn I3BarWidget> {
@@ -136,17 +160,19 @@ impl Block for Toggle {
                     .output()
                     .block_error("toggle", "failed to run toggle command")?;
 
-                if output.status.success() {
-                    self.text.set_state(State::Idle);
-                    self.toggled = !self.toggled;
-                    self.text.set_icon(if self.toggled {
-                        self.icon_on.as_str()
-                    } else {
-                        self.icon_off.as_str()
+                if !output.status.success() {
+                    self.warning_until = Some(Instant::now() + WARNING_DURATION);
+                }
+
+                // Show the actual state, rather than assuming the command
+                // worked. The update is requested rather than run here, so
+                // that the end of a warning is scheduled as well.
+                self.tx_update_request
+                    .send(Task {
+                        id: self.id.clone(),
+                        update_time: Instant::now(),
                     })
-                } else {
-                    self.text.set_state(State::Critical);
-                };
+                    .block_error("toggle", "failed to request an update")?;
             }
         }
 
@@ -157,3 +183,17 @@ impl Block for Toggle {
         &self.id
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_on() {
+        assert!(is_on(true, "DP1 connected\n"));
+        assert!(is_on(true, "on"));
+        assert!(!is_on(true, ""));
+        assert!(!is_on(true, "off\n"));
+        assert!(!is_on(false, "error"));
+    }
+}
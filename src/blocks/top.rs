@@ -0,0 +1,325 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::Sender;
+use serde_derive::Deserialize;
+
+use crate::blocks::{Block, ConfigBlock, Update};
+use crate::config::Config;
+use crate::de::deserialize_duration;
+use crate::errors::*;
+use crate::input::{I3BarEvent, MouseButton};
+use crate::scheduler::Task;
+use crate::util::{pseudo_uuid, read_file, FormatTemplate};
+use crate::widget::I3BarWidget;
+use crate::widgets::text::TextWidget;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TopCriteria {
+    Cpu,
+    Memory,
+}
+
+impl Default for TopCriteria {
+    fn default() -> Self {
+        TopCriteria::Cpu
+    }
+}
+
+/// A single process, as read from /proc/[pid].
+#[derive(Debug, Clone, PartialEq)]
+struct ProcessSample {
+    pid: u32,
+    name: String,
+    /// Time spent in user and kernel mode, in clock ticks
+    ticks: u64,
+    /// Resident set size in KiB
+    rss: u64,
+}
+
+/// Parses the sum of utime and stime out of the contents of /proc/[pid]/stat.
+fn parse_stat_ticks(stat: &str) -> Option<u64> {
+    // The process name is enclosed in parentheses and may itself contain
+    // spaces or parentheses, so the remaining fields start after the last ')'.
+    let fields: Vec<&str> = stat[stat.rfind(')')? + 1..].split_whitespace().collect();
+    // utime and stime are the 14th and 15th fields, the first remaining
+    // field being the 3rd.
+    let utime = fields.get(11)?.parse::<u64>().ok()?;
+    let stime = fields.get(12)?.parse::<u64>().ok()?;
+    Some(utime + stime)
+}
+
+/// Parses the name and the resident set size out of the contents of /proc/[pid]/status.
+fn parse_status(status: &str) -> Option<(String, u64)> {
+    let mut name = None;
+    // Kernel threads have no VmRSS
+    let mut rss = 0;
+    for line in status.lines() {
+        if let Some(value) = line.strip_prefix("Name:") {
+            name = Some(value.trim().to_string());
+        } else if let Some(value) = line.strip_prefix("VmRSS:") {
+            rss = value.trim().trim_end_matches("kB").trim().parse().ok()?;
+        }
+    }
+    Some((name?, rss))
+}
+
+/// Reads a single process. Returns `None` if it exited in the meantime.
+fn read_process(pid: u32) -> Option<ProcessSample> {
+    let dir = Path::new("/proc").join(pid.to_string());
+    let ticks = parse_stat_ticks(&read_file("top", &dir.join("stat")).ok()?)?;
+    let (name, rss) = parse_status(&read_file("top", &dir.join("status")).ok()?)?;
+    Some(ProcessSample {
+        pid,
+        name,
+        ticks,
+        rss,
+    })
+}
+
+fn read_processes() -> Result<Vec<ProcessSample>> {
+    Ok(Path::new("/proc")
+        .read_dir()
+        .block_error("top", "failed to read /proc")?
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse::<u32>().ok())
+        .filter_map(read_process)
+        .collect())
+}
+
+/// Reads the time spent by all cpus, in clock ticks.
+fn read_total_ticks() -> Result<u64> {
+    let stat = read_file("top", Path::new("/proc/stat"))?;
+    let line = stat
+        .lines()
+        .next()
+        .block_error("top", "failed to read /proc/stat")?;
+    Ok(line
+        .split_whitespace()
+        .skip(1)
+        .filter_map(|x| x.parse::<u64>().ok())
+        .sum())
+}
+
+/// Reads the total amount of memory, in KiB.
+fn read_mem_total() -> Result<u64> {
+    let meminfo = read_file("top", Path::new("/proc/meminfo"))?;
+    meminfo
+        .lines()
+        .find_map(|line| line.strip_prefix("MemTotal:"))
+        .and_then(|value| {
+            value
+                .trim()
+                .trim_end_matches("kB")
+                .trim()
+                .parse::<u64>()
+                .ok()
+        })
+        .block_error("top", "failed to read MemTotal from /proc/meminfo")
+}
+
+pub struct Top {
+    text: TextWidget,
+    id: String,
+    update_interval: Duration,
+    criteria: TopCriteria,
+    format_cpu: FormatTemplate,
+    format_memory: FormatTemplate,
+    max_name_width: usize,
+    prev_total_ticks: u64,
+    prev_ticks: HashMap<u32, u64>,
+    tx_update_request: Sender<Task>,
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TopConfig {
+    /// Update interval in seconds
+    #[serde(
+        default = "TopConfig::default_interval",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub interval: Duration,
+
+    /// Whether to show the process using the most cpu or the most memory
+    #[serde(default)]
+    pub criteria: TopCriteria,
+
+    /// Format override
+    #[serde(default = "TopConfig::default_format")]
+    pub format: Option<String>,
+
+    /// Maximum number of characters of the process name to show
+    #[serde(default = "TopConfig::default_max_name_width")]
+    pub max_name_width: usize,
+
+    #[serde(default = "TopConfig::default_color_overrides")]
+    pub color_overrides: Option<BTreeMap<String, String>>,
+}
+
+impl TopConfig {
+    fn default_interval() -> Duration {
+        Duration::from_secs(5)
+    }
+
+    fn default_format() -> Option<String> {
+        None
+    }
+
+    fn default_max_name_width() -> usize {
+        15
+    }
+
+    fn default_color_overrides() -> Option<BTreeMap<String, String>> {
+        None
+    }
+}
+
+impl ConfigBlock for Top {
+    type Config = TopConfig;
+
+    fn new(
+        block_config: Self::Config,
+        config: Config,
+        tx_update_request: Sender<Task>,
+    ) -> Result<Self> {
+        let id = pseudo_uuid();
+        let text = TextWidget::new(config, &id).with_icon(match block_config.criteria {
+            TopCriteria::Cpu => "cpu",
+            TopCriteria::Memory => "memory_mem",
+        });
+
+        let (format_cpu, format_memory) = match block_config.format {
+            Some(ref format) => (format.as_str(), format.as_str()),
+            None => ("{name} {cpu}%", "{name} {mem}%"),
+        };
+
+        Ok(Top {
+            id,
+            update_interval: block_config.interval,
+            text,
+            criteria: block_config.criteria,
+            format_cpu: FormatTemplate::from_string(format_cpu)
+                .block_error("top", "Invalid format specified for top")?,
+            format_memory: FormatTemplate::from_string(format_memory)
+                .block_error("top", "Invalid format specified for top")?,
+            max_name_width: block_config.max_name_width,
+            prev_total_ticks: 0,
+            prev_ticks: HashMap::new(),
+            tx_update_request,
+        })
+    }
+}
+
+impl Block for Top {
+    fn update(&mut self) -> Result<Option<Update>> {
+        let total_ticks = read_total_ticks()?;
+        let mem_total = read_mem_total()?;
+        let processes = read_processes()?;
+
+        // The cpu usage is the share of the time spent by all cpus since the
+        // last update. Processes that were not around back then have no usage yet.
+        let total_delta = total_ticks.saturating_sub(self.prev_total_ticks);
+        let cpu_usage = |process: &ProcessSample| match self.prev_ticks.get(&process.pid) {
+            Some(&prev) if total_delta > 0 && self.prev_total_ticks > 0 => {
+                100.0 * process.ticks.saturating_sub(prev) as f64 / total_delta as f64
+            }
+            _ => 0.0,
+        };
+        let mem_usage = |process: &ProcessSample| {
+            if mem_total == 0 {
+                0.0
+            } else {
+                100.0 * process.rss as f64 / mem_total as f64
+            }
+        };
+
+        let top = match self.criteria {
+            TopCriteria::Cpu => processes
+                .iter()
+                .max_by(|a, b| cpu_usage(a).partial_cmp(&cpu_usage(b)).unwrap()),
+            TopCriteria::Memory => processes.iter().max_by_key(|process| process.rss),
+        };
+
+        let (name, cpu, mem) = match top {
+            Some(process) => (
+                process
+                    .name
+                    .chars()
+                    .take(self.max_name_width)
+                    .collect::<String>(),
+                format!("{:.0}", cpu_usage(process)),
+                format!("{:.0}", mem_usage(process)),
+            ),
+            None => ("N/A".to_string(), "0".to_string(), "0".to_string()),
+        };
+
+        self.prev_total_ticks = total_ticks;
+        self.prev_ticks = processes
+            .iter()
+            .map(|process| (process.pid, process.ticks))
+            .collect();
+
+        let values = map!("{name}" => name,
+                          "{cpu}" => cpu,
+                          "{mem}" => mem);
+        let format = match self.criteria {
+            TopCriteria::Cpu => &self.format_cpu,
+            TopCriteria::Memory => &self.format_memory,
+        };
+        self.text.set_text(format.render_static_str(&values)?);
+
+        Ok(Some(self.update_interval.into()))
+    }
+
+    fn view(&self) -> Vec<&dyn I3BarWidget> {
+        vec![&self.text]
+    }
+
+    fn click(&mut self, e: &I3BarEvent) -> Result<()> {
+        if e.matches_name(&self.id) && e.button == MouseButton::Left {
+            self.criteria = match self.criteria {
+                TopCriteria::Cpu => TopCriteria::Memory,
+                TopCriteria::Memory => TopCriteria::Cpu,
+            };
+            self.text.set_icon(match self.criteria {
+                TopCriteria::Cpu => "cpu",
+                TopCriteria::Memory => "memory_mem",
+            });
+            self.tx_update_request.send(Task {
+                id: self.id.clone(),
+                update_time: Instant::now(),
+            })?;
+        }
+
+        Ok(())
+    }
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_stat_ticks() {
+        let stat = "1234 (Web Content (1)) S 1 1234 1234 0 -1 4194560 \
+                    5000 0 0 0 120 30 0 0 20 0 30 0 5000 0 0";
+        assert_eq!(parse_stat_ticks(stat), Some(150));
+        assert_eq!(parse_stat_ticks("1234 (truncated) S 1"), None);
+    }
+
+    #[test]
+    fn test_parse_status() {
+        let status = "Name:\tfirefox\nUmask:\t0022\nState:\tS (sleeping)\nVmRSS:\t  524288 kB\n";
+        assert_eq!(parse_status(status), Some(("firefox".to_string(), 524288)));
+        assert_eq!(
+            parse_status("Name:\tkthreadd\nState:\tS (sleeping)\n"),
+            Some(("kthreadd".to_string(), 0))
+        );
+    }
+}
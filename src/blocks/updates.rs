@@ -0,0 +1,340 @@
+use std::collections::BTreeMap;
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::{unbounded, Receiver, RecvTimeoutError, Sender};
+use serde_derive::Deserialize;
+
+use crate::blocks::{apt, pacman};
+use crate::blocks::{Block, ConfigBlock, Update};
+use crate::config::Config;
+use crate::de::deserialize_duration;
+use crate::errors::*;
+use crate::input::{I3BarEvent, MouseButton};
+use crate::scheduler::Task;
+use crate::util::{pseudo_uuid, FormatTemplate};
+use crate::widget::{I3BarWidget, State};
+use crate::widgets::button::ButtonWidget;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum UpdatesBackend {
+    Pacman,
+    Apt,
+    Dnf,
+    Xbps,
+}
+
+impl Default for UpdatesBackend {
+    fn default() -> Self {
+        UpdatesBackend::Pacman
+    }
+}
+
+/// Counts the packages in the output of `dnf check-update`, which lists one
+/// package per line as `name.arch version repository`, possibly followed by
+/// the packages that are obsoleted.
+fn parse_dnf_updates(output: &str) -> usize {
+    output
+        .lines()
+        .take_while(|line| !line.starts_with("Obsoleting"))
+        .filter(|line| line.split_whitespace().count() == 3)
+        .count()
+}
+
+/// Counts the packages in the output of `xbps-install -nu`, which lists one
+/// package per line as `pkgver action arch repository ...`.
+fn parse_xbps_updates(output: &str) -> usize {
+    output
+        .lines()
+        .filter(|line| line.split_whitespace().nth(1) == Some("update"))
+        .count()
+}
+
+/// Runs a check and returns its output. If `exit_codes` are given, any other
+/// exit status is an error.
+fn run_check(program: &str, args: &[&str], exit_codes: Option<&[i32]>) -> Result<String> {
+    let output = Command::new(program)
+        .env("LC_ALL", "C")
+        .args(args)
+        .output()
+        .block_error("updates", &format!("Failed to run {}", program))?;
+    if let Some(exit_codes) = exit_codes {
+        if !output
+            .status
+            .code()
+            .map_or(false, |code| exit_codes.contains(&code))
+        {
+            return Err(BlockError(
+                "updates".to_owned(),
+                format!(
+                    "{} failed: {}",
+                    program,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ),
+            ));
+        }
+    }
+    String::from_utf8(output.stdout)
+        .block_error("updates", &format!("Non-UTF8 output of {}", program))
+}
+
+/// Checks for updates without touching the databases of the system.
+fn check_updates(backend: UpdatesBackend, apt_config: Option<&str>) -> Result<usize> {
+    match backend {
+        UpdatesBackend::Pacman => {
+            pacman::check_fakeroot_command_exists()?;
+            Ok(pacman::get_update_count(
+                &pacman::get_pacman_available_updates()?,
+            ))
+        }
+        UpdatesBackend::Apt => {
+            let config_path = apt_config.internal_error("updates", "no apt config")?;
+            Ok(apt::get_update_count(&apt::get_updates_list(config_path)?))
+        }
+        // dnf refreshes its metadata by itself as it gets stale. It exits
+        // with 100 if there are updates, and 0 if there are none.
+        UpdatesBackend::Dnf => Ok(parse_dnf_updates(&run_check(
+            "dnf",
+            &["check-update", "--quiet"],
+            Some(&[0, 100]),
+        )?)),
+        // Only syncs the repository index in memory
+        UpdatesBackend::Xbps => Ok(parse_xbps_updates(&run_check(
+            "xbps-install",
+            &["--memory-sync", "--dry-run", "--update"],
+            None,
+        )?)),
+    }
+}
+
+/// What the worker thread found out so far.
+#[derive(Debug, Default)]
+struct Status {
+    /// The number of updates of the last successful check
+    count: Option<usize>,
+    /// Whether a check is running
+    checking: bool,
+    /// Whether the last check failed
+    failed: bool,
+}
+
+/// Checks for updates in the background, as it may take tens of seconds, and
+/// requests an update of the block whenever a check starts or finishes.
+fn make_thread(
+    backend: UpdatesBackend,
+    apt_config: Option<String>,
+    interval: Duration,
+    status: Arc<Mutex<Status>>,
+    rx_check: Receiver<()>,
+    id: String,
+    tx_update_request: Sender<Task>,
+) {
+    let request_update = move || {
+        tx_update_request
+            .send(Task {
+                id: id.clone(),
+                update_time: Instant::now(),
+            })
+            .is_ok()
+    };
+
+    thread::Builder::new()
+        .name("updates".into())
+        .spawn(move || loop {
+            if let Ok(mut status) = status.lock() {
+                status.checking = true;
+            }
+            if !request_update() {
+                return;
+            }
+
+            let result = check_updates(backend, apt_config.as_deref());
+            if let Ok(mut status) = status.lock() {
+                status.checking = false;
+                status.failed = result.is_err();
+                if let Ok(count) = result {
+                    status.count = Some(count);
+                }
+            }
+            if !request_update() {
+                return;
+            }
+
+            match rx_check.recv_timeout(interval) {
+                // Clicking several times during a check only starts one more
+                Ok(()) => rx_check.try_iter().for_each(drop),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        })
+        .unwrap();
+}
+
+pub struct Updates {
+    output: ButtonWidget,
+    id: String,
+    format: FormatTemplate,
+    hide_when_zero: bool,
+    status: Arc<Mutex<Status>>,
+    tx_check: Sender<()>,
+    hidden: bool,
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct UpdatesConfig {
+    /// The package manager to check for updates with
+    #[serde(default)]
+    pub backend: UpdatesBackend,
+
+    /// Update interval in seconds
+    #[serde(
+        default = "UpdatesConfig::default_interval",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub interval: Duration,
+
+    /// Format override
+    #[serde(default = "UpdatesConfig::default_format")]
+    pub format: String,
+
+    /// Hide the block while there are no updates
+    #[serde(default = "UpdatesConfig::default_hide_when_zero")]
+    pub hide_when_zero: bool,
+
+    #[serde(default = "UpdatesConfig::default_color_overrides")]
+    pub color_overrides: Option<BTreeMap<String, String>>,
+}
+
+impl UpdatesConfig {
+    fn default_interval() -> Duration {
+        Duration::from_secs(60 * 60 * 6)
+    }
+
+    fn default_format() -> String {
+        "{count}".to_owned()
+    }
+
+    fn default_hide_when_zero() -> bool {
+        false
+    }
+
+    fn default_color_overrides() -> Option<BTreeMap<String, String>> {
+        None
+    }
+}
+
+impl ConfigBlock for Updates {
+    type Config = UpdatesConfig;
+
+    fn new(
+        block_config: Self::Config,
+        config: Config,
+        tx_update_request: Sender<Task>,
+    ) -> Result<Self> {
+        let id = pseudo_uuid();
+        let apt_config = match block_config.backend {
+            UpdatesBackend::Apt => Some(apt::create_apt_config()?),
+            _ => None,
+        };
+        let status = Arc::new(Mutex::new(Status::default()));
+        let (tx_check, rx_check) = unbounded();
+
+        make_thread(
+            block_config.backend,
+            apt_config,
+            block_config.interval,
+            status.clone(),
+            rx_check,
+            id.clone(),
+            tx_update_request,
+        );
+
+        Ok(Updates {
+            output: ButtonWidget::new(config, &id).with_icon("update"),
+            id,
+            format: FormatTemplate::from_string(&block_config.format)
+                .block_error("updates", "Invalid format specified for updates")?,
+            hide_when_zero: block_config.hide_when_zero,
+            status,
+            tx_check,
+            hidden: block_config.hide_when_zero,
+        })
+    }
+}
+
+impl Block for Updates {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn view(&self) -> Vec<&dyn I3BarWidget> {
+        if self.hidden {
+            vec![]
+        } else {
+            vec![&self.output]
+        }
+    }
+
+    fn update(&mut self) -> Result<Option<Update>> {
+        let (count, checking, failed) = {
+            let status = self
+                .status
+                .lock()
+                .block_error("updates", "mutex poisoned")?;
+            (status.count, status.checking, status.failed)
+        };
+
+        let values = map!("{count}" => count.map_or_else(|| "?".to_string(), |c| c.to_string()));
+        self.output
+            .set_text(self.format.render_static_str(&values)?);
+        // Show the last known count greyed out while checking
+        self.output.set_state(match count {
+            _ if checking => State::Idle,
+            _ if failed => State::Warning,
+            Some(0) | None => State::Idle,
+            Some(_) => State::Info,
+        });
+        self.hidden = self.hide_when_zero && count.unwrap_or(0) == 0 && !failed;
+
+        // Updates are requested by the checking thread
+        Ok(None)
+    }
+
+    fn click(&mut self, event: &I3BarEvent) -> Result<()> {
+        if event.matches_name(&self.id) && event.button == MouseButton::Left {
+            self.tx_check
+                .send(())
+                .block_error("updates", "checking thread exited")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_dnf_updates() {
+        let output = "\n\
+            firefox.x86_64                 87.0-1.fc33              updates\n\
+            kernel.x86_64                  5.11.10-200.fc33         updates\n\
+            Obsoleting Packages\n\
+            grub2-tools.x86_64             1:2.04-35.fc33           updates\n";
+        assert_eq!(parse_dnf_updates(output), 2);
+        assert_eq!(parse_dnf_updates(""), 0);
+    }
+
+    #[test]
+    fn test_parse_xbps_updates() {
+        let output = "firefox-87.0_1 update x86_64 https://repo-default.voidlinux.org/current 232 58\n\
+                      linux5.11-5.11.10_1 install x86_64 https://repo-default.voidlinux.org/current 1 2\n\
+                      glibc-2.32_2 update x86_64 https://repo-default.voidlinux.org/current 3 4\n";
+        assert_eq!(parse_xbps_updates(output), 2);
+    }
+}
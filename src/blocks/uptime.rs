@@ -7,17 +7,20 @@ use serde_derive::Deserialize;
 
 use crate::blocks::{Block, ConfigBlock, Update};
 use crate::config::Config;
-use crate::de::deserialize_duration;
+use crate::de::deserialize_opt_duration;
 use crate::errors::*;
 use crate::scheduler::Task;
-use crate::util::{pseudo_uuid, read_file};
+use crate::util::{pseudo_uuid, read_file, FormatTemplate};
 use crate::widget::I3BarWidget;
 use crate::widgets::text::TextWidget;
 
 pub struct Uptime {
     text: TextWidget,
     id: String,
-    update_interval: Duration,
+    update_interval: Option<Duration>,
+    format: FormatTemplate,
+    /// Whether the minutes are shown beyond the first day
+    shows_minutes: bool,
 
     //useful, but optional
     #[allow(dead_code)]
@@ -29,20 +32,31 @@ pub struct Uptime {
 #[derive(Deserialize, Debug, Default, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct UptimeConfig {
-    /// Update interval in seconds
+    /// Update interval in seconds. By default, the block is updated when
+    /// the shown minute or hour changes.
     #[serde(
         default = "UptimeConfig::default_interval",
-        deserialize_with = "deserialize_duration"
+        deserialize_with = "deserialize_opt_duration"
     )]
-    pub interval: Duration,
+    pub interval: Option<Duration>,
+
+    /// Format override
+    #[serde(default = "UptimeConfig::default_format")]
+    pub format: String,
+
     #[serde(default = "UptimeConfig::default_color_overrides")]
     pub color_overrides: Option<BTreeMap<String, String>>,
 }
 
 impl UptimeConfig {
-    fn default_interval() -> Duration {
-        Duration::from_secs(60)
+    fn default_interval() -> Option<Duration> {
+        None
     }
+
+    fn default_format() -> String {
+        "{uptime}".to_owned()
+    }
+
     fn default_color_overrides() -> Option<BTreeMap<String, String>> {
         None
     }
@@ -58,10 +72,14 @@ impl ConfigBlock for Uptime {
     ) -> Result<Self> {
         let id = pseudo_uuid();
         let text = TextWidget::new(config.clone(), &id).with_icon("uptime");
+        let format = FormatTemplate::from_string(&block_config.format)
+            .block_error("uptime", "Invalid format specified for uptime")?;
 
         Ok(Uptime {
             id,
             update_interval: block_config.interval,
+            shows_minutes: format.placeholders().contains(&"{minutes}"),
+            format,
             text,
             tx_update_request,
             config,
@@ -91,7 +109,7 @@ impl Block for Uptime {
         };
 
         let total_seconds = match uptime.parse::<f64>() {
-            Ok(uptime) => uptime as u32,
+            Ok(uptime) => uptime as u64,
             Err(e) => {
                 return Err(BlockError(
                     "Uptime".to_owned(),
@@ -100,33 +118,19 @@ impl Block for Uptime {
             }
         };
 
-        // split up seconds into more human readable portions
-        let weeks = (total_seconds / 604_800) as u32;
-        let rem_weeks = (total_seconds % 604_800) as u32;
-        let days = (rem_weeks / 86_400) as u32;
-        let rem_days = (rem_weeks % 86_400) as u32;
-        let hours = (rem_days / 3600) as u32;
-        let rem_hours = (rem_days % 3600) as u32;
-        let minutes = (rem_hours / 60) as u32;
-        let rem_minutes = (rem_hours % 60) as u32;
-        let seconds = rem_minutes as u32;
-
-        // Display the two largest units.
-        let text = if hours == 0 && days == 0 && weeks == 0 {
-            format!("{}m {}s", minutes, seconds)
-        } else if hours > 0 && days == 0 && weeks == 0 {
-            format!("{}h {}m", hours, minutes)
-        } else if days > 0 && weeks == 0 {
-            format!("{}d {}h", days, hours)
-        } else if days == 0 && weeks > 0 {
-            format!("{}w {}h", weeks, hours)
-        } else if weeks > 0 {
-            format!("{}w {}d", weeks, days)
-        } else {
-            unreachable!()
-        };
-        self.text.set_text(text);
-        Ok(Some(self.update_interval.into()))
+        let values = map!(
+            "{uptime}" => format_uptime(total_seconds),
+            "{days}" => (total_seconds / 86_400).to_string(),
+            "{hours}" => (total_seconds % 86_400 / 3600).to_string(),
+            "{minutes}" => (total_seconds % 3600 / 60).to_string()
+        );
+        self.text.set_text(self.format.render_static_str(&values)?);
+
+        Ok(Some(
+            self.update_interval
+                .unwrap_or_else(|| next_update(total_seconds, self.shows_minutes))
+                .into(),
+        ))
     }
 
     fn view(&self) -> Vec<&dyn I3BarWidget> {
@@ -137,3 +141,57 @@ impl Block for Uptime {
         &self.id
     }
 }
+
+/// Shows the two largest units, e.g. `37m`, `5h 12m` or `3d 4h`.
+fn format_uptime(total_seconds: u64) -> String {
+    let days = total_seconds / 86_400;
+    let hours = total_seconds % 86_400 / 3600;
+    let minutes = total_seconds % 3600 / 60;
+
+    if days > 0 {
+        format!("{}d {}h", days, hours)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else {
+        format!("{}m", minutes)
+    }
+}
+
+/// The time until the shown uptime changes: the next minute during the
+/// first day, and the next hour after that, unless the minutes are shown.
+fn next_update(total_seconds: u64, shows_minutes: bool) -> Duration {
+    let unit = if total_seconds < 86_400 || shows_minutes {
+        60
+    } else {
+        3600
+    };
+    Duration::from_secs(unit - total_seconds % unit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_uptime() {
+        assert_eq!(format_uptime(0), "0m");
+        assert_eq!(format_uptime(37 * 60 + 12), "37m");
+        assert_eq!(format_uptime(5 * 3600 + 12 * 60), "5h 12m");
+        assert_eq!(format_uptime(3 * 86_400 + 4 * 3600 + 59 * 60), "3d 4h");
+    }
+
+    #[test]
+    fn test_format_uptime_week_rollover() {
+        assert_eq!(format_uptime(7 * 86_400 - 1), "6d 23h");
+        assert_eq!(format_uptime(7 * 86_400), "7d 0h");
+        assert_eq!(format_uptime(15 * 86_400 + 3600), "15d 1h");
+    }
+
+    #[test]
+    fn test_next_update() {
+        assert_eq!(next_update(59, false), Duration::from_secs(1));
+        assert_eq!(next_update(3600, false), Duration::from_secs(60));
+        assert_eq!(next_update(86_400 + 60, false), Duration::from_secs(3540));
+        assert_eq!(next_update(86_400 + 60, true), Duration::from_secs(60));
+    }
+}
@@ -0,0 +1,278 @@
+use std::collections::BTreeMap;
+use std::fs::read_to_string;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::Sender;
+use serde_derive::Deserialize;
+
+use crate::blocks::{Block, ConfigBlock, Update};
+use crate::config::Config;
+use crate::de::deserialize_duration;
+use crate::errors::*;
+use crate::input::{I3BarEvent, MouseButton};
+use crate::scheduler::Task;
+use crate::subprocess::spawn_child_async;
+use crate::util::{pseudo_uuid, FormatTemplate};
+use crate::widget::{I3BarWidget, State};
+use crate::widgets::text::TextWidget;
+
+/// The IFF_UP bit of /sys/class/net/[interface]/flags
+const IFF_UP: u32 = 0x1;
+
+/// Whether the interface is a WireGuard interface or a tun device, as used by OpenVPN.
+fn is_vpn_interface(interface: &str) -> bool {
+    let path = Path::new("/sys/class/net").join(interface);
+    path.join("tun_flags").exists()
+        || match read_to_string(path.join("uevent")) {
+            Ok(uevent) => uevent.lines().any(|line| line == "DEVTYPE=wireguard"),
+            Err(_) => false,
+        }
+}
+
+/// Whether an interface is up, given the contents of its flags and operstate
+/// files. WireGuard and tun devices don't report a carrier, so their
+/// operstate is "unknown" while they are up.
+fn is_up(flags: &str, operstate: &str) -> bool {
+    let flags = flags.trim().trim_start_matches("0x");
+    match u32::from_str_radix(flags, 16) {
+        Ok(flags) => flags & IFF_UP != 0 && matches!(operstate.trim(), "up" | "unknown"),
+        Err(_) => false,
+    }
+}
+
+/// Whether the interface exists and is up. OpenVPN removes its tun device
+/// when it disconnects, so a missing interface is treated as being down.
+fn is_interface_up(interface: &str) -> bool {
+    let path = Path::new("/sys/class/net").join(interface);
+    match (
+        read_to_string(path.join("flags")),
+        read_to_string(path.join("operstate")),
+    ) {
+        (Ok(flags), Ok(operstate)) => is_up(&flags, &operstate),
+        _ => false,
+    }
+}
+
+/// Lists the WireGuard and tun interfaces, sorted by name.
+fn vpn_interfaces() -> Vec<String> {
+    let mut interfaces: Vec<String> = match Path::new("/sys/class/net").read_dir() {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .filter(|interface| is_vpn_interface(interface))
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    interfaces.sort();
+    interfaces
+}
+
+pub struct Vpn {
+    text: TextWidget,
+    id: String,
+    update_interval: Duration,
+    interfaces: Option<Vec<String>>,
+    format: FormatTemplate,
+    icon_connected: String,
+    icon_disconnected: String,
+    state_connected: State,
+    state_disconnected: State,
+    on_click_connect: Option<String>,
+    on_click_disconnect: Option<String>,
+    connected: bool,
+    tx_update_request: Sender<Task>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct VpnConfig {
+    /// Update interval in seconds
+    #[serde(
+        default = "VpnConfig::default_interval",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub interval: Duration,
+
+    /// The interfaces to check, in order. Defaults to all WireGuard and tun interfaces.
+    #[serde(default = "VpnConfig::default_interfaces")]
+    pub interfaces: Option<Vec<String>>,
+
+    /// Format override
+    #[serde(default = "VpnConfig::default_format")]
+    pub format: String,
+
+    /// Icon ID while a VPN is connected (default is "vpn_connected")
+    #[serde(default = "VpnConfig::default_icon_connected")]
+    pub icon_connected: String,
+
+    /// Icon ID while no VPN is connected (default is "vpn_disconnected")
+    #[serde(default = "VpnConfig::default_icon_disconnected")]
+    pub icon_disconnected: String,
+
+    /// State while a VPN is connected
+    #[serde(default = "VpnConfig::default_state_connected")]
+    pub state_connected: State,
+
+    /// State while no VPN is connected
+    #[serde(default = "VpnConfig::default_state_disconnected")]
+    pub state_disconnected: State,
+
+    /// Shell command to run when clicking the block while no VPN is connected
+    #[serde(default = "VpnConfig::default_on_click")]
+    pub on_click_connect: Option<String>,
+
+    /// Shell command to run when clicking the block while a VPN is connected
+    #[serde(default = "VpnConfig::default_on_click")]
+    pub on_click_disconnect: Option<String>,
+
+    #[serde(default = "VpnConfig::default_color_overrides")]
+    pub color_overrides: Option<BTreeMap<String, String>>,
+}
+
+impl VpnConfig {
+    fn default_interval() -> Duration {
+        Duration::from_secs(5)
+    }
+
+    fn default_interfaces() -> Option<Vec<String>> {
+        None
+    }
+
+    fn default_format() -> String {
+        "{name}".to_owned()
+    }
+
+    fn default_icon_connected() -> String {
+        "vpn_connected".to_owned()
+    }
+
+    fn default_icon_disconnected() -> String {
+        "vpn_disconnected".to_owned()
+    }
+
+    fn default_state_connected() -> State {
+        State::Good
+    }
+
+    fn default_state_disconnected() -> State {
+        State::Idle
+    }
+
+    fn default_on_click() -> Option<String> {
+        None
+    }
+
+    fn default_color_overrides() -> Option<BTreeMap<String, String>> {
+        None
+    }
+}
+
+impl ConfigBlock for Vpn {
+    type Config = VpnConfig;
+
+    fn new(
+        block_config: Self::Config,
+        config: Config,
+        tx_update_request: Sender<Task>,
+    ) -> Result<Self> {
+        let id = pseudo_uuid();
+        let text = TextWidget::new(config, &id).with_icon(&block_config.icon_disconnected);
+
+        Ok(Vpn {
+            id,
+            update_interval: block_config.interval,
+            text,
+            interfaces: block_config.interfaces,
+            format: FormatTemplate::from_string(&block_config.format)
+                .block_error("vpn", "Invalid format specified for vpn")?,
+            icon_connected: block_config.icon_connected,
+            icon_disconnected: block_config.icon_disconnected,
+            state_connected: block_config.state_connected,
+            state_disconnected: block_config.state_disconnected,
+            on_click_connect: block_config.on_click_connect,
+            on_click_disconnect: block_config.on_click_disconnect,
+            connected: false,
+            tx_update_request,
+        })
+    }
+}
+
+impl Block for Vpn {
+    fn update(&mut self) -> Result<Option<Update>> {
+        let interfaces = match self.interfaces {
+            Some(ref interfaces) => interfaces.clone(),
+            None => vpn_interfaces(),
+        };
+
+        // Show the first interface that is up, or the first one to be
+        // checked if none is.
+        let up = interfaces
+            .iter()
+            .find(|interface| is_interface_up(interface));
+        self.connected = up.is_some();
+        let name = up
+            .or_else(|| interfaces.first())
+            .cloned()
+            .unwrap_or_default();
+
+        let (icon, state, status) = if self.connected {
+            (&self.icon_connected, self.state_connected, "connected")
+        } else {
+            (
+                &self.icon_disconnected,
+                self.state_disconnected,
+                "disconnected",
+            )
+        };
+        self.text.set_icon(icon);
+        self.text.set_state(state);
+
+        let values = map!("{name}" => name,
+                          "{status}" => status.to_string());
+        self.text.set_text(self.format.render_static_str(&values)?);
+
+        Ok(Some(self.update_interval.into()))
+    }
+
+    fn view(&self) -> Vec<&dyn I3BarWidget> {
+        vec![&self.text]
+    }
+
+    fn click(&mut self, e: &I3BarEvent) -> Result<()> {
+        if e.matches_name(&self.id) && e.button == MouseButton::Left {
+            let cmd = if self.connected {
+                &self.on_click_disconnect
+            } else {
+                &self.on_click_connect
+            };
+            if let Some(cmd) = cmd {
+                spawn_child_async("sh", &["-c", cmd])
+                    .block_error("vpn", "could not spawn child")?;
+                self.tx_update_request.send(Task {
+                    id: self.id.clone(),
+                    update_time: Instant::now(),
+                })?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_up() {
+        assert!(is_up("0x1091\n", "unknown\n"));
+        assert!(is_up("0x1003\n", "up\n"));
+        assert!(!is_up("0x1090\n", "down\n"));
+        assert!(!is_up("0x1091\n", "down\n"));
+        assert!(!is_up("invalid", "up"));
+    }
+}
@@ -1,7 +1,8 @@
 use std::collections::BTreeMap;
 use std::fs::File;
-use std::io::BufReader;
+use std::io::{BufReader, ErrorKind};
 use std::path::PathBuf;
+use std::process::Command;
 use std::thread;
 use std::time::{Duration, Instant};
 
@@ -10,22 +11,23 @@ use crate::config::Config;
 use crate::de::deserialize_duration;
 use crate::de::deserialize_local_timestamp;
 use crate::errors::*;
-use crate::input::I3BarEvent;
+use crate::input::{I3BarEvent, MouseButton};
 use crate::scheduler::Task;
-use crate::util::{pseudo_uuid, xdg_config_home};
+use crate::util::{pseudo_uuid, xdg_config_home, FormatTemplate};
 use crate::widget::{I3BarWidget, State};
 use crate::widgets::button::ButtonWidget;
 use chrono::offset::Local;
 use chrono::DateTime;
 use crossbeam_channel::Sender;
-use inotify::{EventMask, Inotify, WatchMask};
+use inotify::{Inotify, WatchMask};
 use serde_derive::Deserialize;
 
 pub struct Watson {
     id: String,
     text: ButtonWidget,
     state_path: PathBuf,
-    show_time: bool,
+    format: FormatTemplate,
+    idle_text: String,
     prev_state: Option<WatsonState>,
     update_interval: Duration,
 }
@@ -42,7 +44,13 @@ pub struct WatsonConfig {
         deserialize_with = "deserialize_duration"
     )]
     pub interval: Duration,
-    /// Show time spent
+    /// Format override
+    #[serde(default = "WatsonConfig::default_format")]
+    pub format: Option<String>,
+    /// Text shown while not tracking time
+    #[serde(default = "WatsonConfig::default_idle_text")]
+    pub idle_text: String,
+    /// Show time spent, if no format is set
     #[serde(default = "WatsonConfig::default_show_time")]
     pub show_time: bool,
 
@@ -59,6 +67,12 @@ impl WatsonConfig {
     fn default_interval() -> Duration {
         Duration::from_secs(60)
     }
+    fn default_format() -> Option<String> {
+        None
+    }
+    fn default_idle_text() -> String {
+        String::new()
+    }
     fn default_show_time() -> bool {
         false
     }
@@ -67,6 +81,55 @@ impl WatsonConfig {
     }
 }
 
+/// Requests an update whenever watson saves its state.
+fn watch_state(state_path: PathBuf, id: String, tx_update_request: Sender<Task>) -> Result<()> {
+    // Split filepath into filename and parent directory
+    let file_name = state_path
+        .file_name()
+        .block_error("watson", "watson state file had no name")?
+        .to_owned();
+    let parent_dir = state_path
+        .parent()
+        .block_error("watson", "watson state file had no directory")?
+        .to_owned();
+    let mut notify = Inotify::init().block_error("watson", "failed to start inotify")?;
+
+    // We have to watch the parent directory because watson never modifies the state file,
+    // but rather write to a temporary file, ensures its not corrupted, backups the
+    // previous state file and then renames the new state file. This means that we're
+    // looking for `MOVED_TO` events with the name of the state file, or `CREATE` if it
+    // is written directly.
+    notify
+        .add_watch(&parent_dir, WatchMask::CREATE | WatchMask::MOVED_TO)
+        .block_error("watson", "failed to watch watson state file")?;
+
+    thread::Builder::new()
+        .name("watson".into())
+        .spawn(move || {
+            let mut buffer = [0; 1024];
+            loop {
+                let events = match notify.read_events_blocking(&mut buffer) {
+                    Ok(events) => events,
+                    Err(_) => return,
+                };
+                if events
+                    .into_iter()
+                    .any(|event| event.name == Some(&file_name))
+                    && tx_update_request
+                        .send(Task {
+                            id: id.clone(),
+                            update_time: Instant::now(),
+                        })
+                        .is_err()
+                {
+                    return;
+                }
+            }
+        })
+        .block_error("watson", "failed to start watching thread")?;
+    Ok(())
+}
+
 impl ConfigBlock for Watson {
     type Config = WatsonConfig;
 
@@ -77,98 +140,119 @@ impl ConfigBlock for Watson {
     ) -> Result<Self> {
         let id = pseudo_uuid();
 
+        let show_time = block_config.show_time;
+        let format = block_config.format.unwrap_or_else(|| {
+            if show_time {
+                "{project} {tags} {elapsed}".to_owned()
+            } else {
+                "{project} {tags}".to_owned()
+            }
+        });
+
         let watson = Watson {
             id: id.clone(),
             text: ButtonWidget::new(config, &id),
             state_path: block_config.state_path.clone(),
-            show_time: block_config.show_time,
+            format: FormatTemplate::from_string(&format)
+                .block_error("watson", "Invalid format specified for watson")?,
+            idle_text: block_config.idle_text,
             update_interval: block_config.interval,
             prev_state: None,
         };
 
-        // Spin up a thread to watch for changes to the brightness file for the
-        // device, and schedule an update if needed.
-        thread::spawn(move || {
-            // Split filepath into filename and parent directory
-            let (file_name, parent_dir) = {
-                let name = block_config
-                    .state_path
-                    .file_name()
-                    .expect("watson state file had no name")
-                    .to_owned();
-
-                let mut s = block_config.state_path;
-                s.pop();
-                (name, s)
-            };
-            let mut notify = Inotify::init().expect("failed to start inotify");
-
-            // We have to watch the parent directory because watson never modifies the state file,
-            // but rather write to a temporary file, ensures its not corrupted, backups the
-            // previous state file and then renames the new state file. This means that we're
-            // always looking for `CREATE` events with the name of the state file.
-            notify
-                .add_watch(&parent_dir, WatchMask::CREATE)
-                .expect("failed to watch watson state file");
+        watch_state(block_config.state_path, id, tx_update_request)?;
 
-            let mut buffer = [0; 1024];
-            loop {
-                let events = notify
-                    .read_events_blocking(&mut buffer)
-                    .expect("error while reading inotify events");
+        Ok(watson)
+    }
+}
 
-                for event in events {
-                    match event.mask {
-                        EventMask::CREATE if event.name == Some(&file_name) => {
-                            tx_update_request
-                                .send(Task {
-                                    id: id.clone(),
-                                    update_time: Instant::now(),
-                                })
-                                .expect("unable to send task from watson watcher");
-                        }
-                        _ => {}
-                    }
-                }
+impl Watson {
+    fn read_state(&self) -> Result<WatsonState> {
+        let file = match File::open(&self.state_path) {
+            Ok(file) => BufReader::new(file),
+            // watson hasn't been used yet
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(WatsonState::Idle {}),
+            Err(_) => {
+                return Err(BlockError(
+                    "watson".to_owned(),
+                    "unable to open state file".to_owned(),
+                ))
             }
-        });
+        };
+        serde_json::from_reader(file).block_error("watson", "unable to deserialize state")
+    }
 
-        Ok(watson)
+    fn render(&self, state: &WatsonState) -> Result<String> {
+        if let WatsonState::Active {
+            project,
+            start,
+            tags,
+        } = state
+        {
+            let tags = if tags.is_empty() {
+                String::new()
+            } else {
+                format!("[{}]", tags.join(" "))
+            };
+            let values = map!(
+                "{project}" => project.clone(),
+                "{tags}" => tags,
+                "{elapsed}" => format_elapsed(&(Local::now() - *start))
+            );
+            Ok(self
+                .format
+                .render_static_str(&values)?
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" "))
+        } else {
+            Ok(self.idle_text.clone())
+        }
+    }
+
+    /// Stops the current project, or restarts the last one.
+    fn toggle(&self, active: bool) -> Result<()> {
+        let args: &[&str] = if active { &["stop"] } else { &["restart"] };
+        let output = Command::new("watson")
+            .args(args)
+            .output()
+            .block_error("watson", "Failed to run watson")?;
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(BlockError(
+                "watson".to_owned(),
+                String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+            ))
+        }
     }
 }
 
 impl Block for Watson {
     fn update(&mut self) -> Result<Option<Update>> {
-        let state = {
-            let file = BufReader::new(
-                File::open(&self.state_path).block_error("watson", "unable to open state file")?,
-            );
-            serde_json::from_reader(file).block_error("watson", "unable to deserialize state")?
-        };
+        let state = self.read_state()?;
 
         match state {
             state @ WatsonState::Active { .. } => {
                 self.text.set_state(State::Good);
-                self.text
-                    .set_text(state.format(self.show_time, "started", format_delta_past));
+                self.text.set_text(self.render(&state)?);
 
                 self.prev_state = Some(state);
-                Ok(if self.show_time {
-                    // regular updates if time is enabled
+                Ok(if self.format.placeholders().contains(&"{elapsed}") {
+                    // regular updates if time is shown
                     Some(self.update_interval.into())
                 } else {
                     None
                 })
             }
             WatsonState::Idle {} => {
-                if let Some(prev_state @ WatsonState::Active { .. }) = &self.prev_state {
+                if let Some(WatsonState::Active { project, start, .. }) = &self.prev_state {
                     // The previous state was active, which means that we just now stopped the time
                     // tracking. This means that we could show some statistics.
-                    self.show_time = true;
-                    self.text.set_text(prev_state.format(
-                        self.show_time,
-                        "stopped",
-                        format_delta_after,
+                    self.text.set_text(format!(
+                        "{} stopped {}",
+                        project,
+                        format_delta_after(&(Local::now() - *start))
                     ));
                     self.text.set_state(State::Idle);
                     self.prev_state = Some(state);
@@ -179,27 +263,24 @@ impl Block for Watson {
                     // File is empty which means that there is currently no active time tracking,
                     // and the previous state wasn't time tracking neither so we reset the
                     // contents.
-                    self.show_time = false;
                     self.text.set_state(State::Idle);
-                    self.text.set_text(String::new());
+                    self.text.set_text(self.render(&state)?);
 
                     self.prev_state = Some(state);
-                    Ok(if self.show_time {
-                        // regular updates if time is enabled
-                        Some(Duration::from_secs(60).into())
-                    } else {
-                        None
-                    })
+                    Ok(None)
                 }
             }
         }
     }
 
     fn click(&mut self, e: &I3BarEvent) -> Result<()> {
-        if let Some(ref name) = e.name {
-            if name.as_str() == self.id {
-                self.show_time = !self.show_time;
-                self.update()?;
+        if e.matches_name(&self.id) && e.button == MouseButton::Left {
+            let active = matches!(self.prev_state, Some(WatsonState::Active { .. }));
+            let toggled = self.toggle(active);
+            self.update()?;
+            if let Err(BlockError(_, message)) = toggled {
+                self.text.set_text(message);
+                self.text.set_state(State::Critical);
             }
         }
         Ok(())
@@ -214,20 +295,14 @@ impl Block for Watson {
     }
 }
 
-fn format_delta_past(delta: &chrono::Duration) -> String {
-    let spans = &[
-        ("week", delta.num_weeks()),
-        ("day", delta.num_days()),
-        ("hour", delta.num_hours()),
-        ("minute", delta.num_minutes()),
-    ];
-
-    spans
-        .iter()
-        .filter(|&(_, n)| *n != 0)
-        .map(|&(label, n)| format!("{} {}{} ago", n, label, if n > 1 { "s" } else { "" }))
-        .next()
-        .unwrap_or_else(|| "now".into())
+/// Formats the time spent as e.g. `45m` or `1h 05m`.
+fn format_elapsed(delta: &chrono::Duration) -> String {
+    let minutes = delta.num_minutes().max(0);
+    if minutes >= 60 {
+        format!("{}h {:02}m", minutes / 60, minutes % 60)
+    } else {
+        format!("{}m", minutes)
+    }
 }
 
 fn format_delta_after(delta: &chrono::Duration) -> String {
@@ -260,32 +335,14 @@ enum WatsonState {
     Idle {},
 }
 
-impl WatsonState {
-    fn format(&self, show_time: bool, verb: &str, f: fn(&chrono::Duration) -> String) -> String {
-        if let WatsonState::Active {
-            project,
-            start,
-            tags,
-        } = self
-        {
-            let mut s = String::with_capacity(16);
-            s.push_str(project);
-            if !tags.is_empty() {
-                s.push(' ');
-                s.push('[');
-                s.push_str(&tags.join(" "));
-                s.push(']');
-            }
-            if show_time {
-                s.push(' ');
-                s.push_str(verb);
-                let delta = Local::now() - *start;
-                s.push(' ');
-                s.push_str(&f(&delta));
-            }
-            s
-        } else {
-            panic!("WatsonState::Idle does not have a specified format")
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_elapsed() {
+        assert_eq!(format_elapsed(&chrono::Duration::minutes(45)), "45m");
+        assert_eq!(format_elapsed(&chrono::Duration::minutes(65)), "1h 05m");
+        assert_eq!(format_elapsed(&chrono::Duration::seconds(-5)), "0m");
     }
 }
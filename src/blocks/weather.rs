@@ -1,8 +1,11 @@
+use std::cmp;
 use std::collections::{BTreeMap, HashMap};
 use std::env;
-use std::time::Duration;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 
-use crossbeam_channel::Sender;
+use crossbeam_channel::{unbounded, Receiver, RecvTimeoutError, Sender};
 use serde_derive::Deserialize;
 
 use crate::blocks::{Block, ConfigBlock, Update};
@@ -20,6 +23,9 @@ const OPENWEATHERMAP_API_KEY_ENV: &str = "OPENWEATHERMAP_API_KEY";
 const OPENWEATHERMAP_CITY_ID_ENV: &str = "OPENWEATHERMAP_CITY_ID";
 const OPENWEATHERMAP_PLACE_ENV: &str = "OPENWEATHERMAP_PLACE";
 
+/// The weather services are not queried more often than this
+const MIN_INTERVAL: Duration = Duration::from_secs(600);
+
 #[derive(Clone, Debug, Deserialize)]
 #[serde(tag = "name", rename_all = "lowercase")]
 pub enum WeatherService {
@@ -33,6 +39,12 @@ pub enum WeatherService {
         coordinates: Option<(String, String)>,
         units: OpenWeatherMapUnits,
     },
+    Wttr {
+        /// Located by wttr.in itself if not set
+        place: Option<String>,
+        #[serde(default = "WeatherService::default_wttr_units")]
+        units: OpenWeatherMapUnits,
+    },
 }
 
 impl WeatherService {
@@ -45,6 +57,9 @@ impl WeatherService {
     fn getenv_openweathermap_place() -> Option<String> {
         env::var(OPENWEATHERMAP_PLACE_ENV).ok()
     }
+    fn default_wttr_units() -> OpenWeatherMapUnits {
+        OpenWeatherMapUnits::Metric
+    }
 }
 
 #[derive(Copy, Clone, Debug, Deserialize, PartialEq)]
@@ -57,11 +72,25 @@ pub enum OpenWeatherMapUnits {
 pub struct Weather {
     id: String,
     weather: ButtonWidget,
-    format: String,
-    weather_keys: HashMap<String, String>,
-    service: WeatherService,
-    update_interval: Duration,
-    autolocate: bool,
+    format: FormatTemplate,
+    status: Arc<Mutex<Status>>,
+    tx_refresh: Sender<()>,
+}
+
+/// The weather at some point in time.
+struct Reading {
+    icon: &'static str,
+    keys: HashMap<String, String>,
+    time: Instant,
+}
+
+/// What the worker thread found out so far.
+#[derive(Default)]
+struct Status {
+    /// The last successful reading
+    reading: Option<Reading>,
+    /// The error of the last query, if it failed
+    error: Option<Error>,
 }
 
 fn malformed_json_error() -> Error {
@@ -79,7 +108,8 @@ fn find_ip_location() -> Result<Option<String>> {
     let city = http_call_result
         .content
         .pointer("/city")
-        .map(|v| v.to_string());
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string());
 
     Ok(city)
 }
@@ -139,152 +169,309 @@ fn convert_wind_direction(direction_opt: Option<f64>) -> String {
     }
 }
 
-fn configuration_error(msg: &str) -> Result<()> {
-    Err(ConfigurationError(
-        "weather".to_owned(),
-        (msg.to_owned(), msg.to_owned()),
-    ))
+fn configuration_error(msg: &str) -> Error {
+    ConfigurationError("weather".to_owned(), (msg.to_owned(), msg.to_owned()))
 }
 
-impl Weather {
-    fn update_weather(&mut self) -> Result<()> {
-        match &self.service {
-            WeatherService::OpenWeatherMap {
-                api_key: api_key_opt,
-                city_id,
-                place,
-                units,
-                coordinates,
-            } => {
-                if api_key_opt.is_none() {
-                    return configuration_error(&format!(
-                        "Missing member 'service.api_key'. Add the member or configure with the environment variable {}",
-                        OPENWEATHERMAP_API_KEY_ENV.to_string()));
-                }
+/// The icon for a condition reported by OpenWeatherMap.
+fn openweathermap_icon(weather: &str) -> &'static str {
+    match weather {
+        "Clear" => "weather_sun",
+        "Rain" | "Drizzle" => "weather_rain",
+        "Clouds" | "Fog" | "Mist" => "weather_clouds",
+        "Thunderstorm" => "weather_thunder",
+        "Snow" => "weather_snow",
+        _ => "weather_default",
+    }
+}
 
-                let api_key = api_key_opt.as_ref().unwrap();
-
-                let geoip_city = if self.autolocate {
-                    find_ip_location().ok().unwrap_or(None) // If geo location fails, try other configuration methods
-                } else {
-                    None
-                };
-
-                let location_query = if let Some(city) = geoip_city {
-                    format!("q={}", city)
-                } else if let Some(cid) = city_id.as_ref() {
-                    format!("id={}", cid)
-                } else if let Some(p) = place.as_ref() {
-                    format!("q={}", p)
-                } else if let Some((lat, lon)) = coordinates {
-                    format!("lat={}&lon={}", lat, lon)
-                } else if self.autolocate {
-                    return configuration_error(
-                        "weather is configured to use geolocation, but it could not be obtained",
-                    );
-                } else {
-                    return configuration_error(&format!(
-                        "Either 'service.city_id' or 'service.place' must be provided. Add one to your config file or set with the environment variables {} or {}",
-                        OPENWEATHERMAP_CITY_ID_ENV.to_string(),
-                        OPENWEATHERMAP_PLACE_ENV.to_string()));
-                };
-
-                let openweather_url = &format!(
-                    "https://api.openweathermap.org/data/2.5/weather?{location_query}&appid={api_key}&units={units}",
-                    location_query = location_query,
-                    api_key = api_key,
-                    units = match *units {
-                        OpenWeatherMapUnits::Metric => "metric",
-                        OpenWeatherMapUnits::Imperial => "imperial",
-                    },
-                );
-
-                let output =
-                    http::http_get_json(openweather_url, Some(Duration::from_secs(3)), vec![])?;
-
-                // All 300-399 and >500 http codes should be considered as temporary error,
-                // and not result in block error, i.e. leave the output empty.
-                if (output.code >= 300 && output.code < 400) || output.code >= 500 {
-                    return Err(BlockError(
-                        "weather".to_owned(),
-                        format!("Invalid result from curl: {}", output.code),
-                    ));
-                };
-
-                let json = output.content;
-
-                // Try to convert an API error into a block error.
-                if let Some(val) = json.get("message") {
-                    return Err(BlockError(
-                        "weather".to_string(),
-                        format!("API Error: {}", val.as_str().unwrap()),
-                    ));
-                };
-
-                let raw_weather = json
-                    .pointer("/weather/0/main")
-                    .ok_or_else(malformed_json_error)?
-                    .to_string();
-
-                let raw_temp = json
-                    .pointer("/main/temp")
-                    .and_then(|v| v.as_f64())
-                    .ok_or_else(malformed_json_error)?;
-
-                let raw_humidity = json
-                    .pointer("/main/humidity")
-                    .map_or(Some(0.0), |v| v.as_f64()) // provide default value 0.0
-                    .ok_or_else(malformed_json_error)?;
-
-                let raw_wind_speed: f64 = json
-                    .pointer("/wind/speed")
-                    .map_or(Some(0.0), |v| v.as_f64()) // provide default value 0.0
-                    .ok_or_else(malformed_json_error)?; // error when conversion to f64 fails
-
-                let raw_wind_direction: Option<f64> = json
-                    .pointer("/wind/deg")
-                    .map_or(Some(None), |v| v.as_f64().map(Some)) // provide default value None
-                    .ok_or_else(malformed_json_error)?; // error when conversion to f64 fails
-
-                let raw_location = json
-                    .pointer("/name")
-                    .and_then(|v| v.as_str())
-                    .map(|s| s.to_string())
-                    .ok_or_else(malformed_json_error)?;
-
-                self.weather.set_icon(match raw_weather.as_str() {
-                    "Clear" => "weather_sun",
-                    "Rain" | "Drizzle" => "weather_rain",
-                    "Clouds" | "Fog" | "Mist" => "weather_clouds",
-                    "Thunderstorm" => "weather_thunder",
-                    "Snow" => "weather_snow",
-                    _ => "weather_default",
-                });
-
-                let kmh_wind_speed = if *units == OpenWeatherMapUnits::Metric {
-                    raw_wind_speed * 3600.0 / 1000.0
-                } else {
-                    // convert mph to m/s, then km/h
-                    (raw_wind_speed * 0.447) * 3600.0 / 1000.0
-                };
-
-                let apparent_temp =
-                    australian_apparent_temp(raw_temp, raw_humidity, raw_wind_speed, *units);
-
-                self.weather_keys = map_to_owned!("{weather}" => raw_weather,
+/// The icon for a weather code reported by wttr.in, which uses the codes of
+/// WorldWeatherOnline.
+fn wttr_icon(code: &str) -> &'static str {
+    match code {
+        "113" => "weather_sun",
+        "116" | "119" | "122" | "143" | "248" | "260" => "weather_clouds",
+        "200" | "386" | "389" | "392" | "395" => "weather_thunder",
+        "179" | "182" | "185" | "227" | "230" | "281" | "284" | "311" | "314" | "317" | "320"
+        | "323" | "326" | "329" | "332" | "335" | "338" | "350" | "362" | "365" | "368" | "371"
+        | "374" | "377" => "weather_snow",
+        "176" | "263" | "266" | "293" | "296" | "299" | "302" | "305" | "308" | "353" | "356"
+        | "359" => "weather_rain",
+        _ => "weather_default",
+    }
+}
+
+/// Queries the weather service. This may block for a few seconds.
+fn fetch_weather(service: &WeatherService, autolocate: bool) -> Result<Reading> {
+    let geoip_city = if autolocate {
+        find_ip_location().ok().unwrap_or(None) // If geo location fails, try other configuration methods
+    } else {
+        None
+    };
+
+    match service {
+        WeatherService::OpenWeatherMap {
+            api_key: api_key_opt,
+            city_id,
+            place,
+            units,
+            coordinates,
+        } => {
+            if api_key_opt.is_none() {
+                return Err(configuration_error(&format!(
+                    "Missing member 'service.api_key'. Add the member or configure with the environment variable {}",
+                    OPENWEATHERMAP_API_KEY_ENV.to_string())));
+            }
+
+            let api_key = api_key_opt.as_ref().unwrap();
+
+            let location_query = if let Some(city) = geoip_city {
+                format!("q={}", city)
+            } else if let Some(cid) = city_id.as_ref() {
+                format!("id={}", cid)
+            } else if let Some(p) = place.as_ref() {
+                format!("q={}", p)
+            } else if let Some((lat, lon)) = coordinates {
+                format!("lat={}&lon={}", lat, lon)
+            } else if autolocate {
+                return Err(configuration_error(
+                    "weather is configured to use geolocation, but it could not be obtained",
+                ));
+            } else {
+                return Err(configuration_error(&format!(
+                    "Either 'service.city_id' or 'service.place' must be provided. Add one to your config file or set with the environment variables {} or {}",
+                    OPENWEATHERMAP_CITY_ID_ENV.to_string(),
+                    OPENWEATHERMAP_PLACE_ENV.to_string())));
+            };
+
+            let openweather_url = &format!(
+                "https://api.openweathermap.org/data/2.5/weather?{location_query}&appid={api_key}&units={units}",
+                location_query = location_query,
+                api_key = api_key,
+                units = match *units {
+                    OpenWeatherMapUnits::Metric => "metric",
+                    OpenWeatherMapUnits::Imperial => "imperial",
+                },
+            );
+
+            let output =
+                http::http_get_json(openweather_url, Some(Duration::from_secs(3)), vec![])?;
+
+            // All 300-399 and >500 http codes should be considered as temporary error,
+            // and not result in block error, i.e. leave the output empty.
+            if (output.code >= 300 && output.code < 400) || output.code >= 500 {
+                return Err(BlockError(
+                    "weather".to_owned(),
+                    format!("Invalid result from curl: {}", output.code),
+                ));
+            };
+
+            let json = output.content;
+
+            // Try to convert an API error into a block error.
+            if let Some(val) = json.get("message") {
+                return Err(BlockError(
+                    "weather".to_string(),
+                    format!("API Error: {}", val.as_str().unwrap()),
+                ));
+            };
+
+            let raw_weather = json
+                .pointer("/weather/0/main")
+                .and_then(|v| v.as_str())
+                .ok_or_else(malformed_json_error)?
+                .to_string();
+
+            let raw_temp = json
+                .pointer("/main/temp")
+                .and_then(|v| v.as_f64())
+                .ok_or_else(malformed_json_error)?;
+
+            let raw_humidity = json
+                .pointer("/main/humidity")
+                .map_or(Some(0.0), |v| v.as_f64()) // provide default value 0.0
+                .ok_or_else(malformed_json_error)?;
+
+            let raw_wind_speed: f64 = json
+                .pointer("/wind/speed")
+                .map_or(Some(0.0), |v| v.as_f64()) // provide default value 0.0
+                .ok_or_else(malformed_json_error)?; // error when conversion to f64 fails
+
+            let raw_wind_direction: Option<f64> = json
+                .pointer("/wind/deg")
+                .map_or(Some(None), |v| v.as_f64().map(Some)) // provide default value None
+                .ok_or_else(malformed_json_error)?; // error when conversion to f64 fails
+
+            let raw_location = json
+                .pointer("/name")
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string())
+                .ok_or_else(malformed_json_error)?;
+
+            let kmh_wind_speed = if *units == OpenWeatherMapUnits::Metric {
+                raw_wind_speed * 3600.0 / 1000.0
+            } else {
+                // convert mph to m/s, then km/h
+                (raw_wind_speed * 0.447) * 3600.0 / 1000.0
+            };
+
+            let apparent_temp =
+                australian_apparent_temp(raw_temp, raw_humidity, raw_wind_speed, *units);
+
+            Ok(Reading {
+                icon: openweathermap_icon(&raw_weather),
+                keys: map_to_owned!("{weather}" => raw_weather,
                                   "{temp}" => format!("{:.0}", raw_temp),
                                   "{humidity}" => format!("{:.0}", raw_humidity),
                                   "{apparent}" => format!("{:.0}",apparent_temp),
                                   "{wind}" => format!("{:.1}", raw_wind_speed),
                                   "{wind_kmh}" => format!("{:.1}", kmh_wind_speed),
                                   "{direction}" => convert_wind_direction(raw_wind_direction),
-                                  "{location}" => raw_location);
-                Ok(())
-            }
+                                  "{location}" => raw_location),
+                time: Instant::now(),
+            })
+        }
+        WeatherService::Wttr { place, units } => {
+            let location = geoip_city
+                .as_ref()
+                .or_else(|| place.as_ref())
+                .map_or_else(String::new, |place| place.replace(' ', "+"));
+            let output = http::http_get_json(
+                &format!("https://wttr.in/{}?format=j1", location),
+                Some(Duration::from_secs(10)),
+                vec![],
+            )?;
+
+            if output.code != 200 {
+                return Err(BlockError(
+                    "weather".to_owned(),
+                    format!("Invalid result from curl: {}", output.code),
+                ));
+            };
+
+            let json = output.content;
+            let current = json
+                .pointer("/current_condition/0")
+                .ok_or_else(malformed_json_error)?;
+            // wttr.in reports all numbers as strings
+            let number = |key: &str| -> Result<f64> {
+                current
+                    .get(key)
+                    .and_then(|v| v.as_str())
+                    .and_then(|v| v.parse().ok())
+                    .ok_or_else(malformed_json_error)
+            };
+            let metric = *units == OpenWeatherMapUnits::Metric;
+
+            let raw_weather = current
+                .pointer("/weatherDesc/0/value")
+                .and_then(|v| v.as_str())
+                .ok_or_else(malformed_json_error)?
+                .trim()
+                .to_string();
+            let code = current
+                .get("weatherCode")
+                .and_then(|v| v.as_str())
+                .unwrap_or_default();
+            let raw_location = json
+                .pointer("/nearest_area/0/areaName/0/value")
+                .and_then(|v| v.as_str())
+                .unwrap_or_default()
+                .to_string();
+            let kmh_wind_speed = number("windspeedKmph")?;
+            let (temp, apparent_temp, wind_speed) = if metric {
+                (
+                    number("temp_C")?,
+                    number("FeelsLikeC")?,
+                    kmh_wind_speed * 1000.0 / 3600.0,
+                )
+            } else {
+                (
+                    number("temp_F")?,
+                    number("FeelsLikeF")?,
+                    number("windspeedMiles")?,
+                )
+            };
+
+            Ok(Reading {
+                icon: wttr_icon(code),
+                keys: map_to_owned!("{weather}" => raw_weather,
+                                  "{temp}" => format!("{:.0}", temp),
+                                  "{humidity}" => format!("{:.0}", number("humidity")?),
+                                  "{apparent}" => format!("{:.0}", apparent_temp),
+                                  "{wind}" => format!("{:.1}", wind_speed),
+                                  "{wind_kmh}" => format!("{:.1}", kmh_wind_speed),
+                                  "{direction}" => convert_wind_direction(number("winddirDegree").ok()),
+                                  "{location}" => raw_location),
+                time: Instant::now(),
+            })
         }
     }
 }
 
+/// Formats the age of a reading, e.g. `25min` or `3h`.
+fn format_age(age: Duration) -> String {
+    let minutes = age.as_secs() / 60;
+    match minutes {
+        0..=59 => format!("{}min", minutes),
+        60..=1439 => format!("{}h", minutes / 60),
+        _ => format!("{}d", minutes / 60 / 24),
+    }
+}
+
+/// Queries the weather service in the background, as it may take a few
+/// seconds, and requests an update of the block after each query.
+fn make_thread(
+    service: WeatherService,
+    autolocate: bool,
+    interval: Duration,
+    status: Arc<Mutex<Status>>,
+    rx_refresh: Receiver<()>,
+    id: String,
+    tx_update_request: Sender<Task>,
+) {
+    thread::Builder::new()
+        .name("weather".into())
+        .spawn(move || loop {
+            let fetched = Instant::now();
+            let result = fetch_weather(&service, autolocate);
+            if let Ok(mut status) = status.lock() {
+                match result {
+                    Ok(reading) => {
+                        status.reading = Some(reading);
+                        status.error = None;
+                    }
+                    Err(error) => status.error = Some(error),
+                }
+            }
+            if tx_update_request
+                .send(Task {
+                    id: id.clone(),
+                    update_time: Instant::now(),
+                })
+                .is_err()
+            {
+                return;
+            }
+
+            // A refresh that was requested too soon after the last query is
+            // put off until the service may be queried again
+            let mut next_fetch = fetched + interval;
+            loop {
+                let timeout = next_fetch.saturating_duration_since(Instant::now());
+                match rx_refresh.recv_timeout(timeout) {
+                    Ok(()) => {
+                        rx_refresh.try_iter().for_each(drop);
+                        next_fetch = cmp::min(next_fetch, fetched + MIN_INTERVAL);
+                    }
+                    Err(RecvTimeoutError::Timeout) => break,
+                    Err(RecvTimeoutError::Disconnected) => return,
+                }
+            }
+        })
+        .unwrap();
+}
+
 #[derive(Deserialize, Debug, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct WeatherConfig {
@@ -326,42 +513,73 @@ impl ConfigBlock for Weather {
     fn new(
         block_config: Self::Config,
         config: Config,
-        _tx_update_request: Sender<Task>,
+        tx_update_request: Sender<Task>,
     ) -> Result<Self> {
         let id = pseudo_uuid();
+        let format = FormatTemplate::from_string(&block_config.format)?;
+        let status = Arc::new(Mutex::new(Status::default()));
+        let (tx_refresh, rx_refresh) = unbounded();
+
+        make_thread(
+            block_config.service,
+            block_config.autolocate,
+            cmp::max(block_config.interval, MIN_INTERVAL),
+            status.clone(),
+            rx_refresh,
+            id.clone(),
+            tx_update_request,
+        );
+
         Ok(Weather {
             id: id.clone(),
-            weather: ButtonWidget::new(config, &id),
-            format: block_config.format,
-            weather_keys: HashMap::new(),
-            service: block_config.service,
-            update_interval: block_config.interval,
-            autolocate: block_config.autolocate,
+            weather: ButtonWidget::new(config, &id).with_icon("weather_default"),
+            format,
+            status,
+            tx_refresh,
         })
     }
 }
 
 impl Block for Weather {
     fn update(&mut self) -> Result<Option<Update>> {
-        match self.update_weather() {
-            Ok(_) => {
-                let fmt = FormatTemplate::from_string(&self.format)?;
-                self.weather.set_text(fmt.render(&self.weather_keys));
+        let status = self
+            .status
+            .lock()
+            .block_error("weather", "mutex poisoned")?;
+
+        match (&status.reading, &status.error) {
+            (Some(reading), None) => {
+                self.weather.set_icon(reading.icon);
+                self.weather.set_text(self.format.render(&reading.keys));
                 self.weather.set_state(State::Idle)
             }
-            Err(BlockError(block, _)) | Err(InternalError(block, _, _)) if block == "curl" => {
+            (Some(reading), Some(_)) => {
+                // Keep showing the last reading, along with its age
+                self.weather.set_icon(reading.icon);
+                self.weather.set_text(format!(
+                    "{} ({})",
+                    self.format.render(&reading.keys),
+                    format_age(reading.time.elapsed())
+                ));
+                self.weather.set_state(State::Warning)
+            }
+            (None, Some(BlockError(block, _))) | (None, Some(InternalError(block, _, _)))
+                if block == "curl" =>
+            {
                 // Ignore curl/api errors
-                self.weather.set_icon("weather_default");
                 self.weather.set_text("×".to_string());
                 self.weather.set_state(State::Warning)
             }
-            Err(err) => {
+            (None, Some(err)) => {
                 self.weather.set_text(format!("weather error {}:", err));
                 self.weather.set_state(State::Critical);
             }
+            // Not queried yet
+            (None, None) => {}
         }
 
-        Ok(Some(self.update_interval.into()))
+        // Updates are requested by the querying thread
+        Ok(None)
     }
 
     fn view(&self) -> Vec<&dyn I3BarWidget> {
@@ -371,7 +589,9 @@ impl Block for Weather {
     fn click(&mut self, event: &I3BarEvent) -> Result<()> {
         if event.matches_name(self.id()) {
             if let MouseButton::Left = event.button {
-                self.update()?;
+                self.tx_refresh
+                    .send(())
+                    .block_error("weather", "weather thread exited")?;
             }
         }
         Ok(())
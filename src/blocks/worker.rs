@@ -0,0 +1,464 @@
+//! Runs every block on a thread of its own, so that a slow update only holds
+//! up its own block. Many blocks hold D-Bus connections, which can't be moved
+//! between threads, so every block is created on its thread and stays there.
+//! One more thread greys out the blocks whose updates take longer than their
+//! `timeout`.
+
+use std::cmp;
+use std::collections::BinaryHeap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::{Receiver, RecvTimeoutError, Sender};
+use toml::value::Value;
+
+use crate::blocks::{create_block, Block, Update};
+use crate::config::Config;
+use crate::errors::*;
+use crate::input::I3BarEvent;
+use crate::scheduler::Task;
+use crate::themes::Theme;
+use crate::util::interpolate_colors;
+use crate::widget::I3BarWidget;
+
+/// Work for the thread of a block
+enum Command {
+    Update,
+    Click(I3BarEvent),
+    Signal(i32),
+    SetPaused(bool),
+    Shutdown,
+}
+
+/// What the thread of a block reports back, along with the widgets it shows
+enum Reply {
+    /// An update finished
+    Updated(Result<Option<Update>>, Vec<serde_json::Value>),
+    /// A click or signal was handled
+    Handled(Result<()>, Vec<serde_json::Value>),
+    /// The update with the given number takes longer than the `timeout`
+    Stale(u64),
+}
+
+/// A block as created on its thread: where its replies go, its theme, its
+/// widgets and its timeout
+type Created = (
+    Arc<Outbox>,
+    Option<Theme>,
+    Vec<serde_json::Value>,
+    Option<Duration>,
+);
+
+/// Where the results of a block go
+struct Outbox {
+    id: String,
+    replies: Sender<Reply>,
+    ready: Sender<String>,
+    /// The number of updates which have finished
+    finished: AtomicU64,
+}
+
+impl Outbox {
+    /// Sends a reply, and the id of the block so that the bar picks it up.
+    /// Fails once the bar is gone.
+    fn send(&self, reply: Reply) -> bool {
+        self.replies.send(reply).is_ok() && self.ready.send(self.id.clone()).is_ok()
+    }
+}
+
+/// An update which is reported once it takes longer than the `timeout`
+struct Deadline {
+    time: Instant,
+    number: u64,
+    outbox: Arc<Outbox>,
+}
+
+impl cmp::PartialEq for Deadline {
+    fn eq(&self, other: &Deadline) -> bool {
+        self.time.eq(&other.time)
+    }
+}
+
+impl cmp::Eq for Deadline {}
+
+impl cmp::PartialOrd for Deadline {
+    fn partial_cmp(&self, other: &Deadline) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl cmp::Ord for Deadline {
+    fn cmp(&self, other: &Deadline) -> cmp::Ordering {
+        other.time.cmp(&self.time)
+    }
+}
+
+/// A widget as rendered on the thread of its block
+struct RenderedWidget(serde_json::Value);
+
+impl I3BarWidget for RenderedWidget {
+    fn to_string(&self) -> String {
+        self.0.to_string()
+    }
+
+    fn get_rendered(&self) -> &serde_json::Value {
+        &self.0
+    }
+}
+
+fn render(block: &dyn Block) -> Vec<serde_json::Value> {
+    block
+        .view()
+        .iter()
+        .map(|widget| widget.get_rendered().clone())
+        .collect()
+}
+
+/// Greys out the text of a widget by mixing its color with its background.
+fn grey_out(widget: &mut serde_json::Value, theme: &Theme) {
+    let fg = widget["color"]
+        .as_str()
+        .map(str::to_owned)
+        .or_else(|| theme.idle_fg.clone());
+    let bg = widget["background"]
+        .as_str()
+        .map(str::to_owned)
+        .or_else(|| theme.idle_bg.clone());
+    let grey = match (fg, bg) {
+        (Some(fg), Some(bg)) => interpolate_colors(&fg, &bg, 0.5).ok(),
+        _ => None,
+    };
+    widget["color"] = serde_json::Value::String(grey.unwrap_or_else(|| "#808080".to_owned()));
+}
+
+/// Runs a command for a block on its thread. Returns whether the block is
+/// still running, which it isn't once it is shut down or the bar is gone.
+fn run_command(block: &mut dyn Block, outbox: &Outbox, command: Command) -> bool {
+    let reply = match command {
+        Command::Update => {
+            let result = block.update();
+            outbox.finished.fetch_add(1, Ordering::SeqCst);
+            Reply::Updated(result, render(block))
+        }
+        Command::Click(event) => {
+            let result = block.click(&event);
+            Reply::Handled(result, render(block))
+        }
+        // Blocks request an update on their signal, so only errors are
+        // reported
+        Command::Signal(signal) => match block.signal(signal) {
+            Ok(()) => return true,
+            Err(error) => Reply::Handled(Err(error), render(block)),
+        },
+        Command::SetPaused(paused) => {
+            block.set_paused(paused);
+            return true;
+        }
+        Command::Shutdown => {
+            block.shutdown();
+            return false;
+        }
+    };
+    outbox.send(reply)
+}
+
+/// Runs the commands for a block on its thread, until the block is shut down
+/// or the bar is gone.
+fn run_block(block: &mut dyn Block, outbox: &Outbox, commands: Receiver<Command>) {
+    for command in commands {
+        if !run_command(block, outbox, command) {
+            return;
+        }
+    }
+}
+
+/// Reports the updates which are still running once their timeout passes.
+fn watch_timeouts(deadlines: Receiver<Deadline>) {
+    let mut pending = BinaryHeap::new();
+    loop {
+        let received = match pending.peek() {
+            Some(Deadline { time, .. }) => {
+                deadlines.recv_timeout(time.saturating_duration_since(Instant::now()))
+            }
+            None => deadlines.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match received {
+            Ok(deadline) => pending.push(deadline),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+
+        let now = Instant::now();
+        while pending
+            .peek()
+            .map_or(false, |deadline| deadline.time <= now)
+        {
+            let deadline = pending.pop().unwrap();
+            if deadline.outbox.finished.load(Ordering::SeqCst) < deadline.number {
+                deadline.outbox.send(Reply::Stale(deadline.number));
+            }
+        }
+    }
+}
+
+/// Starts the threads of the blocks of a config, which exit once their blocks
+/// are gone.
+pub struct BlockThreads {
+    deadlines: Sender<Deadline>,
+    ready: Sender<String>,
+}
+
+impl BlockThreads {
+    /// Starts the timeout thread. Once an update, click or signal of a block
+    /// was handled, its id is sent on the ready channel.
+    pub fn new(ready: Sender<String>) -> Result<Self> {
+        let (tx_deadlines, rx_deadlines) = crossbeam_channel::unbounded();
+        thread::Builder::new()
+            .name("timeout".into())
+            .spawn(move || watch_timeouts(rx_deadlines))
+            .internal_error("worker", "failed to start the timeout thread")?;
+
+        Ok(BlockThreads {
+            deadlines: tx_deadlines,
+            ready,
+        })
+    }
+
+    /// Creates a block on a thread of its own.
+    pub fn create_block(
+        &self,
+        name: &str,
+        block_config: Value,
+        config: Config,
+        update_request: Sender<Task>,
+    ) -> Result<ThreadedBlock> {
+        let (tx_commands, rx_commands) = crossbeam_channel::unbounded();
+        let (tx_replies, rx_replies) = crossbeam_channel::unbounded();
+        let (tx_created, rx_created) = crossbeam_channel::bounded(1);
+        let bar_theme = config.theme.clone();
+        let thread_name = name.to_owned();
+        let ready = self.ready.clone();
+        thread::Builder::new()
+            .name(name.into())
+            .spawn(move || {
+                let mut block =
+                    match create_block(&thread_name, block_config, config, update_request) {
+                        Ok(block) => block,
+                        Err(error) => {
+                            let _ = tx_created.send(Err(error));
+                            return;
+                        }
+                    };
+                let outbox = Arc::new(Outbox {
+                    id: block.id().to_owned(),
+                    replies: tx_replies,
+                    ready,
+                    finished: AtomicU64::new(0),
+                });
+                let created = (
+                    outbox.clone(),
+                    block.theme().cloned(),
+                    render(&*block),
+                    block.timeout(),
+                );
+                if tx_created.send(Ok(created)).is_ok() {
+                    run_block(&mut *block, &outbox, rx_commands);
+                }
+            })
+            .internal_error("worker", "failed to start the thread of a block")?;
+
+        let (outbox, theme, widgets, timeout): Created = rx_created
+            .recv()
+            .internal_error("worker", "the thread of a block stopped")??;
+
+        Ok(ThreadedBlock {
+            id: outbox.id.clone(),
+            theme: theme.unwrap_or(bar_theme),
+            widgets: widgets.into_iter().map(RenderedWidget).collect(),
+            commands: tx_commands,
+            replies: rx_replies,
+            deadlines: timeout.map(|timeout| (timeout, self.deadlines.clone())),
+            outbox,
+            started: 0,
+            running: None,
+            queued: false,
+        })
+    }
+}
+
+/// A block which is created and updated on a thread of its own. Its updates
+/// only start the update on that thread, and once it finishes the id of the block
+/// is sent on the ready channel, after which `poll` shows the result.
+pub struct ThreadedBlock {
+    id: String,
+    theme: Theme,
+    widgets: Vec<RenderedWidget>,
+    commands: Sender<Command>,
+    replies: Receiver<Reply>,
+    /// The deadlines of the running updates, if the block has a `timeout`
+    deadlines: Option<(Duration, Sender<Deadline>)>,
+    outbox: Arc<Outbox>,
+    /// The number of updates started so far
+    started: u64,
+    /// The number of the running update
+    running: Option<u64>,
+    /// Whether another update was requested while one was running
+    queued: bool,
+}
+
+impl ThreadedBlock {
+    fn send(&self, command: Command) -> Result<()> {
+        self.commands
+            .send(command)
+            .internal_error("worker", "the thread of a block stopped")
+    }
+
+    fn start_update(&mut self) -> Result<()> {
+        self.send(Command::Update)?;
+        self.started += 1;
+        self.running = Some(self.started);
+        if let Some((timeout, ref deadlines)) = self.deadlines {
+            deadlines
+                .send(Deadline {
+                    time: Instant::now() + timeout,
+                    number: self.started,
+                    outbox: self.outbox.clone(),
+                })
+                .internal_error("worker", "the timeout thread stopped")?;
+        }
+        Ok(())
+    }
+}
+
+impl Block for ThreadedBlock {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn view(&self) -> Vec<&dyn I3BarWidget> {
+        self.widgets
+            .iter()
+            .map(|widget| widget as &dyn I3BarWidget)
+            .collect()
+    }
+
+    fn update(&mut self) -> Result<Option<Update>> {
+        // Only one update runs at a time, and one more follows it if requested
+        // in the meantime
+        if self.running.is_some() {
+            self.queued = true;
+        } else {
+            self.start_update()?;
+        }
+        // The next update is scheduled once this one finishes
+        Ok(None)
+    }
+
+    fn poll(&mut self) -> Result<Option<Update>> {
+        let mut next_update = None;
+        let replies: Vec<Reply> = self.replies.try_iter().collect();
+        for reply in replies {
+            match reply {
+                Reply::Updated(result, widgets) => {
+                    self.running = None;
+                    self.widgets = widgets.into_iter().map(RenderedWidget).collect();
+                    next_update = result?;
+                    if self.queued {
+                        self.queued = false;
+                        self.start_update()?;
+                    }
+                }
+                Reply::Handled(result, widgets) => {
+                    self.widgets = widgets.into_iter().map(RenderedWidget).collect();
+                    result?;
+                }
+                Reply::Stale(number) => {
+                    if self.running == Some(number) {
+                        for widget in &mut self.widgets {
+                            grey_out(&mut widget.0, &self.theme);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(next_update)
+    }
+
+    fn signal(&mut self, signal: i32) -> Result<()> {
+        self.send(Command::Signal(signal))
+    }
+
+    fn click(&mut self, event: &I3BarEvent) -> Result<()> {
+        self.send(Command::Click(event.clone()))
+    }
+
+    fn theme(&self) -> Option<&Theme> {
+        Some(&self.theme)
+    }
+
+    fn shutdown(&mut self) {
+        let _ = self.send(Command::Shutdown);
+    }
+
+    fn set_paused(&mut self, paused: bool) {
+        let _ = self.send(Command::SetPaused(paused));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grey_out() {
+        let mut widget = json!({"full_text": "x", "color": "#FFFFFF", "background": "#000000"});
+        grey_out(&mut widget, &Theme::default());
+        assert_eq!(widget["color"], "#808080FF");
+
+        // Without any colors, the text is still greyed out
+        let theme = Theme {
+            idle_fg: None,
+            ..Theme::default()
+        };
+        let mut widget = json!({"full_text": "x", "color": null, "background": null});
+        grey_out(&mut widget, &theme);
+        assert_eq!(widget["color"], "#808080");
+    }
+
+    #[test]
+    fn test_watch_timeouts() {
+        let (tx_deadlines, rx_deadlines) = crossbeam_channel::unbounded();
+        let (tx_replies, rx_replies) = crossbeam_channel::unbounded();
+        let (tx_ready, rx_ready) = crossbeam_channel::unbounded();
+        let outbox = Arc::new(Outbox {
+            id: "block".to_owned(),
+            replies: tx_replies,
+            ready: tx_ready,
+            finished: AtomicU64::new(1),
+        });
+        let now = Instant::now();
+        for (delay, number) in &[(40, 3), (20, 2), (10, 1)] {
+            tx_deadlines
+                .send(Deadline {
+                    time: now + Duration::from_millis(*delay),
+                    number: *number,
+                    outbox: outbox.clone(),
+                })
+                .unwrap();
+        }
+        thread::spawn(move || watch_timeouts(rx_deadlines));
+
+        // Only the updates which haven't finished are reported, in the order
+        // of their deadlines
+        let stale: Vec<u64> = (0..2)
+            .map(|_| match rx_replies.recv().unwrap() {
+                Reply::Stale(number) => number,
+                _ => panic!("unexpected reply"),
+            })
+            .collect();
+        assert_eq!(stale, vec![2, 3]);
+        assert_eq!(rx_ready.try_iter().count(), 2);
+    }
+}
@@ -215,7 +215,7 @@ impl Xrandr {
                 if self.icons {
                     "{display} {brightness_icon} {brightness} {res_icon} {resolution}"
                 } else {
-                    "{display}: {brightness} [{resolution}]"
+                    "{display}: {brightness} \\[{resolution}\\]"
                 }
             } else if self.icons {
                 "{display} {brightness_icon} {brightness}"
@@ -1,7 +1,12 @@
 use std::collections::HashMap as Map;
+use std::env;
+use std::fmt;
+use std::fs::{read_dir, read_to_string};
+use std::iter::once;
 use std::marker::PhantomData;
 use std::ops::Deref;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::process::Command;
 use std::str::FromStr;
 
 use serde::de::{Deserialize, Deserializer, Error};
@@ -9,9 +14,10 @@ use serde_derive::Deserialize;
 use toml::value;
 
 use crate::de::*;
+use crate::errors::{ConfigurationError, ResultExtInternal};
 use crate::input::MouseButton;
 use crate::themes::{Theme, ThemeConfig};
-use crate::util::deserialize_file;
+use crate::widget::Markup;
 use crate::{errors, icons};
 
 #[derive(Deserialize, Debug, Clone)]
@@ -29,6 +35,25 @@ pub struct Config {
     pub scrolling: Scrolling,
     #[serde(rename = "block", deserialize_with = "deserialize_blocks")]
     pub blocks: Vec<(String, value::Value)>,
+    /// The markup of the block's widgets, set by its `markup` option
+    #[serde(skip)]
+    pub markup: Markup,
+    /// Whether the block's colors follow a gradient, set by its `gradient` option
+    #[serde(skip)]
+    pub gradient: bool,
+    /// The file each of `blocks` is defined in, set by `load_config`
+    #[serde(skip)]
+    pub block_locations: Vec<BlockLocation>,
+}
+
+impl Config {
+    /// Describes where the block at `index` is defined, for error messages.
+    pub fn block_location(&self, index: usize) -> String {
+        match self.block_locations.get(index) {
+            Some(location) => location.to_string(),
+            None => format!("block #{}", index + 1),
+        }
+    }
 }
 
 impl Default for Config {
@@ -38,6 +63,9 @@ impl Default for Config {
             theme: Theme::default(),
             scrolling: Scrolling::default(),
             blocks: Vec::new(),
+            markup: Markup::default(),
+            gradient: false,
+            block_locations: Vec::new(),
         }
     }
 }
@@ -52,10 +80,27 @@ impl From<LegacyConfig> for Config {
                 .unwrap_or_default(),
             scrolling: legacy_config.scrolling,
             blocks: legacy_config.blocks,
+            markup: Markup::default(),
+            gradient: false,
+            block_locations: Vec::new(),
         }
     }
 }
 
+/// Where a block is defined when the config is split across several files
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockLocation {
+    pub file: PathBuf,
+    /// The position of the block within its file
+    pub index: usize,
+}
+
+impl fmt::Display for BlockLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "block #{} of {}", self.index + 1, self.file.display())
+    }
+}
+
 #[derive(Deserialize, Debug, Clone)]
 pub struct LegacyConfig {
     #[serde(default = "icons::default", deserialize_with = "deserialize_icons")]
@@ -140,7 +185,10 @@ where
     map_type!(Icons, String;
               s => Ok(Icons(icons::get_icons(s).ok_or(format!("cannot find icon set called '{}'", s))?)));
 
-    deserializer.deserialize_any(MapType::<Icons, String>(PhantomData, PhantomData))
+    // Overrides without a `name` are layered on top of the default set
+    let mut icons = icons::default();
+    icons.extend(deserializer.deserialize_any(MapType::<Icons, String>(PhantomData, PhantomData))?);
+    Ok(icons)
 }
 
 fn deserialize_themes<'de, D>(deserializer: D) -> Result<Theme, D::Error>
@@ -154,16 +202,187 @@ where
 
 // this function may belong somewhere else...
 pub fn load_config(config_path: &Path) -> errors::Result<Config> {
-    let config: errors::Result<Config> = deserialize_file(config_path.to_str().unwrap());
-    config.or_else(|_| {
-        let legacy_config: errors::Result<LegacyConfig> =
-            deserialize_file(config_path.to_str().unwrap());
-        legacy_config.map(|legacy| legacy.into())
-    })
+    let mut block_locations = Vec::new();
+    let table = read_config(config_path, &mut Vec::new(), &mut block_locations)?;
+    let value = value::Value::Table(table);
+    let mut config = Config::deserialize(value.clone())
+        .or_else(|_| LegacyConfig::deserialize(value).map(Config::from))
+        .configuration_error("failed to parse TOML from file contents")?;
+    config.block_locations = block_locations;
+
+    for index in 0..config.blocks.len() {
+        let location = config.block_location(index);
+        let (name, block_config) = &mut config.blocks[index];
+        if let value::Value::Table(options) = block_config {
+            for (option, value) in options.iter_mut() {
+                substitute(value, option).map_err(|e| e.in_block(&location, name))?;
+            }
+        }
+    }
+
+    Ok(config)
+}
+
+/// Replaces values written as `{ env = "VAR" }` with the environment variable,
+/// and `{ cmd = "..." }` with the first line printed by the command, in the
+/// value of `option` and anything nested in it.
+fn substitute(value: &mut value::Value, option: &str) -> errors::Result<()> {
+    if let Some(substituted) = substitution(value, option)? {
+        *value = value::Value::String(substituted);
+        return Ok(());
+    }
+    match value {
+        value::Value::Table(table) => {
+            for (key, value) in table.iter_mut() {
+                substitute(value, &format!("{}.{}", option, key))?;
+            }
+        }
+        value::Value::Array(values) => {
+            for (index, value) in values.iter_mut().enumerate() {
+                substitute(value, &format!("{}[{}]", option, index))?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn substitution(value: &value::Value, option: &str) -> errors::Result<Option<String>> {
+    let (kind, arg) = match value.as_table() {
+        Some(table) if table.len() == 1 => match table.iter().next() {
+            Some((kind, value::Value::String(arg))) => (kind.as_str(), arg),
+            _ => return Ok(None),
+        },
+        _ => return Ok(None),
+    };
+    let failed = |reason: String| {
+        ConfigurationError(
+            format!("failed to substitute `{}`", option),
+            (reason, String::new()),
+        )
+    };
+    match kind {
+        "env" => env::var(arg)
+            .map(Some)
+            .map_err(|_| failed(format!("environment variable {} is not set", arg))),
+        "cmd" => {
+            let output = Command::new("sh")
+                .args(&["-c", arg])
+                .output()
+                .map_err(|e| failed(format!("failed to run '{}': {}", arg, e)))?;
+            if !output.status.success() {
+                return Err(failed(format!("command '{}' failed", arg)));
+            }
+            // Like `pass`, the secret is the first line
+            Ok(Some(
+                String::from_utf8_lossy(&output.stdout)
+                    .lines()
+                    .next()
+                    .unwrap_or_default()
+                    .to_owned(),
+            ))
+        }
+        _ => Ok(None),
+    }
 }
+
+/// Reads a config file along with the files it includes. The blocks of the
+/// included files are appended to its own in order, while its other settings
+/// take precedence over theirs. `including` holds the files which (indirectly)
+/// include this one, to detect cycles.
+fn read_config(
+    path: &Path,
+    including: &mut Vec<PathBuf>,
+    block_locations: &mut Vec<BlockLocation>,
+) -> errors::Result<value::Table> {
+    let canonical = path
+        .canonicalize()
+        .configuration_error(&format!("failed to open config file {}", path.display()))?;
+    if including.contains(&canonical) {
+        let cycle: Vec<String> = including
+            .iter()
+            .chain(once(&canonical))
+            .map(|file| file.display().to_string())
+            .collect();
+        return Err(ConfigurationError(
+            "config files include each other".to_owned(),
+            (cycle.join(" -> "), String::new()),
+        ));
+    }
+
+    let contents = read_to_string(path)
+        .configuration_error(&format!("failed to read config file {}", path.display()))?;
+    let mut table: value::Table = toml::from_str(&contents)
+        .configuration_error(&format!("failed to parse TOML in {}", path.display()))?;
+
+    // Entries without a block name are skipped by `deserialize_blocks` as well
+    if let Some(value::Value::Array(blocks)) = table.get("block") {
+        let named = blocks
+            .iter()
+            .enumerate()
+            .filter(|(_, block)| block.get("block").and_then(|b| b.as_str()).is_some());
+        block_locations.extend(named.map(|(index, _)| BlockLocation {
+            file: path.to_path_buf(),
+            index,
+        }));
+    }
+
+    let includes: Vec<String> = match table.remove("include") {
+        None => Vec::new(),
+        Some(value::Value::String(include)) => vec![include],
+        Some(include) => Vec::deserialize(include).configuration_error(&format!(
+            "`include` in {} must be a path or a list of paths",
+            path.display()
+        ))?,
+    };
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    including.push(canonical);
+    for include in includes {
+        for file in include_files(&dir.join(include))? {
+            let included = read_config(&file, including, block_locations)?;
+            merge_config(&mut table, included);
+        }
+    }
+    including.pop();
+
+    Ok(table)
+}
+
+/// The files to include for an `include` path, which is either a file or a
+/// directory whose `.toml` files are included in alphabetical order.
+fn include_files(path: &Path) -> errors::Result<Vec<PathBuf>> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut files = read_dir(path)
+        .configuration_error(&format!("failed to read directory {}", path.display()))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|file| file.is_file() && file.extension().map_or(false, |ext| ext == "toml"))
+        .collect::<Vec<_>>();
+    files.sort();
+    Ok(files)
+}
+
+fn merge_config(table: &mut value::Table, included: value::Table) {
+    for (key, value) in included {
+        match table.get_mut(&key) {
+            Some(value::Value::Array(blocks)) if key == "block" => {
+                if let value::Value::Array(included_blocks) = value {
+                    blocks.extend(included_blocks);
+                }
+            }
+            Some(_) => {}
+            None => {
+                table.insert(key, value);
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::config::load_config;
+    use crate::config::{load_config, BlockLocation};
+    use crate::icons;
     use assert_fs::prelude::{FileWriteStr, PathChild};
     use assert_fs::TempDir;
 
@@ -209,4 +428,151 @@ mod tests {
         let config = load_config(config_file_path.path());
         config.unwrap();
     }
+
+    #[test]
+    fn test_load_config_icon_overrides() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_file_path = temp_dir.child("status.toml");
+        config_file_path
+            .write_str(
+                concat!(
+                    "[icons.overrides]\n",
+                    "bat_charging = \"+\"\n",
+                    "my_icon = \"*\"\n",
+                    "[[block]]\n",
+                    "block = \"load\"\n",
+                )
+                .as_ref(),
+            )
+            .unwrap();
+        let config = load_config(config_file_path.path()).unwrap();
+        assert_eq!(config.icons["bat_charging"], "+");
+        assert_eq!(config.icons["bat"], icons::NONE["bat"]);
+        assert_eq!(icons::unknown_icons(&config.icons), vec!["my_icon"]);
+    }
+
+    #[test]
+    fn test_load_config_include() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_file_path = temp_dir.child("status.toml");
+        config_file_path
+            .write_str(
+                concat!(
+                    "include = [\"blocks/net.toml\", \"conf.d\"]\n",
+                    "[[block]]\n",
+                    "block = \"load\"\n",
+                )
+                .as_ref(),
+            )
+            .unwrap();
+        temp_dir
+            .child("blocks/net.toml")
+            .write_str(
+                concat!(
+                    "include = \"../common.toml\"\n",
+                    "[[block]]\n",
+                    "block = \"net\"\n",
+                )
+                .as_ref(),
+            )
+            .unwrap();
+        temp_dir
+            .child("common.toml")
+            .write_str(
+                concat!(
+                    "scrolling = \"natural\"\n",
+                    "[[block]]\n",
+                    "block = \"time\"\n",
+                )
+                .as_ref(),
+            )
+            .unwrap();
+        temp_dir
+            .child("conf.d/20-memory.toml")
+            .write_str("[[block]]\nblock = \"memory\"\n")
+            .unwrap();
+        temp_dir
+            .child("conf.d/10-cpu.toml")
+            .write_str("[[block]]\nblock = \"cpu\"\n")
+            .unwrap();
+        temp_dir
+            .child("conf.d/README")
+            .write_str("not a config file")
+            .unwrap();
+
+        let config = load_config(config_file_path.path()).unwrap();
+        let names: Vec<&str> = config
+            .blocks
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect();
+        assert_eq!(names, vec!["load", "net", "time", "cpu", "memory"]);
+        assert!(matches!(
+            config.scrolling,
+            crate::config::Scrolling::Natural
+        ));
+        assert_eq!(
+            config.block_locations[2],
+            BlockLocation {
+                file: temp_dir.path().join("blocks/../common.toml"),
+                index: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn test_load_config_include_cycle() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_file_path = temp_dir.child("status.toml");
+        config_file_path
+            .write_str("include = \"other.toml\"\n[[block]]\nblock = \"load\"\n")
+            .unwrap();
+        temp_dir
+            .child("other.toml")
+            .write_str("include = \"status.toml\"\n")
+            .unwrap();
+
+        let error = load_config(config_file_path.path()).unwrap_err();
+        assert!(error.to_line().contains("include each other"));
+    }
+
+    #[test]
+    fn test_load_config_substitution() {
+        let temp_dir = TempDir::new().unwrap();
+        let config_file_path = temp_dir.child("status.toml");
+        std::env::set_var("I3RS_TEST_SUBSTITUTION", "from env");
+        config_file_path
+            .write_str(
+                concat!(
+                    "[[block]]\n",
+                    "block = \"custom\"\n",
+                    "command = { cmd = \"echo from cmd; echo second line\" }\n",
+                    "[block.nested]\n",
+                    "values = [\"kept\", { env = \"I3RS_TEST_SUBSTITUTION\" }]\n",
+                )
+                .as_ref(),
+            )
+            .unwrap();
+        let config = load_config(config_file_path.path()).unwrap();
+        let block_config = &config.blocks[0].1;
+        assert_eq!(block_config["command"].as_str(), Some("from cmd"));
+        assert_eq!(
+            block_config["nested"]["values"][1].as_str(),
+            Some("from env")
+        );
+
+        config_file_path
+            .write_str(
+                concat!(
+                    "[[block]]\n",
+                    "block = \"custom\"\n",
+                    "command = { env = \"I3RS_TEST_SUBSTITUTION_UNSET\" }\n",
+                )
+                .as_ref(),
+            )
+            .unwrap();
+        let error = load_config(config_file_path.path()).unwrap_err();
+        assert!(error.to_line().contains("`command`"));
+        assert!(error.to_line().contains("I3RS_TEST_SUBSTITUTION_UNSET"));
+    }
 }
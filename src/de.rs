@@ -236,6 +236,24 @@ where
     }
 }
 
+/// Accepts a list of strings, or a single string as a list of one.
+pub fn deserialize_opt_string_list<'de, D>(deserializer: D) -> Result<Option<Vec<String>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(serde_derive::Deserialize)]
+    #[serde(untagged)]
+    enum StringList {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(Some(match StringList::deserialize(deserializer)? {
+        StringList::One(string) => vec![string],
+        StringList::Many(strings) => strings,
+    }))
+}
+
 pub fn deserialize_local_timestamp<'de, D>(deserializer: D) -> Result<DateTime<Local>, D::Error>
 where
     D: Deserializer<'de>,
@@ -244,11 +262,53 @@ where
     i64::deserialize(deserializer).map(|seconds| Local.timestamp(seconds, 0))
 }
 
+/// The names of the fields of a struct deriving `Deserialize`, which serde
+/// passes to the deserializer.
+pub fn struct_fields<'de, T: Deserialize<'de>>() -> &'static [&'static str] {
+    struct FieldsDeserializer<'a>(&'a mut &'static [&'static str]);
+
+    impl<'de, 'a> Deserializer<'de> for FieldsDeserializer<'a> {
+        type Error = de::value::Error;
+
+        fn deserialize_any<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
+        where
+            V: de::Visitor<'de>,
+        {
+            Err(de::Error::custom("not a struct"))
+        }
+
+        fn deserialize_struct<V>(
+            self,
+            _name: &'static str,
+            fields: &'static [&'static str],
+            _visitor: V,
+        ) -> Result<V::Value, Self::Error>
+        where
+            V: de::Visitor<'de>,
+        {
+            *self.0 = fields;
+            Err(de::Error::custom("only the fields are needed"))
+        }
+
+        serde::forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+            bytes byte_buf option unit unit_struct newtype_struct seq tuple
+            tuple_struct map enum identifier ignored_any
+        }
+    }
+
+    let mut fields: &'static [&'static str] = &[];
+    let _ = T::deserialize(FieldsDeserializer(&mut fields));
+    fields
+}
+
 #[cfg(test)]
 mod tests {
     use crate::blocks::Update;
     use crate::blocks::Update::{Every, Once};
-    use crate::de::{deserialize_duration, deserialize_update};
+    use crate::de::{
+        deserialize_duration, deserialize_opt_string_list, deserialize_update, struct_fields,
+    };
     use serde_derive::Deserialize;
     use std::time::Duration;
 
@@ -290,4 +350,30 @@ mod tests {
         let deserialized: UpdateConfig = toml::from_str(duration_toml).unwrap();
         assert_eq!(Once, deserialized.interval);
     }
+
+    #[derive(Deserialize, Debug, Clone)]
+    pub struct StringListConfig {
+        #[serde(default, deserialize_with = "deserialize_opt_string_list")]
+        pub devices: Option<Vec<String>>,
+    }
+
+    #[test]
+    fn test_deserialize_opt_string_list() {
+        let deserialized: StringListConfig = toml::from_str(r#"devices = "sda""#).unwrap();
+        assert_eq!(deserialized.devices, Some(vec!["sda".to_string()]));
+        let deserialized: StringListConfig = toml::from_str(r#"devices = ["sda", "sdb"]"#).unwrap();
+        assert_eq!(
+            deserialized.devices,
+            Some(vec!["sda".to_string(), "sdb".to_string()])
+        );
+        let deserialized: StringListConfig = toml::from_str("").unwrap();
+        assert_eq!(deserialized.devices, None);
+    }
+
+    #[test]
+    fn test_struct_fields() {
+        assert_eq!(struct_fields::<DurationConfig>(), &["interval"]);
+        assert!(struct_fields::<Duration>().contains(&"secs"));
+        assert!(struct_fields::<String>().is_empty());
+    }
 }
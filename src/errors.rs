@@ -64,6 +64,29 @@ pub enum Error {
     InternalError(String, String, Option<(String, String)>),
 }
 
+impl Error {
+    /// Adds where the block is defined in the config and its name to the error.
+    pub fn in_block(self, location: &str, name: &str) -> Error {
+        match self {
+            BlockError(block, message) => BlockError(block, format!("{} ({})", message, location)),
+            ConfigurationError(message, cause) => {
+                ConfigurationError(format!("{} '{}': {}", location, name, message), cause)
+            }
+            other => other,
+        }
+    }
+
+    /// The error and its cause on a single line.
+    pub fn to_line(&self) -> String {
+        match *self {
+            ConfigurationError(_, (ref cause, _)) | InternalError(_, _, Some((ref cause, _))) => {
+                format!("{} {}", self, cause.replace('\n', " "))
+            }
+            _ => self.to_string(),
+        }
+    }
+}
+
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
@@ -40,12 +40,20 @@ fn http_easy(mut easy: Easy) -> Result<HttpResponse<Vec<u8>>> {
     })
 }
 
-pub fn http_get_socket_json(path: std::path::PathBuf, url: &str) -> Result<HttpResponse<Value>> {
+pub fn http_get_socket_json(
+    path: std::path::PathBuf,
+    url: &str,
+    timeout: Option<Duration>,
+) -> Result<HttpResponse<Value>> {
     let mut easy = curl::easy::Easy::new();
 
     easy.url(url)?;
     easy.unix_socket_path(Some(path))?;
 
+    if let Some(t) = timeout {
+        easy.timeout(t)?;
+    }
+
     let response = http_easy(easy)?;
 
     let content = serde_json::from_slice(&response.content)
@@ -83,6 +91,15 @@ pub fn http_get_json(
 
     let response = http_easy(easy)?;
 
+    // A response to a conditional request, which has no content
+    if response.code == 304 {
+        return Ok(HttpResponse {
+            code: response.code,
+            content: Value::Null,
+            headers: response.headers,
+        });
+    }
+
     let content = serde_json::from_slice(&response.content)
         .internal_error("curl", "could not parse json response from server")?;
 
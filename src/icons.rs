@@ -23,6 +23,8 @@ lazy_static! {
         "bell-slash" => " OFF ",
         "bluetooth" => " BT",
         "calendar" => " CAL ",
+        "caffeine_off" => " IDLE ",
+        "caffeine_on" => " AWAKE ",
         "cogs" => " LOAD ",
         "cpu" => " CPU ",
         "disk_drive" => " DISK ",
@@ -53,6 +55,7 @@ lazy_static! {
         "phone_disconnected" => " PHONE ",
         "ping" => " PING ",
         "pomodoro" => " POMODORO ",
+        "recording" => " REC ",
         "resolution" => " RES ",
         "tasks" => " TSK ",
         "thermometer" => " TEMP ",
@@ -61,9 +64,14 @@ lazy_static! {
         "toggle_on" => " ON ",
         "update" => " UPD ",
         "uptime" => " UP ",
+        "vpn_connected" => " VPN ",
+        "vpn_disconnected" => " NO VPN ",
+        "volume_bt" => " VOL BT ",
         "volume_empty" => " VOL ",
         "volume_full" => " VOL ",
         "volume_half" => " VOL ",
+        "volume_hdmi" => " VOL HDMI ",
+        "volume_headphones" => " VOL HP ",
         "volume_muted" => " VOL MUTED ",
         "microphone_empty" => " MIC ",
         "microphone_full" => " MIC ",
@@ -98,6 +106,8 @@ lazy_static! {
         "bell-slash" => " \u{f1f7} ",
         "bluetooth" => " \u{f294}",
         "calendar" => " \u{f073} ",
+        "caffeine_off" => " \u{f186} ",
+        "caffeine_on" => " \u{f0f4} ",
         "cogs" => " \u{f085} ",
         "cpu" => " \u{f0e4} ",
         "disk_drive" => " \u{f0a0} ",
@@ -130,6 +140,7 @@ lazy_static! {
         "phone_disconnected" => " \u{1f4f5} ",
         "ping" => " \u{21ba} ",
         "pomodoro" => " \u{1f345} ",
+        "recording" => " \u{f111} ",
         "resolution" => " \u{f096} ", // fa-square-o
         "tasks" => " \u{f0ae} ",
         "thermometer" => " \u{f2c8} ",
@@ -139,9 +150,14 @@ lazy_static! {
         "unknown" => " \u{f128} ",
         "update" => " \u{f062} ", // Same as time symbol.
         "uptime" => " \u{f017} ",
+        "vpn_connected" => " \u{f023} ",
+        "vpn_disconnected" => " \u{f09c} ",
+        "volume_bt" => " \u{f293} ",
         "volume_empty" => " \u{f026} ",
         "volume_full" => " \u{f028} ",
         "volume_half" => " \u{f027} ",
+        "volume_hdmi" => " \u{f26c} ",
+        "volume_headphones" => " \u{f025} ",
         "volume_muted" => " \u{f026} \u{f00d} ",
         "microphone_empty" => " \u{f130} ",
         "microphone_full" => " \u{f130} ",
@@ -169,12 +185,15 @@ lazy_static! {
         "bat_empty" => " \u{f244} ",
         "bat_full" => " \u{f240} ",
         "bat_half" => " \u{f242} ",
+        "bat_not_available" => " \u{f244} ",
         "bat_quarter" => " \u{f243} ",
         "bat_three_quarters" => " \u{f241} ",
         "bell" => " \u{f0f3} ",
         "bell-slash" => " \u{f1f6} ",
         "bluetooth" => " \u{f294}",
         "calendar" => " \u{f073} ",
+        "caffeine_off" => " \u{f186} ",
+        "caffeine_on" => " \u{f0f4} ",
         "cogs" => " \u{f085} ",
         "cpu" => " \u{f3fd} ",
         "disk_drive" => " \u{f8b5} ",
@@ -207,6 +226,7 @@ lazy_static! {
         "phone_disconnected" => " \u{1f4f5} ",
         "ping" => " \u{f362} ",
         "pomodoro" => " \u{1f345} ",
+        "recording" => " \u{f111} ",
         "resolution" => " \u{f096} ", // fa-square-o
         "tasks" => " \u{f0ae} ",
         "thermometer" => " \u{f2c8} ",
@@ -216,9 +236,14 @@ lazy_static! {
         "unknown" => " \u{f128} ",
         "update" => " \u{f062} ",
         "uptime" => " \u{f2f2} ",
+        "vpn_connected" => " \u{f023} ",
+        "vpn_disconnected" => " \u{f3c1} ",
+        "volume_bt" => " \u{f294} ",
         "volume_empty" => " \u{f026} ",
         "volume_full" => " \u{f028} ",
         "volume_half" => " \u{f027} ",
+        "volume_hdmi" => " \u{f26c} ",
+        "volume_headphones" => " \u{f025} ",
         "volume_muted" => " \u{f6a9} ",
         "microphone_full" => " \u{f3c9} ",
         "microphone_half" => " \u{f3c9} ",
@@ -240,12 +265,15 @@ lazy_static! {
         "bat_empty" => " \u{e19c} ",
         "bat_full" => " \u{e1a4} ",
         "bat_half" => " \u{e1a5} ",
+        "bat_not_available" => " \u{e1a6} ",
         "bat_quarter" => " \u{e1a5} ",
         "bat_three_quarters" => " \u{e1a5} ",
         "bell" => " \u{e7f4} ",
         "bell-slash" => " \u{e7f8} ",
         "bluetooth" => " \u{e1a7}",
         "calendar" => " \u{e935}",
+        "caffeine_off" => " \u{e3a8} ",
+        "caffeine_on" => " \u{e541} ",
         "cogs" => " \u{e8b8} ",
         "cpu" => " \u{e640} ",
         "disk_drive" => " \u{e1db} ",
@@ -270,6 +298,7 @@ lazy_static! {
         "phone" => " \u{e324} ",
         "phone_disconnected" => " \u{1f4f5} ",
         "pomodoro" => " \u{1f345} ",
+        "recording" => " \u{e061} ",
         "resolution" => " \u{f152} ", // crop-square-rounded
         "tasks" => " \u{e8f9} ",
         "thermometer" => " \u{f2c8} ", // TODO
@@ -278,9 +307,14 @@ lazy_static! {
         "toggle_on" => " \u{e837} ",
         "update" => " \u{e8d7} ",
         "uptime" => " \u{e192} ", // Same as time symbol.
+        "vpn_connected" => " \u{e897} ",
+        "vpn_disconnected" => " \u{e898} ",
+        "volume_bt" => " \u{e60f} ",
         "volume_empty" => " \u{e04e} ",
         "volume_full" => " \u{e050} ",
         "volume_half" => " \u{e04d} ",
+        "volume_hdmi" => " \u{e333} ",
+        "volume_headphones" => " \u{e310} ",
         "volume_muted" => " \u{e04e} \u{e04f} ",
         "xrandr" => " \u{e31e} "
     };
@@ -299,3 +333,22 @@ pub fn get_icons(name: &str) -> Option<Map<String, String>> {
 pub fn default() -> Map<String, String> {
     NONE.clone()
 }
+
+/// Icons which no set contains, but which are used if they are overridden
+const OPTIONAL_ICONS: &[&str] = &["bar_glyphs", "circle_glyphs"];
+
+/// The names of icons which no set contains, e.g. misspelled overrides.
+pub fn unknown_icons(icons: &Map<String, String>) -> Vec<&str> {
+    let mut unknown: Vec<&str> = icons
+        .keys()
+        .map(String::as_str)
+        .filter(|name| {
+            !OPTIONAL_ICONS.contains(name)
+                && ![&*NONE, &*AWESOME, &*AWESOME5, &*MATERIAL]
+                    .iter()
+                    .any(|set| set.contains_key(*name))
+        })
+        .collect();
+    unknown.sort_unstable();
+    unknown
+}
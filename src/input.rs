@@ -15,11 +15,31 @@ pub enum MouseButton {
     Right,
     WheelUp,
     WheelDown,
+    WheelLeft,
+    WheelRight,
     Forward, // On my mouse, these map to forward and back
     Back,
     Unknown,
 }
 
+impl MouseButton {
+    /// The number of the button, as i3bar reports it.
+    pub fn number(self) -> u64 {
+        match self {
+            MouseButton::Left => 1,
+            MouseButton::Middle => 2,
+            MouseButton::Right => 3,
+            MouseButton::WheelUp => 4,
+            MouseButton::WheelDown => 5,
+            MouseButton::WheelLeft => 6,
+            MouseButton::WheelRight => 7,
+            MouseButton::Back => 8,
+            MouseButton::Forward => 9,
+            MouseButton::Unknown => 0,
+        }
+    }
+}
+
 #[derive(Deserialize, Debug, Clone)]
 pub struct I3BarEvent {
     pub name: Option<String>,
@@ -45,20 +65,37 @@ pub fn process_events(sender: Sender<I3BarEvent>) {
         .name("input".into())
         .spawn(move || loop {
             let mut input = String::new();
-            io::stdin().read_line(&mut input).unwrap();
-
-            // Take only the valid JSON object betweem curly braces (cut off leading bracket, commas and whitespace)
-            let slice = input.trim_start_matches(|c| c != '{');
-            let slice = slice.trim_end_matches(|c| c != '}');
+            match io::stdin().read_line(&mut input) {
+                // i3bar has gone away
+                Ok(0) | Err(_) => return,
+                Ok(_) => {}
+            }
 
-            if !slice.is_empty() {
-                let e: I3BarEvent = serde_json::from_str(slice).unwrap();
-                sender.send(e).unwrap();
+            // Every event is sent on its own, so that e.g. fast scrolling
+            // isn't merged into a single step
+            if let Some(e) = parse_event(&input) {
+                if sender.send(e).is_err() {
+                    return;
+                }
             }
         })
         .unwrap();
 }
 
+/// Parses a line of i3bar's click event array, ignoring lines without a
+/// valid event.
+fn parse_event(line: &str) -> Option<I3BarEvent> {
+    // Take only the valid JSON object betweem curly braces (cut off leading bracket, commas and whitespace)
+    let slice = line.trim_start_matches(|c| c != '{');
+    let slice = slice.trim_end_matches(|c| c != '}');
+
+    if slice.is_empty() {
+        None
+    } else {
+        serde_json::from_str(slice).ok()
+    }
+}
+
 fn deserialize_mousebutton<'de, D>(deserializer: D) -> Result<MouseButton, D::Error>
 where
     D: Deserializer<'de>,
@@ -84,6 +121,8 @@ where
                 3 => MouseButton::Right,
                 4 => MouseButton::WheelUp,
                 5 => MouseButton::WheelDown,
+                6 => MouseButton::WheelLeft,
+                7 => MouseButton::WheelRight,
                 9 => MouseButton::Forward,
                 8 => MouseButton::Back,
                 _ => MouseButton::Unknown,
@@ -93,3 +132,27 @@ where
 
     deserializer.deserialize_any(MouseButtonVisitor)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_event() {
+        assert!(parse_event("[\n").is_none());
+        let event =
+            parse_event(r#",{"name":"a","instance":null,"x":10,"y":2,"button":4}"#).unwrap();
+        assert_eq!(event.name.as_deref(), Some("a"));
+        assert_eq!(event.button, MouseButton::WheelUp);
+        for &(number, button) in &[
+            (5, MouseButton::WheelDown),
+            (6, MouseButton::WheelLeft),
+            (7, MouseButton::WheelRight),
+        ] {
+            let line = format!(r#"{{"name":"a","x":0,"y":0,"button":{}}}"#, number);
+            assert_eq!(parse_event(&line).unwrap().button, button);
+            assert_eq!(button.number(), number);
+        }
+        assert!(parse_event(r#"{"name":"a"}"#).is_none());
+    }
+}
@@ -25,18 +25,20 @@ use cpuprofiler::PROFILER;
 
 use std::collections::HashMap;
 use std::ops::DerefMut;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
 use clap::{crate_authors, crate_description, App, Arg, ArgMatches};
 use crossbeam_channel::{select, Receiver, Sender};
 
-use crate::blocks::create_block;
+use crate::blocks::worker::BlockThreads;
 use crate::blocks::Block;
+use crate::blocks::{check_block_config, create_block};
 use crate::config::{load_config, Config};
 use crate::errors::*;
 use crate::input::{process_events, I3BarEvent};
 use crate::scheduler::{Task, UpdateScheduler};
-use crate::signals::process_signals;
+use crate::signals::{check_unique_signals, parse_signal, process_signals};
 use crate::widget::{I3BarWidget, State};
 use crate::widgets::text::TextWidget;
 
@@ -74,6 +76,34 @@ fn main() {
                 .long("never-pause")
                 .takes_value(false),
         )
+        .arg(
+            Arg::with_name("stop-signal")
+                .help(
+                    "The signal i3bar sends to pause the bar while it is hidden, by name or number",
+                )
+                .long("stop-signal")
+                .value_name("SIGNAL")
+                .default_value("SIGTSTP"),
+        )
+        .arg(
+            Arg::with_name("cont-signal")
+                .help("The signal i3bar sends to resume the bar once it is shown again")
+                .long("cont-signal")
+                .value_name("SIGNAL")
+                .default_value("SIGCONT"),
+        )
+        .arg(
+            Arg::with_name("single-threaded")
+                .help("Update the blocks one after another on the main thread, for debugging")
+                .long("single-threaded")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("check")
+                .help("Check the config file for errors, printing one line per problem, and exit")
+                .long("check")
+                .takes_value(false),
+        )
         .arg(
             Arg::with_name("one-shot")
                 .help("Print blocks once and exit")
@@ -102,6 +132,11 @@ fn main() {
     let matches = builder.get_matches();
     let exit_on_error = matches.is_present("exit-on-error");
 
+    if matches.is_present("check") {
+        let valid = check_config(&config_path(&matches));
+        ::std::process::exit(if valid { 0 } else { 1 });
+    }
+
     // Run and match for potential error
     if let Err(error) = run(&matches) {
         if exit_on_error {
@@ -126,31 +161,77 @@ fn main() {
     }
 }
 
+fn config_path(matches: &ArgMatches) -> PathBuf {
+    match matches.value_of("config") {
+        Some(config_path) => PathBuf::from(config_path),
+        None => util::xdg_config_home().join("i3status-rust/config.toml"),
+    }
+}
+
+/// Prints one line for every problem in the config, and returns whether there
+/// were none. Only the configs of the blocks are checked, without creating the
+/// blocks, so that no threads or commands are started.
+fn check_config(config_path: &Path) -> bool {
+    let config = match load_config(config_path) {
+        Ok(config) => config,
+        Err(error) => {
+            println!("{}", error.to_line());
+            return false;
+        }
+    };
+
+    let mut errors = Vec::new();
+    if let Err(error) = check_unique_signals(&config.blocks) {
+        errors.push(error);
+    }
+    for (index, &(ref block_name, ref block_config)) in config.blocks.iter().enumerate() {
+        if let Err(error) = check_block_config(block_name, block_config.clone(), config.clone()) {
+            errors.push(error.in_block(&config.block_location(index), block_name));
+        }
+    }
+
+    for error in &errors {
+        println!("{}", error.to_line());
+    }
+    errors.is_empty()
+}
+
 fn run(matches: &ArgMatches) -> Result<()> {
+    // Signals sent by i3bar to pause the bar while it is hidden, and to resume it
+    let pause_signals = if matches.is_present("never-pause") {
+        None
+    } else {
+        let stop = parse_signal(matches.value_of("stop-signal").unwrap())?;
+        let cont = parse_signal(matches.value_of("cont-signal").unwrap())?;
+        if stop == cont {
+            return Err(ConfigurationError(
+                "The stop and cont signals must differ".to_owned(),
+                (String::new(), String::new()),
+            ));
+        }
+        Some((stop, cont))
+    };
+
     // Now we can start to run the i3bar protocol
-    let initialise = if matches.is_present("never-pause") {
-        format!(
+    let initialise = match pause_signals {
+        Some((stop, cont)) => format!(
+            "\"version\": 1, \"click_events\": true, \"stop_signal\": {}, \"cont_signal\": {}",
+            stop, cont
+        ),
+        None => format!(
             "\"version\": 1, \"click_events\": true, \"stop_signal\": {}",
             nix::sys::signal::Signal::SIGCONT as i8
-        )
-    } else {
-        "\"version\": 1, \"click_events\": true".to_string()
+        ),
     };
     print!("{{{}}}\n[", initialise);
 
     // Read & parse the config file
-    let config_path = match matches.value_of("config") {
-        Some(config_path) => std::path::PathBuf::from(config_path),
-        None => util::xdg_config_home().join("i3status-rust/config.toml"),
-    };
-    let config = load_config(&config_path)?;
-
-    // Update request channel
-    let (tx_update_requests, rx_update_requests): (Sender<Task>, Receiver<Task>) =
-        crossbeam_channel::unbounded();
+    let config_path = config_path(matches);
+    let mut config = load_config(&config_path)?;
 
     // In dev build, we might diverge into profiling blocks here
     if let Some(name) = matches.value_of("profile") {
+        let (tx_update_requests, _rx_update_requests) = crossbeam_channel::unbounded();
         profile_config(
             name,
             matches.value_of("profile-runs").unwrap(),
@@ -160,17 +241,136 @@ fn run(matches: &ArgMatches) -> Result<()> {
         return Ok(());
     }
 
+    // We wait for click events in a separate thread, to avoid blocking to wait for stdin
+    let (tx_clicks, rx_clicks): (Sender<I3BarEvent>, Receiver<I3BarEvent>) =
+        crossbeam_channel::unbounded();
+    process_events(tx_clicks);
+
+    // We wait for signals in a separate thread
+    let (tx_signals, rx_signals): (Sender<i32>, Receiver<i32>) = crossbeam_channel::unbounded();
+    let extra_signals = match pause_signals {
+        Some((stop, cont)) => vec![stop, cont],
+        None => Vec::new(),
+    };
+    process_signals(tx_signals, &extra_signals);
+
+    // Reloading the config replaces the blocks, while i3bar keeps reading the
+    // same stream
+    let one_shot = matches.is_present("one-shot");
+    // Blocks are only shown once they have finished updating on their threads
+    let threaded = !matches.is_present("single-threaded") && !one_shot;
+    let mut blocks = create_blocks(&config, threaded)?;
+    while let Some((new_config, new_blocks)) = run_blocks(
+        &config,
+        blocks,
+        &config_path,
+        &rx_clicks,
+        &rx_signals,
+        pause_signals,
+        threaded,
+        one_shot,
+    )? {
+        config = new_config;
+        blocks = new_blocks;
+    }
+    Ok(())
+}
+
+/// The blocks of a config, along with the channels they send to
+struct Blocks {
+    blocks: Vec<Box<dyn Block>>,
+    rx_update_requests: Receiver<Task>,
+    rx_ready: Receiver<String>,
+}
+
+/// Creates all blocks of the config, failing if any of them can't be created.
+fn create_blocks(config: &Config, threaded: bool) -> Result<Blocks> {
+    for name in icons::unknown_icons(&config.icons) {
+        eprintln!(
+            "Warning: no icon set contains the overridden icon '{}'",
+            name
+        );
+    }
+
+    check_unique_signals(&config.blocks)?;
+
+    // Update request channel. It is dropped when the config is reloaded, so
+    // that the threads of the old blocks stop once they request an update.
+    let (tx_update_requests, rx_update_requests): (Sender<Task>, Receiver<Task>) =
+        crossbeam_channel::unbounded();
+
+    // Blocks updating on their own threads send their id once they are done
+    let (tx_ready, rx_ready): (Sender<String>, Receiver<String>) = crossbeam_channel::unbounded();
+
+    let threads = if threaded {
+        Some(BlockThreads::new(tx_ready)?)
+    } else {
+        None
+    };
+
     // Initialize the blocks
     let mut blocks: Vec<Box<dyn Block>> = Vec::new();
-    for &(ref block_name, ref block_config) in &config.blocks {
-        blocks.push(create_block(
-            block_name,
-            block_config.clone(),
-            config.clone(),
-            tx_update_requests.clone(),
-        )?);
+    for (index, &(ref block_name, ref block_config)) in config.blocks.iter().enumerate() {
+        let block = if let Some(ref threads) = threads {
+            threads
+                .create_block(
+                    block_name,
+                    block_config.clone(),
+                    config.clone(),
+                    tx_update_requests.clone(),
+                )
+                .map(|block| Box::new(block) as Box<dyn Block>)
+        } else {
+            create_block(
+                block_name,
+                block_config.clone(),
+                config.clone(),
+                tx_update_requests.clone(),
+            )
+        };
+        match block {
+            Ok(block) => blocks.push(block),
+            Err(error) => {
+                // The blocks created so far may already have started threads
+                // or commands
+                for block in &mut blocks {
+                    block.shutdown();
+                }
+                return Err(error.in_block(&config.block_location(index), block_name));
+            }
+        }
     }
 
+    Ok(Blocks {
+        blocks,
+        rx_update_requests,
+        rx_ready,
+    })
+}
+
+/// Runs the blocks of the config until the bar exits, or until the config is
+/// reloaded, in which case the new config and its blocks are returned.
+#[allow(clippy::too_many_arguments)]
+fn run_blocks(
+    config: &Config,
+    blocks: Blocks,
+    config_path: &Path,
+    rx_clicks: &Receiver<I3BarEvent>,
+    rx_signals: &Receiver<i32>,
+    pause_signals: Option<(i32, i32)>,
+    threaded: bool,
+    one_shot: bool,
+) -> Result<Option<(Config, Blocks)>> {
+    let (stop_signal, cont_signal) = match pause_signals {
+        Some((stop, cont)) => (Some(stop), Some(cont)),
+        None => (None, None),
+    };
+    let Blocks {
+        mut blocks,
+        rx_update_requests,
+        rx_ready,
+    } = blocks;
+
     // We save the order of the blocks here,
     // because they will be passed to an unordered HashMap
     let order = blocks
@@ -186,38 +386,56 @@ fn run(matches: &ArgMatches) -> Result<()> {
         block_map.insert(String::from(block.id()), (*block).deref_mut());
     }
 
-    // We wait for click events in a separate thread, to avoid blocking to wait for stdin
-    let (tx_clicks, rx_clicks): (Sender<I3BarEvent>, Receiver<I3BarEvent>) =
-        crossbeam_channel::unbounded();
-    process_events(tx_clicks);
-
-    // We wait for signals in a separate thread
-    let (tx_signals, rx_signals): (Sender<i32>, Receiver<i32>) = crossbeam_channel::unbounded();
-    process_signals(tx_signals);
-
     // Time to next update channel.
     // Fires immediately for first updates
     let mut ttnu = crossbeam_channel::after(Duration::from_millis(0));
 
-    let one_shot = matches.is_present("one-shot");
+    // Whether i3bar has hidden the bar, during which no updates are scheduled
+    let mut paused = false;
+
     loop {
         // We use the message passing concept of channel selection
         // to avoid busy wait
         select! {
             // Receive click events
             recv(rx_clicks) -> res => if let Ok(event) = res {
-                    for block in block_map.values_mut() {
-                        block.click(&event)?;
+                    // Only the block showing the clicked widget handles the click
+                    if let Some(id) = widget_owner(&block_map, &event) {
+                        block_map
+                            .get_mut(&id)
+                            .internal_error("click", "could not get clicked block")?
+                            .click(&event)?;
                     }
                     util::print_blocks(&order, &block_map, &config)?;
             },
             // Receive async update requests
             recv(rx_update_requests) -> request => if let Ok(req) = request {
-                // Process immediately and forget
-                block_map
-                    .get_mut(&req.id)
-                    .internal_error("scheduler", "could not get required block")?
-                    .update()?;
+                // Requests which arrived during the last update are coalesced,
+                // so that each block is only updated once
+                let ids = coalesce(req.id, rx_update_requests.try_iter().map(|req| req.id));
+                // Every block is updated once the bar resumes
+                if !paused {
+                    for id in ids {
+                        // Process immediately, scheduling the next update only if
+                        // there is none pending yet
+                        let update = block_map
+                            .get_mut(&id)
+                            .internal_error("scheduler", "could not get required block")?
+                            .update()?;
+                        scheduler.schedule(&id, update);
+                    }
+                    util::print_blocks(&order, &block_map, &config)?;
+                }
+            },
+            // Receive the results of updates which ran on the blocks' threads
+            recv(rx_ready) -> ready => if let Ok(id) = ready {
+                for id in coalesce(id, rx_ready.try_iter()) {
+                    let update = block_map
+                        .get_mut(&id)
+                        .internal_error("worker", "could not get updated block")?
+                        .poll()?;
+                    scheduler.schedule(&id, update);
+                }
                 util::print_blocks(&order, &block_map, &config)?;
             },
             // Receive update timer events
@@ -228,40 +446,108 @@ fn run(matches: &ArgMatches) -> Result<()> {
             },
             // Receive signal events
             recv(rx_signals) -> res => if let Ok(sig) = res {
-                match sig {
-                    signal_hook::SIGUSR1 => {
-                        //USR1 signal that updates every block in the bar
-                        for block in block_map.values_mut() {
-                            block.update()?;
-                        }
-                        util::print_blocks(&order, &block_map, &config)?;
-                    },
-                    signal_hook::SIGUSR2 => {
-                        //USR2 signal that should reload the config
-                        //TODO not implemented
-                        //unimplemented!("SIGUSR2 is meant to be used to reload the config toml, but this feature is yet not implemented");
-                    },
-                    _ => {
-                        //Real time signal that updates only the blocks listening
-                        //for that signal
-                        for block in block_map.values_mut() {
-                            block.signal(sig)?;
-                        }
-                    },
-                };
+                // Signals sent while the bar was busy are coalesced as well
+                for sig in coalesce(sig, rx_signals.try_iter()) {
+                    match sig {
+                        _ if Some(sig) == stop_signal => {
+                            if !paused {
+                                paused = true;
+                                for block in block_map.values_mut() {
+                                    block.set_paused(true);
+                                }
+                            }
+                        },
+                        _ if Some(sig) == cont_signal => {
+                            if paused {
+                                paused = false;
+                                for block in block_map.values_mut() {
+                                    block.set_paused(false);
+                                }
+                                scheduler.schedule_all_now(block_map.keys());
+                            }
+                        },
+                        signal_hook::SIGUSR1 => {
+                            //USR1 signal that updates every block in the bar,
+                            //which happens anyway once a paused bar resumes
+                            if paused {
+                                continue;
+                            }
+                            for block in block_map.values_mut() {
+                                block.update()?;
+                            }
+                            util::print_blocks(&order, &block_map, &config)?;
+                        },
+                        signal_hook::SIGHUP | signal_hook::SIGUSR2 => {
+                            //HUP and USR2 signals that reload the config. The
+                            //current blocks keep running unless all of the new
+                            //ones could be created.
+                            let reloaded = load_config(config_path).and_then(|new_config| {
+                                let new_blocks = create_blocks(&new_config, threaded)?;
+                                Ok((new_config, new_blocks))
+                            });
+                            match reloaded {
+                                Ok(reloaded) => {
+                                    for block in block_map.values_mut() {
+                                        block.shutdown();
+                                    }
+                                    return Ok(Some(reloaded));
+                                }
+                                Err(error) => eprintln!(
+                                    "Failed to reload the config, keeping the current one: {}",
+                                    error.to_line()
+                                ),
+                            }
+                        },
+                        _ => {
+                            //Real time signal that updates only the blocks listening
+                            //for that signal
+                            for block in block_map.values_mut() {
+                                block.signal(sig)?;
+                            }
+                        },
+                    };
+                }
             }
         }
 
         // Set the time-to-next-update timer
-        if let Some(time) = scheduler.time_to_next_update() {
+        if paused {
+            ttnu = crossbeam_channel::never();
+        } else if let Some(time) = scheduler.time_to_next_update() {
             ttnu = crossbeam_channel::after(time)
         }
         if one_shot {
-            break Ok(());
+            break Ok(None);
         }
     }
 }
 
+/// Returns `first` and the distinct messages among those already `waiting`, in
+/// the order they were received.
+fn coalesce<T: PartialEq>(first: T, waiting: impl Iterator<Item = T>) -> Vec<T> {
+    let mut messages = vec![first];
+    for message in waiting {
+        if !messages.contains(&message) {
+            messages.push(message);
+        }
+    }
+    messages
+}
+
+/// Finds the block which shows the widget an event is for, by the widget's name.
+fn widget_owner(block_map: &HashMap<String, &mut dyn Block>, event: &I3BarEvent) -> Option<String> {
+    let name = event.name.as_ref()?;
+    block_map
+        .iter()
+        .find(|(_, block)| {
+            block
+                .view()
+                .iter()
+                .any(|widget| widget.get_rendered()["name"] == name.as_str())
+        })
+        .map(|(id, _)| id.clone())
+}
+
 #[cfg(feature = "profiling")]
 fn profile(iterations: i32, name: &str, block: &mut dyn Block) {
     let mut bar = progress::Bar::new();
@@ -2,8 +2,7 @@ use crate::blocks::Update;
 use std::cmp;
 use std::collections::{BinaryHeap, HashMap};
 use std::fmt;
-use std::thread;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use crate::blocks::Block;
 use crate::errors::*;
@@ -40,6 +39,34 @@ impl cmp::Ord for Task {
     }
 }
 
+/// Updates which are due within this window of the next one are run along
+/// with it, so that the bar wakes up and prints a line once for all of them.
+pub const COALESCE_WINDOW: Duration = Duration::from_millis(50);
+
+/// The time until the wall clock reaches the next multiple of `interval`, so
+/// that e.g. the minute flips exactly when it should rather than up to an
+/// interval late.
+fn time_to_boundary(since_epoch: Duration, interval: Duration) -> Duration {
+    let interval = interval.as_millis().max(1);
+    let elapsed = since_epoch.as_millis() % interval;
+    Duration::from_millis((interval - elapsed) as u64)
+}
+
+/// When a block which was updated at `now` wants to be updated next.
+fn next_update_time(update: Update, now: Instant) -> Option<Instant> {
+    match update {
+        Update::Every(interval) => Some(now + interval),
+        Update::Aligned(interval) => {
+            let since_epoch = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            Some(now + time_to_boundary(since_epoch, interval))
+        }
+        Update::At(update_time) => Some(update_time),
+        Update::Once => None,
+    }
+}
+
 pub struct UpdateScheduler {
     schedule: BinaryHeap<Task>,
 }
@@ -60,37 +87,48 @@ impl UpdateScheduler {
     }
 
     pub fn time_to_next_update(&self) -> Option<Duration> {
-        if let Some(peeked) = self.schedule.peek() {
-            let next_update = peeked.update_time;
-            let now = Instant::now();
-
-            if next_update > now {
-                Some(next_update - now)
-            } else {
-                Some(Duration::new(0, 0))
-            }
+        let next_update = self.next_batch()?;
+        let now = Instant::now();
+
+        if next_update > now {
+            Some(next_update - now)
         } else {
-            None
+            Some(Duration::new(0, 0))
         }
     }
 
+    /// The time of the last update which is due within `COALESCE_WINDOW` of
+    /// the next one, when the updates are run together.
+    fn next_batch(&self) -> Option<Instant> {
+        let window_end = self.schedule.peek()?.update_time + COALESCE_WINDOW;
+        self.schedule
+            .iter()
+            .map(|task| task.update_time)
+            .filter(|&update_time| update_time <= window_end)
+            .max()
+    }
+
     pub fn do_scheduled_updates(
         &mut self,
         block_map: &mut HashMap<String, &mut dyn Block>,
     ) -> Result<()> {
-        let t = self
-            .schedule
-            .pop()
+        let batch = self
+            .next_batch()
             .internal_error("scheduler", "schedule is empty")?;
-        let mut tasks_next = vec![t.clone()];
-
-        while !self.schedule.is_empty()
-            && t.update_time
-                == self
-                    .schedule
-                    .peek()
-                    .internal_error("scheduler", "schedule is empty")?
-                    .update_time
+        // Rather late than early, so that e.g. the minute has flipped. The
+        // timer waits until the batch is due, so this only happens if a new
+        // update was scheduled in the meantime, and the timer is set again.
+        let now = Instant::now();
+        if batch > now {
+            return Ok(());
+        }
+
+        // The tasks are popped, and the blocks updated, in the order they are due
+        let mut tasks_next = Vec::new();
+        while self
+            .schedule
+            .peek()
+            .map_or(false, |task| task.update_time <= batch)
         {
             tasks_next.push(
                 self.schedule
@@ -99,29 +137,155 @@ impl UpdateScheduler {
             )
         }
 
-        let now = Instant::now();
-        if t.update_time > now {
-            thread::sleep(t.update_time - now);
+        for task in tasks_next {
+            let update = block_map
+                .get_mut(&task.id)
+                .internal_error("scheduler", "could not get required block")?
+                .update()?;
+            if let Some(update_time) = update.and_then(|update| next_update_time(update, now)) {
+                self.schedule.push(Task {
+                    id: task.id,
+                    update_time,
+                });
+            }
         }
 
+        Ok(())
+    }
+
+    /// Replaces the scheduled updates with an immediate update of every block,
+    /// e.g. when the bar resumes after being paused.
+    pub fn schedule_all_now<'a>(&mut self, ids: impl Iterator<Item = &'a String>) {
         let now = Instant::now();
+        self.schedule = ids
+            .map(|id| Task {
+                id: id.clone(),
+                update_time: now,
+            })
+            .collect();
+    }
 
-        for task in tasks_next {
-            if let Some(dur) = block_map
-                .get_mut(&task.id)
-                .internal_error("scheduler", "could not get required block")?
-                .update()?
-            {
-                match dur {
-                    Update::Every(d) => self.schedule.push(Task {
-                        id: task.id,
-                        update_time: now + d,
-                    }),
-                    Update::Once => {} // do not schedule this task again
+    /// Schedules the next update of a block that was updated on request. If
+    /// an update of the block is scheduled already, the earlier one is kept.
+    pub fn schedule(&mut self, id: &str, update: Option<Update>) {
+        let update_time = match update.and_then(|update| next_update_time(update, Instant::now())) {
+            Some(update_time) => update_time,
+            None => return,
+        };
+        match self.schedule.iter().find(|task| task.id == id) {
+            Some(task) if task.update_time <= update_time => {}
+            Some(_) => {
+                // The heap can't be reordered in place
+                let mut tasks = std::mem::take(&mut self.schedule).into_vec();
+                for task in tasks.iter_mut().filter(|task| task.id == id) {
+                    task.update_time = update_time;
                 }
+                self.schedule = tasks.into();
             }
+            None => self.schedule.push(Task {
+                id: id.to_string(),
+                update_time,
+            }),
         }
+    }
+}
 
-        Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::widget::I3BarWidget;
+    use std::thread;
+
+    /// A block which counts its updates
+    struct Counter {
+        id: String,
+        updates: usize,
+    }
+
+    impl Block for Counter {
+        fn id(&self) -> &str {
+            &self.id
+        }
+
+        fn view(&self) -> Vec<&dyn I3BarWidget> {
+            Vec::new()
+        }
+
+        fn update(&mut self) -> Result<Option<Update>> {
+            self.updates += 1;
+            Ok(Some(Update::Once))
+        }
+    }
+
+    #[test]
+    fn test_time_to_boundary() {
+        let minute = Duration::from_secs(60);
+        assert_eq!(
+            time_to_boundary(Duration::from_millis(120_250), minute),
+            Duration::from_millis(59_750)
+        );
+        // Exactly on the boundary, the next one is a whole interval away
+        assert_eq!(time_to_boundary(Duration::from_secs(120), minute), minute);
+        assert_eq!(
+            time_to_boundary(Duration::from_millis(7_900), Duration::from_secs(1)),
+            Duration::from_millis(100)
+        );
+    }
+
+    #[test]
+    fn test_coalesce_updates() {
+        let mut blocks: Vec<Counter> = (0..4)
+            .map(|i| Counter {
+                id: i.to_string(),
+                updates: 0,
+            })
+            .collect();
+        let now = Instant::now();
+        let offsets = [0, 10, 50, 200];
+        let mut scheduler = UpdateScheduler {
+            schedule: blocks
+                .iter()
+                .zip(offsets.iter())
+                .map(|(block, &offset)| Task {
+                    id: block.id.clone(),
+                    update_time: now + Duration::from_millis(offset),
+                })
+                .collect(),
+        };
+        assert!(scheduler.time_to_next_update().unwrap() <= Duration::from_millis(50));
+
+        let mut block_map: HashMap<String, &mut dyn Block> = blocks
+            .iter_mut()
+            .map(|block| (block.id.clone(), block as &mut dyn Block))
+            .collect();
+        // Nothing is updated before the last update within the window is due
+        scheduler.do_scheduled_updates(&mut block_map).unwrap();
+        assert_eq!(scheduler.schedule.len(), 4);
+
+        // The updates within the window run together
+        thread::sleep(scheduler.time_to_next_update().unwrap());
+        scheduler.do_scheduled_updates(&mut block_map).unwrap();
+        assert!(Instant::now() >= now + Duration::from_millis(50));
+        drop(block_map);
+        let updates: Vec<usize> = blocks.iter().map(|block| block.updates).collect();
+        assert_eq!(updates, vec![1, 1, 1, 0]);
+        assert_eq!(scheduler.schedule.len(), 1);
+    }
+
+    #[test]
+    fn test_schedule_keeps_earlier_update() {
+        let mut scheduler = UpdateScheduler {
+            schedule: BinaryHeap::new(),
+        };
+        scheduler.schedule("a", Some(Update::Every(Duration::from_secs(60))));
+        scheduler.schedule("b", Some(Update::Every(Duration::from_secs(30))));
+        // A later update doesn't postpone the pending one
+        scheduler.schedule("a", Some(Update::Every(Duration::from_secs(120))));
+        assert!(scheduler.time_to_next_update().unwrap() <= Duration::from_secs(30));
+        // An earlier one replaces it
+        scheduler.schedule("a", Some(Update::Every(Duration::from_secs(1))));
+        assert_eq!(scheduler.schedule.len(), 2);
+        assert_eq!(scheduler.schedule.peek().unwrap().id, "a");
+        assert!(scheduler.time_to_next_update().unwrap() <= Duration::from_secs(1));
     }
 }
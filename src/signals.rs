@@ -1,9 +1,16 @@
 use crate::errors::*;
 use crossbeam_channel::Sender;
+use nix::sys::signal::Signal;
+use std::collections::HashMap;
+use std::str::FromStr;
 use std::thread;
+use toml::value;
 
-/// Starts a thread that listens for provided signals and sends these on the provided channel
-pub fn process_signals(sender: Sender<i32>) {
+/// Starts a thread that listens for provided signals and sends these on the provided channel.
+/// `extra` are listened for in addition to the real-time signals, `SIGUSR1`, `SIGUSR2` and
+/// `SIGHUP`.
+pub fn process_signals(sender: Sender<i32>, extra: &[i32]) {
+    let extra = extra.to_vec();
     thread::Builder::new()
         .name("signals".into())
         .spawn(move || {
@@ -14,9 +21,11 @@ pub fn process_signals(sender: Sender<i32>) {
                 sigmax = __libc_current_sigrtmax();
             }
             loop {
-                let mut signals = (sigmin..sigmax).collect::<Vec<_>>();
+                let mut signals = (sigmin..=sigmax).collect::<Vec<_>>();
                 signals.push(signal_hook::SIGUSR1);
                 signals.push(signal_hook::SIGUSR2);
+                signals.push(signal_hook::SIGHUP);
+                signals.extend(&extra);
                 let signals = signal_hook::iterator::Signals::new(&signals).unwrap();
                 for sig in signals.forever() {
                     sender.send(sig).unwrap();
@@ -57,6 +66,47 @@ pub fn convert_to_valid_signal(signal: i32) -> Result<i32> {
     }
 }
 
+/// Checks that no two blocks update on the same signal, as sending it would update both.
+pub fn check_unique_signals(blocks: &[(String, value::Value)]) -> Result<()> {
+    let mut claimed: HashMap<i64, &str> = HashMap::new();
+    for (name, config) in blocks {
+        if let Some(signal) = config.get("signal").and_then(|signal| signal.as_integer()) {
+            if let Some(other) = claimed.insert(signal, name.as_str()) {
+                return Err(Error::ConfigurationError(
+                    format!("Signal {} is used by more than one block", signal),
+                    (
+                        format!(
+                            "Both a `{}` and a `{}` block update on signal {}",
+                            other, name, signal
+                        ),
+                        String::new(),
+                    ),
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Parses a signal given by its name, e.g. `SIGTSTP`, or its number.
+pub fn parse_signal(signal: &str) -> Result<i32> {
+    let number = match signal.parse::<i32>() {
+        Ok(number) => number,
+        Err(_) => Signal::from_str(signal)
+            .configuration_error(&format!("Unknown signal '{}'", signal))? as i32,
+    };
+    if number == Signal::SIGSTOP as i32 || number == Signal::SIGKILL as i32 {
+        return Err(Error::ConfigurationError(
+            format!("Signal {} can't be handled", signal),
+            (
+                "SIGSTOP and SIGKILL can't be caught".to_owned(),
+                String::new(),
+            ),
+        ));
+    }
+    Ok(number)
+}
+
 //TODO when libc exposes this through their library and even better when the nix crate does we
 //should be using that binding rather than a C-binding.
 ///C bindings to SIGMIN and SIGMAX values
@@ -64,3 +114,33 @@ extern "C" {
     fn __libc_current_sigrtmin() -> i32;
     fn __libc_current_sigrtmax() -> i32;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(name: &str, config: &str) -> (String, value::Value) {
+        (name.to_owned(), config.parse().unwrap())
+    }
+
+    #[test]
+    fn test_check_unique_signals() {
+        let blocks = vec![
+            block("custom", "signal = 1"),
+            block("custom", "signal = 2"),
+            block("time", "interval = 1"),
+        ];
+        assert!(check_unique_signals(&blocks).is_ok());
+
+        let blocks = vec![block("custom", "signal = 1"), block("custom", "signal = 1")];
+        assert!(check_unique_signals(&blocks).is_err());
+    }
+
+    #[test]
+    fn test_parse_signal() {
+        assert_eq!(parse_signal("SIGTSTP").unwrap(), Signal::SIGTSTP as i32);
+        assert_eq!(parse_signal("10").unwrap(), 10);
+        assert!(parse_signal("SIGSTOP").is_err());
+        assert!(parse_signal("SIGNOPE").is_err());
+    }
+}
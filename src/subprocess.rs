@@ -1,4 +1,5 @@
 use std::io;
+use std::os::unix::process::CommandExt;
 use std::process::{Command, Stdio};
 use std::thread;
 
@@ -16,3 +17,14 @@ pub fn spawn_child_async(name: &str, args: &[&str]) -> io::Result<()> {
         .unwrap();
     Ok(())
 }
+
+/// Has the child process terminated when the bar exits, so that long-running commands aren't
+/// orphaned.
+pub fn die_with_parent(command: &mut Command) -> &mut Command {
+    unsafe {
+        command.pre_exec(|| {
+            nix::libc::prctl(nix::libc::PR_SET_PDEATHSIG, nix::libc::SIGTERM);
+            Ok(())
+        })
+    }
+}
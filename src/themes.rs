@@ -263,6 +263,26 @@ impl Theme {
         }
     }
 
+    /// Replaces the keys which are set in `overrides`.
+    pub fn with_overrides(mut self, overrides: ThemeOverrides) -> Theme {
+        self.idle_bg = overrides.idle_bg.or(self.idle_bg);
+        self.idle_fg = overrides.idle_fg.or(self.idle_fg);
+        self.info_bg = overrides.info_bg.or(self.info_bg);
+        self.info_fg = overrides.info_fg.or(self.info_fg);
+        self.good_bg = overrides.good_bg.or(self.good_bg);
+        self.good_fg = overrides.good_fg.or(self.good_fg);
+        self.warning_bg = overrides.warning_bg.or(self.warning_bg);
+        self.warning_fg = overrides.warning_fg.or(self.warning_fg);
+        self.critical_bg = overrides.critical_bg.or(self.critical_bg);
+        self.critical_fg = overrides.critical_fg.or(self.critical_fg);
+        self.separator = overrides.separator.unwrap_or(self.separator);
+        self.separator_bg = overrides.separator_bg.or(self.separator_bg);
+        self.separator_fg = overrides.separator_fg.or(self.separator_fg);
+        self.alternating_tint_bg = overrides.alternating_tint_bg.or(self.alternating_tint_bg);
+        self.alternating_tint_fg = overrides.alternating_tint_fg.or(self.alternating_tint_fg);
+        self
+    }
+
     pub fn from_file(file: &str) -> Option<Theme> {
         let full_path = Path::new(file);
         let xdg_path = util::xdg_config_home()
@@ -320,21 +340,7 @@ impl ThemeConfig {
             None
         }?;
         if let Some(overrides) = self.overrides {
-            theme.idle_bg = overrides.idle_bg.or(theme.idle_bg);
-            theme.idle_fg = overrides.idle_fg.or(theme.idle_fg);
-            theme.info_bg = overrides.info_bg.or(theme.info_bg);
-            theme.info_fg = overrides.info_fg.or(theme.info_fg);
-            theme.good_bg = overrides.good_bg.or(theme.good_bg);
-            theme.good_fg = overrides.good_fg.or(theme.good_fg);
-            theme.warning_bg = overrides.warning_bg.or(theme.warning_bg);
-            theme.warning_fg = overrides.warning_fg.or(theme.warning_fg);
-            theme.critical_bg = overrides.critical_bg.or(theme.critical_bg);
-            theme.critical_fg = overrides.critical_fg.or(theme.critical_fg);
-            theme.separator = overrides.separator.unwrap_or(theme.separator);
-            theme.separator_bg = overrides.separator_bg.or(theme.separator_bg);
-            theme.separator_fg = overrides.separator_fg.or(theme.separator_fg);
-            theme.alternating_tint_bg = overrides.alternating_tint_bg.or(theme.alternating_tint_bg);
-            theme.alternating_tint_fg = overrides.alternating_tint_fg.or(theme.alternating_tint_fg);
+            theme = theme.with_overrides(overrides);
         }
         Some(theme)
     }
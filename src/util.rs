@@ -1,12 +1,14 @@
-use num_traits::{clamp, ToPrimitive};
-use std::collections::HashMap;
+use num_traits::clamp;
+use std::collections::{HashMap, VecDeque};
 use std::fmt::Display;
 use std::fs::{File, OpenOptions};
 use std::io::prelude::*;
 use std::io::BufReader;
+use std::iter::Peekable;
 use std::path::{Path, PathBuf};
 use std::prelude::v1::String;
 use std::process::Command;
+use std::str::CharIndices;
 
 use regex::Regex;
 use serde::de::DeserializeOwned;
@@ -14,6 +16,7 @@ use serde::de::DeserializeOwned;
 use crate::blocks::Block;
 use crate::config::Config;
 use crate::errors::*;
+use crate::widget::Markup;
 
 pub const USR_SHARE_PATH: &str = "/usr/share/i3status-rust";
 
@@ -24,6 +27,11 @@ pub fn pseudo_uuid() -> String {
     uuid
 }
 
+/// Quotes a string so that it is passed to `sh` as a single argument.
+pub fn escape_shell_argument(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
 pub fn escape_pango_text(text: String) -> String {
     text.chars()
         .map(|x| match x {
@@ -93,6 +101,36 @@ pub fn format_number(raw_value: f64, total_digits: usize, min_unit: &str, suffix
     format!("{:.*}{}{}", decimal_precision as usize, value, unit, suffix)
 }
 
+/// Formats a throughput with one decimal and the unit scaled to its magnitude,
+/// padded to a fixed width so that the output does not change size. Bytes use
+/// binary units (KiB/s, MiB/s, ...), bits use decimal ones (Kbit/s, Mbit/s, ...).
+pub fn format_throughput(bytes_per_second: f64, use_bits: bool) -> String {
+    let (mut value, base, units): (f64, f64, &[&str]) = if use_bits {
+        (
+            bytes_per_second * 8.,
+            1000.,
+            &["bit/s", "Kbit/s", "Mbit/s", "Gbit/s", "Tbit/s"],
+        )
+    } else {
+        (
+            bytes_per_second,
+            1024.,
+            &["B/s", "KiB/s", "MiB/s", "GiB/s", "TiB/s"],
+        )
+    };
+
+    // Switch to the next unit once the value would need four digits, even
+    // if that is below the base of binary units.
+    let mut unit = 0;
+    while value >= 999.95 && unit < units.len() - 1 {
+        value /= base;
+        unit += 1;
+    }
+
+    let unit_width = units.iter().map(|unit| unit.len()).max().unwrap_or(0);
+    format!("{:>5.1}{:<width$}", value, units[unit], width = unit_width)
+}
+
 pub fn battery_level_to_icon(charge_level: Result<u64>) -> &'static str {
     match charge_level {
         Ok(0..=5) => "bat_empty",
@@ -113,6 +151,14 @@ pub fn xdg_config_home() -> PathBuf {
     PathBuf::from(&config_path)
 }
 
+pub fn xdg_cache_home() -> PathBuf {
+    let cache_path = std::env::var("XDG_CACHE_HOME").unwrap_or(format!(
+        "{}/.cache",
+        std::env::var("HOME").unwrap_or_else(|_| "".to_string())
+    ));
+    PathBuf::from(&cache_path)
+}
+
 pub fn deserialize_file<T>(file: &str) -> Result<T>
 where
     T: DeserializeOwned,
@@ -211,6 +257,7 @@ pub fn print_blocks(
         if widgets.is_empty() {
             continue;
         }
+        let theme = block.theme().unwrap_or(&config.theme);
 
         // Get the final JSON from all the widgets for this block
         let mut rendered_widgets = widgets
@@ -221,12 +268,12 @@ pub fn print_blocks(
                     // Apply tint for all widgets of every second block
                     *w_json.get_mut("background").unwrap() = json!(add_colors(
                         w_json["background"].as_str(),
-                        config.theme.alternating_tint_bg.as_deref()
+                        theme.alternating_tint_bg.as_deref()
                     )
                     .unwrap());
                     *w_json.get_mut("color").unwrap() = json!(add_colors(
                         w_json["color"].as_str(),
-                        config.theme.alternating_tint_fg.as_deref()
+                        theme.alternating_tint_fg.as_deref()
                     )
                     .unwrap());
                 }
@@ -236,7 +283,7 @@ pub fn print_blocks(
 
         alternator = !alternator;
 
-        if config.theme.native_separators == Some(true) {
+        if theme.native_separators == Some(true) {
             // Re-add native separator on last widget for native theme
             *rendered_widgets
                 .last_mut()
@@ -257,7 +304,7 @@ pub fn print_blocks(
             .collect::<Vec<String>>()
             .join(",");
 
-        if config.theme.native_separators == Some(true) {
+        if theme.native_separators == Some(true) {
             // Skip separator block for native theme
             rendered_blocks.push(block_str.to_string());
             continue;
@@ -268,21 +315,21 @@ pub fn print_blocks(
             .as_str()
             .internal_error("util", "couldn't get background color")?;
 
-        let sep_fg = if config.theme.separator_fg == Some("auto".to_string()) {
+        let sep_fg = if theme.separator_fg == Some("auto".to_string()) {
             Some(first_bg.to_string())
         } else {
-            config.theme.separator_fg.clone()
+            theme.separator_fg.clone()
         };
 
         // The separator's BG is the last block's last widget's BG
-        let sep_bg = if config.theme.separator_bg == Some("auto".to_string()) {
+        let sep_bg = if theme.separator_bg == Some("auto".to_string()) {
             last_bg
         } else {
-            config.theme.separator_bg.clone()
+            theme.separator_bg.clone()
         };
 
         let separator = json!({
-            "full_text": config.theme.separator,
+            "full_text": theme.separator,
             "separator": false,
             "separator_block_width": 0,
             "background": sep_bg,
@@ -346,6 +393,79 @@ pub fn add_colors(
     }
 }
 
+/// Mixes two colors, with `t` going from 0 (`a`) to 1 (`b`). The colors are interpolated in
+/// HSV along the shorter way around the hue circle, so that e.g. green and red mix to yellow
+/// rather than to a muddy brown.
+pub fn interpolate_colors(
+    a: &str,
+    b: &str,
+    t: f64,
+) -> ::std::result::Result<String, Box<dyn std::error::Error>> {
+    let t = clamp(t, 0., 1.);
+    let (r_a, g_a, b_a, a_a) = color_from_rgba(a)?;
+    let (r_b, g_b, b_b, a_b) = color_from_rgba(b)?;
+    let (mut h_a, s_a, v_a) = rgb_to_hsv(r_a, g_a, b_a);
+    let (mut h_b, s_b, v_b) = rgb_to_hsv(r_b, g_b, b_b);
+
+    // Grays have no hue of their own, so take the other color's
+    if s_a == 0. {
+        h_a = h_b;
+    } else if s_b == 0. {
+        h_b = h_a;
+    }
+    let mut dh = h_b - h_a;
+    if dh > 180. {
+        dh -= 360.;
+    } else if dh < -180. {
+        dh += 360.;
+    }
+
+    let lerp = |x: f64, y: f64| x + (y - x) * t;
+    let (r, g, b) = hsv_to_rgb(
+        (h_a + dh * t).rem_euclid(360.),
+        lerp(s_a, s_b),
+        lerp(v_a, v_b),
+    );
+    let alpha = lerp(a_a as f64, a_b as f64).round() as u8;
+    Ok(color_to_rgba((r, g, b, alpha)))
+}
+
+/// Converts a color to hue (0-360), saturation and value (both 0-1).
+fn rgb_to_hsv(r: u8, g: u8, b: u8) -> (f64, f64, f64) {
+    let (r, g, b) = (r as f64 / 255., g as f64 / 255., b as f64 / 255.);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let delta = max - min;
+
+    let h = if delta == 0. {
+        0.
+    } else if max == r {
+        60. * ((g - b) / delta).rem_euclid(6.)
+    } else if max == g {
+        60. * ((b - r) / delta + 2.)
+    } else {
+        60. * ((r - g) / delta + 4.)
+    };
+    let s = if max == 0. { 0. } else { delta / max };
+    (h, s, max)
+}
+
+fn hsv_to_rgb(h: f64, s: f64, v: f64) -> (u8, u8, u8) {
+    let c = v * s;
+    let x = c * (1. - ((h / 60.).rem_euclid(2.) - 1.).abs());
+    let m = v - c;
+    let (r, g, b) = match (h / 60.) as u8 {
+        0 => (c, x, 0.),
+        1 => (x, c, 0.),
+        2 => (0., c, x),
+        3 => (0., x, c),
+        4 => (x, 0., c),
+        _ => (c, 0., x),
+    };
+    let channel = |c: f64| ((c + m) * 255.).round() as u8;
+    (channel(r), channel(g), channel(b))
+}
+
 pub fn format_percent_bar(percent: f32) -> String {
     let percent = percent.min(100.0);
     let percent = percent.max(0.0);
@@ -376,134 +496,566 @@ pub fn format_percent_bar(percent: f32) -> String {
         .collect()
 }
 
-pub fn format_vec_to_bar_graph<T>(content: &[T], min: Option<T>, max: Option<T>) -> String
-where
-    T: Ord + ToPrimitive,
-{
+/// The default glyphs of `{value:bar}`, from an empty to a full cell in eighths
+pub const BAR_GLYPHS: &str = " \u{258f}\u{258e}\u{258d}\u{258c}\u{258b}\u{258a}\u{2589}\u{2588}";
+/// The default glyphs of `{value:circle}`, from an empty to a full circle
+pub const CIRCLE_GLYPHS: &str = "\u{25cb}\u{25d4}\u{25d1}\u{25d5}\u{25cf}";
+
+/// Renders a percentage as a bar of `width` cells, each of which is filled
+/// in steps of the given glyphs, from empty to full.
+pub fn format_bar(percent: f64, width: usize, glyphs: &[char]) -> String {
+    let steps = glyphs.len().saturating_sub(1);
+    if steps == 0 {
+        return String::new();
+    }
+    let percent = clamp(percent, 0.0, 100.0);
+    let filled = (percent / 100.0 * (width * steps) as f64).round() as usize;
+    (0..width)
+        .map(|cell| glyphs[filled.saturating_sub(cell * steps).min(steps)])
+        .collect()
+}
+
+/// Renders a percentage as the closest of the given glyphs, from empty to full.
+pub fn format_circle(percent: f64, glyphs: &[char]) -> String {
+    let steps = match glyphs.len() {
+        0 => return String::new(),
+        len => len - 1,
+    };
+    let percent = clamp(percent, 0.0, 100.0);
+    glyphs[(percent / 100.0 * steps as f64).round() as usize].to_string()
+}
+
+/// Renders the last `width` samples as a sparkline, scaled against the
+/// largest of them. Missing samples are padded with the lowest bar, so the
+/// output is always `width` characters long.
+pub fn format_sparkline(samples: &VecDeque<f64>, width: usize) -> String {
     // (x * one eighth block) https://en.wikipedia.org/wiki/Block_Elements
     let bars = [
         '\u{2581}', '\u{2582}', '\u{2583}', '\u{2584}', '\u{2585}', '\u{2586}', '\u{2587}',
         '\u{2588}',
     ];
-    let min: f64 = match min {
-        Some(x) => x.to_f64().unwrap(),
-        None => content.iter().min().unwrap().to_f64().unwrap(),
-    };
-    let max: f64 = match max {
-        Some(x) => x.to_f64().unwrap(),
-        None => content.iter().max().unwrap().to_f64().unwrap(),
-    };
-    let extant = max - min;
-    if extant.is_normal() {
-        let length = bars.len() as f64 - 1.0;
-        content
-            .iter()
-            .map(|x| {
-                bars[((clamp(x.to_f64().unwrap(), min, max) - min) / extant * length) as usize]
-            })
-            .collect::<_>()
-    } else {
-        (0..content.len() - 1).map(|_| bars[0]).collect::<_>()
+    let window: Vec<f64> = samples
+        .iter()
+        .skip(samples.len().saturating_sub(width))
+        .cloned()
+        .collect();
+    let max = window.iter().cloned().fold(0., f64::max);
+    let length = bars.len() as f64 - 1.0;
+
+    let padding = (window.len()..width).map(|_| bars[0]);
+    let graph = window.iter().map(|&x| {
+        if max.is_normal() {
+            bars[(clamp(x, 0., max) / max * length).round() as usize]
+        } else {
+            bars[0]
+        }
+    });
+    padding.chain(graph).collect()
+}
+
+/// A value to fill into a placeholder. Numbers can be formatted with the
+/// precision and unit of the placeholder, e.g. `{speed:6.1;K}`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FormatValue {
+    Integer(i64),
+    Float(f64),
+    Text(String),
+}
+
+impl FormatValue {
+    /// Parses a value which has already been formatted, so that numbers can
+    /// still be formatted by the placeholder.
+    fn from_display<T: Display>(value: &T) -> Self {
+        let text = value.to_string();
+        if let Ok(integer) = text.parse() {
+            FormatValue::Integer(integer)
+        } else if let Ok(float) = text.parse() {
+            FormatValue::Float(float)
+        } else {
+            FormatValue::Text(text)
+        }
+    }
+}
+
+impl From<i64> for FormatValue {
+    fn from(value: i64) -> Self {
+        FormatValue::Integer(value)
+    }
+}
+
+impl From<u64> for FormatValue {
+    fn from(value: u64) -> Self {
+        FormatValue::Integer(value as i64)
+    }
+}
+
+impl From<f64> for FormatValue {
+    fn from(value: f64) -> Self {
+        FormatValue::Float(value)
+    }
+}
+
+impl From<String> for FormatValue {
+    fn from(value: String) -> Self {
+        FormatValue::Text(value)
+    }
+}
+
+impl From<&str> for FormatValue {
+    fn from(value: &str) -> Self {
+        FormatValue::Text(value.to_owned())
+    }
+}
+
+/// Glyphs to show a percentage with instead of its number
+#[derive(Debug, Clone, PartialEq)]
+pub enum Glyphs {
+    /// A bar of the given number of cells, e.g. `{volume:bar:5}`
+    Bar(usize, Vec<char>),
+    /// A single glyph, e.g. `{percentage:circle}`
+    Circle(Vec<char>),
+}
+
+/// How a placeholder is formatted, parsed from `{name:width.precision;unit}`,
+/// or `{name:bar:width}` and `{name:circle}` for glyphs.
+/// All parts are optional. A trailing `?` makes zero count as empty, so that
+/// an optional section with a counter is hidden while it is zero.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FormatSpec {
+    /// The minimum width, numbers are aligned to the right and text to the left
+    pub min_width: Option<usize>,
+    /// The number of decimals
+    pub precision: Option<usize>,
+    /// The unit prefix numbers are scaled to, e.g. `K` for thousands
+    pub unit: Option<char>,
+    /// Whether zero is empty
+    pub zero_is_empty: bool,
+    /// Glyphs to show percentages with
+    pub glyphs: Option<Glyphs>,
+}
+
+impl FormatSpec {
+    fn parse(placeholder: &str, spec: &str) -> Result<Self> {
+        let invalid = |reason: &str| {
+            ConfigurationError(
+                format!("Invalid placeholder {} in format string", placeholder),
+                (reason.to_owned(), String::new()),
+            )
+        };
+
+        let zero_is_empty = spec.ends_with('?');
+        let spec = if zero_is_empty {
+            &spec[..spec.len() - 1]
+        } else {
+            spec
+        };
+        if spec == "circle" {
+            return Ok(FormatSpec {
+                zero_is_empty,
+                glyphs: Some(Glyphs::Circle(CIRCLE_GLYPHS.chars().collect())),
+                ..FormatSpec::default()
+            });
+        }
+        if spec == "bar" || spec.starts_with("bar:") {
+            let width = match spec.get(4..) {
+                None => 5,
+                Some(width) => match width.parse() {
+                    Ok(width) if width > 0 => width,
+                    _ => return Err(invalid(&format!("'{}' is not a valid bar width", width))),
+                },
+            };
+            return Ok(FormatSpec {
+                zero_is_empty,
+                glyphs: Some(Glyphs::Bar(width, BAR_GLYPHS.chars().collect())),
+                ..FormatSpec::default()
+            });
+        }
+
+        let (number, unit) = match spec.find(';') {
+            Some(i) => (&spec[..i], Some(&spec[i + 1..])),
+            None => (spec, None),
+        };
+        let (width, precision) = match number.find('.') {
+            Some(i) => (&number[..i], Some(&number[i + 1..])),
+            None => (number, None),
+        };
+
+        let min_width = match width {
+            "" => None,
+            width => Some(
+                width
+                    .parse()
+                    .map_err(|_| invalid(&format!("'{}' is not a valid width", width)))?,
+            ),
+        };
+        let precision = match precision {
+            None => None,
+            Some(precision) => Some(
+                precision
+                    .parse()
+                    .map_err(|_| invalid(&format!("'{}' is not a valid precision", precision)))?,
+            ),
+        };
+        let unit = match unit {
+            None => None,
+            Some(unit) if unit.len() == 1 && "num1KMGT".contains(unit) => unit.chars().next(),
+            Some(unit) => {
+                return Err(invalid(&format!(
+                    "'{}' is not a valid unit, expected one of n, u, m, 1, K, M, G or T",
+                    unit
+                )))
+            }
+        };
+
+        Ok(FormatSpec {
+            min_width,
+            precision,
+            unit,
+            zero_is_empty,
+            glyphs: None,
+        })
+    }
+
+    /// Whether values are shown as they are.
+    fn is_plain(&self) -> bool {
+        self.min_width.is_none()
+            && self.precision.is_none()
+            && self.unit.is_none()
+            && self.glyphs.is_none()
+    }
+
+    /// Whether the value counts as missing, for optional sections.
+    fn is_empty(&self, value: &FormatValue) -> bool {
+        match *value {
+            FormatValue::Text(ref text) => text.is_empty(),
+            FormatValue::Integer(integer) => self.zero_is_empty && integer == 0,
+            FormatValue::Float(float) => self.zero_is_empty && float == 0.0,
+        }
+    }
+
+    fn format(&self, value: &FormatValue) -> String {
+        let number = match *value {
+            FormatValue::Integer(integer) => integer as f64,
+            FormatValue::Float(float) => float,
+            // Text can only be padded
+            FormatValue::Text(ref text) => {
+                return format!("{:<1$}", text, self.min_width.unwrap_or(0));
+            }
+        };
+
+        match self.glyphs {
+            Some(Glyphs::Bar(width, ref glyphs)) => return format_bar(number, width, glyphs),
+            Some(Glyphs::Circle(ref glyphs)) => return format_circle(number, glyphs),
+            None => {}
+        }
+
+        let scaled = match self.unit {
+            Some('n') => number * 1e9,
+            Some('u') => number * 1e6,
+            Some('m') => number * 1e3,
+            Some('K') => number / 1e3,
+            Some('M') => number / 1e6,
+            Some('G') => number / 1e9,
+            Some('T') => number / 1e12,
+            _ => number,
+        };
+        let mut formatted = match (self.precision, value) {
+            (Some(precision), _) => format!("{:.*}", precision, scaled),
+            // Keep integers as they are, unless they are scaled
+            (None, FormatValue::Integer(integer)) if self.unit.is_none() => integer.to_string(),
+            (None, _) => scaled.to_string(),
+        };
+        match self.unit {
+            Some('1') | None => {}
+            Some(unit) => formatted.push(unit),
+        }
+        format!("{:>1$}", formatted, self.min_width.unwrap_or(0))
     }
 }
 
 #[derive(Debug, Clone)]
 pub enum FormatTemplate {
     Str(String, Option<Box<FormatTemplate>>),
-    Var(String, Option<Box<FormatTemplate>>),
+    /// A placeholder, named with its braces, e.g. `{max}`
+    Var(String, FormatSpec, Option<Box<FormatTemplate>>),
+    /// An optional section in square brackets, which is left out if all of
+    /// its placeholders are empty
+    Section(Box<FormatTemplate>, Option<Box<FormatTemplate>>),
+}
+
+/// The result of rendering (a part of) a template.
+struct Rendered {
+    text: String,
+    /// Whether there were any placeholders
+    placeholders: bool,
+    /// Whether any of the placeholders had a value
+    values: bool,
 }
 
 impl FormatTemplate {
     pub fn from_string(s: &str) -> Result<FormatTemplate> {
-        let s_as_bytes = s.as_bytes();
-
-        //valid var tokens: {} containing any amount of alphanumericals
-        let re = Regex::new(r"\{[a-zA-Z0-9_-]+?\}").internal_error("util", "invalid regex")?;
-
-        let mut token_vec: Vec<FormatTemplate> = vec![];
-        let mut start: usize = 0;
-
-        for re_match in re.find_iter(&s) {
-            if re_match.start() != start {
-                let str_vec: Vec<u8> = (&s_as_bytes)[start..re_match.start()].to_vec();
-                token_vec.push(FormatTemplate::Str(
-                    String::from_utf8(str_vec)
-                        .internal_error("util", "failed to convert string from UTF8")?,
-                    None,
-                ));
+        //valid var tokens: {} containing any amount of alphanumericals, optionally
+        //followed by a format spec, e.g. {speed:6.1;K}
+        let re = Regex::new(r"^\{([a-zA-Z0-9_-]+?)(?::([^{}]*))?\}")
+            .internal_error("util", "invalid regex")?;
+
+        let mut chars = s.char_indices().peekable();
+        let tokens = Self::parse_tokens(s, &mut chars, &re, false)?;
+        Ok(Self::link(tokens))
+    }
+
+    /// Parses the template up to the end of the current section.
+    fn parse_tokens(
+        s: &str,
+        chars: &mut Peekable<CharIndices>,
+        re: &Regex,
+        in_section: bool,
+    ) -> Result<Vec<FormatTemplate>> {
+        let invalid = |reason: &str| {
+            ConfigurationError(
+                format!("Invalid format string '{}'", s),
+                (reason.to_owned(), String::new()),
+            )
+        };
+
+        let mut tokens = Vec::new();
+        let mut text = String::new();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                // Escaped brackets are shown as they are
+                '\\' => match chars.peek() {
+                    Some(&(_, next)) if next == '[' || next == ']' || next == '\\' => {
+                        text.push(next);
+                        chars.next();
+                    }
+                    _ => text.push(c),
+                },
+                '[' => {
+                    if !text.is_empty() {
+                        tokens.push(FormatTemplate::Str(text.split_off(0), None));
+                    }
+                    let section = Self::parse_tokens(s, chars, re, true)?;
+                    tokens.push(FormatTemplate::Section(Box::new(Self::link(section)), None));
+                }
+                ']' if in_section => {
+                    if !text.is_empty() {
+                        tokens.push(FormatTemplate::Str(text, None));
+                    }
+                    return Ok(tokens);
+                }
+                ']' => return Err(invalid("']' without '[', use '\\]' for a literal bracket")),
+                '{' => match re.captures(&s[i..]) {
+                    Some(captures) => {
+                        if !text.is_empty() {
+                            tokens.push(FormatTemplate::Str(text.split_off(0), None));
+                        }
+                        let placeholder = &captures[0];
+                        let spec = match captures.get(2) {
+                            Some(spec) => FormatSpec::parse(placeholder, spec.as_str())?,
+                            None => FormatSpec::default(),
+                        };
+                        tokens.push(FormatTemplate::Var(
+                            format!("{{{}}}", &captures[1]),
+                            spec,
+                            None,
+                        ));
+                        let end = i + placeholder.len();
+                        while chars.peek().map_or(false, |&(j, _)| j < end) {
+                            chars.next();
+                        }
+                    }
+                    None => text.push(c),
+                },
+                _ => text.push(c),
             }
-            token_vec.push(FormatTemplate::Var(re_match.as_str().to_string(), None));
-            start = re_match.end();
         }
-        let str_vec: Vec<u8> = (&s_as_bytes)[start..].to_vec();
-        token_vec.push(FormatTemplate::Str(
-            String::from_utf8(str_vec)
-                .internal_error("util", "failed to convert string from UTF8")?,
-            None,
-        ));
-        let mut template: FormatTemplate = match token_vec.pop() {
+        if in_section {
+            return Err(invalid("'[' without ']', use '\\[' for a literal bracket"));
+        }
+        if !text.is_empty() {
+            tokens.push(FormatTemplate::Str(text, None));
+        }
+        Ok(tokens)
+    }
+
+    /// Links the tokens into a template.
+    fn link(mut tokens: Vec<FormatTemplate>) -> FormatTemplate {
+        let mut template: FormatTemplate = match tokens.pop() {
             Some(token) => token,
             _ => FormatTemplate::Str("".to_string(), None),
         };
-        while let Some(token) = token_vec.pop() {
+        while let Some(token) = tokens.pop() {
+            let next = Some(Box::new(template));
             template = match token {
-                FormatTemplate::Str(s, _) => FormatTemplate::Str(s, Some(Box::new(template))),
-                FormatTemplate::Var(s, _) => FormatTemplate::Var(s, Some(Box::new(template))),
+                FormatTemplate::Str(s, _) => FormatTemplate::Str(s, next),
+                FormatTemplate::Var(s, spec, _) => FormatTemplate::Var(s, spec, next),
+                FormatTemplate::Section(section, _) => FormatTemplate::Section(section, next),
             }
         }
-        Ok(template)
+        template
     }
 
-    // TODO: Make this function tail-recursive for compiler optimization, also only use the version below, static_str
-    pub fn render<T: Display>(&self, vars: &HashMap<String, T>) -> String {
+    /// Uses the glyph sets `bar_glyphs` and `circle_glyphs` of the icons, if
+    /// they are set, e.g. for fonts which lack the default glyphs.
+    pub fn with_glyphs(self, icons: &HashMap<String, String>) -> Self {
         use self::FormatTemplate::*;
-        let mut rendered = String::new();
-        match *self {
-            Str(ref s, ref next) => {
-                rendered.push_str(s);
-                if let Some(ref next) = *next {
-                    rendered.push_str(&*next.render(vars));
-                };
-            }
-            Var(ref key, ref next) => {
-                rendered.push_str(&format!(
-                    "{}",
-                    vars.get(key)
-                        .unwrap_or_else(|| panic!("Unknown placeholder in format string: {}", key))
-                ));
-                if let Some(ref next) = *next {
-                    rendered.push_str(&*next.render(vars));
+        // A set needs at least an empty and a full glyph
+        let glyphs = |name: &str| {
+            icons
+                .get(name)
+                .map(|glyphs| glyphs.chars().collect::<Vec<_>>())
+                .filter(|glyphs| glyphs.len() >= 2)
+        };
+        let next = |next: Option<Box<FormatTemplate>>| next.map(|n| Box::new(n.with_glyphs(icons)));
+        match self {
+            Str(s, n) => Str(s, next(n)),
+            Var(key, mut spec, n) => {
+                spec.glyphs = match spec.glyphs {
+                    Some(Glyphs::Bar(width, default)) => {
+                        Some(Glyphs::Bar(width, glyphs("bar_glyphs").unwrap_or(default)))
+                    }
+                    Some(Glyphs::Circle(default)) => {
+                        Some(Glyphs::Circle(glyphs("circle_glyphs").unwrap_or(default)))
+                    }
+                    None => None,
                 };
+                Var(key, spec, next(n))
             }
-        };
-        rendered
+            Section(section, n) => Section(Box::new(section.with_glyphs(icons)), next(n)),
+        }
     }
 
-    pub fn render_static_str<T: Display>(&self, vars: &HashMap<&str, T>) -> Result<String> {
+    /// Returns the names of all placeholders used in the template, including
+    /// their braces, e.g. `{max}`.
+    pub fn placeholders(&self) -> Vec<&str> {
         use self::FormatTemplate::*;
-        let mut rendered = String::new();
-        match *self {
-            Str(ref s, ref next) => {
-                rendered.push_str(s);
-                if let Some(ref next) = *next {
-                    rendered.push_str(&*next.render_static_str(vars)?);
-                };
-            }
-            Var(ref key, ref next) => {
-                rendered.push_str(&format!(
-                    "{}",
-                    vars.get(&**key).internal_error(
-                        "util",
-                        &format!("Unknown placeholder in format string: {}", key)
-                    )?
-                ));
-                if let Some(ref next) = *next {
-                    rendered.push_str(&*next.render_static_str(vars)?);
-                };
-            }
+        let mut placeholders = Vec::new();
+        let mut current = Some(self);
+        while let Some(token) = current {
+            current = match *token {
+                Str(_, ref next) => next.as_deref(),
+                Var(ref key, _, ref next) => {
+                    placeholders.push(key.as_str());
+                    next.as_deref()
+                }
+                Section(ref section, ref next) => {
+                    placeholders.extend(section.placeholders());
+                    next.as_deref()
+                }
+            };
+        }
+        placeholders
+    }
+
+    /// Renders the template, with `lookup` giving the text of a placeholder
+    /// and whether it is empty.
+    fn render_with(
+        &self,
+        lookup: &dyn Fn(&str, &FormatSpec) -> Result<(String, bool)>,
+    ) -> Result<Rendered> {
+        use self::FormatTemplate::*;
+        let mut rendered = Rendered {
+            text: String::new(),
+            placeholders: false,
+            values: false,
         };
+        let mut current = Some(self);
+        while let Some(token) = current {
+            current = match *token {
+                Str(ref s, ref next) => {
+                    rendered.text.push_str(s);
+                    next.as_deref()
+                }
+                Var(ref key, ref spec, ref next) => {
+                    let (text, empty) = lookup(key, spec)?;
+                    rendered.text.push_str(&text);
+                    rendered.placeholders = true;
+                    rendered.values |= !empty;
+                    next.as_deref()
+                }
+                Section(ref section, ref next) => {
+                    let section = section.render_with(lookup)?;
+                    if section.values || !section.placeholders {
+                        rendered.text.push_str(&section.text);
+                    }
+                    rendered.placeholders |= section.placeholders;
+                    rendered.values |= section.values;
+                    next.as_deref()
+                }
+            };
+        }
         Ok(rendered)
     }
+
+    pub fn render<T: Display>(&self, vars: &HashMap<String, T>) -> String {
+        self.render_with(&|key, spec| {
+            let value = vars
+                .get(key)
+                .unwrap_or_else(|| panic!("Unknown placeholder in format string: {}", key));
+            Ok(render_var(spec, value))
+        })
+        .map(|rendered| rendered.text)
+        .unwrap_or_default()
+    }
+
+    pub fn render_static_str<T: Display>(&self, vars: &HashMap<&str, T>) -> Result<String> {
+        self.render_with(&|key, spec| {
+            let value = vars.get(key).internal_error(
+                "util",
+                &format!("Unknown placeholder in format string: {}", key),
+            )?;
+            Ok(render_var(spec, value))
+        })
+        .map(|rendered| rendered.text)
+    }
+
+    /// Like `render_static_str`, but escapes the values if the text is Pango
+    /// markup. The markup of the format itself is kept, so that e.g. a `&` in
+    /// a song title doesn't break `<b>{title}</b>`.
+    pub fn render_markup<T: Display>(
+        &self,
+        vars: &HashMap<&str, T>,
+        markup: Markup,
+    ) -> Result<String> {
+        self.render_with(&|key, spec| {
+            let value = vars.get(key).internal_error(
+                "util",
+                &format!("Unknown placeholder in format string: {}", key),
+            )?;
+            let (text, empty) = render_var(spec, value);
+            Ok(match markup {
+                Markup::Pango => (escape_pango_text(text), empty),
+                Markup::None => (text, empty),
+            })
+        })
+        .map(|rendered| rendered.text)
+    }
+
+    /// Like `render_static_str`, but with typed values, so that numbers don't
+    /// have to be formatted before.
+    pub fn render_values(&self, vars: &HashMap<&str, FormatValue>) -> Result<String> {
+        self.render_with(&|key, spec| {
+            let value = vars.get(key).internal_error(
+                "util",
+                &format!("Unknown placeholder in format string: {}", key),
+            )?;
+            Ok((spec.format(value), spec.is_empty(value)))
+        })
+        .map(|rendered| rendered.text)
+    }
+}
+
+/// Fills in a value which has already been formatted, and tells whether it
+/// is empty.
+fn render_var<T: Display>(spec: &FormatSpec, value: &T) -> (String, bool) {
+    let parsed = FormatValue::from_display(value);
+    let text = if spec.is_plain() {
+        // Keep the value as it is, e.g. with leading zeros
+        value.to_string()
+    } else {
+        spec.format(&parsed)
+    };
+    (text, spec.is_empty(&parsed))
 }
 
 macro_rules! if_debug {
@@ -512,7 +1064,14 @@ macro_rules! if_debug {
 
 #[cfg(test)]
 mod tests {
-    use crate::util::{color_from_rgba, has_command};
+    use crate::errors::ConfigurationError;
+    use crate::util::{
+        color_from_rgba, escape_shell_argument, format_bar, format_circle, format_sparkline,
+        format_throughput, has_command, interpolate_colors, FormatTemplate, FormatValue,
+        BAR_GLYPHS, CIRCLE_GLYPHS,
+    };
+    use crate::widget::Markup;
+    use std::collections::VecDeque;
 
     #[test]
     // we assume sh is always available
@@ -531,6 +1090,173 @@ mod tests {
         let has_command = has_command.unwrap();
         assert!(!has_command)
     }
+    #[test]
+    fn test_format_placeholders() {
+        let template = FormatTemplate::from_string("CPU: {Package_id_0} max {max}").unwrap();
+        assert_eq!(template.placeholders(), vec!["{Package_id_0}", "{max}"]);
+        let template = FormatTemplate::from_string("no placeholders").unwrap();
+        assert!(template.placeholders().is_empty());
+    }
+
+    #[test]
+    fn test_format_spec() {
+        let template = FormatTemplate::from_string("{speed_down:6.1;K}/s {load:.2}").unwrap();
+        assert_eq!(template.placeholders(), vec!["{speed_down}", "{load}"]);
+        let values = map!(
+            "{speed_down}" => FormatValue::Integer(1_234_567),
+            "{load}" => FormatValue::Float(0.5)
+        );
+        assert_eq!(template.render_values(&values).unwrap(), "1234.6K/s 0.50");
+
+        let template = FormatTemplate::from_string(r"\[{name:6}\] \[{n:4}\] \[{n:;1}\]").unwrap();
+        let values = map!(
+            "{name}" => FormatValue::Text("eth0".to_owned()),
+            "{n}" => FormatValue::Integer(42)
+        );
+        assert_eq!(
+            template.render_values(&values).unwrap(),
+            "[eth0  ] [  42] [42]"
+        );
+
+        // Already formatted values are formatted again, if they are numbers
+        let template = FormatTemplate::from_string("{a:5.1} {b:5} {c}").unwrap();
+        let values = map!("{a}" => "3.14159", "{b}" => "up", "{c}" => "3.14159");
+        assert_eq!(
+            template.render_static_str(&values).unwrap(),
+            "  3.1 up    3.14159"
+        );
+    }
+
+    #[test]
+    fn test_format_spec_invalid() {
+        for format in &[
+            "{a:x}",
+            "{a:6.}",
+            "{a:.y}",
+            "{a:;Q}",
+            "{a:6.1;KB}",
+            "{a:-1}",
+        ] {
+            match FormatTemplate::from_string(format) {
+                Err(ConfigurationError(message, _)) => assert!(message.contains(format)),
+                _ => panic!("{} should be invalid", format),
+            }
+        }
+        // Braces without a valid name are left as they are
+        let template = FormatTemplate::from_string("{} {:6}").unwrap();
+        assert!(template.placeholders().is_empty());
+    }
+
+    #[test]
+    fn test_format_bar() {
+        let glyphs: Vec<char> = BAR_GLYPHS.chars().collect();
+        assert_eq!(format_bar(0.0, 5, &glyphs), "     ");
+        assert_eq!(
+            format_bar(100.0, 5, &glyphs),
+            "\u{2588}\u{2588}\u{2588}\u{2588}\u{2588}"
+        );
+        assert_eq!(format_bar(150.0, 2, &glyphs), "\u{2588}\u{2588}");
+        assert_eq!(format_bar(-5.0, 2, &glyphs), "  ");
+        assert_eq!(format_bar(50.0, 5, &glyphs), "\u{2588}\u{2588}\u{258c}  ");
+        // Half a step rounds up
+        assert_eq!(format_bar(6.25, 1, &glyphs), "\u{258f}");
+        assert_eq!(format_bar(6.2, 1, &glyphs), " ");
+        assert_eq!(format_bar(93.8, 1, &glyphs), "\u{2588}");
+        assert_eq!(format_bar(93.7, 1, &glyphs), "\u{2589}");
+    }
+
+    #[test]
+    fn test_format_circle() {
+        let glyphs: Vec<char> = CIRCLE_GLYPHS.chars().collect();
+        assert_eq!(format_circle(0.0, &glyphs), "\u{25cb}");
+        assert_eq!(format_circle(100.0, &glyphs), "\u{25cf}");
+        assert_eq!(format_circle(12.4, &glyphs), "\u{25cb}");
+        assert_eq!(format_circle(12.5, &glyphs), "\u{25d4}");
+        assert_eq!(format_circle(62.5, &glyphs), "\u{25d5}");
+    }
+
+    #[test]
+    fn test_format_glyphs() {
+        let template = FormatTemplate::from_string("{v:bar:2}|{v:circle}|{v:bar}").unwrap();
+        let values = map!("{v}" => "50");
+        assert_eq!(
+            template.render_static_str(&values).unwrap(),
+            "\u{2588} |\u{25d1}|\u{2588}\u{2588}\u{258c}  "
+        );
+
+        let icons = map_to_owned!("bar_glyphs" => "-=", "circle_glyphs" => "x");
+        let template = template.with_glyphs(&icons);
+        // Sets with less than two glyphs are ignored
+        assert_eq!(
+            template.render_static_str(&values).unwrap(),
+            "=-|\u{25d1}|===--"
+        );
+
+        for format in &["{v:bar:0}", "{v:bar:}", "{v:bar:x}"] {
+            assert!(FormatTemplate::from_string(format).is_err());
+        }
+    }
+
+    #[test]
+    fn test_render_markup() {
+        let template = FormatTemplate::from_string("<b>{title}</b> {artist}").unwrap();
+        let values = map!("{title}" => "Rock & Roll", "{artist}" => "<3");
+        assert_eq!(
+            template.render_markup(&values, Markup::Pango).unwrap(),
+            "<b>Rock &amp; Roll</b> &lt;3"
+        );
+        assert_eq!(
+            template.render_markup(&values, Markup::None).unwrap(),
+            "<b>Rock & Roll</b> <3"
+        );
+    }
+
+    #[test]
+    fn test_format_sections() {
+        let template = FormatTemplate::from_string("{artist} - {title}[ ({album})]").unwrap();
+        let mut values = map!("{artist}" => "a", "{title}" => "t", "{album}" => "");
+        assert_eq!(template.render_static_str(&values).unwrap(), "a - t");
+        values.insert("{album}", "b");
+        assert_eq!(template.render_static_str(&values).unwrap(), "a - t (b)");
+
+        // Nested sections are shown if any of their placeholders has a value
+        let template = FormatTemplate::from_string("[{a}[ {b}][ {c}]]!").unwrap();
+        assert_eq!(template.placeholders(), vec!["{a}", "{b}", "{c}"]);
+        let values = map!("{a}" => "", "{b}" => "", "{c}" => "");
+        assert_eq!(template.render_static_str(&values).unwrap(), "!");
+        let values = map!("{a}" => "", "{b}" => "", "{c}" => "c");
+        assert_eq!(template.render_static_str(&values).unwrap(), " c!");
+        let values = map!("{a}" => "a", "{b}" => "b", "{c}" => "");
+        assert_eq!(template.render_static_str(&values).unwrap(), "a b!");
+
+        // Sections without placeholders are always shown
+        let template = FormatTemplate::from_string("[x]").unwrap();
+        assert_eq!(template.render_static_str(&map!("{a}" => "")).unwrap(), "x");
+
+        // Zero only counts as empty if asked for
+        let template = FormatTemplate::from_string("[{n}][ {n:?} new]").unwrap();
+        let values = map!("{n}" => FormatValue::Integer(0));
+        assert_eq!(template.render_values(&values).unwrap(), "0");
+        let values = map!("{n}" => FormatValue::Integer(2));
+        assert_eq!(template.render_values(&values).unwrap(), "2 2 new");
+    }
+
+    #[test]
+    fn test_format_sections_escaped() {
+        let template = FormatTemplate::from_string(r"\[{a}\] \\ \x").unwrap();
+        assert_eq!(
+            template.render_static_str(&map!("{a}" => "")).unwrap(),
+            r"[] \ \x"
+        );
+
+        for format in &["[{a}", "{a}]", "[[{a}]", r"\[{a}]"] {
+            match FormatTemplate::from_string(format) {
+                Err(ConfigurationError(message, _)) => assert!(message.contains(format)),
+                _ => panic!("{} should be invalid", format),
+            }
+        }
+    }
+
     #[test]
     fn test_color_from_rgba() {
         let valid_rgb = "#AABBCC"; //rgb
@@ -555,4 +1281,69 @@ mod tests {
         let rgba = color_from_rgba(invalid);
         assert!(rgba.is_err());
     }
+
+    #[test]
+    fn test_escape_shell_argument() {
+        assert_eq!(escape_shell_argument("/home"), "'/home'");
+        assert_eq!(escape_shell_argument("/mnt/My Drive"), "'/mnt/My Drive'");
+        assert_eq!(escape_shell_argument("it's"), "'it'\\''s'");
+    }
+
+    #[test]
+    fn test_format_throughput() {
+        assert_eq!(format_throughput(0., false), "  0.0B/s  ");
+        assert_eq!(format_throughput(999., false), "999.0B/s  ");
+        assert_eq!(format_throughput(999.94, false), "999.9B/s  ");
+        assert_eq!(format_throughput(999.95, false), "  1.0KiB/s");
+        assert_eq!(format_throughput(1000., false), "  1.0KiB/s");
+        assert_eq!(format_throughput(1536., false), "  1.5KiB/s");
+        assert_eq!(format_throughput(999. * 1024., false), "999.0KiB/s");
+        assert_eq!(format_throughput(1000. * 1024., false), "  1.0MiB/s");
+        assert_eq!(
+            format_throughput(3. * 1024. * 1024. * 1024., false),
+            "  3.0GiB/s"
+        );
+
+        assert_eq!(format_throughput(124.875, true), "999.0bit/s ");
+        assert_eq!(format_throughput(125., true), "  1.0Kbit/s");
+        assert_eq!(format_throughput(125_000_000., true), "  1.0Gbit/s");
+    }
+
+    #[test]
+    fn test_format_sparkline() {
+        let samples: VecDeque<f64> = vec![0., 1., 2., 4., 8.].into_iter().collect();
+        assert_eq!(format_sparkline(&samples, 5), "▁▂▃▅█");
+        assert_eq!(format_sparkline(&samples, 3), "▃▅█");
+        assert_eq!(format_sparkline(&samples, 7), "▁▁▁▂▃▅█");
+        assert_eq!(format_sparkline(&VecDeque::new(), 3), "▁▁▁");
+        let idle: VecDeque<f64> = vec![0., 0.].into_iter().collect();
+        assert_eq!(format_sparkline(&idle, 2), "▁▁");
+    }
+
+    #[test]
+    fn test_interpolate_colors() {
+        assert_eq!(
+            interpolate_colors("#00FF00", "#FF0000", 0.).unwrap(),
+            "#00FF00FF"
+        );
+        assert_eq!(
+            interpolate_colors("#00FF00", "#FF0000", 1.).unwrap(),
+            "#FF0000FF"
+        );
+        // Green and red mix to yellow
+        assert_eq!(
+            interpolate_colors("#00FF00", "#FF0000", 0.5).unwrap(),
+            "#FFFF00FF"
+        );
+        // The hue takes the shorter way around
+        assert_eq!(
+            interpolate_colors("#FF0000", "#FF00FF", 0.5).unwrap(),
+            "#FF0080FF"
+        );
+        assert_eq!(
+            interpolate_colors("#00FF0000", "#00FF00FF", 0.5).unwrap(),
+            "#00FF0080"
+        );
+        assert!(interpolate_colors("#00FF00", "red", 0.5).is_err());
+    }
 }
@@ -6,6 +6,7 @@ use serde_derive::Deserialize;
 use serde_json::value::Value;
 
 use crate::themes::Theme;
+use crate::util::interpolate_colors;
 
 #[derive(Debug, Copy, Clone, Deserialize)]
 pub enum Spacing {
@@ -17,6 +18,22 @@ pub enum Spacing {
     Hidden,
 }
 
+/// How i3bar interprets the text of widgets
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Markup {
+    /// The text is shown as it is
+    None,
+    /// The text is Pango markup, e.g. `<b>bold</b>`
+    Pango,
+}
+
+impl Default for Markup {
+    fn default() -> Self {
+        Markup::None
+    }
+}
+
 #[derive(Debug, Copy, Clone, Deserialize)]
 pub enum State {
     Idle,
@@ -39,6 +56,47 @@ impl State {
     }
 }
 
+/// The background and foreground colors for a value between the `stops`, each of which is the
+/// value at which the colors of its state are reached. Between two stops the colors of their
+/// states are mixed; colors which are unset or invalid switch over halfway instead.
+pub fn gradient_keys(
+    theme: &Theme,
+    value: f64,
+    stops: &[(f64, State)],
+) -> (Option<String>, Option<String>) {
+    let mut stops = stops.to_vec();
+    stops.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+
+    let (from, to, t) = match stops.iter().position(|&(stop, _)| value < stop) {
+        None => match stops.last() {
+            Some(&(_, state)) => (state, state, 0.),
+            None => (State::Idle, State::Idle, 0.),
+        },
+        Some(0) => (stops[0].1, stops[0].1, 0.),
+        Some(i) => {
+            let (low, from) = stops[i - 1];
+            let (high, to) = stops[i];
+            (from, to, (value - low) / (high - low))
+        }
+    };
+
+    let (from_bg, from_fg) = from.theme_keys(theme);
+    let (to_bg, to_fg) = to.theme_keys(theme);
+    let mix = |a: &Option<String>, b: &Option<String>| match (a, b) {
+        (Some(a), Some(b)) => interpolate_colors(a, b, t)
+            .ok()
+            .or_else(|| Some(if t < 0.5 { a.clone() } else { b.clone() })),
+        _ => {
+            if t < 0.5 {
+                a.clone()
+            } else {
+                b.clone()
+            }
+        }
+    };
+    (mix(from_bg, to_bg), mix(from_fg, to_fg))
+}
+
 impl FromStr for State {
     type Err = ();
 
@@ -52,3 +110,52 @@ pub trait I3BarWidget {
     fn to_string(&self) -> String;
     fn get_rendered(&self) -> &Value;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gradient_keys() {
+        let mut theme = Theme::default();
+        theme.good_bg = Some("#00FF00".to_string());
+        theme.warning_bg = Some("#FFFF00".to_string());
+        theme.critical_bg = Some("#FF0000".to_string());
+        theme.good_fg = None;
+        let stops = [
+            (10., State::Critical),
+            (30., State::Warning),
+            (50., State::Good),
+        ];
+
+        assert_eq!(
+            gradient_keys(&theme, 100., &stops).0,
+            Some("#00FF00FF".to_string())
+        );
+        assert_eq!(
+            gradient_keys(&theme, 0., &stops).0,
+            Some("#FF0000FF".to_string())
+        );
+        assert_eq!(
+            gradient_keys(&theme, 30., &stops).0,
+            Some("#FFFF00FF".to_string())
+        );
+        assert_eq!(
+            gradient_keys(&theme, 20., &stops).0,
+            Some("#FF8000FF".to_string())
+        );
+        // Unset colors switch over halfway
+        assert_eq!(gradient_keys(&theme, 35., &stops).1, theme.warning_fg);
+        assert_eq!(gradient_keys(&theme, 45., &stops).1, None);
+        // The stops may be given in either order
+        let reversed = [
+            (50., State::Good),
+            (30., State::Warning),
+            (10., State::Critical),
+        ];
+        assert_eq!(
+            gradient_keys(&theme, 20., &reversed),
+            gradient_keys(&theme, 20., &stops)
+        );
+    }
+}
@@ -2,14 +2,18 @@ use serde_json::value::Value;
 
 use super::super::widget::I3BarWidget;
 use crate::config::Config;
+use crate::widget::Markup;
 use crate::widget::Spacing;
-use crate::widget::State;
+use crate::widget::{gradient_keys, State};
 
 #[derive(Clone, Debug)]
 pub struct ButtonWidget {
     content: Option<String>,
+    short_text: Option<String>,
     icon: Option<String>,
     state: State,
+    /// Colors blended between states, which take precedence over the state's
+    colors: Option<(Option<String>, Option<String>)>,
     spacing: Spacing,
     id: String,
     rendered: Value,
@@ -21,8 +25,10 @@ impl ButtonWidget {
     pub fn new(config: Config, id: &str) -> Self {
         ButtonWidget {
             content: None,
+            short_text: None,
             icon: None,
             state: State::Idle,
+            colors: None,
             spacing: Spacing::Normal,
             id: String::from(id),
             rendered: json!({
@@ -30,8 +36,7 @@ impl ButtonWidget {
                 "separator": false,
                 "separator_block_width": 0,
                 "background": "#000000",
-                "color": "#000000",
-                "markup": "pango"
+                "color": "#000000"
             }),
             config,
             cached_output: None,
@@ -73,6 +78,12 @@ impl ButtonWidget {
         self.update();
     }
 
+    /// Sets the text i3bar shows instead when the bar is too narrow.
+    pub fn set_short_text(&mut self, short_text: Option<String>) {
+        self.short_text = short_text;
+        self.update();
+    }
+
     pub fn set_icon(&mut self, name: &str) {
         self.icon = self.config.icons.get(name).cloned();
         self.update();
@@ -80,16 +91,29 @@ impl ButtonWidget {
 
     pub fn set_state(&mut self, state: State) {
         self.state = state;
+        self.colors = None;
         self.update();
     }
 
+    /// Blends the colors between the states of the `stops` that `value` falls between, if the
+    /// block's `gradient` option is set. The colors are reset by the next `set_state`.
+    pub fn set_gradient(&mut self, value: f64, stops: &[(f64, State)]) {
+        if self.config.gradient {
+            self.colors = Some(gradient_keys(&self.config.theme, value, stops));
+            self.update();
+        }
+    }
+
     pub fn set_spacing(&mut self, spacing: Spacing) {
         self.spacing = spacing;
         self.update();
     }
 
     fn update(&mut self) {
-        let (key_bg, key_fg) = self.state.theme_keys(&self.config.theme);
+        let (key_bg, key_fg) = match self.colors {
+            Some((ref bg, ref fg)) => (bg, fg),
+            None => self.state.theme_keys(&self.config.theme),
+        };
 
         // When rendered inline, remove the leading space
         self.rendered = json!({
@@ -110,9 +134,26 @@ impl ButtonWidget {
             "name": self.id.clone(),
             "separator_block_width": 0,
             "background": key_bg,
-            "color": key_fg,
-            "markup": "pango"
+            "color": key_fg
         });
+        if self.config.markup == Markup::Pango {
+            self.rendered["markup"] = json!("pango");
+        }
+
+        if let Some(ref short_text) = self.short_text {
+            self.rendered["short_text"] = Value::String(format!(
+                "{}{}{}",
+                self.icon.clone().unwrap_or_else(|| match self.spacing {
+                    Spacing::Normal => String::from(" "),
+                    _ => String::from(""),
+                }),
+                short_text,
+                match self.spacing {
+                    Spacing::Hidden => "",
+                    _ => " ",
+                }
+            ));
+        }
 
         self.cached_output = Some(self.rendered.to_string());
     }
@@ -4,7 +4,7 @@ use serde_json::value::Value;
 
 use crate::config::Config;
 use crate::errors::*;
-use crate::widget::{I3BarWidget, Spacing, State};
+use crate::widget::{I3BarWidget, Markup, Spacing, State};
 
 #[derive(Clone, Debug)]
 pub struct RotatingTextWidget {
@@ -15,6 +15,7 @@ pub struct RotatingTextWidget {
     rotation_speed: Duration,
     next_rotation: Option<Instant>,
     content: String,
+    short_text: Option<String>,
     icon: Option<String>,
     state: State,
     spacing: Spacing,
@@ -43,6 +44,7 @@ impl RotatingTextWidget {
             rotation_speed: speed,
             next_rotation: None,
             content: String::new(),
+            short_text: None,
             icon: None,
             state: State::Idle,
             spacing: Spacing::Normal,
@@ -81,7 +83,7 @@ impl RotatingTextWidget {
     pub fn with_text(mut self, content: &str) -> Self {
         self.content = String::from(content);
         self.rotation_pos = 0;
-        if self.content.chars().count() > self.max_width {
+        if self.is_too_long() {
             self.next_rotation = Some(Instant::now() + self.rotation_interval);
         } else {
             self.next_rotation = None;
@@ -104,7 +106,7 @@ impl RotatingTextWidget {
         if self.content != content {
             self.content = content;
             self.rotation_pos = 0;
-            if self.content.chars().count() > self.max_width {
+            if self.is_too_long() {
                 self.next_rotation = Some(Instant::now() + self.rotation_interval);
             } else {
                 self.next_rotation = None;
@@ -113,12 +115,40 @@ impl RotatingTextWidget {
         self.update()
     }
 
+    /// Replaces the text without restarting its rotation, for when only a part
+    /// of it changed, like a playback position.
+    pub fn set_text_keeping_rotation(&mut self, content: String) {
+        self.content = content;
+        if !self.is_too_long() {
+            self.rotation_pos = 0;
+            self.rotating = false;
+            self.next_rotation = None;
+        } else if self.next_rotation.is_none() {
+            self.next_rotation = Some(Instant::now() + self.rotation_interval);
+        } else {
+            self.rotation_pos = self.rotation_pos.min(self.content.chars().count());
+        }
+        self.update()
+    }
+
+    /// Sets the text i3bar shows instead when the bar is too narrow. It isn't rotated.
+    pub fn set_short_text(&mut self, short_text: Option<String>) {
+        self.short_text = short_text;
+        self.update();
+    }
+
     pub fn is_empty(&self) -> bool {
         self.content.is_empty()
     }
 
+    /// Whether the content needs rotating. Markup is never rotated, as that
+    /// could cut tags and entities apart.
+    fn is_too_long(&self) -> bool {
+        self.config.markup != Markup::Pango && self.content.chars().count() > self.max_width
+    }
+
     fn get_rotated_content(&self) -> String {
-        if self.content.chars().count() > self.max_width {
+        if self.is_too_long() {
             let missing =
                 (self.rotation_pos + self.max_width).saturating_sub(self.content.chars().count());
             if missing == 0 {
@@ -179,10 +209,31 @@ impl RotatingTextWidget {
             "name": self.id.clone(),
             "color": key_fg
         });
+        if self.config.markup == Markup::Pango {
+            self.rendered["markup"] = json!("pango");
+        }
+
+        if let Some(ref short_text) = self.short_text {
+            self.rendered["short_text"] = Value::String(format!(
+                "{}{}{}",
+                icon,
+                short_text,
+                match self.spacing {
+                    Spacing::Hidden => "",
+                    _ => " ",
+                }
+            ));
+        }
 
         self.cached_output = Some(self.rendered.to_string());
     }
 
+    /// The time until the text is rotated next, if it doesn't fit.
+    pub fn time_to_next_rotation(&self) -> Option<Duration> {
+        self.next_rotation
+            .map(|next_rotation| next_rotation.saturating_duration_since(Instant::now()))
+    }
+
     pub fn next(&mut self) -> Result<(bool, Option<Duration>)> {
         if let Some(next_rotation) = self.next_rotation {
             let now = Instant::now();
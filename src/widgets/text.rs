@@ -2,15 +2,20 @@ use serde_json::value::Value;
 
 use super::super::widget::I3BarWidget;
 use crate::config::Config;
+use crate::widget::Markup;
 use crate::widget::Spacing;
-use crate::widget::State;
+use crate::widget::{gradient_keys, State};
 
 #[derive(Clone, Debug)]
 pub struct TextWidget {
     content: Option<String>,
+    short_text: Option<String>,
     icon: Option<String>,
     state: State,
+    /// Colors blended between states, which take precedence over the state's
+    colors: Option<(Option<String>, Option<String>)>,
     spacing: Spacing,
+    urgent: bool,
     id: String,
     rendered: Value,
     cached_output: Option<String>,
@@ -21,9 +26,12 @@ impl TextWidget {
     pub fn new(config: Config, id: &str) -> Self {
         TextWidget {
             content: None,
+            short_text: None,
             icon: None,
             state: State::Idle,
+            colors: None,
             spacing: Spacing::Normal,
+            urgent: false,
             id: id.to_string(),
             rendered: json!({
                 "full_text": "",
@@ -66,6 +74,12 @@ impl TextWidget {
         self.update();
     }
 
+    /// Sets the text i3bar shows instead when the bar is too narrow.
+    pub fn set_short_text(&mut self, short_text: Option<String>) {
+        self.short_text = short_text;
+        self.update();
+    }
+
     pub fn set_icon(&mut self, name: &str) {
         self.icon = self.config.icons.get(name).cloned();
         self.update();
@@ -73,16 +87,35 @@ impl TextWidget {
 
     pub fn set_state(&mut self, state: State) {
         self.state = state;
+        self.colors = None;
         self.update();
     }
 
+    /// Blends the colors between the states of the `stops` that `value` falls between, if the
+    /// block's `gradient` option is set. The colors are reset by the next `set_state`.
+    pub fn set_gradient(&mut self, value: f64, stops: &[(f64, State)]) {
+        if self.config.gradient {
+            self.colors = Some(gradient_keys(&self.config.theme, value, stops));
+            self.update();
+        }
+    }
+
     pub fn set_spacing(&mut self, spacing: Spacing) {
         self.spacing = spacing;
         self.update();
     }
 
+    /// Sets i3bar's `urgent` flag, which makes the bar highlight the widget.
+    pub fn set_urgent(&mut self, urgent: bool) {
+        self.urgent = urgent;
+        self.update();
+    }
+
     fn update(&mut self) {
-        let (key_bg, key_fg) = self.state.theme_keys(&self.config.theme);
+        let (key_bg, key_fg) = match self.colors {
+            Some((ref bg, ref fg)) => (bg, fg),
+            None => self.state.theme_keys(&self.config.theme),
+        };
 
         self.rendered = json!({
             "full_text": format!("{}{}{}",
@@ -104,6 +137,26 @@ impl TextWidget {
             "background": key_bg.to_owned(),
             "color": key_fg.to_owned()
         });
+        if let Some(ref short_text) = self.short_text {
+            self.rendered["short_text"] = Value::String(format!(
+                "{}{}{}",
+                self.icon.clone().unwrap_or_else(|| match self.spacing {
+                    Spacing::Normal => String::from(" "),
+                    _ => String::from(""),
+                }),
+                short_text,
+                match self.spacing {
+                    Spacing::Hidden => "",
+                    _ => " ",
+                }
+            ));
+        }
+        if self.config.markup == Markup::Pango {
+            self.rendered["markup"] = json!("pango");
+        }
+        if self.urgent {
+            self.rendered["urgent"] = json!(true);
+        }
 
         self.cached_output = Some(self.rendered.to_string());
     }